@@ -0,0 +1,195 @@
+// a chord played a note at a time over and over, synced to a timeline
+// steps are rate beats apart from the start of the region, the last one starting before its end,
+// and each is held for gate of its step
+use alloc::vec::Vec;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{func::noise, instrument::Instrument, timeline::Timeline, PackError, TimedWavePacker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pattern {
+    #[default]
+    Up,
+    Down,
+    // up then back down without playing the top or bottom twice
+    UpDown,
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Arpeggiator {
+    pub pattern: Pattern,
+    // beats per step, 0.25 for sixteenths
+    pub rate: f64,
+    pub gate: f64,
+    // how many octaves the chord's spread over, 1 plays it as it is
+    pub octaves: u32,
+    pub seed: u32,
+}
+impl Arpeggiator {
+    pub fn new(pattern: Pattern, rate: f64) -> Self {
+        Arpeggiator {
+            pattern,
+            rate,
+            gate: 0.5,
+            octaves: 1,
+            seed: 0,
+        }
+    }
+    // (pitch, beat) for every step between from and to, in order
+    pub fn steps(&self, chord: &[f32], (from, to): (f64, f64)) -> Vec<(f32, f64)> {
+        let mut notes: Vec<f32> = (0..self.octaves.max(1))
+            .flat_map(|octave| chord.iter().map(move |p| p + 12. * octave as f32))
+            .collect();
+        notes.sort_by(f32::total_cmp);
+        if notes.is_empty() || self.rate <= 0. {
+            return Vec::new();
+        }
+        let cycle: Vec<f32> = match self.pattern {
+            Pattern::Up | Pattern::Random => notes.clone(),
+            Pattern::Down => notes.iter().rev().copied().collect(),
+            Pattern::UpDown => {
+                let down = notes.iter().rev().skip(1);
+                let down = down.take(notes.len().saturating_sub(2));
+                notes.iter().chain(down).copied().collect()
+            }
+        };
+        let count = ((to - from) / self.rate).ceil().max(0.) as usize;
+        (0..count)
+            .map(|step| {
+                let pitch = match self.pattern {
+                    Pattern::Random => {
+                        let random = noise(step as i64 + ((self.seed as i64) << 32), 0);
+                        let index = ((random + 1.) / 2. * cycle.len() as f32) as usize;
+                        cycle[index.min(cycle.len() - 1)]
+                    }
+                    _ => cycle[step % cycle.len()],
+                };
+                (pitch, from + step as f64 * self.rate)
+            })
+            .collect()
+    }
+}
+
+impl TimedWavePacker {
+    // the steps between from and to beats as notes on the end like try_extend, normalize after if
+    // anything later is already in
+    pub fn add_arpeggio(
+        &mut self,
+        timeline: &Timeline,
+        instrument: &Instrument,
+        arpeggiator: &Arpeggiator,
+        chord: &[f32],
+        region: (f64, f64),
+        velocity: f32,
+    ) -> Result<(), PackError> {
+        let held = arpeggiator.rate * arpeggiator.gate;
+        for (pitch, beat) in arpeggiator.steps(chord, region) {
+            self.add_note_at(timeline, instrument, pitch, (beat, held), velocity)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn arpeggios() {
+    let chord = [64., 60., 67.];
+    let up = Arpeggiator::new(Pattern::Up, 0.5);
+    let pitches = |arp: &Arpeggiator, region| -> Vec<f32> {
+        arp.steps(&chord, region)
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect()
+    };
+    assert_eq!(pitches(&up, (0., 2.5)), [60., 64., 67., 60., 64.]);
+    let beats: Vec<f64> = up
+        .steps(&chord, (1., 2.2))
+        .into_iter()
+        .map(|(_, b)| b)
+        .collect();
+    assert_eq!(beats, [1., 1.5, 2.]);
+    let down = Arpeggiator::new(Pattern::Down, 0.5);
+    assert_eq!(pitches(&down, (0., 2.)), [67., 64., 60., 67.]);
+    let both = Arpeggiator {
+        octaves: 2,
+        ..Arpeggiator::new(Pattern::UpDown, 0.25)
+    };
+    assert_eq!(
+        pitches(&both, (0., 3.)),
+        [60., 64., 67., 72., 76., 79., 76., 72., 67., 64., 60., 64.]
+    );
+    let random = Arpeggiator::new(Pattern::Random, 0.25);
+    let picked = pitches(&random, (0., 8.));
+    assert!(picked.iter().all(|p| chord.contains(p)));
+    assert!(chord.iter().all(|p| picked.contains(p)));
+    assert_eq!(picked, pitches(&random, (0., 8.)));
+    assert!(up.steps(&[], (0., 4.)).is_empty());
+
+    let timeline = Timeline::new(120., 48000.);
+    let mut packer = TimedWavePacker::new();
+    let instrument = Instrument::new(48000.);
+    packer
+        .add_arpeggio(&timeline, &instrument, &up, &chord, (0., 2.), 1.)
+        .unwrap();
+    assert_eq!(packer.timings.len(), 4);
+    assert_eq!(packer.timings[1][0], 12000);
+    assert_eq!(packer.envelopes[1].gate, 6000.);
+    assert!(packer.get_pack().is_some());
+}
+
+#[test]
+fn arpeggio_grid() {
+    // half a second a beat, then a second a beat from beat 2, so steps and gates double there
+    let mut timeline = Timeline::new(120., 1000.);
+    timeline.set_tempo(2., 60.);
+    let instrument = Instrument::new(1000.);
+    let eighths = Arpeggiator::new(Pattern::Up, 0.5);
+    let mut packer = TimedWavePacker::new();
+    packer
+        .add_arpeggio(&timeline, &instrument, &eighths, &[60., 64.], (0., 4.), 1.)
+        .unwrap();
+    let starts: Vec<i64> = packer.timings.iter().map(|t| t[0]).collect();
+    assert_eq!(starts, [0, 250, 500, 750, 1000, 1500, 2000, 2500]);
+    let gates: Vec<f32> = packer.envelopes.iter().map(|e| e.gate).collect();
+    assert_eq!(gates, [125., 125., 125., 125., 250., 250., 250., 250.]);
+    // a longer gate holds each note for more of its step, all the way to legato
+    let legato = Arpeggiator {
+        gate: 1.,
+        ..eighths
+    };
+    let mut packer = TimedWavePacker::new();
+    packer
+        .add_arpeggio(&timeline, &instrument, &legato, &[60., 64.], (1., 3.), 1.)
+        .unwrap();
+    let gates: Vec<f32> = packer.envelopes.iter().map(|e| e.gate).collect();
+    assert_eq!(gates, [250., 250., 500., 500.]);
+
+    // up and down on a single note or two just repeats them
+    let bounce = Arpeggiator::new(Pattern::UpDown, 1.);
+    let pitches = |chord: &[f32]| -> Vec<f32> {
+        bounce
+            .steps(chord, (0., 4.))
+            .into_iter()
+            .map(|(p, _)| p)
+            .collect()
+    };
+    assert_eq!(pitches(&[60.]), [60.; 4]);
+    assert_eq!(pitches(&[67., 60.]), [60., 67., 60., 67.]);
+    // each seed is its own order, the same seed is the same order
+    let random = |seed| Arpeggiator {
+        seed,
+        ..Arpeggiator::new(Pattern::Random, 0.25)
+    };
+    let chord = [60., 64., 67., 71.];
+    assert_eq!(
+        random(3).steps(&chord, (0., 8.)),
+        random(3).steps(&chord, (0., 8.))
+    );
+    assert_ne!(
+        random(3).steps(&chord, (0., 8.)),
+        random(4).steps(&chord, (0., 8.))
+    );
+}
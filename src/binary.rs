@@ -0,0 +1,491 @@
+// on-disk layout for packs
+//
+// magic, version and lane count, then an (offset, length) pair in bytes for every lane.
+// lanes follow in the same order as PackSlices, each one starting on an 8 byte boundary
+// so a loaded (or memory mapped) file can be borrowed in place without parsing or copying.
+// everything is native endian, a file written with the other byte order fails the version check.
+// writing needs std for io, reading only needs the bytes so it works without.
+use alloc::{vec, vec::Vec};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+#[cfg(feature = "std")]
+use bytemuck::cast_slice;
+use bytemuck::{
+    checked::{try_cast_slice, try_pod_read_unaligned},
+    CheckedBitPattern, NoUninit,
+};
+
+use crate::{
+    func::{Interpolation, RunLength, Waveform},
+    PackSlices, PackedTimedWaves, TimedWavePacker,
+};
+
+pub const MAGIC: [u8; 8] = *b"AUDYPACK";
+// version 1 stored runs as single bytes, version 2 had no mix lanes, version 3 no modulation lanes,
+// version 4 no lfo ratios, version 5 no wavetables, version 6 no clips, version 7 no ids and
+// version 8 no markers, TimedWavePacker::from_bytes still reads all of them
+// new lanes go on the end, so an older file holds the first however many lanes of a newer one
+pub const VERSION: u32 = 9;
+const LANES: usize = 36;
+const UNMARKED_LANES: usize = 33;
+const UNNAMED_LANES: usize = 32;
+const UNCLIPPED_LANES: usize = 31;
+const UNTABLED_LANES: usize = 27;
+const UNRATIOED_LANES: usize = 26;
+const UNMODULATED_LANES: usize = 17;
+const UNMIXED_LANES: usize = 13;
+
+#[cfg(feature = "std")]
+const fn header_len(lanes: usize) -> usize {
+    MAGIC.len() + 4 + 4 + lanes * 16
+}
+
+#[cfg(feature = "std")]
+fn align(pos: usize) -> usize {
+    pos.next_multiple_of(8)
+}
+
+impl TimedWavePacker {
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, out: W) -> io::Result<()> {
+        let slices = self.as_slices();
+        let lanes: [&[u8]; LANES] = [
+            cast_slice(slices.timings),
+            cast_slice(slices.freq_coef),
+            cast_slice(slices.freq_runs),
+            cast_slice(slices.amp_coef),
+            cast_slice(slices.amp_runs),
+            cast_slice(slices.phases),
+            cast_slice(slices.modes),
+            cast_slice(slices.shapes),
+            cast_slice(slices.width_coef),
+            cast_slice(slices.width_runs),
+            cast_slice(slices.envelopes),
+            cast_slice(slices.envelope_runs),
+            cast_slice(slices.fades),
+            cast_slice(slices.gain_coef),
+            cast_slice(slices.gain_runs),
+            cast_slice(slices.pan_coef),
+            cast_slice(slices.pan_runs),
+            cast_slice(slices.lfo_rates),
+            cast_slice(slices.lfo_offsets),
+            cast_slice(slices.lfo_shapes),
+            cast_slice(slices.mod_amp_coef),
+            cast_slice(slices.mod_amp_runs),
+            cast_slice(slices.mod_freq_coef),
+            cast_slice(slices.mod_freq_runs),
+            cast_slice(slices.mod_phase_coef),
+            cast_slice(slices.mod_phase_runs),
+            cast_slice(slices.lfo_ratios),
+            cast_slice(slices.table_coef),
+            cast_slice(slices.table_runs),
+            cast_slice(slices.table_levels),
+            cast_slice(slices.table_interpolations),
+            cast_slice(slices.table_one_shots),
+            cast_slice(slices.ids),
+            cast_slice(slices.marker_times),
+            cast_slice(slices.marker_names),
+            cast_slice(slices.marker_runs),
+        ];
+        write_lanes(out, VERSION, &lanes)
+    }
+    // an owned copy, unlike PackSlices::from_bytes the buffer can sit anywhere in memory and
+    // older versions are taken too, lanes an older version didn't have are filled in as unused
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let versions = [
+            (VERSION, LANES),
+            (8, UNMARKED_LANES),
+            (7, UNNAMED_LANES),
+            (6, UNCLIPPED_LANES),
+            (5, UNTABLED_LANES),
+            (4, UNRATIOED_LANES),
+            (3, UNMODULATED_LANES),
+            (2, UNMIXED_LANES),
+        ];
+        let opened = versions
+            .into_iter()
+            .find_map(|(version, lanes)| Some((Reader::open(bytes, version, lanes)?, lanes)));
+        let (mut packer, lanes) = match opened {
+            Some((mut reader, lanes)) => {
+                let mut packer = read_unmixed::<RunLength>(&mut reader)?;
+                if lanes > UNMIXED_LANES {
+                    packer.gain_coef = reader.lane()?;
+                    packer.gain_runs = reader.lane()?;
+                    packer.pan_coef = reader.lane()?;
+                    packer.pan_runs = reader.lane()?;
+                }
+                if lanes > UNMODULATED_LANES {
+                    packer.lfo_rates = reader.lane()?;
+                    packer.lfo_offsets = reader.lane()?;
+                    packer.lfo_shapes = reader.lane()?;
+                    packer.mod_amp_coef = reader.lane()?;
+                    packer.mod_amp_runs = reader.lane()?;
+                    packer.mod_freq_coef = reader.lane()?;
+                    packer.mod_freq_runs = reader.lane()?;
+                    packer.mod_phase_coef = reader.lane()?;
+                    packer.mod_phase_runs = reader.lane()?;
+                }
+                if lanes > UNRATIOED_LANES {
+                    packer.lfo_ratios = reader.lane()?;
+                }
+                if lanes > UNTABLED_LANES {
+                    packer.table_coef = reader.lane()?;
+                    packer.table_runs = reader.lane()?;
+                    packer.table_levels = reader.lane()?;
+                    packer.table_interpolations = reader.lane()?;
+                }
+                if lanes > UNCLIPPED_LANES {
+                    packer.table_one_shots = reader.lane()?;
+                }
+                if lanes > UNNAMED_LANES {
+                    packer.ids = reader.lane()?;
+                }
+                if lanes > UNMARKED_LANES {
+                    packer.marker_times = reader.lane()?;
+                    packer.marker_names = reader.lane()?;
+                    packer.marker_runs = reader.lane()?;
+                }
+                (packer, lanes)
+            }
+            None => {
+                let mut reader = Reader::open(bytes, 1, UNMIXED_LANES)?;
+                (read_unmixed::<u8>(&mut reader)?, UNMIXED_LANES)
+            }
+        };
+        let count = packer.timings.len();
+        if lanes <= UNMIXED_LANES {
+            packer.gain_runs = vec![0; count];
+            packer.pan_runs = vec![0; count];
+        }
+        if lanes <= UNMODULATED_LANES {
+            packer.lfo_rates = vec![0.; count];
+            packer.lfo_offsets = vec![0.; count];
+            packer.lfo_shapes = vec![Waveform::Sine; count];
+            packer.mod_amp_runs = vec![0; count];
+            packer.mod_freq_runs = vec![0; count];
+            packer.mod_phase_runs = vec![0; count];
+        }
+        if lanes <= UNRATIOED_LANES {
+            packer.lfo_ratios = vec![0.; count];
+        }
+        if lanes <= UNTABLED_LANES {
+            packer.table_runs = vec![0; count];
+            packer.table_levels = vec![0; count];
+            packer.table_interpolations = vec![Interpolation::default(); count];
+        }
+        if lanes <= UNCLIPPED_LANES {
+            packer.table_one_shots = vec![false; count];
+        }
+        if lanes <= UNNAMED_LANES {
+            packer.ids = vec![0; count];
+        }
+        Some(packer)
+    }
+}
+
+// the lanes from before mix lanes existed, with runs stored as R
+fn read_unmixed<R: NoUninit + CheckedBitPattern + Into<RunLength>>(
+    reader: &mut Reader,
+) -> Option<TimedWavePacker> {
+    Some(TimedWavePacker {
+        timings: reader.lane()?,
+        freq_coef: reader.lane()?,
+        freq_runs: reader.runs::<R>()?,
+        amp_coef: reader.lane()?,
+        amp_runs: reader.runs::<R>()?,
+        phases: reader.lane()?,
+        modes: reader.lane()?,
+        shapes: reader.lane()?,
+        width_coef: reader.lane()?,
+        width_runs: reader.runs::<R>()?,
+        envelopes: reader.lane()?,
+        envelope_runs: reader.runs::<R>()?,
+        fades: reader.lane()?,
+        ..TimedWavePacker::default()
+    })
+}
+
+#[cfg(feature = "std")]
+fn write_lanes<W: Write>(mut out: W, version: u32, lanes: &[&[u8]]) -> io::Result<()> {
+    out.write_all(&MAGIC)?;
+    out.write_all(&version.to_ne_bytes())?;
+    out.write_all(&(lanes.len() as u32).to_ne_bytes())?;
+    let mut pos = header_len(lanes.len());
+    for lane in lanes {
+        let offset = align(pos);
+        out.write_all(&(offset as u64).to_ne_bytes())?;
+        out.write_all(&(lane.len() as u64).to_ne_bytes())?;
+        pos = offset + lane.len();
+    }
+    let mut pos = header_len(lanes.len());
+    for lane in lanes {
+        let offset = align(pos);
+        out.write_all(&[0; 8][..offset - pos])?;
+        out.write_all(lane)?;
+        pos = offset + lane.len();
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    // checks the header and leaves the reader at the lane table
+    fn open(bytes: &'a [u8], version: u32, lanes: usize) -> Option<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        (reader.take(MAGIC.len())? == MAGIC).then_some(())?;
+        (reader.u32()? == version).then_some(())?;
+        (reader.u32()? as usize == lanes).then_some(())?;
+        Some(reader)
+    }
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let taken = self.bytes.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(taken)
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_ne_bytes(self.take(4)?.try_into().ok()?))
+    }
+    fn u64(&mut self) -> Option<usize> {
+        usize::try_from(u64::from_ne_bytes(self.take(8)?.try_into().ok()?)).ok()
+    }
+    fn lane_bytes(&mut self) -> Option<&'a [u8]> {
+        let (offset, len) = (self.u64()?, self.u64()?);
+        self.bytes.get(offset..offset.checked_add(len)?)
+    }
+    // borrowed in place, so the lane has to be aligned for T
+    fn borrowed<T: NoUninit + CheckedBitPattern>(&mut self) -> Option<&'a [T]> {
+        try_cast_slice(self.lane_bytes()?).ok()
+    }
+    // copied out a value at a time, wherever the lane happens to be
+    fn lane<T: NoUninit + CheckedBitPattern>(&mut self) -> Option<Vec<T>> {
+        let lane = self.lane_bytes()?;
+        let size = size_of::<T>();
+        (lane.len() % size == 0).then_some(())?;
+        lane.chunks_exact(size)
+            .map(|value| try_pod_read_unaligned(value).ok())
+            .collect()
+    }
+    fn runs<R: NoUninit + CheckedBitPattern + Into<RunLength>>(
+        &mut self,
+    ) -> Option<Vec<RunLength>> {
+        Some(self.lane::<R>()?.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<'a> PackSlices<'a> {
+    // the buffer has to be 8 byte aligned, which memory maps always are
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        let mut reader = Reader::open(bytes, VERSION, LANES)?;
+        Some(PackSlices {
+            timings: reader.borrowed()?,
+            freq_coef: reader.borrowed()?,
+            freq_runs: reader.borrowed()?,
+            amp_coef: reader.borrowed()?,
+            amp_runs: reader.borrowed()?,
+            phases: reader.borrowed()?,
+            modes: reader.borrowed()?,
+            shapes: reader.borrowed()?,
+            width_coef: reader.borrowed()?,
+            width_runs: reader.borrowed()?,
+            envelopes: reader.borrowed()?,
+            envelope_runs: reader.borrowed()?,
+            fades: reader.borrowed()?,
+            gain_coef: reader.borrowed()?,
+            gain_runs: reader.borrowed()?,
+            pan_coef: reader.borrowed()?,
+            pan_runs: reader.borrowed()?,
+            lfo_rates: reader.borrowed()?,
+            lfo_offsets: reader.borrowed()?,
+            lfo_shapes: reader.borrowed()?,
+            mod_amp_coef: reader.borrowed()?,
+            mod_amp_runs: reader.borrowed()?,
+            mod_freq_coef: reader.borrowed()?,
+            mod_freq_runs: reader.borrowed()?,
+            mod_phase_coef: reader.borrowed()?,
+            mod_phase_runs: reader.borrowed()?,
+            lfo_ratios: reader.borrowed()?,
+            table_coef: reader.borrowed()?,
+            table_runs: reader.borrowed()?,
+            table_levels: reader.borrowed()?,
+            table_interpolations: reader.borrowed()?,
+            table_one_shots: reader.borrowed()?,
+            ids: reader.borrowed()?,
+            marker_times: reader.borrowed()?,
+            marker_names: reader.borrowed()?,
+            marker_runs: reader.borrowed()?,
+        })
+    }
+}
+
+impl<'a> PackedTimedWaves<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        Self::new(PackSlices::from_bytes(bytes)?)
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn round_trip() {
+    use crate::{
+        func::{Adsr, Envelope, Interpolation, PhaseMode, Wave, Waveform, Wavetable},
+        Fade, FadeCurve, Mix, Modulation, Player, TimedWave,
+    };
+
+    let plain = TimedWave {
+        start: 0,
+        end: 6,
+        wave: Wave {
+            freq: &[0.05, 0.001][..],
+            amp: Envelope::Poly(&[0.25][..]),
+            phase: 0.1,
+            mode: PhaseMode::Integral,
+            shape: Waveform::Pulse,
+            width: &[0.3][..],
+        },
+        fade: Fade {
+            fade_in: 2,
+            release: 3,
+            curve: FadeCurve::Smooth,
+        },
+        mix: Mix {
+            gain: &[0.5, 0.1][..],
+            pan: &[-0.5][..],
+            id: 3,
+        },
+        modulation: Modulation {
+            rate: 0.2,
+            ratio: 0.5,
+            offset: 0.1,
+            shape: Waveform::Triangle,
+            amp: &[0.5][..],
+            freq: &[][..],
+            phase: &[0.1, 0.01][..],
+        },
+        table: Wavetable::from_parts(&[0., 1., 0.5, -1.][..], 1, Interpolation::Cubic).unwrap(),
+    };
+    let clip = Wavetable::clip(&[0.5, -0.25, 1.], Interpolation::Linear).unwrap();
+    let enveloped = TimedWave {
+        start: 4,
+        end: 9,
+        wave: Wave {
+            freq: &[0.02][..],
+            amp: Envelope::Adsr(Adsr {
+                peak: 0.5,
+                attack: 1.,
+                decay: 1.,
+                sustain: 0.5,
+                gate: 3.,
+                release: 2.,
+            }),
+            phase: 0.,
+            mode: PhaseMode::Product,
+            shape: Waveform::Triangle,
+            width: &[][..],
+        },
+        fade: Fade::default(),
+        mix: Mix::default(),
+        modulation: Modulation::default(),
+        table: clip.as_slices(),
+    };
+    let mut packer: TimedWavePacker = [plain, enveloped].into_iter().collect();
+    packer.add_marker(5, "drop").unwrap();
+    let mut bytes = Vec::new();
+    packer.write_to(&mut bytes).unwrap();
+
+    // copy into u64s to get the alignment a memory map would have
+    let mut aligned = vec![0u64; bytes.len().div_ceil(8)];
+    bytemuck::cast_slice_mut::<u64, u8>(&mut aligned)[..bytes.len()].copy_from_slice(&bytes);
+    let loaded = &bytemuck::cast_slice::<u64, u8>(&aligned)[..bytes.len()];
+
+    let mut original = [[0f32; 2]; 16];
+    let mut copy = [[0f32; 2]; 16];
+    Player::new(packer.get_pack().unwrap(), 0, Some(20))
+        .play_stereo(&mut original)
+        .unwrap();
+    Player::new(PackedTimedWaves::from_bytes(loaded).unwrap(), 0, Some(20))
+        .play_stereo(&mut copy)
+        .unwrap();
+    assert_eq!(original, copy);
+    assert_eq!(PackSlices::from_bytes(loaded).unwrap().ids, [3, 0]);
+    assert_eq!(TimedWavePacker::from_bytes(loaded).unwrap(), packer);
+
+    // a byte along, like a file embedded after something else, only the copy can read it
+    let mut shifted = vec![0u64; aligned.len() + 1];
+    bytemuck::cast_slice_mut::<u64, u8>(&mut shifted)[1..=bytes.len()].copy_from_slice(&bytes);
+    let unaligned = &bytemuck::cast_slice::<u64, u8>(&shifted)[1..=bytes.len()];
+    assert!(PackSlices::from_bytes(unaligned).is_none());
+    assert_eq!(TimedWavePacker::from_bytes(unaligned).unwrap(), packer);
+
+    let mut wrong_version = loaded.to_vec();
+    wrong_version[8] ^= 0xff;
+    assert!(PackSlices::from_bytes(&wrong_version).is_none());
+    assert!(PackSlices::from_bytes(&loaded[..header_len(LANES)]).is_none());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn version_one() {
+    use crate::{
+        func::{PhaseMode, Wave, Waveform, Wavetable},
+        Fade, Mix, Modulation, TimedWave,
+    };
+
+    let packer: TimedWavePacker = [(0, 4), (2, 6)]
+        .into_iter()
+        .map(|(start, end)| TimedWave {
+            start,
+            end,
+            wave: Wave {
+                freq: &[0.1, 0.01][..],
+                amp: &[0.5][..],
+                phase: 0.,
+                mode: PhaseMode::Product,
+                shape: Waveform::Saw,
+                width: &[][..],
+            },
+            fade: Fade::default(),
+            mix: Mix::default(),
+            modulation: Modulation::default(),
+            table: Wavetable::default(),
+        })
+        .collect();
+    let narrow = |runs: &[RunLength]| runs.iter().map(|&r| r as u8).collect::<Vec<_>>();
+    let (freq_runs, amp_runs, width_runs, envelope_runs) = (
+        narrow(&packer.freq_runs),
+        narrow(&packer.amp_runs),
+        narrow(&packer.width_runs),
+        narrow(&packer.envelope_runs),
+    );
+    let mut old = Vec::new();
+    write_lanes(
+        &mut old,
+        1,
+        &[
+            cast_slice(&packer.timings),
+            cast_slice(&packer.freq_coef),
+            &freq_runs,
+            cast_slice(&packer.amp_coef),
+            &amp_runs,
+            cast_slice(&packer.phases),
+            cast_slice(&packer.modes),
+            cast_slice(&packer.shapes),
+            cast_slice(&packer.width_coef),
+            &width_runs,
+            cast_slice(&packer.envelopes),
+            &envelope_runs,
+            cast_slice(&packer.fades),
+        ],
+    )
+    .unwrap();
+    let mut aligned = vec![0u64; old.len().div_ceil(8)];
+    bytemuck::cast_slice_mut::<u64, u8>(&mut aligned)[..old.len()].copy_from_slice(&old);
+    let old = &bytemuck::cast_slice::<u64, u8>(&aligned)[..old.len()];
+
+    // version 1 can't be borrowed in place any more but still loads as a copy
+    assert!(PackSlices::from_bytes(old).is_none());
+    assert_eq!(TimedWavePacker::from_bytes(old), Some(packer));
+}
@@ -0,0 +1,135 @@
+// swaps one player for another by fading between them, like going from the exploring music into combat
+// the fade keeps the combined power steady (cos out, sin in) so there's no dip halfway through
+// once the fade's over the outgoing player is dropped, with whatever it had left unplayed
+use core::f32::consts::FRAC_PI_2;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{Player, MIX_BLOCK};
+
+#[derive(Debug, Clone)]
+pub struct Crossfader<'a> {
+    current: Player<'a>,
+    outgoing: Option<Outgoing<'a>>,
+}
+
+#[derive(Debug, Clone)]
+struct Outgoing<'a> {
+    player: Player<'a>,
+    length: usize,
+    done: usize,
+}
+
+impl<'a> Crossfader<'a> {
+    pub fn new(player: Player<'a>) -> Self {
+        Crossfader {
+            current: player,
+            outgoing: None,
+        }
+    }
+    // next fades in over length samples while the current player fades out and is dropped
+    // a fade that's already going has to finish first, so next gets handed back
+    #[allow(clippy::result_large_err)]
+    pub fn crossfade(&mut self, next: Player<'a>, length: usize) -> Result<(), Player<'a>> {
+        if self.outgoing.is_some() {
+            return Err(next);
+        }
+        let player = core::mem::replace(&mut self.current, next);
+        if length > 0 {
+            self.outgoing = Some(Outgoing {
+                player,
+                length,
+                done: 0,
+            });
+        }
+        Ok(())
+    }
+    pub fn is_crossfading(&self) -> bool {
+        self.outgoing.is_some()
+    }
+    // the player that's fading in, or just playing if there's no fade
+    pub fn current(&self) -> &Player<'a> {
+        &self.current
+    }
+    pub fn current_mut(&mut self) -> &mut Player<'a> {
+        &mut self.current
+    }
+    pub fn into_current(self) -> Player<'a> {
+        self.current
+    }
+    pub fn play(&mut self, output: &mut [f32]) {
+        let (frames, _) = output.as_chunks_mut::<1>();
+        self.render(frames);
+    }
+    pub fn play_stereo(&mut self, output: &mut [[f32; 2]]) {
+        self.render(output);
+    }
+    fn render<const C: usize>(&mut self, output: &mut [[f32; C]]) {
+        for chunk in output.chunks_mut(MIX_BLOCK) {
+            play(&mut self.current, chunk);
+            let Some(outgoing) = &mut self.outgoing else {
+                continue;
+            };
+            let mut faded = [[0f32; C]; MIX_BLOCK];
+            let faded = &mut faded[..chunk.len()];
+            play(&mut outgoing.player, faded);
+            for (i, (frame, old)) in chunk.iter_mut().zip(faded.iter()).enumerate() {
+                let progress = ((outgoing.done + i) as f32 / outgoing.length as f32).min(1.);
+                let (fade_in, fade_out) = (progress * FRAC_PI_2).sin_cos();
+                for (sample, old) in frame.iter_mut().zip(old) {
+                    *sample = *sample * fade_in + old * fade_out;
+                }
+            }
+            outgoing.done += chunk.len();
+            if outgoing.done >= outgoing.length {
+                self.outgoing = None;
+            }
+        }
+    }
+}
+
+fn play<const C: usize>(player: &mut Player, frames: &mut [[f32; C]]) {
+    // either side stopping early fades from or into silence, see Player::play
+    let _ = player.render(frames);
+}
+
+#[test]
+fn constant_power() {
+    use crate::{LiveWave, TimedWavePacker};
+
+    // both players hold 0.5 the whole way, so any change in level is the fade's doing
+    let level =
+        |amp: f32| -> TimedWavePacker { [LiveWave::sine(0, 10000, 1., amp)].into_iter().collect() };
+    let (explore, combat) = (level(0.5), level(0.5));
+    let mut fader = Crossfader::new(Player::new(explore.get_pack().unwrap(), 0, Some(10000)));
+    let mut before = [0.; 100];
+    fader.play(&mut before);
+    assert!(before.iter().all(|&s| (s - 0.5).abs() < 1e-6));
+
+    let next = Player::new(combat.get_pack().unwrap(), 0, Some(10000));
+    fader.crossfade(next, 1000).unwrap();
+    let again = Player::new(combat.get_pack().unwrap(), 0, Some(10000));
+    assert!(fader.crossfade(again, 10).is_err());
+    let mut during = [0.; 1200];
+    fader.play(&mut during);
+    // two copies of the same thing at cos and sin swell by up to root 2 in the middle
+    assert!((during[500] - 0.5 * 2f32.sqrt()).abs() < 1e-3);
+    assert!((during[0] - 0.5).abs() < 1e-6);
+    assert!(during[1000..].iter().all(|&s| (s - 0.5).abs() < 1e-6));
+    assert!(!fader.is_crossfading());
+    assert_eq!(fader.current().current_time(), 1200);
+
+    // fading into silence, the old player follows a quarter cosine down
+    let quiet = TimedWavePacker::new();
+    let mut fader = Crossfader::new(Player::new(explore.get_pack().unwrap(), 0, Some(10000)));
+    fader
+        .crossfade(Player::new(quiet.get_pack().unwrap(), 0, Some(10000)), 400)
+        .unwrap();
+    let mut stereo = [[0.; 2]; 400];
+    fader.play_stereo(&mut stereo);
+    for (i, [left, right]) in stereo.into_iter().enumerate() {
+        let expected =
+            0.5 * (i as f32 / 400. * FRAC_PI_2).cos() * core::f32::consts::FRAC_PI_4.cos();
+        assert!((left - expected).abs() < 1e-5 && (right - expected).abs() < 1e-5);
+    }
+}
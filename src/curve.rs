@@ -1,81 +1,235 @@
-use std::{
+use alloc::{vec, vec::Vec};
+use core::{
     iter::Sum,
     ops::{Add, AddAssign, Mul, Neg, Sub},
 };
 
-use crate::func::{derive_polynomial, ts};
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{
+    func::{derive_polynomial, real_roots, ts, Coefficient, Function},
+    poly::{add, compose, fit_polynomial, mul, sub},
+};
 
-#[derive(Debug, Clone, Copy)]
-pub struct Vec2(pub [f32; 2]);
-impl Vec2 {
+// a point with D components, Vec2 is what the rest of the crate mostly talks in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VecN<const D: usize>(pub [f32; D]);
+pub type Vec2 = VecN<2>;
+pub type Vec3 = VecN<3>;
+impl VecN<2> {
     pub fn new(a: f32, b: f32) -> Vec2 {
-        Vec2([a, b])
+        VecN([a, b])
+    }
+}
+impl VecN<3> {
+    pub fn new(a: f32, b: f32, c: f32) -> Vec3 {
+        VecN([a, b, c])
+    }
+}
+impl<const D: usize> VecN<D> {
+    pub fn dot(self, rhs: Self) -> f32 {
+        self.0.iter().zip(rhs.0).map(|(a, b)| a * b).sum()
+    }
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
     }
 }
-impl Default for Vec2 {
+impl<const D: usize> Default for VecN<D> {
     fn default() -> Self {
         0f32.into()
     }
 }
-impl From<[f32; 2]> for Vec2 {
-    fn from(x: [f32; 2]) -> Self {
-        Vec2(x)
+impl<const D: usize> From<[f32; D]> for VecN<D> {
+    fn from(x: [f32; D]) -> Self {
+        VecN(x)
     }
 }
 impl From<(f32, f32)> for Vec2 {
     fn from((a, b): (f32, f32)) -> Self {
-        Vec2([a, b])
+        VecN([a, b])
+    }
+}
+impl From<(f32, f32, f32)> for Vec3 {
+    fn from((a, b, c): (f32, f32, f32)) -> Self {
+        VecN([a, b, c])
     }
 }
-impl From<f32> for Vec2 {
+impl<const D: usize> From<f32> for VecN<D> {
     fn from(n: f32) -> Self {
-        Vec2([n, n])
+        VecN([n; D])
     }
 }
-impl Add for Vec2 {
-    type Output = Vec2;
+impl<const D: usize> Add for VecN<D> {
+    type Output = Self;
 
-    fn add(self, rhs: Self) -> Self::Output {
-        Vec2([self.0[0] + rhs.0[0], self.0[1] + rhs.0[1]])
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
     }
 }
-impl AddAssign for Vec2 {
+impl<const D: usize> AddAssign for VecN<D> {
     fn add_assign(&mut self, rhs: Self) {
         for (dest, right) in self.0.iter_mut().zip(rhs.0) {
-            *dest = right;
+            *dest += right;
         }
     }
 }
-impl Mul<f32> for Vec2 {
-    type Output = Vec2;
+impl<const D: usize> Coefficient for VecN<D> {
+    type Scalar = f32;
+}
+impl<const D: usize> Mul<f32> for VecN<D> {
+    type Output = Self;
 
     fn mul(self, rhs: f32) -> Self::Output {
-        Vec2(self.0.map(|n| n * rhs))
+        VecN(self.0.map(|n| n * rhs))
     }
 }
-impl Sub for Vec2 {
-    type Output = Vec2;
+impl<const D: usize> Sub for VecN<D> {
+    type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Vec2([self.0[0] - rhs.0[0], self.0[1] - rhs.0[1]])
+        self + -rhs
     }
 }
-impl Neg for Vec2 {
-    type Output = Vec2;
+impl<const D: usize> Neg for VecN<D> {
+    type Output = Self;
 
     fn neg(self) -> Self::Output {
-        Vec2(self.0.map(Neg::neg))
+        VecN(self.0.map(Neg::neg))
     }
 }
-impl Sum for Vec2 {
+impl<const D: usize> Sum for VecN<D> {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
-        iter.fold(Vec2::default(), |r, l| r + l)
+        iter.fold(VecN::default(), |r, l| r + l)
+    }
+}
+
+// what the curves are made of, f32 for 1d envelopes and VecN for everything with more axes
+pub trait Point:
+    Copy
+    + Default
+    + Add<Output = Self>
+    + AddAssign
+    + Sub<Output = Self>
+    + Mul<f32, Output = Self>
+    + Neg<Output = Self>
+    + Sum
+    + Coefficient<Scalar = f32>
+{
+    const DIM: usize;
+    fn get(&self, axis: usize) -> f32;
+    fn set(&mut self, axis: usize, value: f32);
+    fn dot(self, rhs: Self) -> f32 {
+        (0..Self::DIM)
+            .map(|axis| self.get(axis) * rhs.get(axis))
+            .sum()
+    }
+    fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+}
+impl<const D: usize> Point for VecN<D> {
+    const DIM: usize = D;
+    fn get(&self, axis: usize) -> f32 {
+        self.0[axis]
+    }
+    fn set(&mut self, axis: usize, value: f32) {
+        self.0[axis] = value;
+    }
+}
+impl Point for f32 {
+    const DIM: usize = 1;
+    fn get(&self, _: usize) -> f32 {
+        *self
+    }
+    fn set(&mut self, _: usize, value: f32) {
+        *self = value;
     }
 }
 
-pub struct CubicBezier(pub [Vec2; 4]);
-impl CubicBezier {
-    pub fn new(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) -> Self {
+// glam and nalgebra vectors convert both ways, and glam's can be curve points as they are
+#[cfg(feature = "glam")]
+impl From<glam::Vec2> for Vec2 {
+    fn from(v: glam::Vec2) -> Self {
+        VecN(v.to_array())
+    }
+}
+#[cfg(feature = "glam")]
+impl From<Vec2> for glam::Vec2 {
+    fn from(v: Vec2) -> Self {
+        glam::Vec2::from_array(v.0)
+    }
+}
+#[cfg(feature = "glam")]
+impl From<glam::Vec3> for Vec3 {
+    fn from(v: glam::Vec3) -> Self {
+        VecN(v.to_array())
+    }
+}
+#[cfg(feature = "glam")]
+impl From<Vec3> for glam::Vec3 {
+    fn from(v: Vec3) -> Self {
+        glam::Vec3::from_array(v.0)
+    }
+}
+#[cfg(feature = "glam")]
+impl Coefficient for glam::Vec2 {
+    type Scalar = f32;
+}
+#[cfg(feature = "glam")]
+impl Point for glam::Vec2 {
+    const DIM: usize = 2;
+    fn get(&self, axis: usize) -> f32 {
+        self[axis]
+    }
+    fn set(&mut self, axis: usize, value: f32) {
+        self[axis] = value;
+    }
+}
+#[cfg(feature = "glam")]
+impl Coefficient for glam::Vec3 {
+    type Scalar = f32;
+}
+#[cfg(feature = "glam")]
+impl Point for glam::Vec3 {
+    const DIM: usize = 3;
+    fn get(&self, axis: usize) -> f32 {
+        self[axis]
+    }
+    fn set(&mut self, axis: usize, value: f32) {
+        self[axis] = value;
+    }
+}
+
+#[cfg(feature = "nalgebra")]
+impl<const D: usize> From<nalgebra::SVector<f32, D>> for VecN<D> {
+    fn from(v: nalgebra::SVector<f32, D>) -> Self {
+        VecN(v.into())
+    }
+}
+#[cfg(feature = "nalgebra")]
+impl<const D: usize> From<VecN<D>> for nalgebra::SVector<f32, D> {
+    fn from(v: VecN<D>) -> Self {
+        v.0.into()
+    }
+}
+#[cfg(feature = "nalgebra")]
+impl<const D: usize> From<nalgebra::Point<f32, D>> for VecN<D> {
+    fn from(p: nalgebra::Point<f32, D>) -> Self {
+        p.coords.into()
+    }
+}
+#[cfg(feature = "nalgebra")]
+impl<const D: usize> From<VecN<D>> for nalgebra::Point<f32, D> {
+    fn from(v: VecN<D>) -> Self {
+        nalgebra::Point::from(v.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CubicBezier<V = Vec2>(pub [V; 4]);
+impl<V: Point> CubicBezier<V> {
+    pub fn new(p0: V, p1: V, p2: V, p3: V) -> Self {
         // grabbed this from Freya Holmér's splines video
         Self([
             p0,
@@ -84,26 +238,946 @@ impl CubicBezier {
             -p0 + p1 * 3. + p2 * -3. + p3,
         ])
     }
-    pub fn eval(&self, t: f32) -> Vec2 {
+    // for handles in someone else's vector type
+    pub fn from_points<P: Into<V>>(p0: P, p1: P, p2: P, p3: P) -> Self {
+        Self::new(p0.into(), p1.into(), p2.into(), p3.into())
+    }
+    // the control points back out of the coefficients, new run backwards
+    pub fn handles(&self) -> [V; 4] {
+        let [c0, c1, c2, c3] = self.0;
+        [
+            c0,
+            c0 + c1 * (1. / 3.),
+            c0 + c1 * (2. / 3.) + c2 * (1. / 3.),
+            c0 + c1 + c2 + c3,
+        ]
+    }
+    pub fn eval(&self, t: f32) -> V {
         ts(t).zip(&self.0).map(|(t_term, p)| *p * t_term).sum()
     }
-    pub fn derive(&self) -> QuadraticBezier {
-        let mut out = [Vec2::default(); 3];
+    // de casteljau, the two halves meet at eval(t) and together trace the same curve
+    pub fn split(&self, t: f32) -> (CubicBezier<V>, CubicBezier<V>) {
+        let lerp = |a: V, b: V| a + (b - a) * t;
+        let [p0, p1, p2, p3] = self.handles();
+        let (a, b, c) = (lerp(p0, p1), lerp(p1, p2), lerp(p2, p3));
+        let (d, e) = (lerp(a, b), lerp(b, c));
+        let mid = lerp(d, e);
+        (
+            CubicBezier::new(p0, a, d, mid),
+            CubicBezier::new(mid, e, c, p3),
+        )
+    }
+    // points along the curve, start and end included, joined up by straight lines they stay
+    // within tolerance of it, halves get split until the handles sit that close to the chord
+    pub fn flatten(&self, tolerance: f32) -> Vec<V> {
+        let mut points = vec![self.eval(0.)];
+        self.flatten_into(tolerance, 0, &mut points);
+        points
+    }
+    fn flatten_into(&self, tolerance: f32, depth: u32, points: &mut Vec<V>) {
+        const MAX_DEPTH: u32 = 16;
+        let [p0, p1, p2, p3] = self.handles();
+        let chord = p3 - p0;
+        let off = |p: V| {
+            let d = p - p0;
+            let length = chord.dot(chord);
+            if length == 0. {
+                d.length()
+            } else {
+                (d - chord * (d.dot(chord) / length)).length()
+            }
+        };
+        // the curve stays inside the hull of its handles, so this bounds how far it strays
+        if depth >= MAX_DEPTH || off(p1).max(off(p2)) <= tolerance {
+            points.push(p3);
+            return;
+        }
+        let (first, second) = self.split(0.5);
+        first.flatten_into(tolerance, depth + 1, points);
+        second.flatten_into(tolerance, depth + 1, points);
+    }
+    // the length of the flattened curve, always a touch short of the real thing
+    pub fn arc_length(&self, tolerance: f32) -> f32 {
+        let points = self.flatten(tolerance);
+        points.windows(2).map(|w| (w[1] - w[0]).length()).sum()
+    }
+    pub fn derive(&self) -> QuadraticBezier<V> {
+        let mut out = [V::default(); 3];
         derive_polynomial(self.0)
             .zip(&mut out)
             .for_each(|(l, r)| *r += l);
         QuadraticBezier(out)
     }
+    // parameters where each axis turns around, the roots of derive
+    pub fn extrema(&self) -> Vec<Vec<f32>> {
+        turning_points(&self.derive().0)
+    }
+    pub fn bounding_box(&self) -> (V, V) {
+        bounds(|t| self.eval(t), self.extrema())
+    }
+}
+impl CubicBezier<Vec2> {
+    // a curve drawn as (time, value) handles turned into value over time, ready for an amp or freq lane
+    // time has to keep moving forward along the curve or there's no single value to give, so that's None
+    // evenly spaced handles come out exact, anything else gets fitted with degree + 1 coefficients
+    pub fn to_envelope(&self, degree: usize) -> Option<Vec<f32>> {
+        let x: Vec<f64> = self.0.iter().map(|p| p.0[0] as f64).collect();
+        let y: Vec<f64> = self.0.iter().map(|p| p.0[1] as f64).collect();
+        // x' is a quadratic, so it's enough to check the ends and its turning point
+        let slope: Vec<f64> = derive_polynomial(&x).collect();
+        let vertex = -slope[1] / (2. * slope[2]);
+        let lowest = [0., 1., vertex]
+            .into_iter()
+            .filter(|s| (0. ..=1.).contains(s))
+            .map(|s| slope.eval(s))
+            .fold(f64::MAX, f64::min);
+        if lowest < 0. || x.eval(1.) <= x[0] {
+            return None;
+        }
+        let single = |coeffs: Vec<f64>| Some(coeffs.into_iter().map(|c| c as f32).collect());
+        if x[2] == 0. && x[3] == 0. {
+            // time goes straight along the curve, so value over time is just the cubic rescaled
+            return single(compose(&y, &[-x[0] / x[1], 1. / x[1]]));
+        }
+        const SAMPLES: usize = 128;
+        let (from, to) = (x[0], x.eval(1.));
+        let samples: Vec<(f32, f32)> = (0..=SAMPLES)
+            .map(|i| {
+                let time = from + (to - from) * i as f64 / SAMPLES as f64;
+                let shifted = sub(&x, &[time]);
+                // monotone, so there's exactly one s for every time, give or take rounding
+                let s = real_roots(&shifted)
+                    .into_iter()
+                    .min_by(|a, b| (a - 0.5).abs().total_cmp(&(b - 0.5).abs()))
+                    .unwrap_or(0.)
+                    .clamp(0., 1.);
+                (time as f32, y.eval(s) as f32)
+            })
+            .collect();
+        Some(fit_polynomial(&samples, degree))
+    }
+}
+
+#[test]
+fn bezier_envelope() {
+    // handles a third apart in time, so this is exact
+    let swell = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(10., 1.),
+        Vec2::new(20., 1.),
+        Vec2::new(30., 0.),
+    );
+    let env = swell.to_envelope(3).unwrap();
+    for s in [0., 0.25, 0.5, 1.] {
+        let point = swell.eval(s).0;
+        assert!((env.eval(point[0]) - point[1]).abs() < 1e-5);
+    }
+    // an ease in, bunched up handles need the fit
+    let ease = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(40., 0.),
+        Vec2::new(60., 1.),
+        Vec2::new(100., 1.),
+    );
+    let env = ease.to_envelope(7).unwrap();
+    for i in 0..=20 {
+        let point = ease.eval(i as f32 / 20.).0;
+        assert!((env.eval(point[0]) - point[1]).abs() < 0.02);
+    }
+    // doubles back on itself in time
+    let loopy = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(50., 0.),
+        Vec2::new(-20., 1.),
+        Vec2::new(30., 1.),
+    );
+    assert!(loopy.to_envelope(3).is_none());
 }
 
-pub struct QuadraticBezier(pub [Vec2; 3]);
-impl QuadraticBezier {
-    pub fn new(p0: Vec2, p1: Vec2, p2: Vec2) -> Self {
+#[derive(Debug, Clone, Copy)]
+pub struct QuadraticBezier<V = Vec2>(pub [V; 3]);
+impl<V: Point> QuadraticBezier<V> {
+    pub fn new(p0: V, p1: V, p2: V) -> Self {
         // did this by hand and I am prone to mistakes
         // but it looks like the code above so I feel good
-        Self([p0, p0 * 2. + p1 * 2., p0 - p1 * 2. + p2])
+        Self([p0, p0 * -2. + p1 * 2., p0 - p1 * 2. + p2])
     }
-    pub fn eval(&self, t: f32) -> Vec2 {
+    pub fn from_points<P: Into<V>>(p0: P, p1: P, p2: P) -> Self {
+        Self::new(p0.into(), p1.into(), p2.into())
+    }
+    pub fn eval(&self, t: f32) -> V {
         ts(t).zip(&self.0).map(|(t_term, p)| *p * t_term).sum()
     }
+    pub fn extrema(&self) -> Vec<Vec<f32>> {
+        let slope: Vec<V> = derive_polynomial(self.0).collect();
+        turning_points(&slope)
+    }
+    pub fn bounding_box(&self) -> (V, V) {
+        bounds(|t| self.eval(t), self.extrema())
+    }
+}
+
+// where each axis stops and turns around, as parameters between 0 and 1
+fn turning_points<V: Point>(slope: &[V]) -> Vec<Vec<f32>> {
+    (0..V::DIM)
+        .map(|axis| {
+            let component: Vec<f32> = slope.iter().map(|p| p.get(axis)).collect();
+            real_roots(&component)
+                .into_iter()
+                .filter(|t| (0. ..=1.).contains(t))
+                .collect()
+        })
+        .collect()
+}
+// the box is made of the ends and the turning points, nothing else can stick out further
+fn bounds<V: Point>(eval: impl Fn(f32) -> V, extrema: Vec<Vec<f32>>) -> (V, V) {
+    let mut lo = eval(0.);
+    let mut hi = lo;
+    for p in [1.]
+        .into_iter()
+        .chain(extrema.into_iter().flatten())
+        .map(eval)
+    {
+        for axis in 0..V::DIM {
+            lo.set(axis, lo.get(axis).min(p.get(axis)));
+            hi.set(axis, hi.get(axis).max(p.get(axis)));
+        }
+    }
+    (lo, hi)
+}
+
+#[test]
+fn bounding() {
+    let near = |a: Vec2, b: Vec2| (a - b).length() < 1e-4;
+    let arch = QuadraticBezier::new(Vec2::new(0., 0.), Vec2::new(1., 2.), Vec2::new(2., 0.));
+    assert!(near(arch.eval(0.5), Vec2::new(1., 1.)));
+    assert_eq!(arch.extrema(), [vec![], vec![0.5]]);
+    let (lo, hi) = arch.bounding_box();
+    assert!(near(lo, Vec2::new(0., 0.)) && near(hi, Vec2::new(2., 1.)));
+
+    // overshoots past both ends in y
+    let wiggle = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(1., 3.),
+        Vec2::new(2., -3.),
+        Vec2::new(3., 0.),
+    );
+    let extrema = wiggle.extrema();
+    let (x, y) = (&extrema[0], &extrema[1]);
+    assert!(x.is_empty() && y.len() == 2);
+    let (lo, hi) = wiggle.bounding_box();
+    let peak = wiggle.eval(y[0]).0[1].max(wiggle.eval(y[1]).0[1]);
+    assert!(near(lo, Vec2::new(0., -peak)) && near(hi, Vec2::new(3., peak)));
+    for i in 0..=50 {
+        let p = wiggle.eval(i as f32 / 50.).0;
+        assert!(p[1] >= lo.0[1] - 1e-5 && p[1] <= hi.0[1] + 1e-5);
+    }
+}
+
+#[test]
+fn subdivision() {
+    let near = |a: Vec2, b: Vec2| (a - b).length() < 1e-4;
+    let bezier = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(10., 30.),
+        Vec2::new(40., -10.),
+        Vec2::new(50., 5.),
+    );
+    let (first, second) = bezier.split(0.3);
+    for s in [0., 0.5, 1.] {
+        assert!(near(first.eval(s), bezier.eval(0.3 * s)));
+        assert!(near(second.eval(s), bezier.eval(0.3 + 0.7 * s)));
+    }
+
+    let coarse = bezier.flatten(1.);
+    let fine = bezier.flatten(0.01);
+    assert!(near(coarse[0], bezier.eval(0.)) && near(*fine.last().unwrap(), bezier.eval(1.)));
+    assert!(coarse.len() < fine.len());
+    // every point on the curve is near some segment of the line
+    for i in 0..=100 {
+        let point = bezier.eval(i as f32 / 100.);
+        let closest = fine
+            .windows(2)
+            .map(|w| {
+                let (a, b) = (w[0], w[1]);
+                let along = ((point - a).dot(b - a) / (b - a).dot(b - a)).clamp(0., 1.);
+                (point - (a + (b - a) * along)).length()
+            })
+            .fold(f32::MAX, f32::min);
+        assert!(closest <= 0.01 + 1e-4);
+    }
+    // a straight curve is one segment
+    let line = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(1., 1.),
+        Vec2::new(2., 2.),
+        Vec2::new(3., 3.),
+    );
+    assert_eq!(line.flatten(0.001).len(), 2);
+}
+
+// a cubic from where it starts and ends and which way it's heading at each end
+#[derive(Debug, Clone, Copy)]
+pub struct CubicHermite<V = Vec2> {
+    pub start: V,
+    pub start_tangent: V,
+    pub end: V,
+    pub end_tangent: V,
+}
+impl<V: Point> CubicHermite<V> {
+    pub fn to_bezier(&self) -> CubicBezier<V> {
+        CubicBezier::new(
+            self.start,
+            self.start + self.start_tangent * (1. / 3.),
+            self.end - self.end_tangent * (1. / 3.),
+            self.end,
+        )
+    }
+    pub fn from_bezier(bezier: &CubicBezier<V>) -> Self {
+        let [p0, p1, p2, p3] = bezier.handles();
+        CubicHermite {
+            start: p0,
+            start_tangent: (p1 - p0) * 3.,
+            end: p3,
+            end_tangent: (p3 - p2) * 3.,
+        }
+    }
+    pub fn eval(&self, t: f32) -> V {
+        self.to_bezier().eval(t)
+    }
+    pub fn derive(&self) -> QuadraticBezier<V> {
+        self.to_bezier().derive()
+    }
+}
+
+// goes from the second point to the third, the outer two only set the tangents
+// chain them up with through to get a path that hits every point
+#[derive(Debug, Clone, Copy)]
+pub struct CatmullRom<V = Vec2>(pub [V; 4]);
+impl<V: Point> CatmullRom<V> {
+    // the ends get repeated so the path starts and stops on the first and last points
+    pub fn through(points: &[V]) -> Vec<CatmullRom<V>> {
+        let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+            return Vec::new();
+        };
+        let padded: Vec<V> = [first]
+            .into_iter()
+            .chain(points.iter().copied())
+            .chain([last])
+            .collect();
+        padded
+            .windows(4)
+            .map(|w| CatmullRom([w[0], w[1], w[2], w[3]]))
+            .collect()
+    }
+    pub fn to_hermite(&self) -> CubicHermite<V> {
+        let [p0, p1, p2, p3] = self.0;
+        CubicHermite {
+            start: p1,
+            start_tangent: (p2 - p0) * 0.5,
+            end: p2,
+            end_tangent: (p3 - p1) * 0.5,
+        }
+    }
+    pub fn to_bezier(&self) -> CubicBezier<V> {
+        self.to_hermite().to_bezier()
+    }
+    pub fn from_bezier(bezier: &CubicBezier<V>) -> Self {
+        let CubicHermite {
+            start,
+            start_tangent,
+            end,
+            end_tangent,
+        } = CubicHermite::from_bezier(bezier);
+        CatmullRom([
+            end - start_tangent * 2.,
+            start,
+            end,
+            start + end_tangent * 2.,
+        ])
+    }
+    pub fn eval(&self, t: f32) -> V {
+        self.to_bezier().eval(t)
+    }
+    pub fn derive(&self) -> QuadraticBezier<V> {
+        self.to_bezier().derive()
+    }
+}
+
+#[test]
+fn splines() {
+    let near = |a: Vec2, b: Vec2| (a - b).0.iter().all(|d| d.abs() < 1e-4);
+    let points = [
+        Vec2::new(0., 0.),
+        Vec2::new(10., 1.),
+        Vec2::new(25., 0.5),
+        Vec2::new(40., 0.),
+    ];
+    let path = CatmullRom::through(&points);
+    assert_eq!(path.len(), 3);
+    for (segment, pair) in path.iter().zip(points.windows(2)) {
+        assert!(near(segment.eval(0.), pair[0]) && near(segment.eval(1.), pair[1]));
+    }
+    // neighbouring segments leave and arrive along the same tangent
+    assert!(near(path[0].derive().eval(1.), path[1].derive().eval(0.)));
+
+    let hermite = path[1].to_hermite();
+    assert!(near(hermite.derive().eval(0.), hermite.start_tangent));
+    let back = CubicHermite::from_bezier(&hermite.to_bezier());
+    assert!(near(back.start_tangent, hermite.start_tangent) && near(back.end, hermite.end));
+    let back = CatmullRom::from_bezier(&path[1].to_bezier());
+    for s in [0., 0.3, 1.] {
+        assert!(near(back.eval(s), path[1].eval(s)));
+    }
+    assert!(CatmullRom::<Vec2>::through(&[]).is_empty());
+}
+
+// points pulled on by overlapping basis functions, moving one point only bends the curve nearby
+// there are points + degree + 1 knots, never going down, and the curve runs between
+// knots[degree] and knots[points]
+#[derive(Debug, Clone)]
+pub struct BSpline<V = Vec2> {
+    degree: usize,
+    knots: Vec<f32>,
+    points: Vec<V>,
+}
+impl<V: Point> BSpline<V> {
+    pub fn new(degree: usize, knots: Vec<f32>, points: Vec<V>) -> Option<Self> {
+        if points.len() <= degree
+            || knots.len() != points.len() + degree + 1
+            || knots.windows(2).any(|w| w[0] > w[1])
+            || knots[degree] >= knots[points.len()]
+        {
+            return None;
+        }
+        Some(BSpline {
+            degree,
+            knots,
+            points,
+        })
+    }
+    // knots one apart, the curve doesn't reach the first or last point
+    pub fn uniform(degree: usize, points: Vec<V>) -> Option<Self> {
+        let knots = (0..points.len() + degree + 1).map(|k| k as f32).collect();
+        Self::new(degree, knots, points)
+    }
+    // uniform in the middle with the end knots repeated so it starts and stops on the end points,
+    // runs from 0 to 1
+    pub fn clamped(degree: usize, points: Vec<V>) -> Option<Self> {
+        let inner = points.len().checked_sub(degree)?;
+        let knots = (0..points.len() + degree + 1)
+            .map(|k| (k.saturating_sub(degree).min(inner)) as f32 / inner as f32)
+            .collect();
+        Self::new(degree, knots, points)
+    }
+    pub fn degree(&self) -> usize {
+        self.degree
+    }
+    pub fn knots(&self) -> &[f32] {
+        &self.knots
+    }
+    pub fn points(&self) -> &[V] {
+        &self.points
+    }
+    pub fn domain(&self) -> (f32, f32) {
+        (self.knots[self.degree], self.knots[self.points.len()])
+    }
+    // the knot span t is in, the end of the domain belongs to the last span
+    fn span(&self, t: f32) -> usize {
+        let (from, to) = (self.degree, self.points.len() - 1);
+        let after = self.knots[..=to].partition_point(|&k| k <= t);
+        after.saturating_sub(1).clamp(from, to)
+    }
+    // de boor's algorithm, t outside the domain carries on the nearest span
+    pub fn eval(&self, t: f32) -> V {
+        let (p, k) = (self.degree, self.span(t));
+        let mut d: Vec<V> = self.points[k - p..=k].to_vec();
+        for r in 1..=p {
+            for j in (r..=p).rev() {
+                let i = j + k - p;
+                let (lo, hi) = (self.knots[i], self.knots[i + p - r + 1]);
+                let alpha = if hi == lo { 0. } else { (t - lo) / (hi - lo) };
+                d[j] = d[j - 1] * (1. - alpha) + d[j] * alpha;
+            }
+        }
+        d[p]
+    }
+    pub fn derive(&self) -> BSpline<V> {
+        let p = self.degree;
+        if p == 0 {
+            return BSpline {
+                points: vec![V::default(); self.points.len()],
+                ..self.clone()
+            };
+        }
+        let points = self
+            .points
+            .windows(2)
+            .enumerate()
+            .map(|(i, w)| {
+                let width = self.knots[i + p + 1] - self.knots[i + 1];
+                if width == 0. {
+                    V::default()
+                } else {
+                    (w[1] - w[0]) * (p as f32 / width)
+                }
+            })
+            .collect();
+        BSpline {
+            degree: p - 1,
+            knots: self.knots[1..self.knots.len() - 1].to_vec(),
+            points,
+        }
+    }
+    // every non empty knot span in the domain as (start, end, coefficients), the coefficients
+    // take time from the start of their span like a Piecewise does
+    pub fn spans(&self) -> Vec<(f32, f32, Vec<V>)> {
+        let p = self.degree;
+        (p..self.points.len())
+            .filter(|&k| self.knots[k] < self.knots[k + 1])
+            .map(|k| {
+                let start = self.knots[k];
+                // cox de boor with polynomials in s = t - start, only the p + 1 basis
+                // functions that are alive in this span
+                let mut basis: Vec<Vec<f32>> = vec![vec![1.]];
+                for d in 1..=p {
+                    let mut next = vec![Vec::new(); d + 1];
+                    for (j, b) in basis.iter().enumerate() {
+                        let i = k - (d - 1) + j;
+                        // b is N_i,d-1, it feeds N_i-1,d on the way down and N_i,d on the way up
+                        // both use the knots it spans at the new degree
+                        let (lo, hi) = (self.knots[i], self.knots[i + d]);
+                        if hi > lo {
+                            let up = [(start - lo) / (hi - lo), 1. / (hi - lo)];
+                            let down = [(hi - start) / (hi - lo), -1. / (hi - lo)];
+                            next[j + 1] = add(&next[j + 1], &mul(b, &up));
+                            next[j] = add(&next[j], &mul(b, &down));
+                        }
+                    }
+                    basis = next;
+                }
+                let mut coeffs = vec![V::default(); p + 1];
+                for (point, b) in self.points[k - p..=k].iter().zip(&basis) {
+                    for (c, &weight) in coeffs.iter_mut().zip(b) {
+                        *c += *point * weight;
+                    }
+                }
+                (start, self.knots[k + 1], coeffs)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn bspline() {
+    let near = |a: Vec2, b: Vec2| (a - b).0.iter().all(|d| d.abs() < 1e-4);
+    let handles = [
+        Vec2::new(0., 0.),
+        Vec2::new(1., 2.),
+        Vec2::new(3., 2.),
+        Vec2::new(4., 0.),
+    ];
+    // clamped with no inner knots is just a bezier
+    let spline = BSpline::clamped(3, handles.to_vec()).unwrap();
+    let bezier = CubicBezier::new(handles[0], handles[1], handles[2], handles[3]);
+    for s in [0., 0.2, 0.5, 1.] {
+        assert!(near(spline.eval(s), bezier.eval(s)));
+    }
+
+    let points: Vec<Vec2> = (0..7)
+        .map(|i| Vec2::new(i as f32, (i * i % 5) as f32))
+        .collect();
+    let uneven = vec![0., 0., 0., 0., 0.5, 2., 2.5, 4., 4., 4., 4.];
+    for spline in [
+        BSpline::uniform(3, points.clone()).unwrap(),
+        BSpline::new(3, uneven, points.clone()).unwrap(),
+        BSpline::clamped(2, points.clone()).unwrap(),
+    ] {
+        let (from, to) = spline.domain();
+        let slope = spline.derive();
+        // off the knots, where a quadratic's curvature jumps
+        for i in 0..40 {
+            let t = from + (to - from) * (i as f32 + 0.3) / 40.;
+            let h = 2e-3;
+            let numeric = (spline.eval(t + h) - spline.eval(t - h)) * (0.5 / h);
+            assert!((numeric - slope.eval(t)).0.iter().all(|d| d.abs() < 1e-2));
+        }
+        for (start, end, coeffs) in spline.spans() {
+            for t in [start, (start + end) / 2., end] {
+                let poly: Vec2 = ts(t - start).zip(&coeffs).map(|(x, c)| *c * x).sum();
+                assert!(near(poly, spline.eval(t)));
+            }
+        }
+    }
+    assert!(BSpline::uniform(3, points[..3].to_vec()).is_none());
+    assert!(BSpline::new(1, vec![0., 1., 0.5, 2.], points[..2].to_vec()).is_none());
+}
+
+// how segments in a path meet, C0 only joins the ends, C1 also matches the tangents exactly
+// and G1 just lines their directions up, leaving each handle its own length
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Continuity {
+    #[default]
+    C0,
+    C1,
+    G1,
+}
+
+// cubic segments one after another, each new one gets bent to meet the last as continuity asks
+// the global parameter goes from 0 to the number of segments, segment i covers i to i + 1
+#[derive(Debug, Clone)]
+pub struct Path<V = Vec2> {
+    segments: Vec<CubicBezier<V>>,
+    continuity: Continuity,
+}
+impl<V: Point> Path<V> {
+    pub fn new(continuity: Continuity) -> Self {
+        Path {
+            segments: Vec::new(),
+            continuity,
+        }
+    }
+    pub fn from_segments(
+        continuity: Continuity,
+        segments: impl IntoIterator<Item = CubicBezier<V>>,
+    ) -> Self {
+        let mut path = Self::new(continuity);
+        segments.into_iter().for_each(|s| path.push(s));
+        path
+    }
+    // the start and first handle move, the rest of the segment stays where it was put
+    pub fn push(&mut self, segment: CubicBezier<V>) {
+        let [mut p0, mut p1, p2, p3] = segment.handles();
+        if let Some(last) = self.segments.last() {
+            let [_, _, q2, q3] = last.handles();
+            let out = q3 - q2;
+            let reach = p1 - p0;
+            p0 = q3;
+            p1 = match self.continuity {
+                Continuity::C0 => p0 + reach,
+                Continuity::C1 => p0 + out,
+                // a handle sitting on its end point has no direction to line up
+                Continuity::G1 if out.length() == 0. => p0 + reach,
+                Continuity::G1 => p0 + out * (reach.length() / out.length()),
+            };
+        }
+        self.segments.push(CubicBezier::new(p0, p1, p2, p3));
+    }
+    pub fn continuity(&self) -> Continuity {
+        self.continuity
+    }
+    pub fn segments(&self) -> &[CubicBezier<V>] {
+        &self.segments
+    }
+    pub fn segment(&self, i: usize) -> Option<&CubicBezier<V>> {
+        self.segments.get(i)
+    }
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+    // t outside 0..len carries on the first or last segment, an empty path has nowhere to be
+    pub fn eval(&self, t: f32) -> Option<V> {
+        let last = self.segments.len().checked_sub(1)?;
+        let i = (t.floor().max(0.) as usize).min(last);
+        Some(self.segments[i].eval(t - i as f32))
+    }
+    pub fn derive(&self, t: f32) -> Option<V> {
+        let last = self.segments.len().checked_sub(1)?;
+        let i = (t.floor().max(0.) as usize).min(last);
+        Some(self.segments[i].derive().eval(t - i as f32))
+    }
+    // the flattened lines come up short of the curve by a bit less than tolerance would suggest
+    pub fn arc_length(&self, tolerance: f32) -> f32 {
+        self.segments.iter().map(|s| s.arc_length(tolerance)).sum()
+    }
+
+    // schneider's fitting from graphics gems, a least squares cubic through the points that gets cut
+    // where it misses worst until every point is within error of its segment
+    // recorded gestures come out as a handful of segments, joined G1 with the tangent measured at each cut
+    pub fn fit(points: &[V], error: f32) -> Self {
+        let mut path = Self::new(Continuity::G1);
+        // repeats have no direction and would only get in the way of the parameterization
+        let mut distinct: Vec<V> = Vec::with_capacity(points.len());
+        for &p in points {
+            if distinct.last().is_none_or(|&q| (p - q).length() > 0.) {
+                distinct.push(p);
+            }
+        }
+        if let [first, .., last] = distinct[..] {
+            let start = unit(distinct[1] - first);
+            let end = unit(distinct[distinct.len() - 2] - last);
+            fit_into(&distinct, start, end, error, &mut path.segments);
+        }
+        path
+    }
+}
+
+fn unit<V: Point>(v: V) -> V {
+    let length = v.length();
+    if length == 0. {
+        v
+    } else {
+        v * (1. / length)
+    }
+}
+
+// start and end are the directions the handles leave the end points in
+fn fit_into<V: Point>(points: &[V], start: V, end: V, error: f32, out: &mut Vec<CubicBezier<V>>) {
+    const REPARAMETERIZE: usize = 8;
+    let (first, last) = (points[0], points[points.len() - 1]);
+    if points.len() == 2 {
+        let reach = (last - first).length() / 3.;
+        out.push(CubicBezier::new(
+            first,
+            first + start * reach,
+            last + end * reach,
+            last,
+        ));
+        return;
+    }
+    // chord length to start with, then newton's method pulls each parameter to the closest point
+    let mut u: Vec<f32> = Vec::with_capacity(points.len());
+    let mut along = 0.;
+    for (i, &p) in points.iter().enumerate() {
+        if i > 0 {
+            along += (p - points[i - 1]).length();
+        }
+        u.push(along);
+    }
+    u.iter_mut().for_each(|u| *u /= along);
+
+    let mut worst = (f32::MAX, points.len() / 2);
+    let mut previous = f32::MAX;
+    for round in 0..=REPARAMETERIZE {
+        let bezier = least_squares(points, &u, start, end);
+        worst = points[1..points.len() - 1]
+            .iter()
+            .zip(&u[1..])
+            .enumerate()
+            .map(|(i, (&p, &u))| ((bezier.eval(u) - p).length(), i + 1))
+            .fold((0., 1), |a, b| if b.0 > a.0 { b } else { a });
+        if worst.0 <= error {
+            out.push(bezier);
+            return;
+        }
+        // a better parameterization isn't going to save it, cutting will
+        if worst.0 > previous * 0.8 || round == REPARAMETERIZE {
+            break;
+        }
+        previous = worst.0;
+        let [_, c1, c2, c3] = bezier.0;
+        // the ends stay pinned at 0 and 1
+        let inner = 1..points.len() - 1;
+        for (u, &p) in u[inner.clone()].iter_mut().zip(&points[inner]) {
+            let off = bezier.eval(*u) - p;
+            let slope = c1 + c2 * (2. * *u) + c3 * (3. * *u * *u);
+            let bend = c2 * 2. + c3 * (6. * *u);
+            let denominator = slope.dot(slope) + off.dot(bend);
+            if denominator != 0. {
+                *u = (*u - off.dot(slope) / denominator).clamp(0., 1.);
+            }
+        }
+    }
+    let split = worst.1;
+    let center = unit(points[split - 1] - points[split + 1]);
+    fit_into(&points[..=split], start, center, error, out);
+    fit_into(&points[split..], -center, end, error, out);
+}
+
+// only the handle lengths are free, which leaves two equations in two unknowns
+fn least_squares<V: Point>(points: &[V], u: &[f32], start: V, end: V) -> CubicBezier<V> {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    let (mut c, mut x) = ([[0f32; 2]; 2], [0f32; 2]);
+    for (&p, &u) in points.iter().zip(u) {
+        let v = 1. - u;
+        let (b0, b1, b2, b3) = (v * v * v, 3. * u * v * v, 3. * u * u * v, u * u * u);
+        let (a1, a2) = (start * b1, end * b2);
+        c[0][0] += a1.dot(a1);
+        c[0][1] += a1.dot(a2);
+        c[1][1] += a2.dot(a2);
+        let rest = p - (first * (b0 + b1) + last * (b2 + b3));
+        x[0] += a1.dot(rest);
+        x[1] += a2.dot(rest);
+    }
+    c[1][0] = c[0][1];
+    let det = c[0][0] * c[1][1] - c[0][1] * c[1][0];
+    let (mut alpha1, mut alpha2) = if det == 0. {
+        (0., 0.)
+    } else {
+        (
+            (x[0] * c[1][1] - x[1] * c[0][1]) / det,
+            (c[0][0] * x[1] - c[1][0] * x[0]) / det,
+        )
+    };
+    // handles that come out backwards or on top of the ends fall back to a third of the chord
+    let chord = (last - first).length();
+    let tiny = 1e-6 * chord;
+    if alpha1 < tiny || alpha2 < tiny {
+        alpha1 = chord / 3.;
+        alpha2 = chord / 3.;
+    }
+    CubicBezier::new(first, first + start * alpha1, last + end * alpha2, last)
+}
+
+#[test]
+fn paths() {
+    let near = |a: Vec2, b: Vec2| (a - b).length() < 1e-5;
+    let first = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(1., 1.),
+        Vec2::new(2., 1.),
+        Vec2::new(3., 0.),
+    );
+    let second = CubicBezier::new(
+        Vec2::new(5., 5.),
+        Vec2::new(5., 7.),
+        Vec2::new(6., 1.),
+        Vec2::new(7., 0.),
+    );
+    let slope = |c: &CubicBezier, s: f32| c.derive().eval(s);
+    for continuity in [Continuity::C0, Continuity::C1, Continuity::G1] {
+        let path = Path::from_segments(continuity, [first, second]);
+        assert_eq!(path.len(), 2);
+        let joined = path.segment(1).unwrap();
+        assert!(near(joined.eval(0.), first.eval(1.)));
+        assert!(near(joined.eval(1.), second.eval(1.)));
+        assert!(near(path.eval(1.5).unwrap(), joined.eval(0.5)));
+        let (into, out) = (slope(&first, 1.), slope(joined, 0.));
+        match continuity {
+            Continuity::C0 => assert!(near(out, slope(&second, 0.))),
+            Continuity::C1 => assert!(near(into, out)),
+            // the handle was 2 long, and the slope is 3 times the handle
+            Continuity::G1 => assert!(near(out, into * (6. / into.length()))),
+        }
+    }
+    // a straight line, so flattening has nothing to miss
+    let line = Path::from_segments(
+        Continuity::C1,
+        [
+            CubicBezier::new(
+                Vec2::new(0., 0.),
+                Vec2::new(1., 0.),
+                Vec2::new(2., 0.),
+                Vec2::new(3., 0.),
+            ),
+            CubicBezier::new(
+                Vec2::new(0., 0.),
+                Vec2::new(1., 0.),
+                Vec2::new(3., 0.),
+                Vec2::new(5., 0.),
+            ),
+        ],
+    );
+    assert!((line.arc_length(1e-3) - 5.).abs() < 1e-5);
+    assert_eq!(line.eval(-1.), Some(Vec2::new(-3., 0.)));
+    assert_eq!(line.derive(2.), Some(Vec2::new(6., 0.)));
+    assert!(Path::<Vec2>::new(Continuity::G1).eval(0.).is_none());
+}
+
+#[test]
+fn fitting() {
+    // a gesture that rises, holds and falls back, sampled unevenly
+    let gesture: Vec<Vec2> = (0..200)
+        .map(|i| {
+            let t = (i as f32 / 199.).powf(1.3) * 4.;
+            let value = if t < 1. {
+                t * t
+            } else if t < 3. {
+                1.
+            } else {
+                1. - (t - 3.) * (t - 3.)
+            };
+            Vec2::new(t, value)
+        })
+        .collect();
+    for error in [0.05, 0.01, 0.001] {
+        let path = Path::fit(&gesture, error);
+        assert!(path.len() < 40);
+        assert_eq!(path.eval(0.), Some(gesture[0]));
+        assert!((path.eval(path.len() as f32).unwrap() - gesture[199]).length() < 1e-5);
+        // every sample is close to somewhere on the path
+        let line = path
+            .segments()
+            .iter()
+            .flat_map(|s| s.flatten(error / 10.))
+            .collect::<Vec<_>>();
+        for &p in &gesture {
+            let closest = line
+                .windows(2)
+                .map(|w| {
+                    let (d, along) = (w[1] - w[0], p - w[0]);
+                    let s = (along.dot(d) / d.dot(d)).clamp(0., 1.);
+                    (along - d * s).length()
+                })
+                .fold(f32::MAX, f32::min);
+            assert!(closest < error * 1.2);
+        }
+    }
+    // a single curve's own samples need just the one segment
+    let bezier = CubicBezier::new(
+        Vec2::new(0., 0.),
+        Vec2::new(1., 2.),
+        Vec2::new(3., 2.),
+        Vec2::new(4., 0.),
+    );
+    // give or take the end tangents, which are only as good as the first and last pairs of samples
+    let samples: Vec<Vec2> = (0..=100).map(|i| bezier.eval(i as f32 / 100.)).collect();
+    assert_eq!(Path::fit(&samples, 1e-2).len(), 1);
+    assert!(Path::fit(&[Vec2::new(1., 1.); 3], 0.1).is_empty());
+    assert_eq!(Path::fit(&[0f32, 1.], 0.1).eval(0.5), Some(0.5));
+}
+
+#[test]
+fn dimensions() {
+    // a 1d curve is just an envelope over the parameter
+    let env = CubicBezier::new(0f32, 1., 1., 0.);
+    assert_eq!(env.eval(0.5), 0.75);
+    assert_eq!(env.extrema(), [vec![0.5]]);
+    let path = CatmullRom::through(&[0f32, 1., 0.5]);
+    assert_eq!(path[1].eval(0.), 1.);
+
+    let helix = CubicBezier::new(
+        Vec3::new(0., 0., 0.),
+        Vec3::new(1., 0., 1.),
+        Vec3::new(1., 1., 2.),
+        Vec3::new(0., 1., 3.),
+    );
+    let (lo, hi) = helix.bounding_box();
+    assert_eq!((lo.0[2], hi.0[2]), (0., 3.));
+    let (first, _) = helix.split(0.5);
+    assert_eq!(first.eval(1.), helix.eval(0.5));
+}
+
+#[cfg(feature = "glam")]
+#[test]
+fn glam_points() {
+    let handles = [
+        glam::Vec2::new(0., 0.),
+        glam::Vec2::new(1., 2.),
+        glam::Vec2::new(3., 2.),
+        glam::Vec2::new(4., 0.),
+    ];
+    let ours: CubicBezier =
+        CubicBezier::from_points(handles[0], handles[1], handles[2], handles[3]);
+    let theirs = CubicBezier::new(handles[0], handles[1], handles[2], handles[3]);
+    for s in [0., 0.3, 1.] {
+        assert_eq!(glam::Vec2::from(ours.eval(s)), theirs.eval(s));
+    }
+    let (lo, hi) = theirs.bounding_box();
+    assert_eq!((lo.y, hi.x), (0., 4.));
+}
+
+#[cfg(feature = "nalgebra")]
+#[test]
+fn nalgebra_points() {
+    let arch: QuadraticBezier<Vec3> = QuadraticBezier::from_points(
+        nalgebra::Point3::new(0., 0., 0.),
+        nalgebra::Point3::new(1., 2., 0.),
+        nalgebra::Point3::new(2., 0., 1.),
+    );
+    let middle: nalgebra::Vector3<f32> = arch.eval(0.5).into();
+    assert_eq!(middle, nalgebra::Vector3::new(1., 1., 0.25));
 }
@@ -0,0 +1,215 @@
+// drum hits made from scratch, a sine falling in pitch for the body and noise for the rattle,
+// everything dying away on its own so a hit only needs a time and a velocity
+// velocity goes from 0 to 1 and a tom's pitch is a midi note number like an instrument's
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{
+    func::{Adsr, Envelope, PhaseMode, Wave, Waveform, Wavetable},
+    pitch::midi_to_hz,
+    Fade, Mix, Modulation, PackError, TimedWave, TimedWavePacker,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Drum {
+    Kick,
+    Snare,
+    ClosedHat,
+    OpenHat,
+    Tom(f32),
+}
+
+// the squares an 808 hat is made of, pushed up to sit with the noise
+const METAL: [f32; 6] = [205.3, 304.4, 369.6, 522.7, 540., 800.];
+
+impl Drum {
+    pub fn hit(
+        &self,
+        sample_rate: f32,
+        start: i64,
+        velocity: f32,
+    ) -> Vec<TimedWave<Vec<f32>, Envelope<Vec<f32>>>> {
+        // a shape falling from one pitch to another over its whole length in seconds
+        // the fall is a cubic, quick at first and flattening out like the real thing
+        let part = |shape: Waveform, (from, to): (f32, f32), length: f32, gain: f32| {
+            let length = length * sample_rate;
+            let (to, fall) = (to / sample_rate, (from - to) / sample_rate);
+            let attack = (0.001 * sample_rate).min(length / 2.);
+            TimedWave {
+                start,
+                end: start + length.ceil() as i64,
+                wave: Wave {
+                    freq: vec![
+                        to + fall,
+                        -3. * fall / length,
+                        3. * fall / (length * length),
+                        -fall / (length * length * length),
+                    ],
+                    amp: Envelope::Adsr(Adsr {
+                        peak: gain * velocity,
+                        attack,
+                        decay: length - attack,
+                        sustain: 0.,
+                        gate: length,
+                        release: 0.,
+                    }),
+                    phase: 0.,
+                    mode: PhaseMode::Integral,
+                    shape,
+                    width: vec![0.5],
+                },
+                fade: Fade::default(),
+                mix: Mix::default(),
+                modulation: Modulation::default(),
+                table: Wavetable::default(),
+            }
+        };
+        // a new random value every sample
+        let noise = |length: f32, gain: f32| {
+            let rate = sample_rate;
+            part(Waveform::White, (rate, rate), length, gain)
+        };
+        let hat = |length: f32| {
+            let metal = METAL.map(|hz| part(Waveform::Square, (4. * hz, 4. * hz), length, 0.05));
+            [noise(length, 0.3)].into_iter().chain(metal).collect()
+        };
+        match *self {
+            Drum::Kick => vec![
+                part(Waveform::Sine, (160., 45.), 0.35, 1.),
+                noise(0.01, 0.3),
+            ],
+            Drum::Snare => vec![
+                part(Waveform::Sine, (220., 180.), 0.12, 0.5),
+                noise(0.2, 0.6),
+            ],
+            Drum::ClosedHat => hat(0.05),
+            Drum::OpenHat => hat(0.4),
+            Drum::Tom(pitch) => {
+                let hz = midi_to_hz(pitch);
+                vec![
+                    part(Waveform::Sine, (1.5 * hz, hz), 0.4, 0.9),
+                    noise(0.03, 0.15),
+                ]
+            }
+        }
+    }
+}
+
+impl TimedWavePacker {
+    // hits are (start, velocity), every wave of every hit goes on the end like try_extend, so
+    // normalize once a pattern is written down out of order
+    pub fn add_hits(
+        &mut self,
+        drum: Drum,
+        sample_rate: f32,
+        hits: impl IntoIterator<Item = (i64, f32)>,
+    ) -> Result<(), PackError> {
+        for (start, velocity) in hits {
+            self.try_extend(drum.hit(sample_rate, start, velocity))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn drum_hits() {
+    use crate::{func::Function, Clipping, Player};
+
+    let rate = 1000.;
+    let kick = Drum::Kick.hit(rate, 100, 1.);
+    let body = &kick[0];
+    assert_eq!((body.start, body.end), (100, 450));
+    // falls from 160 to 45 and stops falling there
+    assert!((body.wave.freq.eval(0.) - 0.16).abs() < 1e-6);
+    assert!((body.wave.freq.eval(350.) - 0.045).abs() < 1e-6);
+    assert!(body.wave.freq.eval(175.) < 0.06);
+    assert_eq!(body.wave.amp.eval(1.), 1.);
+    assert!(body.wave.amp.eval(349.9) < 1e-2);
+    let soft = Drum::Kick.hit(rate, 100, 0.5);
+    assert_eq!(soft[0].wave.amp.eval(1.), 0.5);
+
+    let mut packer = TimedWavePacker::new();
+    packer
+        .add_hits(Drum::Kick, rate, [(0, 1.), (500, 0.8)])
+        .unwrap();
+    packer.add_hits(Drum::Snare, rate, [(250, 1.)]).unwrap();
+    packer
+        .add_hits(Drum::ClosedHat, rate, [(125, 0.5)])
+        .unwrap();
+    packer.add_hits(Drum::Tom(45.), rate, [(750, 1.)]).unwrap();
+    assert_eq!(packer.timings.len(), 2 * 2 + 2 + 7 + 2);
+    packer.normalize().unwrap();
+    let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(1200));
+    player.set_clipping(Clipping::Off);
+    let mut out = [0f32; 1200];
+    player.play(&mut out).unwrap();
+    assert!(out.iter().all(|s| s.is_finite()));
+    // every hit is heard and the quiet between is quiet
+    for hit in [0, 125, 250, 500, 750] {
+        assert!(out[hit..hit + 20].iter().any(|s| s.abs() > 0.1), "{}", hit);
+    }
+    assert!(out[1170..].iter().all(|s| s.abs() < 0.05));
+}
+
+#[test]
+fn drum_kit() {
+    use crate::{func::Function, Player};
+
+    let rate = 1000.;
+    let lengths = |drum: Drum| -> Vec<i64> {
+        let hit = drum.hit(rate, 10, 1.);
+        hit.iter().map(|part| part.end - part.start).collect()
+    };
+    assert_eq!(lengths(Drum::Snare), [120, 200]);
+    assert_eq!(lengths(Drum::ClosedHat), [50; 7]);
+    assert_eq!(lengths(Drum::OpenHat), [400; 7]);
+    // a tom is its midi note, dropping to it from a fifth above
+    let tom = Drum::Tom(57.).hit(rate, 10, 1.);
+    assert_eq!(tom[0].end - tom[0].start, 400);
+    assert!((tom[0].wave.freq.eval(0.) - 0.33).abs() < 1e-6);
+    assert!((tom[0].wave.freq.eval(400.) - 0.22).abs() < 1e-6);
+    let higher = Drum::Tom(69.).hit(rate, 10, 1.);
+    assert!((higher[0].wave.freq.eval(400.) - 0.44).abs() < 1e-6);
+    // a hat's metal rings at one pitch the whole time
+    for square in &Drum::OpenHat.hit(rate, 10, 1.)[1..] {
+        assert_eq!(square.wave.shape, Waveform::Square);
+        assert_eq!(square.wave.freq.eval(0.), square.wave.freq.eval(399.));
+    }
+
+    // velocity scales every part of every drum the same
+    for drum in [
+        Drum::Kick,
+        Drum::Snare,
+        Drum::ClosedHat,
+        Drum::OpenHat,
+        Drum::Tom(50.),
+    ] {
+        let loud = drum.hit(rate, 10, 1.);
+        let soft = drum.hit(rate, 10, 0.25);
+        for (loud, soft) in loud.iter().zip(&soft) {
+            let peak = loud.wave.amp.eval(1.);
+            assert!(peak > 0.);
+            assert!(
+                (soft.wave.amp.eval(1.) - 0.25 * peak).abs() < 1e-6,
+                "{:?}",
+                drum
+            );
+        }
+    }
+
+    // the noise comes from the time alone, so the same hit always sounds the same
+    let render = || {
+        let mut packer = TimedWavePacker::new();
+        packer.add_hits(Drum::Snare, rate, [(10, 1.)]).unwrap();
+        let mut out = [0f32; 300];
+        Player::new(packer.get_pack().unwrap(), 0, Some(300))
+            .play(&mut out)
+            .unwrap();
+        out
+    };
+    let snare = render();
+    assert_eq!(snare, render());
+    assert!(snare[..10].iter().chain(&snare[210..]).all(|&s| s == 0.));
+}
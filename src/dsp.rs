@@ -0,0 +1,806 @@
+// effects for rendered audio, the filters' settings are functions of time, so a sweep is just a polynomial
+// cutoff is in cycles per sample like a wave's freq, q is unitless and gain is in db (shelves and peaks only)
+// time is whatever the caller hands process, for a graph that's the graph's time
+// the formulas are robert bristow-johnson's audio eq cookbook
+use alloc::{vec, vec::Vec};
+use core::f32::consts::TAU;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{
+    func::{chebyshev_to_power, Function, Wave},
+    graph::Effect,
+    poly::fit_polynomial,
+};
+
+// settings get worked out again every this many samples, which is plenty for a sweep
+const CONTROL: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    LowPass,
+    HighPass,
+    // peaks at 0db on the cutoff
+    BandPass,
+    LowShelf,
+    HighShelf,
+    // a bell around the cutoff, q sets how wide
+    Peak,
+}
+
+#[derive(Debug, Clone)]
+pub struct Biquad<F = Vec<f32>> {
+    pub kind: FilterKind,
+    pub cutoff: F,
+    pub q: F,
+    pub gain: F,
+    // transposed direct form 2, two numbers of memory per channel
+    state: [[f32; 2]; 2],
+}
+impl<F: Function> Biquad<F> {
+    pub fn new(kind: FilterKind, cutoff: F, q: F, gain: F) -> Self {
+        Biquad {
+            kind,
+            cutoff,
+            q,
+            gain,
+            state: [[0.; 2]; 2],
+        }
+    }
+    // forget the last few samples, for reusing a filter on unrelated audio
+    pub fn reset(&mut self) {
+        self.state = [[0.; 2]; 2];
+    }
+    // b0, b1, b2, a1, a2 with a0 divided out
+    fn coefficients(&self, t: f32) -> [f32; 5] {
+        let cutoff = self.cutoff.eval(t).clamp(1e-5, 0.49);
+        let q = self.q.eval(t).max(1e-3);
+        let (sin, cos) = (TAU * cutoff).sin_cos();
+        let alpha = sin / (2. * q);
+        let a = 10f32.powf(self.gain.eval(t) / 40.);
+        let shelf = 2. * a.sqrt() * alpha;
+        let [b0, b1, b2, a0, a1, a2] = match self.kind {
+            FilterKind::LowPass => [
+                (1. - cos) / 2.,
+                1. - cos,
+                (1. - cos) / 2.,
+                1. + alpha,
+                -2. * cos,
+                1. - alpha,
+            ],
+            FilterKind::HighPass => [
+                (1. + cos) / 2.,
+                -(1. + cos),
+                (1. + cos) / 2.,
+                1. + alpha,
+                -2. * cos,
+                1. - alpha,
+            ],
+            FilterKind::BandPass => [alpha, 0., -alpha, 1. + alpha, -2. * cos, 1. - alpha],
+            FilterKind::LowShelf => [
+                a * ((a + 1.) - (a - 1.) * cos + shelf),
+                2. * a * ((a - 1.) - (a + 1.) * cos),
+                a * ((a + 1.) - (a - 1.) * cos - shelf),
+                (a + 1.) + (a - 1.) * cos + shelf,
+                -2. * ((a - 1.) + (a + 1.) * cos),
+                (a + 1.) + (a - 1.) * cos - shelf,
+            ],
+            FilterKind::HighShelf => [
+                a * ((a + 1.) + (a - 1.) * cos + shelf),
+                -2. * a * ((a - 1.) + (a + 1.) * cos),
+                a * ((a + 1.) + (a - 1.) * cos - shelf),
+                (a + 1.) - (a - 1.) * cos + shelf,
+                2. * ((a - 1.) - (a + 1.) * cos),
+                (a + 1.) - (a - 1.) * cos - shelf,
+            ],
+            FilterKind::Peak => [
+                1. + alpha * a,
+                -2. * cos,
+                1. - alpha * a,
+                1. + alpha / a,
+                -2. * cos,
+                1. - alpha / a,
+            ],
+        };
+        [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+    }
+    fn run<const C: usize>(&mut self, time: i64, frames: &mut [[f32; C]]) {
+        for (chunk, time) in frames.chunks_mut(CONTROL).zip((time..).step_by(CONTROL)) {
+            let [b0, b1, b2, a1, a2] = self.coefficients(time as f32);
+            for frame in chunk {
+                for (x, [z1, z2]) in frame.iter_mut().zip(&mut self.state) {
+                    let y = b0 * *x + *z1;
+                    *z1 = b1 * *x - a1 * y + *z2;
+                    *z2 = b2 * *x - a2 * y;
+                    *x = y;
+                }
+            }
+        }
+    }
+    pub fn process_mono(&mut self, time: i64, samples: &mut [f32]) {
+        let (frames, _) = samples.as_chunks_mut::<1>();
+        self.run(time, frames);
+    }
+    pub fn process_stereo(&mut self, time: i64, frames: &mut [[f32; 2]]) {
+        self.run(time, frames);
+    }
+}
+impl<F: Function> Effect for Biquad<F> {
+    fn process(&mut self, time: i64, frames: &mut [[f32; 2]]) {
+        self.process_stereo(time, frames);
+    }
+}
+
+// a parametric eq, bands run one after another, each one just a biquad so its frequency, q and
+// gain can all follow the song along
+#[derive(Debug, Clone)]
+pub struct Equalizer<F = Vec<f32>> {
+    bands: Vec<Biquad<F>>,
+}
+impl<F> Default for Equalizer<F> {
+    fn default() -> Self {
+        Equalizer { bands: Vec::new() }
+    }
+}
+impl<F: Function> Equalizer<F> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // the index the band can be found at later
+    pub fn add_band(&mut self, kind: FilterKind, freq: F, q: F, gain: F) -> usize {
+        self.bands.push(Biquad::new(kind, freq, q, gain));
+        self.bands.len() - 1
+    }
+    pub fn band(&self, index: usize) -> Option<&Biquad<F>> {
+        self.bands.get(index)
+    }
+    pub fn band_mut(&mut self, index: usize) -> Option<&mut Biquad<F>> {
+        self.bands.get_mut(index)
+    }
+    pub fn bands(&self) -> &[Biquad<F>] {
+        &self.bands
+    }
+    pub fn reset(&mut self) {
+        self.bands.iter_mut().for_each(Biquad::reset);
+    }
+    pub fn process_mono(&mut self, time: i64, samples: &mut [f32]) {
+        for band in &mut self.bands {
+            band.process_mono(time, samples);
+        }
+    }
+    pub fn process_stereo(&mut self, time: i64, frames: &mut [[f32; 2]]) {
+        for band in &mut self.bands {
+            band.process_stereo(time, frames);
+        }
+    }
+}
+impl<F: Function> Effect for Equalizer<F> {
+    fn process(&mut self, time: i64, frames: &mut [[f32; 2]]) {
+        self.process_stereo(time, frames);
+    }
+}
+
+// a compressor, anything over threshold db only gets 1/ratio as much louder, with an infinite
+// ratio and no attack it's a limiter that never lets a sample past the threshold
+// the sides are linked, the loudest one decides how far everything gets turned down
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    pub threshold: f32,
+    pub ratio: f32,
+    // width in db of the quadratic bend from no compression into the full ratio, 0 is a hard knee
+    pub knee: f32,
+    // roughly how many samples it takes to clamp down and to let go again
+    pub attack: f32,
+    pub release: f32,
+    // db of gain afterwards, to make up for what the compression took off
+    pub makeup: f32,
+    // how many db it's turning down right now
+    reduction: f32,
+}
+impl Compressor {
+    pub fn new(threshold: f32, ratio: f32, attack: f32, release: f32) -> Self {
+        Compressor {
+            threshold,
+            ratio,
+            knee: 0.,
+            attack,
+            release,
+            makeup: 0.,
+            reduction: 0.,
+        }
+    }
+    pub fn limiter(ceiling: f32, release: f32) -> Self {
+        Self::new(ceiling, f32::INFINITY, 0., release)
+    }
+    pub fn reset(&mut self) {
+        self.reduction = 0.;
+    }
+    // how many db a steady level in db gets turned down by
+    fn curve(&self, level: f32) -> f32 {
+        let over = level - self.threshold;
+        let slope = 1. - 1. / self.ratio.max(1.);
+        if 2. * over.abs() < self.knee {
+            slope * (over + self.knee / 2.).powi(2) / (2. * self.knee)
+        } else if over > 0. {
+            slope * over
+        } else {
+            0.
+        }
+    }
+    pub(crate) fn run<const C: usize>(&mut self, frames: &mut [[f32; C]]) {
+        let attack = (-1. / self.attack).exp();
+        let release = (-1. / self.release).exp();
+        for frame in frames {
+            let peak = frame.iter().fold(0f32, |peak, x| peak.max(x.abs()));
+            let target = self.curve(20. * peak.max(1e-9).log10());
+            let smoothing = if target > self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction = target + (self.reduction - target) * smoothing;
+            let gain = 10f32.powf((self.makeup - self.reduction) / 20.);
+            frame.iter_mut().for_each(|x| *x *= gain);
+        }
+    }
+    pub fn process_mono(&mut self, samples: &mut [f32]) {
+        let (frames, _) = samples.as_chunks_mut::<1>();
+        self.run(frames);
+    }
+    pub fn process_stereo(&mut self, frames: &mut [[f32; 2]]) {
+        self.run(frames);
+    }
+}
+impl Effect for Compressor {
+    fn process(&mut self, _: i64, frames: &mut [[f32; 2]]) {
+        self.process_stereo(frames);
+    }
+}
+
+#[test]
+fn compressing() {
+    use crate::{Clipping, LiveWave, Player, TimedWavePacker};
+
+    let mut compressor = Compressor::new(-20., 4., 10., 100.);
+    assert_eq!(compressor.curve(-30.), 0.);
+    assert_eq!(compressor.curve(0.), 15.);
+    compressor.knee = 10.;
+    assert_eq!(compressor.curve(-20.), 0.75 * 25. / 20.);
+    assert_eq!(compressor.curve(-25.), 0.);
+    assert_eq!(compressor.curve(-15.), 3.75);
+
+    // a steady full scale signal starts out barely touched and settles 15db down
+    compressor.knee = 0.;
+    let mut samples = [1.; 2000];
+    compressor.process_mono(&mut samples);
+    assert!(samples[0] > 0.8);
+    assert!((samples[1999] - 10f32.powf(-0.75)).abs() < 1e-4);
+
+    // a limiter on a player keeps a loud pack under the ceiling with nothing clipped
+    let loud: TimedWavePacker = [LiveWave::sine(0, 1000, 0.01, 2.)].into_iter().collect();
+    let mut player = Player::new(loud.get_pack().unwrap(), 0, Some(1000));
+    player.set_clipping(Clipping::Off);
+    player.set_dynamics(Some(Compressor::limiter(-6., 1000.)));
+    let mut frames = [[0f32; 2]; 1000];
+    player.play_stereo(&mut frames).unwrap();
+    let ceiling = 10f32.powf(-6. / 20.);
+    assert!(frames.iter().flatten().all(|s| s.abs() <= ceiling + 1e-6));
+    assert!(frames.iter().flatten().any(|s| s.abs() > 0.9 * ceiling));
+}
+
+// a feedback echo, every repeat comes back time samples after the last at feedback times the level
+// feedback at 1 or past it never dies away
+#[derive(Debug, Clone)]
+pub struct Delay {
+    pub feedback: f32,
+    // 0 is all dry and 1 is only the echoes
+    pub mix: f32,
+    line: Vec<[f32; 2]>,
+    pos: usize,
+}
+impl Delay {
+    pub fn new(time: usize, feedback: f32, mix: f32) -> Self {
+        Delay {
+            feedback,
+            mix,
+            line: vec![[0.; 2]; time.max(1)],
+            pos: 0,
+        }
+    }
+    // echoes every beats beats at bpm
+    pub fn synced(bpm: f32, beats: f32, sample_rate: u32, feedback: f32, mix: f32) -> Self {
+        let time = (60. / bpm * beats * sample_rate as f32).round();
+        Self::new(time as usize, feedback, mix)
+    }
+    pub fn time(&self) -> usize {
+        self.line.len()
+    }
+    // whatever was still echoing is dropped
+    pub fn set_time(&mut self, time: usize) {
+        self.line = vec![[0.; 2]; time.max(1)];
+        self.pos = 0;
+    }
+    pub fn reset(&mut self) {
+        self.line.fill([0.; 2]);
+    }
+    fn run<const C: usize>(&mut self, frames: &mut [[f32; C]]) {
+        for frame in frames {
+            let delayed = &mut self.line[self.pos];
+            for (x, d) in frame.iter_mut().zip(delayed) {
+                let echo = *d;
+                *d = *x + echo * self.feedback;
+                *x = *x * (1. - self.mix) + echo * self.mix;
+            }
+            self.pos = (self.pos + 1) % self.line.len();
+        }
+    }
+    pub fn process_mono(&mut self, samples: &mut [f32]) {
+        let (frames, _) = samples.as_chunks_mut::<1>();
+        self.run(frames);
+    }
+    pub fn process_stereo(&mut self, frames: &mut [[f32; 2]]) {
+        self.run(frames);
+    }
+}
+impl Effect for Delay {
+    fn process(&mut self, _: i64, frames: &mut [[f32; 2]]) {
+        self.process_stereo(frames);
+    }
+}
+
+// a delay whose length wobbles with an lfo, which is just a wave, so rate is the wave's freq and
+// depth is its amp, in samples, and both can change over time like anything else
+// a few ms of delay and no feedback is a chorus, under a couple of ms with feedback is a flanger,
+// all wet is vibrato
+#[derive(Debug, Clone)]
+pub struct Chorus<F = Vec<f32>, A = Vec<f32>> {
+    pub lfo: Wave<F, A>,
+    // the delay the lfo swings around, in samples
+    pub delay: f32,
+    pub feedback: f32,
+    pub mix: f32,
+    // how far ahead the right side's lfo is, in cycles, half a cycle makes the sides move opposite
+    pub spread: f32,
+    line: Vec<[f32; 2]>,
+    pos: usize,
+}
+impl<F: Function, A: Function> Chorus<F, A> {
+    // the delay never goes past max_delay however far the lfo swings
+    pub fn new(lfo: Wave<F, A>, delay: f32, max_delay: usize) -> Self {
+        Chorus {
+            lfo,
+            delay,
+            feedback: 0.,
+            mix: 0.5,
+            spread: 0.,
+            line: vec![[0.; 2]; max_delay.max(1) + 2],
+            pos: 0,
+        }
+    }
+    pub fn reset(&mut self) {
+        self.line.fill([0.; 2]);
+    }
+    fn run<const C: usize>(&mut self, time: i64, frames: &mut [[f32; C]]) {
+        let len = self.line.len();
+        for (frame, time) in frames.iter_mut().zip(time..) {
+            let t = time as f32;
+            let (cycles, amp) = (self.lfo.cycles(t), self.lfo.amp.eval(t));
+            let width = self.lfo.width_at(t);
+            for (side, x) in frame.iter_mut().enumerate() {
+                let swing = amp * self.lfo.shape.at(cycles + side as f32 * self.spread, width);
+                let delay = (self.delay + swing).clamp(1., (len - 2) as f32);
+                // linear interpolation between the two samples either side of delay ago
+                let back = (self.pos + len) as f32 - delay;
+                let (i, frac) = (back.floor() as usize, back.fract());
+                let (a, b) = (self.line[i % len][side], self.line[(i + 1) % len][side]);
+                let wet = a + (b - a) * frac;
+                self.line[self.pos][side] = *x + wet * self.feedback;
+                *x = *x * (1. - self.mix) + wet * self.mix;
+            }
+            self.pos = (self.pos + 1) % len;
+        }
+    }
+    pub fn process_mono(&mut self, time: i64, samples: &mut [f32]) {
+        let (frames, _) = samples.as_chunks_mut::<1>();
+        self.run(time, frames);
+    }
+    pub fn process_stereo(&mut self, time: i64, frames: &mut [[f32; 2]]) {
+        self.run(time, frames);
+    }
+}
+impl<F: Function, A: Function> Effect for Chorus<F, A> {
+    fn process(&mut self, time: i64, frames: &mut [[f32; 2]]) {
+        self.process_stereo(time, frames);
+    }
+}
+
+#[test]
+fn modulating() {
+    use crate::func::{PhaseMode, Waveform};
+
+    // swinging between 10 and 30 samples of delay every 4000 samples
+    let lfo = |amp: f32| Wave {
+        freq: vec![1. / 4000.],
+        amp: vec![amp],
+        phase: 0.,
+        mode: PhaseMode::Product,
+        shape: Waveform::Sine,
+        width: vec![],
+    };
+    let mut still = Chorus::new(lfo(0.), 20., 40);
+    still.mix = 1.;
+    let mut samples = [0.; 50];
+    samples[0] = 1.;
+    still.process_mono(0, &mut samples);
+    assert_eq!(samples[20], 1.);
+    assert_eq!(samples.iter().sum::<f32>(), 1.);
+
+    let mut vibrato = Chorus::new(lfo(10.), 20., 40);
+    vibrato.mix = 1.;
+    vibrato.spread = 0.5;
+    let mut frames = vec![[0.; 2]; 4000];
+    frames[1000] = [1., 1.];
+    frames[3000] = [1., 1.];
+    // rendered in two goes, the lfo follows the time it's handed
+    vibrato.process_stereo(0, &mut frames[..2000]);
+    vibrato.process_stereo(2000, &mut frames[2000..]);
+    let loudest = |from: usize, side: usize| {
+        (from..from + 50)
+            .max_by(|&a, &b| frames[a][side].total_cmp(&frames[b][side]))
+            .unwrap()
+    };
+    // the lfo is at its top at 1000 and bottom at 3000, the right side the other way round
+    assert!(loudest(1000, 0).abs_diff(1030) <= 1);
+    assert!(loudest(1000, 1).abs_diff(1010) <= 1);
+    assert!(loudest(3000, 0).abs_diff(3010) <= 1);
+    assert!(loudest(3000, 1).abs_diff(3030) <= 1);
+}
+
+// distortion by running every sample through a transfer curve, usually a polynomial
+// the curve only ever sees -1 to 1, input past that after drive is held at the ends, which is
+// what stops a polynomial from running off to infinity on a loud signal
+#[derive(Debug, Clone)]
+pub struct Waveshaper<F = Vec<f32>> {
+    pub curve: F,
+    // gain before the curve, more pushes further into the bend
+    pub drive: f32,
+    pub mix: f32,
+}
+impl<F: Function> Waveshaper<F> {
+    pub fn new(curve: F) -> Self {
+        Waveshaper {
+            curve,
+            drive: 1.,
+            mix: 1.,
+        }
+    }
+    fn run<const C: usize>(&mut self, frames: &mut [[f32; C]]) {
+        for x in frames.as_flattened_mut() {
+            let shaped = self.curve.eval((*x * self.drive).clamp(-1., 1.));
+            *x = *x * (1. - self.mix) + shaped * self.mix;
+        }
+    }
+    pub fn process_mono(&mut self, samples: &mut [f32]) {
+        let (frames, _) = samples.as_chunks_mut::<1>();
+        self.run(frames);
+    }
+    pub fn process_stereo(&mut self, frames: &mut [[f32; 2]]) {
+        self.run(frames);
+    }
+}
+impl Waveshaper {
+    // the cubic 1.5x - 0.5x^3, flat at 1 so the clamp is a smooth join
+    pub fn soft_clip() -> Self {
+        Self::new(vec![0., 1.5, 0., -0.5])
+    }
+    // tanh(hardness x) scaled to reach 1 at 1, as a degree 9 fit, close for hardness up to about 3
+    // it straightens out as hardness goes to 0, and 0 itself would divide by tanh(0), so anything
+    // that soft, negative or NaN is just the straight line
+    pub fn tanh(hardness: f32) -> Self {
+        if hardness.is_nan() || hardness <= 1e-3 {
+            return Self::new(vec![0., 1.]);
+        }
+        let samples: Vec<(f32, f32)> = (0..=200)
+            .map(|i| {
+                let x = i as f32 / 100. - 1.;
+                (x, (hardness * x).tanh() / hardness.tanh())
+            })
+            .collect();
+        let mut curve = fit_polynomial(&samples, 9);
+        // the fit is a little off at the ends, nudged so it meets the clamp exactly
+        let top: f32 = curve.iter().sum();
+        curve.iter_mut().for_each(|c| *c /= top);
+        Self::new(curve)
+    }
+    // a full scale sine comes out as harmonic n at harmonics[n], from chebyshev polynomials
+    // since T_n(cos x) = cos(nx), quieter input gets a different, softer mix
+    pub fn chebyshev(harmonics: &[f32]) -> Self {
+        Self::new(chebyshev_to_power(harmonics))
+    }
+}
+impl<F: Function> Effect for Waveshaper<F> {
+    fn process(&mut self, _: i64, frames: &mut [[f32; 2]]) {
+        self.process_stereo(frames);
+    }
+}
+
+#[test]
+fn shaping() {
+    let shape = |shaper: &mut Waveshaper, x: f32| {
+        let mut samples = [x];
+        shaper.process_mono(&mut samples);
+        samples[0]
+    };
+    let mut soft = Waveshaper::soft_clip();
+    assert_eq!(shape(&mut soft, 0.5), 0.6875);
+    assert_eq!(shape(&mut soft, 1.), 1.);
+    assert_eq!(shape(&mut soft, -3.), -1.);
+    assert_eq!(shape(&mut Waveshaper::tanh(2.), 1.), 1.);
+    soft.drive = 2.;
+    soft.mix = 0.5;
+    assert_eq!(shape(&mut soft, 0.25), 0.125 + 0.5 * 0.6875);
+
+    let mut tanh = Waveshaper::tanh(2.);
+    for i in -10..=10 {
+        let x = i as f32 / 10.;
+        assert!((shape(&mut tanh, x) - (2. * x).tanh() / 2f32.tanh()).abs() < 2e-3);
+        for hardness in [0., -1., f32::NAN, 1e-9] {
+            assert_eq!(shape(&mut Waveshaper::tanh(hardness), x), x);
+        }
+    }
+
+    // a cosine at full scale turns into its second and third harmonics
+    let mut harmonics = Waveshaper::chebyshev(&[0., 0., 0.5, 0.25]);
+    let mut frames: Vec<[f32; 2]> = (0..100)
+        .map(|i| [(TAU * i as f32 / 100.).cos(); 2])
+        .collect();
+    harmonics.process_stereo(&mut frames);
+    for (i, [left, right]) in frames.into_iter().enumerate() {
+        let x = TAU * i as f32 / 100.;
+        assert!((left - 0.5 * (2. * x).cos() - 0.25 * (3. * x).cos()).abs() < 1e-5);
+        assert_eq!(left, right);
+    }
+}
+
+// in samples at 44100
+const COMBS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASSES: [usize; 4] = [556, 441, 341, 225];
+const SPREAD: usize = 23;
+
+// freeverb, jezar's tuning of schroeder's design, eight damped combs in parallel into four
+// allpasses in series for each side, the right side's delays a little longer so the sides differ
+// as a send it wants mix at 1, the dry signal already went through the other connection
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    // 0 to 1, how long the tail hangs around
+    pub room_size: f32,
+    // 0 to 1, how quickly the highs die away compared to the lows
+    pub damping: f32,
+    pub mix: f32,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+}
+
+#[derive(Debug, Clone)]
+struct Comb {
+    line: Vec<f32>,
+    pos: usize,
+    // the one pole low pass in the feedback
+    low: f32,
+}
+impl Comb {
+    fn run(&mut self, x: f32, feedback: f32, damping: f32) -> f32 {
+        let out = self.line[self.pos];
+        self.low = out * (1. - damping) + self.low * damping;
+        self.line[self.pos] = x + self.low * feedback;
+        self.pos = (self.pos + 1) % self.line.len();
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Allpass {
+    line: Vec<f32>,
+    pos: usize,
+}
+impl Allpass {
+    fn run(&mut self, x: f32) -> f32 {
+        let delayed = self.line[self.pos];
+        self.line[self.pos] = x + delayed * 0.5;
+        self.pos = (self.pos + 1) % self.line.len();
+        delayed - x
+    }
+}
+
+impl Reverb {
+    pub fn new(sample_rate: u32, room_size: f32, damping: f32, mix: f32) -> Self {
+        let scale = |length: usize, side: usize| {
+            ((length + side * SPREAD) as u64 * sample_rate as u64 / 44100).max(1) as usize
+        };
+        let combs = [0, 1].map(|side| {
+            COMBS
+                .iter()
+                .map(|&length| Comb {
+                    line: vec![0.; scale(length, side)],
+                    pos: 0,
+                    low: 0.,
+                })
+                .collect()
+        });
+        let allpasses = [0, 1].map(|side| {
+            ALLPASSES
+                .iter()
+                .map(|&length| Allpass {
+                    line: vec![0.; scale(length, side)],
+                    pos: 0,
+                })
+                .collect()
+        });
+        Reverb {
+            room_size,
+            damping,
+            mix,
+            combs,
+            allpasses,
+        }
+    }
+    pub fn reset(&mut self) {
+        for comb in self.combs.iter_mut().flatten() {
+            comb.line.fill(0.);
+            comb.low = 0.;
+        }
+        for allpass in self.allpasses.iter_mut().flatten() {
+            allpass.line.fill(0.);
+        }
+    }
+    fn run<const C: usize>(&mut self, frames: &mut [[f32; C]]) {
+        let feedback = self.room_size.clamp(0., 1.) * 0.28 + 0.7;
+        let damping = self.damping.clamp(0., 1.) * 0.4;
+        for frame in frames {
+            // both sides hear the same thing, the networks are what spread it out
+            let input = frame.iter().sum::<f32>() / C as f32 * 0.015;
+            for (side, x) in frame.iter_mut().enumerate() {
+                let mut wet: f32 = self.combs[side]
+                    .iter_mut()
+                    .map(|comb| comb.run(input, feedback, damping))
+                    .sum();
+                for allpass in &mut self.allpasses[side] {
+                    wet = allpass.run(wet);
+                }
+                *x = *x * (1. - self.mix) + wet * self.mix;
+            }
+        }
+    }
+    pub fn process_mono(&mut self, samples: &mut [f32]) {
+        let (frames, _) = samples.as_chunks_mut::<1>();
+        self.run(frames);
+    }
+    pub fn process_stereo(&mut self, frames: &mut [[f32; 2]]) {
+        self.run(frames);
+    }
+}
+impl Effect for Reverb {
+    fn process(&mut self, _: i64, frames: &mut [[f32; 2]]) {
+        self.process_stereo(frames);
+    }
+}
+
+#[test]
+fn reverberating() {
+    let tail = |room_size: f32, damping: f32| {
+        let mut reverb = Reverb::new(48000, room_size, damping, 1.);
+        let mut frames = vec![[0.; 2]; 96000];
+        frames[0] = [1., 1.];
+        reverb.process_stereo(&mut frames);
+        frames
+    };
+    let power = |frames: &[[f32; 2]]| frames.iter().flatten().map(|s| s * s).sum::<f32>();
+    let small = tail(0.2, 0.5);
+    let big = tail(0.9, 0.5);
+    // nothing comes back before the shortest comb
+    assert!(small[..1200].iter().all(|&f| f == [0.; 2]));
+    assert!(power(&small[1200..]) > 0.);
+    // the bigger room is still ringing a second in, and it isn't blowing up
+    assert!(power(&big[48000..]) > 100. * power(&small[48000..]));
+    assert!(big.iter().flatten().all(|s| s.abs() < 1.));
+    assert!(big.iter().any(|[l, r]| l != r));
+    // damping takes the fizz off the tail, which shows up as less difference between samples
+    let roughness = |frames: &[[f32; 2]]| {
+        let diff: f32 = frames.windows(2).map(|w| (w[1][0] - w[0][0]).powi(2)).sum();
+        diff / power(frames)
+    };
+    assert!(roughness(&tail(0.9, 1.)[24000..]) < roughness(&big[24000..]));
+
+    // all dry passes straight through
+    let mut reverb = Reverb::new(48000, 0.5, 0.5, 0.);
+    let mut samples = [0.5; 100];
+    reverb.process_mono(&mut samples);
+    assert_eq!(samples, [0.5; 100]);
+}
+
+#[test]
+fn echoes() {
+    let mut delay = Delay::new(3, 0.5, 0.5);
+    let mut samples = [0.; 12];
+    samples[0] = 1.;
+    delay.process_mono(&mut samples);
+    assert_eq!(
+        samples,
+        [0.5, 0., 0., 0.5, 0., 0., 0.25, 0., 0., 0.125, 0., 0.]
+    );
+    // a quarter note at 120bpm is half a second
+    let mut synced = Delay::synced(120., 1., 48000, 0., 1.);
+    assert_eq!(synced.time(), 24000);
+    let mut frames = vec![[0.; 2]; 24001];
+    frames[0] = [1., -1.];
+    synced.process_stereo(&mut frames[..10000]);
+    synced.process_stereo(&mut frames[10000..]);
+    assert_eq!(frames[24000], [1., -1.]);
+    assert_eq!(frames[0], [0., 0.]);
+}
+
+#[test]
+fn filtering() {
+    let tone =
+        |freq: f32| -> Vec<f32> { (0..4000).map(|i| (TAU * freq * i as f32).sin()).collect() };
+    // the amplitude of a sine with the same power, sampled peaks can miss the top
+    let loudness = |samples: &[f32]| {
+        (2. * samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    };
+    let butterworth = vec![core::f32::consts::FRAC_1_SQRT_2];
+    let filtered = |kind, cutoff: Vec<f32>, gain: Vec<f32>, freq: f32| {
+        let mut filter = Biquad::new(kind, cutoff, butterworth.clone(), gain);
+        let mut samples = tone(freq);
+        filter.process_mono(0, &mut samples);
+        // past where the filter has settled
+        loudness(&samples[2000..])
+    };
+
+    assert!(filtered(FilterKind::LowPass, vec![0.01], vec![], 0.001) > 0.99);
+    assert!(filtered(FilterKind::LowPass, vec![0.01], vec![], 0.1) < 0.01);
+    assert!(filtered(FilterKind::HighPass, vec![0.01], vec![], 0.001) < 0.01);
+    assert!(filtered(FilterKind::HighPass, vec![0.01], vec![], 0.1) > 0.99);
+    assert!((filtered(FilterKind::BandPass, vec![0.02], vec![], 0.02) - 1.).abs() < 0.01);
+    assert!(filtered(FilterKind::BandPass, vec![0.02], vec![], 0.2) < 0.2);
+    // 12db is 4 times as loud
+    assert!((filtered(FilterKind::LowShelf, vec![0.05], vec![12.], 0.001) - 3.98).abs() < 0.05);
+    assert!((filtered(FilterKind::LowShelf, vec![0.05], vec![12.], 0.4) - 1.).abs() < 0.05);
+    assert!((filtered(FilterKind::HighShelf, vec![0.05], vec![-6.], 0.4) - 0.5).abs() < 0.02);
+
+    assert!((filtered(FilterKind::Peak, vec![0.02], vec![6.], 0.02) - 2.).abs() < 0.02);
+    assert!((filtered(FilterKind::Peak, vec![0.02], vec![6.], 0.3) - 1.).abs() < 0.02);
+
+    // a low pass opening up over time lets a high tone through only at the end
+    let mut sweep = Biquad::new(
+        FilterKind::LowPass,
+        vec![0.001, 0.0001],
+        butterworth.clone(),
+        vec![],
+    );
+    let mut samples: Vec<[f32; 2]> = tone(0.2).into_iter().map(|s| [s, -s]).collect();
+    sweep.process_stereo(0, &mut samples);
+    let left: Vec<f32> = samples.iter().map(|[l, _]| *l).collect();
+    assert!(loudness(&left[200..300]) < 0.05);
+    assert!(loudness(&left[3500..]) > 0.9);
+    assert!(samples.iter().all(|[l, r]| l == &-r));
+
+    // a scooped low end with the mids coming up over the song
+    let mut eq = Equalizer::new();
+    eq.add_band(
+        FilterKind::LowShelf,
+        vec![0.005],
+        butterworth.clone(),
+        vec![-12.],
+    );
+    let mids = eq.add_band(FilterKind::Peak, vec![0.02], vec![2.], vec![0.]);
+    eq.band_mut(mids).unwrap().gain = vec![0., 12. / 8000.];
+    let mut low = tone(0.0005);
+    let mut mid: Vec<f32> = (0..8000).map(|i| (TAU * 0.02 * i as f32).sin()).collect();
+    eq.process_mono(0, &mut low);
+    eq.reset();
+    eq.process_mono(0, &mut mid);
+    assert!(loudness(&low[2000..]) < 0.3);
+    assert!(loudness(&mid[200..600]) < 1.1);
+    assert!(loudness(&mid[7500..]) > 3.);
+    assert_eq!(eq.bands().len(), 2);
+}
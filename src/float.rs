@@ -0,0 +1,108 @@
+// the float math std has and core doesn't, from libm for builds without std
+// with std the inherent methods are there instead, so this only gets built when they're missing
+pub(crate) trait Float: Sized {
+    fn acos(self) -> Self;
+    fn cbrt(self) -> Self;
+    fn ceil(self) -> Self;
+    fn cos(self) -> Self;
+    fn exp(self) -> Self;
+    fn exp2(self) -> Self;
+    fn floor(self) -> Self;
+    fn fract(self) -> Self;
+    fn ln(self) -> Self;
+    fn log10(self) -> Self;
+    fn log2(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn rem_euclid(self, rhs: Self) -> Self;
+    fn round(self) -> Self;
+    fn sin(self) -> Self;
+    fn sin_cos(self) -> (Self, Self);
+    fn sinh(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn tanh(self) -> Self;
+}
+
+// the same for both widths, libm's f32 functions are the f64 ones with an f on the end
+macro_rules! float {
+    ($float:ty, $acos:ident, $cbrt:ident, $ceil:ident, $cos:ident, $exp:ident, $exp2:ident,
+     $floor:ident, $ln:ident, $log10:ident, $log2:ident, $pow:ident, $round:ident, $sin:ident,
+     $sincos:ident, $sinh:ident, $sqrt:ident, $tanh:ident, $trunc:ident) => {
+        impl Float for $float {
+            fn acos(self) -> Self {
+                libm::$acos(self)
+            }
+            fn cbrt(self) -> Self {
+                libm::$cbrt(self)
+            }
+            fn ceil(self) -> Self {
+                libm::$ceil(self)
+            }
+            fn cos(self) -> Self {
+                libm::$cos(self)
+            }
+            fn exp(self) -> Self {
+                libm::$exp(self)
+            }
+            fn exp2(self) -> Self {
+                libm::$exp2(self)
+            }
+            fn floor(self) -> Self {
+                libm::$floor(self)
+            }
+            fn fract(self) -> Self {
+                self - libm::$trunc(self)
+            }
+            fn ln(self) -> Self {
+                libm::$ln(self)
+            }
+            fn log10(self) -> Self {
+                libm::$log10(self)
+            }
+            fn log2(self) -> Self {
+                libm::$log2(self)
+            }
+            fn powf(self, n: Self) -> Self {
+                libm::$pow(self, n)
+            }
+            fn powi(self, n: i32) -> Self {
+                libm::$pow(self, n as $float)
+            }
+            // like std, never negative whatever the signs
+            fn rem_euclid(self, rhs: Self) -> Self {
+                let rem = self % rhs;
+                if rem < 0. {
+                    rem + rhs.abs()
+                } else {
+                    rem
+                }
+            }
+            fn round(self) -> Self {
+                libm::$round(self)
+            }
+            fn sin(self) -> Self {
+                libm::$sin(self)
+            }
+            fn sin_cos(self) -> (Self, Self) {
+                libm::$sincos(self)
+            }
+            fn sinh(self) -> Self {
+                libm::$sinh(self)
+            }
+            fn sqrt(self) -> Self {
+                libm::$sqrt(self)
+            }
+            fn tanh(self) -> Self {
+                libm::$tanh(self)
+            }
+        }
+    };
+}
+float!(
+    f32, acosf, cbrtf, ceilf, cosf, expf, exp2f, floorf, logf, log10f, log2f, powf, roundf, sinf,
+    sincosf, sinhf, sqrtf, tanhf, truncf
+);
+float!(
+    f64, acos, cbrt, ceil, cos, exp, exp2, floor, log, log10, log2, pow, round, sin, sincos, sinh,
+    sqrt, tanh, trunc
+);
@@ -1,42 +1,301 @@
-use std::{borrow::Borrow, iter::repeat_with, ops::Mul, slice::Iter};
+use alloc::{vec, vec::Vec};
+use core::{
+    borrow::Borrow,
+    fmt::Debug,
+    iter::{self, once, repeat_with},
+    mem::replace,
+    ops::{Add, Div, Mul, Neg, Sub},
+    slice::Iter,
+};
 
-pub trait Function {
-    fn eval(&self, t: f32) -> f32;
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::PackError;
+
+// the floats polynomials can be written in, the player only ever plays f32 but envelopes
+// that run for hours or need the precision can be worked out in f64 and converted after
+pub trait Scalar:
+    Copy
+    + Debug
+    + Default
+    + PartialOrd
+    + iter::Sum
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    fn from_f32(x: f32) -> Self;
+    fn from_usize(x: usize) -> Self;
+    fn to_f32(self) -> f32;
+    fn from_f64(x: f64) -> Self;
+    fn to_f64(self) -> f64;
 }
+impl Scalar for f32 {
+    fn from_f32(x: f32) -> Self {
+        x
+    }
+    fn from_usize(x: usize) -> Self {
+        x as f32
+    }
+    fn to_f32(self) -> f32 {
+        self
+    }
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+impl Scalar for f64 {
+    fn from_f32(x: f32) -> Self {
+        x as f64
+    }
+    fn from_usize(x: usize) -> Self {
+        x as f64
+    }
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+pub trait Function<S: Scalar = f32> {
+    fn eval(&self, t: S) -> S;
+    // definite integral over [0, t]
+    // falls back to simpson's rule for anything that can't do it exactly
+    fn integral(&self, t: S) -> S {
+        const STEPS: usize = 64;
+        let h = t / S::from_usize(STEPS);
+        let inner: S = (1..STEPS)
+            .map(|i| {
+                let weight = S::from_f32(if i % 2 == 0 { 2. } else { 4. });
+                weight * self.eval(S::from_usize(i) * h)
+            })
+            .sum();
+        (self.eval(S::default()) + inner + self.eval(t)) * h / S::from_f32(3.)
+    }
+    // integral in double precision, for phases that keep growing long after f32 would have run
+    // out of digits, anything that can't do better just widens its own result
+    fn integral_f64(&self, t: f64) -> f64 {
+        self.integral(S::from_f64(t)).to_f64()
+    }
+    // out[i] = eval(t0 + i * dt), worth overriding when neighbouring samples share work
+    fn eval_block(&self, t0: S, dt: S, out: &mut [S]) {
+        for (i, value) in out.iter_mut().enumerate() {
+            *value = self.eval(t0 + S::from_usize(i) * dt);
+        }
+    }
+}
+// how many samples the overrides work through at a time, keeps their scratch on the stack
+const BLOCK: usize = 64;
 
-impl<T: Borrow<[f32]>> Function for T {
+impl<S: Scalar, T: Borrow<[S]>> Function<S> for T {
     #[inline(always)]
-    fn eval(&self, t: f32) -> f32 {
-        let f: &[f32] = self.borrow();
+    fn eval(&self, t: S) -> S {
+        let f: &[S] = self.borrow();
         f.eval(t)
     }
+    #[inline(always)]
+    fn integral(&self, t: S) -> S {
+        let f: &[S] = self.borrow();
+        f.integral(t)
+    }
+    #[inline(always)]
+    fn integral_f64(&self, t: f64) -> f64 {
+        let f: &[S] = self.borrow();
+        f.integral_f64(t)
+    }
+    #[inline(always)]
+    fn eval_block(&self, t0: S, dt: S, out: &mut [S]) {
+        let f: &[S] = self.borrow();
+        f.eval_block(t0, dt, out)
+    }
 }
 
-impl Function for [f32] {
+impl<S: Scalar> Function<S> for [S] {
     #[inline(always)]
-    fn eval(&self, t: f32) -> f32 {
+    fn eval(&self, t: S) -> S {
         if self.len() == 1 {
             return self[0];
         }
-        self.iter().zip(ts(t)).map(|(&l, r)| l * r).sum()
+        horner(self, t)
+    }
+    #[inline(always)]
+    fn integral(&self, t: S) -> S {
+        // horner on the integrated coefficients with the factor of t left over pulled out
+        self.iter()
+            .enumerate()
+            .rev()
+            .fold(S::default(), |acc, (i, &c)| {
+                acc * t + c / S::from_usize(i + 1)
+            })
+            * t
+    }
+    fn integral_f64(&self, t: f64) -> f64 {
+        self.iter()
+            .enumerate()
+            .rev()
+            .fold(0., |acc, (i, &c)| acc * t + c.to_f64() / (i + 1) as f64)
+            * t
+    }
+    // constants skip the work entirely, short polynomials step along by forward differences, one
+    // add per coefficient a sample, and long ones get horner's rule from each sample's own t
+    // the differences are kept in f64 and worked out again from exact values every RESYNC samples,
+    // so rounding can't build up past f32 precision
+    fn eval_block(&self, t0: S, dt: S, out: &mut [S]) {
+        match self {
+            [] => out.fill(S::default()),
+            [constant] => out.fill(*constant),
+            _ if self.len() <= STEPPED => {
+                let (t0, dt) = (t0.to_f64(), dt.to_f64());
+                for (chunk, part) in out.chunks_mut(RESYNC).enumerate() {
+                    let mut steps = [0f64; STEPPED];
+                    let steps = &mut steps[..self.len()];
+                    for (j, step) in steps.iter_mut().enumerate() {
+                        let t = t0 + (chunk * RESYNC + j) as f64 * dt;
+                        *step = self.iter().rev().fold(0., |acc, c| acc * t + c.to_f64());
+                    }
+                    // the first value and its differences, the last one never changes
+                    for k in 1..steps.len() {
+                        for j in (k..steps.len()).rev() {
+                            steps[j] -= steps[j - 1];
+                        }
+                    }
+                    for value in part {
+                        *value = S::from_f64(steps[0]);
+                        for k in 1..steps.len() {
+                            steps[k - 1] += steps[k];
+                        }
+                    }
+                }
+            }
+            _ => {
+                for (i, value) in out.iter_mut().enumerate() {
+                    *value = horner(self, t0 + S::from_usize(i) * dt);
+                }
+            }
+        }
+    }
+}
+
+// the most coefficients eval_block steps through with forward differences, more than this and the
+// differences get too big for f64 to carry them a whole RESYNC without losing f32 precision
+const STEPPED: usize = 6;
+const RESYNC: usize = 64;
+
+// one multiply and add per coefficient and no powers of t that blow up along the way
+fn horner<S: Scalar>(coeffs: &[S], t: S) -> S {
+    coeffs
+        .iter()
+        .rev()
+        .fold(S::default(), |acc, &c| acc * t + c)
+}
+
+// the old way, every coefficient times its own power of t and then summed
+// kept around to compare against
+pub fn eval_powers<S: Scalar>(poly: &[S], t: S) -> S {
+    poly.iter().zip(ts(t)).map(|(&l, r)| l * r).sum()
+}
+
+#[test]
+fn horner_accuracy() {
+    // (t - 1)^12 expanded, near t = 1 the terms are huge and nearly cancel
+    let mut poly = vec![1f64];
+    for _ in 0..12 {
+        poly.push(0.);
+        for i in (1..poly.len()).rev() {
+            poly[i] = poly[i - 1] - poly[i];
+        }
+        poly[0] = -poly[0];
+    }
+    let single: Vec<f32> = poly.iter().map(|&c| c as f32).collect();
+    let exact = |t: f64| poly.iter().rev().fold(0., |acc, &c| acc * t + c);
+    let (mut horner_err, mut powers_err) = (0f64, 0f64);
+    for i in 0..=100 {
+        let t = 0.5 + i as f32 / 100.;
+        horner_err += (single.eval(t) as f64 - exact(t as f64)).abs();
+        powers_err += (eval_powers(&single, t) as f64 - exact(t as f64)).abs();
     }
+    assert!(horner_err < powers_err);
+    assert!(horner_err / 101. < 1e-3);
+
+    // a gentle degree 15 one should match to float precision
+    let gentle: Vec<f32> = (0..16).map(|i| 1. / (i + 1) as f32).collect();
+    let exact = |t: f64| (0..16).map(|i| t.powi(i) / (i + 1) as f64).sum::<f64>();
+    let exact_integral = |t: f64| {
+        (0..16)
+            .map(|i| t.powi(i + 1) / ((i + 1) * (i + 1)) as f64)
+            .sum::<f64>()
+    };
+    for i in 0..=20 {
+        let t = -1. + i as f32 / 10.;
+        assert!((gentle.eval(t) as f64 - exact(t as f64)).abs() < 1e-5);
+        assert!((gentle.integral(t) as f64 - exact_integral(t as f64)).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn double_precision() {
+    // a slow sweep an hour in at 48khz, where neighbouring f32 times are 16 samples apart
+    let t = 172_800_001.;
+    let sweep = [0.01f64, 1e-12];
+    assert_eq!(sweep.eval(t), 0.01 + 1e-12 * t);
+    assert_ne!([0.01f32, 1e-12].eval(t as f32) as f64, sweep.eval(t));
+    assert!((sweep.integral(t) - (0.01 * t + 0.5e-12 * t * t)).abs() < 1e-6);
+
+    let coeffs = [1f64, 2., 3., 0.5];
+    let runs = [1, 3];
+    let polys: Vec<&[f64]> = MultiPoly::new(&coeffs[..], &runs[..]).unwrap().collect();
+    assert_eq!(polys[1].eval(2.), 2. + 6. + 2.);
+    let derived: Vec<f64> = derive_polynomial(polys[1]).collect();
+    assert_eq!(derived, [3., 1.]);
+    // converted at the edge for the player
+    let single: Vec<f32> = derived.iter().map(|c| c.to_f32()).collect();
+    assert_eq!(single, [3f32, 1.]);
 }
 
-pub fn derive_polynomial<
-    I: IntoIterator<Item = T>,
-    T: Borrow<N>,
-    N: Mul<f32, Output = N> + Clone,
->(
+// anything a polynomial can have for coefficients, the helpers below scale them by its scalar
+pub trait Coefficient: Mul<Self::Scalar, Output = Self> + Clone {
+    type Scalar: Scalar;
+}
+impl Coefficient for f32 {
+    type Scalar = f32;
+}
+impl Coefficient for f64 {
+    type Scalar = f64;
+}
+
+pub fn derive_polynomial<I: IntoIterator<Item = T>, T: Borrow<N>, N: Coefficient>(
     poly: I,
 ) -> impl Iterator<Item = N> {
     (1..)
-        .map(|i| i as f32)
+        .map(N::Scalar::from_usize)
         .zip(poly.into_iter().skip(1))
         .map(|(l, r)| r.borrow().clone() * l)
 }
 
-pub(crate) fn ts(t: f32) -> impl Iterator<Item = f32> {
-    let mut t_term = 1.0;
+// the constant of integration is always zero
+pub fn integrate_polynomial<I: IntoIterator<Item = T>, T: Borrow<N>, N: Coefficient + Default>(
+    poly: I,
+) -> impl Iterator<Item = N> {
+    once(N::default()).chain(
+        (1..)
+            .map(|i| N::Scalar::from_f32(1.) / N::Scalar::from_usize(i))
+            .zip(poly)
+            .map(|(l, r)| r.borrow().clone() * l),
+    )
+}
+
+pub(crate) fn ts<S: Scalar>(t: S) -> impl Iterator<Item = S> {
+    let mut t_term = S::from_f32(1.);
     repeat_with(move || {
         let term = t_term;
         t_term = term * t;
@@ -44,40 +303,956 @@ pub(crate) fn ts(t: f32) -> impl Iterator<Item = f32> {
     })
 }
 
-pub fn scale_polynomial<
-    I: IntoIterator<Item = T>,
-    T: Borrow<N>,
-    N: Mul<f32, Output = N> + Clone,
->(
+pub fn scale_polynomial<I: IntoIterator<Item = T>, T: Borrow<N>, N: Coefficient>(
     poly: I,
-    scale: f32,
+    scale: N::Scalar,
 ) -> impl Iterator<Item = N> {
     poly.into_iter()
         .map(move |coef| coef.borrow().clone() * scale)
 }
 
-pub fn stretch_polynomial<
-    I: IntoIterator<Item = T>,
-    T: Borrow<N>,
-    N: Mul<f32, Output = N> + Clone,
->(
+pub fn stretch_polynomial<I: IntoIterator<Item = T>, T: Borrow<N>, N: Coefficient>(
     poly: I,
-    stretch: f32,
+    stretch: N::Scalar,
 ) -> impl Iterator<Item = N> {
-    ts(1. / stretch)
+    ts(N::Scalar::from_f32(1.) / stretch)
         .zip(poly)
         .map(move |(scale, coef)| coef.borrow().clone() * scale)
 }
 
+// every real t where the polynomial is zero, sorted and with repeated roots only showing up once
+// a polynomial that's zero everywhere has no roots as far as this is concerned
+// all the work happens in f64 whatever the coefficients are
+pub fn real_roots<S: Scalar>(poly: &[S]) -> Vec<S> {
+    let coeffs: Vec<f64> = poly.iter().map(|c| c.to_f64()).collect();
+    let mut roots = roots_f64(trim(&coeffs));
+    roots.sort_by(f64::total_cmp);
+    roots.dedup_by(|a, b| (*a - *b).abs() <= 1e-9 * b.abs().max(1.));
+    roots.into_iter().map(S::from_f64).collect()
+}
+
+// like asking when an amplitude envelope first hits zero, from and to are both included
+pub fn roots_between<S: Scalar>(poly: &[S], from: S, to: S) -> Vec<S> {
+    real_roots(poly)
+        .into_iter()
+        .filter(|&t| t >= from && t <= to)
+        .collect()
+}
+
+// leading zeros don't change the polynomial but would divide by zero below
+fn trim(coeffs: &[f64]) -> &[f64] {
+    let degree = coeffs.iter().rposition(|&c| c != 0.).map_or(0, |i| i + 1);
+    &coeffs[..degree]
+}
+
+fn roots_f64(coeffs: &[f64]) -> Vec<f64> {
+    match *coeffs {
+        [] | [_] => Vec::new(),
+        [c, b] => vec![-c / b],
+        [c, b, a] => quadratic(a, b, c),
+        [d, c, b, a] => cubic(a, b, c, d)
+            .into_iter()
+            .map(|t| newton_step(coeffs, t))
+            .collect(),
+        _ => bracketed(coeffs),
+    }
+}
+
+fn quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let disc = b * b - 4. * a * c;
+    if disc < 0. {
+        return Vec::new();
+    }
+    if disc == 0. {
+        return vec![-b / (2. * a)];
+    }
+    // the larger root first then the other from the product, avoids cancellation
+    let q = -(b + b.signum() * disc.sqrt()) / 2.;
+    if q == 0. {
+        return vec![0.];
+    }
+    vec![q / a, c / q]
+}
+
+fn cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    let (b, c, d) = (b / a, c / a, d / a);
+    // t = x - b/3 gets rid of the square term, leaving t^3 + pt + q
+    let shift = b / 3.;
+    let p = c - b * b / 3.;
+    let q = 2. * b * b * b / 27. - b * c / 3. + d;
+    let disc = (q / 2.).powi(2) + (p / 3.).powi(3);
+    let scale = 1e-12 * (q * q + p.abs().powi(3)).max(f64::MIN_POSITIVE);
+    let depressed = if disc.abs() <= scale {
+        if p == 0. {
+            vec![0.]
+        } else {
+            vec![3. * q / p, -3. * q / (2. * p)]
+        }
+    } else if disc > 0. {
+        let root = disc.sqrt();
+        vec![(-q / 2. + root).cbrt() + (-q / 2. - root).cbrt()]
+    } else {
+        // three real roots, trigonometric form
+        let r = (-p / 3.).sqrt();
+        let angle = (3. * q / (2. * p * r)).clamp(-1., 1.).acos() / 3.;
+        (0..3)
+            .map(|k| 2. * r * (angle - core::f64::consts::TAU * k as f64 / 3.).cos())
+            .collect()
+    };
+    depressed.into_iter().map(|t| t - shift).collect()
+}
+
+fn newton_step(coeffs: &[f64], t: f64) -> f64 {
+    let (value, slope) = crate::poly::eval_with_derivative(coeffs, t);
+    if slope == 0. {
+        t
+    } else {
+        t - value / slope
+    }
+}
+
+// roots of the derivative split the line into stretches where the polynomial only goes one way,
+// so each one holds at most one root and it can be chased down with newton's method,
+// falling back to bisection whenever a step leaves the stretch
+fn bracketed(coeffs: &[f64]) -> Vec<f64> {
+    let derivative: Vec<f64> = derive_polynomial(coeffs).collect();
+    let lead = coeffs[coeffs.len() - 1];
+    // cauchy's bound, every root is closer to zero than this
+    let bound = 1. + coeffs.iter().map(|c| (c / lead).abs()).fold(0., f64::max);
+    let mut edges = vec![-bound];
+    edges.extend(
+        roots_f64(trim(&derivative))
+            .into_iter()
+            .filter(|t| t.abs() < bound),
+    );
+    edges.push(bound);
+    edges.sort_by(f64::total_cmp);
+    let tolerance = |t: f64| {
+        1e-9 * coeffs.iter().map(|c| c.abs()).sum::<f64>()
+            * t.abs().max(1.).powi(coeffs.len() as i32)
+    };
+    let mut roots = Vec::new();
+    for pair in edges.windows(2) {
+        let (lo, hi) = (pair[0], pair[1]);
+        let (f_lo, f_hi) = (coeffs.eval(lo), coeffs.eval(hi));
+        if f_lo.abs() <= tolerance(lo) {
+            // touches zero at a turning point without crossing
+            roots.push(lo);
+        } else if f_lo.signum() != f_hi.signum() {
+            roots.push(chase(coeffs, lo, hi));
+        }
+    }
+    if coeffs.eval(bound).abs() <= tolerance(bound) {
+        roots.push(bound);
+    }
+    roots
+}
+
+fn chase(coeffs: &[f64], mut lo: f64, mut hi: f64) -> f64 {
+    let rising = coeffs.eval(hi) > coeffs.eval(lo);
+    let mut t = (lo + hi) / 2.;
+    for _ in 0..100 {
+        let value = coeffs.eval(t);
+        if value == 0. {
+            return t;
+        }
+        if (value > 0.) == rising {
+            hi = t;
+        } else {
+            lo = t;
+        }
+        let next = newton_step(coeffs, t);
+        let next = if next > lo && next < hi {
+            next
+        } else {
+            (lo + hi) / 2.
+        };
+        if (next - t).abs() <= f64::EPSILON * t.abs() {
+            return next;
+        }
+        t = next;
+    }
+    t
+}
+
+#[test]
+fn roots() {
+    let close = |found: Vec<f64>, expected: &[f64]| {
+        assert_eq!(found.len(), expected.len(), "{:?}", found);
+        for (f, e) in found.iter().zip(expected) {
+            assert!((f - e).abs() < 1e-7, "{:?}", found);
+        }
+    };
+    close(real_roots(&[-2., 1.]), &[2.]);
+    // (t - 1)(t - 3)
+    close(real_roots(&[3., -4., 1.]), &[1., 3.]);
+    close(real_roots(&[1., 0., 1.]), &[]);
+    close(real_roots(&[1., -2., 1.]), &[1.]);
+    // (t + 1)(t - 2)(t - 5), and one with a single real root, t^3 + t - 2
+    close(real_roots(&[10., 3., -6., 1.]), &[-1., 2., 5.]);
+    close(real_roots(&[-2., 1., 0., 1.]), &[1.]);
+    // (t - 1)^2 (t + 2)
+    close(real_roots(&[2., -3., 0., 1.]), &[-2., 1.]);
+    // (t + 3)(t + 0.5)(t - 1)(t - 2)(t - 4), and (t^2 + 1)(t - 0.25)^2
+    let quintic = crate::poly::mul(
+        &crate::poly::mul(&[3., 1.], &[0.5, 1.]),
+        &crate::poly::mul(&crate::poly::mul(&[-1., 1.], &[-2., 1.]), &[-4., 1.]),
+    );
+    close(real_roots(&quintic), &[-3., -0.5, 1., 2., 4.]);
+    let touching = crate::poly::mul(&[1., 0., 1.], &[0.0625, -0.5, 1.]);
+    close(real_roots(&touching), &[0.25]);
+    // trailing zeros are ignored and f32 goes through the same way
+    assert_eq!(real_roots(&[-2f32, 1., 0., 0.]), [2.]);
+
+    // a decaying amp envelope that goes negative after 100 samples
+    let amp = [1f32, -0.01];
+    assert_eq!(roots_between(&amp, 0., 1000.), [100.]);
+    assert_eq!(roots_between(&amp, 0., 50.), []);
+}
+
+// chebyshev coefficients say how much of each T_k, where T_0 = 1, T_1 = t and
+// T_k+1 = 2t T_k - T_k-1, they're much better behaved than powers on [-1, 1]
+pub fn eval_chebyshev<S: Scalar>(coeffs: &[S], t: S) -> S {
+    // clenshaw's recurrence, horner for the chebyshev basis
+    let two_t = S::from_f32(2.) * t;
+    let (b1, b2) = coeffs
+        .iter()
+        .skip(1)
+        .rev()
+        .fold((S::default(), S::default()), |(b1, b2), &c| {
+            (two_t * b1 - b2 + c, b1)
+        });
+    match coeffs.first() {
+        Some(&c) => t * b1 - b2 + c,
+        None => S::default(),
+    }
+}
+
+pub fn chebyshev_to_power<S: Scalar>(coeffs: &[S]) -> Vec<S> {
+    let mut out = vec![S::default(); coeffs.len()];
+    let one = S::from_f32(1.);
+    let (mut prev, mut current) = (vec![one], vec![S::default(), one]);
+    for (k, &c) in coeffs.iter().enumerate() {
+        let term = if k == 0 { &prev } else { &current };
+        for (out, &x) in out.iter_mut().zip(term) {
+            *out = *out + c * x;
+        }
+        if k > 0 {
+            let mut next = vec![S::default()];
+            next.extend(current.iter().map(|&x| S::from_f32(2.) * x));
+            let next = crate::poly::sub(&next, &prev);
+            prev = replace(&mut current, next);
+        }
+    }
+    out
+}
+
+pub fn power_to_chebyshev<S: Scalar>(poly: &[S]) -> Vec<S> {
+    // horner again, multiplying by t in the chebyshev basis with t T_k = (T_k+1 + T_k-1) / 2
+    let half = S::from_f32(0.5);
+    let mut out: Vec<S> = Vec::with_capacity(poly.len());
+    for &c in poly.iter().rev() {
+        let mut shifted = vec![S::default(); out.len() + 1];
+        for (k, &x) in out.iter().enumerate() {
+            if k == 0 {
+                shifted[1] = shifted[1] + x;
+            } else {
+                shifted[k + 1] = shifted[k + 1] + half * x;
+                shifted[k - 1] = shifted[k - 1] + half * x;
+            }
+        }
+        shifted[0] = shifted[0] + c;
+        out = shifted;
+    }
+    out.truncate(poly.len());
+    out
+}
+
+// bernstein coefficients of degree n are the control values of a 1d bezier over t in [0, 1]
+pub fn eval_bernstein<S: Scalar>(coeffs: &[S], t: S) -> S {
+    // de casteljau, lerping neighbours until one is left
+    let mut points = coeffs.to_vec();
+    let s = S::from_f32(1.) - t;
+    for len in (1..points.len()).rev() {
+        for i in 0..len {
+            points[i] = s * points[i] + t * points[i + 1];
+        }
+    }
+    points.first().copied().unwrap_or_default()
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1., |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+pub fn bernstein_to_power<S: Scalar>(coeffs: &[S]) -> Vec<S> {
+    let n = coeffs.len().saturating_sub(1);
+    (0..coeffs.len())
+        .map(|j| {
+            (0..=j)
+                .map(|i| {
+                    let sign = if (j - i) % 2 == 0 { 1. } else { -1. };
+                    coeffs[i] * S::from_f64(sign * binomial(n, j) * binomial(j, i))
+                })
+                .sum()
+        })
+        .collect()
+}
+
+// the bernstein form has the same degree as the polynomial
+pub fn power_to_bernstein<S: Scalar>(poly: &[S]) -> Vec<S> {
+    let n = poly.len().saturating_sub(1);
+    (0..poly.len())
+        .map(|i| {
+            (0..=i)
+                .map(|j| poly[j] * S::from_f64(binomial(i, j) / binomial(n, j)))
+                .sum()
+        })
+        .collect()
+}
+
+#[test]
+fn bases() {
+    let poly = [0.5f64, -1., 0.25, 2., -0.75];
+    let cheb = power_to_chebyshev(&poly);
+    let bern = power_to_bernstein(&poly);
+    for (a, b) in chebyshev_to_power(&cheb).iter().zip(&poly) {
+        assert!((a - b).abs() < 1e-12);
+    }
+    for (a, b) in bernstein_to_power(&bern).iter().zip(&poly) {
+        assert!((a - b).abs() < 1e-12);
+    }
+    for i in 0..=10 {
+        let t = i as f64 / 10.;
+        assert!((eval_chebyshev(&cheb, t) - poly.eval(t)).abs() < 1e-12);
+        assert!((eval_bernstein(&bern, t) - poly.eval(t)).abs() < 1e-12);
+    }
+    // T_3 = 4t^3 - 3t
+    assert_eq!(chebyshev_to_power(&[0f32, 0., 0., 1.]), [0., -3., 0., 4.]);
+    // a cubic bezier's handles are its bernstein coefficients
+    assert_eq!(bernstein_to_power(&[0f32, 1., 1., 0.]), [0., 3., -3., 0.]);
+    assert_eq!(eval_chebyshev::<f32>(&[], 0.5), 0.);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(bytemuck::NoUninit, bytemuck::CheckedBitPattern)]
+#[repr(u8)]
+pub enum PhaseMode {
+    // sin(TAU * (t + phase) * freq(t))
+    // only has the right pitch when freq is constant
+    #[default]
+    Product,
+    // sin(TAU * integral of freq), correct for sweeps
+    Integral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(bytemuck::NoUninit, bytemuck::CheckedBitPattern)]
+#[repr(u8)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    Square,
+    Triangle,
+    Saw,
+    // high for the fraction of each cycle given by the wave's width
+    Pulse,
+    // noise, a new random value every cycle held until the next, so a freq of 1 is a fresh one
+    // every sample, it's the same every time so the phase picking where it starts is the seed
+    // all three noises loop every NOISE_WRAP cycles, at a freq of 1 that's about 21.8s at 48000
+    White,
+    // 1/f, rows of held noise each held twice as long as the one before added up
+    Pink,
+    // 1/f^2 like a random walk, the same rows as pink leaning on the slow ones
+    Brown,
+    // a plucked string ringing out from the start of the wave, the width is extra damping on top
+    // of what the string loses by itself, every pluck starts from the same burst of noise
+    Pluck,
+}
+// how sine shapes get their sines, from slowest and most accurate down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Sine {
+    #[default]
+    Exact,
+    // an odd 7th order polynomial over a quarter of the cycle, off by at most 8e-7 so under 1e-6
+    Polynomial,
+    // straight lines between SINE_TABLE points over the cycle, off by under 1e-5
+    Table,
+}
+// the polynomial's coefficients for x, x^3, x^5 and x^7, fitted to be as close as they can over
+// -pi/2 to pi/2 rather than taken from the taylor series, which is 1e-4 out at the ends
+const SINE_POLY: [f32; 4] = [0.999_996_6, -0.166_648_28, 0.008_306_325, -0.000_183_636_53];
+const SINE_TABLE: usize = 1024;
+// the table's points, worked out while compiling so there's nothing to set up at runtime
+static SINE_POINTS: [f32; SINE_TABLE + 1] = sine_points();
+// a quarter of the cycle from its taylor series, which is well past f32 out to pi/2, and the
+// rest mirrored from it so the zeros and peaks land exactly
+const fn sine_points() -> [f32; SINE_TABLE + 1] {
+    let mut points = [0.; SINE_TABLE + 1];
+    let mut i = 0;
+    while i <= SINE_TABLE {
+        let half = i % (SINE_TABLE / 2);
+        let quarter = if half > SINE_TABLE / 4 {
+            SINE_TABLE / 2 - half
+        } else {
+            half
+        };
+        let x = core::f64::consts::TAU * quarter as f64 / SINE_TABLE as f64;
+        let (mut term, mut sine, mut n) = (x, x, 1.);
+        while n < 40. {
+            term *= -x * x / ((n + 1.) * (n + 2.));
+            sine += term;
+            n += 2.;
+        }
+        let sine = if i >= SINE_TABLE / 2 { -sine } else { sine };
+        points[i] = sine as f32;
+        i += 1;
+    }
+    points
+}
+impl Sine {
+    // sin(TAU * cycles)
+    pub fn at(&self, cycles: f32) -> f32 {
+        let pos = cycles.rem_euclid(1.);
+        match self {
+            Sine::Exact => (core::f32::consts::TAU * pos).sin(),
+            Sine::Polynomial => {
+                // folded onto the quarter either side of 0, sine's mirrored round the peaks
+                let x = match pos {
+                    p if p > 0.75 => p - 1.,
+                    p if p > 0.25 => 0.5 - p,
+                    p => p,
+                };
+                let x = core::f32::consts::TAU * x;
+                x * horner(&SINE_POLY, x * x)
+            }
+            Sine::Table => {
+                let table = &SINE_POINTS;
+                let at = pos * SINE_TABLE as f32;
+                let i = (at as usize).min(SINE_TABLE - 1);
+                let within = at - i as f32;
+                table[i] + (table[i + 1] - table[i]) * within
+            }
+        }
+    }
+}
+
+// how many rows pink and brown noise add up, the slowest one changes every 2^15 cycles
+const NOISE_ROWS: u32 = 16;
+// how far noise goes before starting over once a wave's been playing long enough for it to
+// matter, whole cycles stay exact in f32 up to here and every row repeats within it
+// so noise changing every sample loops after 2^20 samples, going further would cost the cycles
+// their precision within a step
+const NOISE_WRAP: f64 = (1 << 20) as f64;
+// harmonics in a pluck's burst, as many as a delay line twice as long can hold
+const PLUCK_HARMONICS: usize = 32;
+impl Waveform {
+    pub fn is_noise(&self) -> bool {
+        matches!(self, Waveform::White | Waveform::Pink | Waveform::Brown)
+    }
+    // cycles brought back near 0 without changing what at or integral give, so however long
+    // something's been going there's still plenty of precision left for the shape
+    // a pluck is still ringing down so it gets them as they are
+    pub fn wrap(&self, cycles: f64) -> f32 {
+        if self.is_noise() {
+            cycles.rem_euclid(NOISE_WRAP) as f32
+        } else if *self == Waveform::Pluck {
+            cycles as f32
+        } else {
+            (cycles - cycles.floor()) as f32
+        }
+    }
+    // every shape crosses zero going up at the start of a cycle, same as sine
+    // sin only gets the angle within the cycle, it's far less accurate a long way out from 0
+    pub fn at(&self, cycles: f32, width: f32) -> f32 {
+        let pos = cycles.rem_euclid(1.);
+        match self {
+            Waveform::Sine => (core::f32::consts::TAU * pos).sin(),
+            Waveform::Square => Waveform::Pulse.at(cycles, 0.5),
+            Waveform::Triangle => 1. - 4. * ((pos + 0.25).fract() - 0.5).abs(),
+            Waveform::Saw => 2. * (pos + 0.5).fract() - 1.,
+            Waveform::Pulse => {
+                if pos < width {
+                    1.
+                } else {
+                    -1.
+                }
+            }
+            Waveform::White => noise(cycles.floor() as i64, 0),
+            // divided by the total weight so it stays between -1 and 1, which leaves both a bit
+            // quieter than white
+            Waveform::Pink | Waveform::Brown => {
+                let step = cycles.floor() as i64;
+                let (mut total, mut weights) = (0., 0.);
+                for row in 0..NOISE_ROWS {
+                    let weight = match self {
+                        Waveform::Brown => (row as f32 / 2.).exp2(),
+                        _ => 1.,
+                    };
+                    total += weight * noise(step >> row, row + 1);
+                    weights += weight;
+                }
+                total / weights
+            }
+            Waveform::Pluck => pluck(cycles, width, PLUCK_HARMONICS),
+        }
+    }
+    // at with polyblep/polyblamp corrections for samples dt cycles apart, silent once dt reaches
+    // nyquist
+    // noise is left as it is, its freq is how often it changes rather than a pitch
+    pub fn at_bandlimited(&self, cycles: f32, width: f32, dt: f32) -> f32 {
+        if self.is_noise() {
+            return self.at(cycles, width);
+        }
+        if dt >= 0.5 {
+            return 0.;
+        }
+        if *self == Waveform::Pluck {
+            return pluck(cycles, width, (0.5 / dt).ceil() as usize - 1);
+        }
+        let width = match self {
+            Waveform::Pulse => width,
+            _ => 0.5,
+        };
+        let pos = cycles.rem_euclid(1.);
+        // distance in samples from an edge at the given point in the cycle
+        let from = |edge: f32| ((pos - edge + 0.5).rem_euclid(1.) - 0.5) / dt;
+        let naive = self.at(cycles, width);
+        let correction = match self {
+            Waveform::Sine => 0.,
+            Waveform::Saw => -2. * blep(from(0.5)),
+            Waveform::Square | Waveform::Pulse => 2. * (blep(from(0.)) - blep(from(width))),
+            Waveform::Triangle => 8. * dt * (blamp(from(0.75)) - blamp(from(0.25))),
+            _ => 0.,
+        };
+        naive + correction
+    }
+    // the area under at from 0 to cycles
+    // noise would have to be added up one step at a time, so it counts as 0 and a noise lfo
+    // can't push the frequency around, only the amp and phase
+    pub fn integral(&self, cycles: f32, width: f32) -> f32 {
+        let pos = cycles.rem_euclid(1.);
+        match self {
+            Waveform::Sine => (1. - (core::f32::consts::TAU * pos).cos()) / core::f32::consts::TAU,
+            Waveform::Square => Waveform::Pulse.integral(cycles, 0.5),
+            Waveform::Triangle if pos < 0.25 => 2. * pos * pos,
+            Waveform::Triangle if pos < 0.75 => 2. * pos - 2. * pos * pos - 0.25,
+            Waveform::Triangle => 2. * (1. - pos) * (1. - pos),
+            Waveform::Saw if pos < 0.5 => pos * pos,
+            Waveform::Saw => (1. - pos) * (1. - pos),
+            // the only shape that doesn't average out to 0 over a cycle
+            Waveform::Pulse => {
+                let within = if pos < width { pos } else { 2. * width - pos };
+                cycles.floor() * (2. * width - 1.) + within
+            }
+            Waveform::White | Waveform::Pink | Waveform::Brown | Waveform::Pluck => 0.,
+        }
+    }
+}
+// karplus strong worked out in closed form, every time round the delay line the burst loses the
+// damping and gets averaged with its neighbour, which takes cos(PI * h / (2 * PLUCK_HARMONICS))
+// off harmonic h, so any point in the ring can be found without running the loop up to it
+// harmonics past the given one are left out, the last one the burst has is gone straight away
+fn pluck(cycles: f32, damping: f32, harmonics: usize) -> f32 {
+    let cycles = cycles.max(0.);
+    let keep = 1. - damping.clamp(0., 1.);
+    let mut total = 0.;
+    for h in 1..=harmonics.min(PLUCK_HARMONICS - 1) {
+        let angle = core::f32::consts::PI * h as f32 / (2 * PLUCK_HARMONICS) as f32;
+        let gain = (keep * angle.cos()).powf(cycles);
+        // the loss only grows with the harmonic, so everything from here on is quieter still
+        if gain < 1e-5 {
+            break;
+        }
+        let (sin, cos) = (core::f32::consts::TAU * (h as f32 * cycles).fract()).sin_cos();
+        total +=
+            gain * (noise(h as i64, NOISE_ROWS + 1) * cos + noise(h as i64, NOISE_ROWS + 2) * sin);
+    }
+    // about a third of full scale on average when it's plucked
+    total / (3. * PLUCK_HARMONICS as f32).sqrt()
+}
+// splitmix64 of the step and row, spread evenly over -1 to 1
+pub(crate) fn noise(step: i64, row: u32) -> f32 {
+    let mut z = (step as u64 ^ ((row as u64) << 56)).wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 23) as f32 - 1.
+}
+
+#[test]
+fn noises() {
+    let wave = |shape: Waveform, phase: f32| Wave {
+        freq: &[1.][..],
+        amp: &[0.5][..],
+        phase,
+        mode: PhaseMode::Product,
+        shape,
+        width: &[][..],
+    };
+    let samples = |wave: Wave<&[f32], &[f32]>, count: usize| {
+        let mut out = vec![0.; count];
+        wave.eval_block(0., 1., &mut out);
+        out
+    };
+    let white = wave(Waveform::White, 0.);
+    let mut block = [0f32; 4000];
+    white.eval_block(0., 1., &mut block);
+    assert!(block.iter().all(|s| s.abs() <= 0.5));
+    assert_eq!(block[17], white.eval(17.));
+    // another seed is another sequence, the same one is the same sequence
+    assert_ne!(block[..100], samples(wave(Waveform::White, 5000.), 100)[..]);
+    assert_eq!(block[..100], samples(wave(Waveform::White, 0.), 100)[..]);
+    // half the freq holds each value for two samples
+    let held = Wave {
+        freq: &[0.5][..],
+        ..white.clone()
+    };
+    assert_eq!(held.eval(2.), held.eval(3.));
+    assert_eq!(
+        white.shape.at_bandlimited(3.5, 0.5, 1.),
+        white.shape.at(3.5, 0.5)
+    );
+
+    // the slower the noise the smaller the steps from one sample to the next compared to how
+    // far it swings overall
+    let roughness = |shape: Waveform| {
+        let samples = samples(wave(shape, 0.), 20000);
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        let spread: f32 = samples.iter().map(|s| (s - mean) * (s - mean)).sum();
+        let steps: f32 = samples
+            .windows(2)
+            .map(|w| (w[1] - w[0]) * (w[1] - w[0]))
+            .sum();
+        steps / spread
+    };
+    let (white, pink, brown) = (
+        roughness(Waveform::White),
+        roughness(Waveform::Pink),
+        roughness(Waveform::Brown),
+    );
+    assert!((white - 2.).abs() < 0.1, "{}", white);
+    assert!(
+        pink < white / 2. && brown < pink / 2.,
+        "{} {} {}",
+        white,
+        pink,
+        brown
+    );
+    // the loop, one NOISE_WRAP on it all comes round again
+    for shape in [Waveform::White, Waveform::Pink, Waveform::Brown] {
+        for cycles in [0.5, 12345.25, 700000.] {
+            let (now, looped) = (shape.wrap(cycles), shape.wrap(cycles + NOISE_WRAP));
+            assert_eq!(shape.at(now, 0.5), shape.at(looped, 0.5));
+        }
+    }
+}
+
+#[test]
+fn plucks() {
+    let string = |damping: &'static [f32]| Wave {
+        freq: &[0.01][..],
+        amp: &[1.][..],
+        phase: 0.,
+        mode: PhaseMode::Product,
+        shape: Waveform::Pluck,
+        width: damping,
+    };
+    // loudness and roughness over one period starting at time t
+    let measure = |wave: &Wave<&[f32], &[f32]>, t: f32| {
+        let mut period = [0f32; 100];
+        wave.eval_block(t, 1., &mut period);
+        let power = period.iter().map(|s| s * s).sum::<f32>();
+        let steps = period
+            .windows(2)
+            .map(|w| (w[1] - w[0]).powi(2))
+            .sum::<f32>();
+        (power, steps / power)
+    };
+    let plain = string(&[]);
+    let (early, late) = (measure(&plain, 0.), measure(&plain, 2000.));
+    // it rings on but gets quieter and loses its top end
+    assert!(late.0 < early.0 / 4. && late.0 > 0.);
+    assert!(late.1 < early.1 / 4., "{:?} {:?}", early, late);
+    // once the high harmonics die down it nearly repeats every cycle
+    assert!((plain.eval(2000.) - plain.eval(2100.)).abs() < 0.01);
+    // more damping dies away sooner
+    let damped = string(&[0.05]);
+    assert!(measure(&damped, 2000.).0 < late.0 / 4.);
+    // the band limited pluck only keeps harmonics under nyquist, at 0.2 cycles a sample that's 2
+    assert_eq!(
+        Waveform::Pluck.at_bandlimited(0.3, 0., 0.2),
+        pluck(0.3, 0., 2)
+    );
+}
+
+#[test]
+fn shape_integrals() {
+    for (shape, width) in [
+        (Waveform::Sine, 0.5),
+        (Waveform::Square, 0.5),
+        (Waveform::Triangle, 0.5),
+        (Waveform::Saw, 0.5),
+        (Waveform::Pulse, 0.3),
+    ] {
+        let mut area = 0.;
+        let step = 1. / 4000.;
+        for i in 0..10000 {
+            let cycles = (i as f32 + 0.5) * step;
+            area += shape.at(cycles, width) * step;
+            if i % 500 == 499 {
+                let expected = shape.integral((i + 1) as f32 * step, width);
+                assert!((area - expected).abs() < 1e-3, "{:?} {}", shape, i);
+            }
+        }
+    }
+}
+
+// what a wave's phase can be, the usual constant or something that moves over time
+// either way it's a time offset, so another wave as the phase is phase modulation, 2 operator fm
+// with the modulator's amp as the depth in samples
+pub trait Phase {
+    fn phase_at(&self, t: f32) -> f32;
+}
+impl Phase for f32 {
+    fn phase_at(&self, _: f32) -> f32 {
+        *self
+    }
+}
+impl<F: Function, A: Function, W: Function, P: Phase> Phase for Wave<F, A, W, P> {
+    fn phase_at(&self, t: f32) -> f32 {
+        self.eval(t)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
-pub struct Wave<F, A> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wave<F, A, W = A, P = f32> {
     pub freq: F,
     pub amp: A,
-    pub phase: f32,
+    pub phase: P,
+    pub mode: PhaseMode,
+    pub shape: Waveform,
+    // pulse width or how damped a pluck is over time, ignored by the other shapes
+    pub width: W,
 }
-impl<F: Function, A: Function> Function for Wave<F, A> {
+impl<F: Function, A: Function, W: Function, P: Phase> Wave<F, A, W, P> {
+    // number of cycles completed by time t
+    pub fn cycles(&self, t: f32) -> f32 {
+        self.cycles_f64(t as f64) as f32
+    }
+    // cycles with the time and the cycles themselves in double precision, the lanes still get
+    // evaluated in f32 but their values don't grow the way time does
+    pub fn cycles_f64(&self, t: f64) -> f64 {
+        let phase = self.phase.phase_at(t as f32) as f64;
+        match self.mode {
+            PhaseMode::Product => (t + phase) * self.freq.eval(t as f32) as f64,
+            // phase stays a time offset so constant frequencies sound the same in both modes
+            PhaseMode::Integral => self.freq.integral_f64(t) + phase * self.freq.eval(0.) as f64,
+        }
+    }
+}
+impl<F: Function, A: Function, W: Function, P: Phase> Wave<F, A, W, P> {
+    // polyblep/polyblamp corrected version of eval
+    // sample_rate is in samples per unit of t, anything above nyquist is silent
+    pub fn eval_bandlimited(&self, t: f32, sample_rate: f32) -> f32 {
+        let cycles = self.cycles(t);
+        let dt = (self.cycles(t + 1. / sample_rate) - cycles).abs();
+        self.amp.eval(t) * self.shape.at_bandlimited(cycles, self.width_at(t), dt)
+    }
+    // the pulse width or pluck damping, other shapes are always 0.5
+    pub fn width_at(&self, t: f32) -> f32 {
+        match self.shape {
+            Waveform::Pulse | Waveform::Pluck => self.width.eval(t),
+            _ => 0.5,
+        }
+    }
+}
+// residual of a unit step smoothed over a sample either side of it
+fn blep(x: f32) -> f32 {
+    if x <= -1. || x >= 1. {
+        0.
+    } else if x < 0. {
+        (1. + x) * (1. + x) / 2.
+    } else {
+        -(1. - x) * (1. - x) / 2.
+    }
+}
+// integral of blep, the residual for a unit change in slope
+fn blamp(x: f32) -> f32 {
+    if x <= -1. || x >= 1. {
+        0.
+    } else if x < 0. {
+        (1. + x).powi(3) / 6.
+    } else {
+        (1. - x).powi(3) / 6.
+    }
+}
+impl<F: Function, A: Function, W: Function, P: Phase> Function for Wave<F, A, W, P> {
     fn eval(&self, t: f32) -> f32 {
-        self.amp.eval(t) * (std::f32::consts::TAU * (t + self.phase) * self.freq.eval(t)).sin()
+        let cycles = self.shape.wrap(self.cycles_f64(t as f64));
+        self.amp.eval(t) * self.shape.at(cycles, self.width_at(t))
+    }
+    // the lanes get evaluated a block at a time, integral mode accumulates the phase from the
+    // frequency with the trapezoid rule and starts over from the exact integral every block,
+    // so it's exact for linear sweeps and close for everything else
+    // the phase is worked out in f64 and wrapped before it's handed to the shape
+    fn eval_block(&self, t0: f32, dt: f32, out: &mut [f32]) {
+        for (i, out) in out.chunks_mut(BLOCK).enumerate() {
+            let t0 = t0 + (i * BLOCK) as f32 * dt;
+            let mut cycles = [0f32; BLOCK];
+            let cycles = &mut cycles[..out.len()];
+            match self.mode {
+                PhaseMode::Product => {
+                    self.freq.eval_block(t0, dt, cycles);
+                    for (j, c) in cycles.iter_mut().enumerate() {
+                        let t = t0 + j as f32 * dt;
+                        let phase = self.phase.phase_at(t);
+                        *c = self.shape.wrap((t as f64 + phase as f64) * *c as f64);
+                    }
+                }
+                PhaseMode::Integral => {
+                    let mut freqs = [0f32; BLOCK + 1];
+                    let freqs = &mut freqs[..out.len() + 1];
+                    self.freq.eval_block(t0, dt, freqs);
+                    let mut phase = self.cycles_f64(t0 as f64);
+                    for (c, f) in cycles.iter_mut().zip(freqs.windows(2)) {
+                        *c = self.shape.wrap(phase);
+                        phase += ((f[0] + f[1]) * dt / 2.) as f64;
+                    }
+                }
+            }
+            let mut widths = [0.5f32; BLOCK];
+            let widths = &mut widths[..out.len()];
+            if matches!(self.shape, Waveform::Pulse | Waveform::Pluck) {
+                self.width.eval_block(t0, dt, widths);
+            }
+            self.amp.eval_block(t0, dt, out);
+            for ((value, &c), &width) in out.iter_mut().zip(cycles.iter()).zip(widths.iter()) {
+                *value *= self.shape.at(c, width);
+            }
+        }
+    }
+}
+
+#[test]
+fn sines() {
+    for (sine, tolerance) in [
+        (Sine::Exact, 1e-6),
+        (Sine::Polynomial, 1e-6),
+        (Sine::Table, 1e-5),
+    ] {
+        for i in -2000..2000 {
+            let cycles = i as f32 / 997.;
+            let exact = (core::f64::consts::TAU * cycles as f64).sin();
+            let error = (sine.at(cycles) as f64 - exact).abs();
+            assert!(error < tolerance, "{:?} {} {}", sine, cycles, error);
+        }
+        assert_eq!(sine.at(0.), 0.);
+    }
+    assert_eq!(Sine::Table.at(0.25), 1.);
+    for (i, &point) in SINE_POINTS.iter().enumerate() {
+        let exact = (core::f64::consts::TAU * i as f64 / SINE_TABLE as f64).sin();
+        assert!((point as f64 - exact).abs() < 1e-7, "{} {}", i, point);
+    }
+}
+
+#[test]
+fn wrapped_phases() {
+    // whole cycles that f32 still holds exactly, but TAU times them doesn't
+    let far = (1 << 21) as f32;
+    assert!((Waveform::Sine.at(far + 0.25, 0.5) - 1.).abs() < 1e-6);
+    assert!((Waveform::Sine.at(-far - 0.75, 0.5) - 1.).abs() < 1e-6);
+    let drone = Wave {
+        freq: &[1. / 1024.][..],
+        amp: &[1.][..],
+        phase: 0.,
+        mode: PhaseMode::Integral,
+        shape: Waveform::Sine,
+        width: &[][..],
+    };
+    // a quarter of the way into cycle 2^20, t itself is still exact in f32
+    let t = (1 << 30) as f32 + 256.;
+    assert!((drone.eval(t) - 1.).abs() < 1e-3);
+    let mut block = [0f32; 3];
+    drone.eval_block(t - 256., 256., &mut block);
+    assert!(block[0].abs() < 1e-3 && (block[1] - 1.).abs() < 1e-3 && block[2].abs() < 1e-3);
+}
+
+#[test]
+fn block_eval() {
+    let poly = [0.5, -0.25, 0.125, 0.01];
+    let mut block = [0f32; 100];
+    poly.eval_block(-2., 0.05, &mut block);
+    for (i, value) in block.into_iter().enumerate() {
+        assert!((value - poly.eval(-2. + i as f32 * 0.05)).abs() < 1e-5);
+    }
+
+    let product = Wave {
+        freq: &[0.01][..],
+        amp: &[0.5, 0.001][..],
+        phase: 0.25,
+        mode: PhaseMode::Product,
+        shape: Waveform::Sine,
+        width: &[][..],
+    };
+    let sweep = Wave {
+        freq: &[0.01, 0.00002][..],
+        mode: PhaseMode::Integral,
+        ..product.clone()
+    };
+    let pulse = Wave {
+        shape: Waveform::Pulse,
+        width: &[0.2, 0.001][..],
+        ..product.clone()
+    };
+    for wave in [product, sweep] {
+        let mut block = [0f32; 300];
+        wave.eval_block(3., 1., &mut block);
+        for (i, value) in block.into_iter().enumerate() {
+            assert!((value - wave.eval(3. + i as f32)).abs() < 1e-4);
+        }
+    }
+    // pulses are only compared away from their edges
+    let mut block = [0f32; 300];
+    pulse.eval_block(0., 1., &mut block);
+    assert_eq!(
+        block.map(f32::signum),
+        core::array::from_fn(|t| pulse.eval(t as f32).signum())
+    );
+}
+
+#[test]
+fn stepped_blocks() {
+    // every length either side of STEPPED, over a second at 48k starting well in, with terms that
+    // all swing about as far as each other so none of them hides the others
+    for len in 2..=STEPPED + 2 {
+        let poly: Vec<f32> = (0..len)
+            .map(|k| 0.7 * (-1f32).powi(k as i32) / 20000f32.powi(k as i32))
+            .collect();
+        let mut block = vec![0f32; 48000];
+        poly.eval_block(5000., 0.5, &mut block);
+        for (i, &value) in block.iter().enumerate() {
+            let t = 5000. + i as f64 * 0.5;
+            let exact = poly.iter().rev().fold(0., |acc, &c| acc * t + c as f64);
+            let sample = poly.eval(5000. + i as f32 * 0.5);
+            // stepping is as good as f32 gets, however far into the block it's got
+            let error = (value as f64 - exact).abs() / exact.abs().max(1.);
+            assert!(len > STEPPED || error < 1e-6, "{} {} {}", len, i, value);
+            assert!((value - sample).abs() < 1e-5 * exact.abs().max(1.) as f32);
+        }
+    }
+    // a block that isn't a whole number of resyncs, and one that's shorter than the polynomial
+    let poly = [1., -0.5, 0.25];
+    for count in [RESYNC + 5, 2] {
+        let mut block = vec![0f32; count];
+        poly.eval_block(-1., 0.125, &mut block);
+        for (i, &value) in block.iter().enumerate() {
+            assert!((value - poly.eval(-1. + i as f32 * 0.125)).abs() < 1e-6);
+        }
     }
 }
 impl<'a> Default for Wave<&'a [f32], &'a [f32]> {
@@ -86,25 +1261,553 @@ impl<'a> Default for Wave<&'a [f32], &'a [f32]> {
             freq: &[],
             amp: &[],
             phase: 0.0,
+            mode: PhaseMode::Product,
+            shape: Waveform::Sine,
+            width: &[],
+        }
+    }
+}
+
+#[test]
+fn sweep_pitch() {
+    // freq(t) = 1 + 2t, so the phase should be t + t^2
+    let freq = [1., 2.];
+    assert_eq!(freq.integral(3.), 12.);
+    let product = Wave {
+        freq: &freq[..],
+        amp: &[1.][..],
+        phase: 0.0,
+        mode: PhaseMode::Product,
+        shape: Waveform::Sine,
+        width: &[][..],
+    };
+    let integral = Wave {
+        mode: PhaseMode::Integral,
+        ..product.clone()
+    };
+    assert_eq!(integral.cycles(3.), 12.);
+    assert_eq!(product.cycles(3.), 21.);
+
+    let constant = Wave {
+        freq: &[0.25][..],
+        amp: &[1.][..],
+        phase: 0.5,
+        mode: PhaseMode::Product,
+        shape: Waveform::Sine,
+        width: &[][..],
+    };
+    let swept = Wave {
+        mode: PhaseMode::Integral,
+        ..constant.clone()
+    };
+    for t in 0..8 {
+        assert_eq!(constant.eval(t as f32), swept.eval(t as f32));
+    }
+}
+
+// functions put together from other functions, so ring modulation is a Product of two waves, an
+// envelope on anything is a Product with it, layers are a Sum and Compose(a, b) is a(b(t)), like
+// running an envelope on a warped clock
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Product<A, B>(pub A, pub B);
+impl<S: Scalar, A: Function<S>, B: Function<S>> Function<S> for Product<A, B> {
+    fn eval(&self, t: S) -> S {
+        self.0.eval(t) * self.1.eval(t)
+    }
+    fn eval_block(&self, t0: S, dt: S, out: &mut [S]) {
+        for (i, out) in out.chunks_mut(BLOCK).enumerate() {
+            let t0 = t0 + S::from_usize(i * BLOCK) * dt;
+            let mut other = [S::default(); BLOCK];
+            let other = &mut other[..out.len()];
+            self.0.eval_block(t0, dt, out);
+            self.1.eval_block(t0, dt, other);
+            for (value, other) in out.iter_mut().zip(other.iter()) {
+                *value = *value * *other;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sum<A, B>(pub A, pub B);
+impl<S: Scalar, A: Function<S>, B: Function<S>> Function<S> for Sum<A, B> {
+    fn eval(&self, t: S) -> S {
+        self.0.eval(t) + self.1.eval(t)
+    }
+    // exact whenever both sides are
+    fn integral(&self, t: S) -> S {
+        self.0.integral(t) + self.1.integral(t)
+    }
+    fn eval_block(&self, t0: S, dt: S, out: &mut [S]) {
+        for (i, out) in out.chunks_mut(BLOCK).enumerate() {
+            let t0 = t0 + S::from_usize(i * BLOCK) * dt;
+            let mut other = [S::default(); BLOCK];
+            let other = &mut other[..out.len()];
+            self.0.eval_block(t0, dt, out);
+            self.1.eval_block(t0, dt, other);
+            for (value, other) in out.iter_mut().zip(other.iter()) {
+                *value = *value + *other;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Compose<A, B>(pub A, pub B);
+impl<S: Scalar, A: Function<S>, B: Function<S>> Function<S> for Compose<A, B> {
+    fn eval(&self, t: S) -> S {
+        self.0.eval(self.1.eval(t))
+    }
+}
+
+#[test]
+fn combinators() {
+    let carrier = Wave {
+        freq: &[0.01][..],
+        amp: &[1.][..],
+        phase: 0.,
+        mode: PhaseMode::Product,
+        shape: Waveform::Sine,
+        width: &[][..],
+    };
+    let modulator = Wave {
+        freq: &[0.003][..],
+        ..carrier.clone()
+    };
+    let ring = Product(carrier.clone(), modulator.clone());
+    let layered = Sum(carrier.clone(), [0.5, 0.01]);
+    let mut block = [0f32; 300];
+    ring.eval_block(2., 1., &mut block);
+    for (i, value) in block.into_iter().enumerate() {
+        let t = 2. + i as f32;
+        assert_eq!(ring.eval(t), carrier.eval(t) * modulator.eval(t));
+        assert!((value - ring.eval(t)).abs() < 1e-5);
+    }
+    layered.eval_block(0., 0.5, &mut block);
+    for (i, value) in block.into_iter().enumerate() {
+        let t = i as f32 * 0.5;
+        assert!((value - carrier.eval(t) - 0.5 - 0.01 * t).abs() < 1e-5);
+    }
+    // a sum of polynomials still integrates exactly, 2t + t^2 at 3
+    assert_eq!(Sum([2.], [0., 2.]).integral(3.), 15.);
+
+    // an envelope played at twice the speed
+    let envelope = Adsr {
+        peak: 1.,
+        attack: 10.,
+        decay: 10.,
+        sustain: 0.5,
+        gate: 40.,
+        release: 10.,
+    };
+    let fast = Compose(envelope, [0., 2.]);
+    assert_eq!(fast.eval(5.), 1.);
+    assert_eq!(fast.eval(25.), 0.);
+    // and nested, a tremolo on a sweep
+    let tremolo = Product(Sum([0.75], Product([0.25], modulator)), carrier);
+    assert!(tremolo.eval(25.) <= 1.);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(bytemuck::NoUninit, bytemuck::CheckedBitPattern)]
+#[repr(u8)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // catmull rom through the four nearest samples
+    Cubic,
+}
+
+// one cycle of any shape sampled evenly, played at the wave's frequency instead of its waveform
+// mip levels come after the table itself, all the same length, each with half the harmonics of
+// the one before so fast notes can use one that stays under nyquist
+// as a Function t is in cycles, an empty table is silent
+// a one shot table is a clip instead, played through once from the start of the wave and silent
+// after, with the wave's freq as the speed and its amp as the gain
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wavetable<T = Vec<f32>> {
+    samples: T,
+    levels: u8,
+    pub interpolation: Interpolation,
+    pub one_shot: bool,
+}
+impl Wavetable {
+    // levels counts the table itself, so 1 is no mips, None for an empty cycle or no levels
+    pub fn new(cycle: &[f32], levels: u8, interpolation: Interpolation) -> Option<Self> {
+        if cycle.is_empty() || levels == 0 {
+            return None;
+        }
+        let n = cycle.len();
+        let mut samples = cycle.to_vec();
+        if levels > 1 {
+            // a plain dft, it only runs once per table
+            let harmonics: Vec<(f64, f64)> = (0..=n / 2)
+                .map(|h| {
+                    cycle
+                        .iter()
+                        .enumerate()
+                        .fold((0., 0.), |(re, im), (i, &s)| {
+                            let angle = core::f64::consts::TAU * (h * i) as f64 / n as f64;
+                            (re + s as f64 * angle.cos(), im - s as f64 * angle.sin())
+                        })
+                })
+                .collect();
+            for level in 1..levels {
+                let top = (n / 2) >> level;
+                samples.extend((0..n).map(|i| {
+                    let value = harmonics[..=top].iter().enumerate().map(|(h, &(re, im))| {
+                        // the dc and nyquist bins aren't doubled up by a mirror image
+                        let scale = if h == 0 || 2 * h == n { 1. } else { 2. };
+                        let angle = core::f64::consts::TAU * (h * i) as f64 / n as f64;
+                        scale * (re * angle.cos() - im * angle.sin())
+                    });
+                    (value.sum::<f64>() / n as f64) as f32
+                }));
+            }
+        }
+        Some(Wavetable {
+            samples,
+            levels,
+            interpolation,
+            one_shot: false,
+        })
+    }
+    // a recording to play once, it has to fit in a run so it can be packed
+    // clips don't get mip levels, they'd take a dft of the whole thing
+    pub fn clip(samples: &[f32], interpolation: Interpolation) -> Option<Self> {
+        Some(Wavetable {
+            one_shot: true,
+            ..Self::new(samples, 1, interpolation)?
+        })
+    }
+    // one cycle of a Waveform, for a table that starts out as a plain shape
+    pub fn from_shape(
+        shape: Waveform,
+        width: f32,
+        length: usize,
+        levels: u8,
+        interpolation: Interpolation,
+    ) -> Option<Self> {
+        let cycle: Vec<f32> = (0..length)
+            .map(|i| shape.at(i as f32 / length as f32, width))
+            .collect();
+        Self::new(&cycle, levels, interpolation)
+    }
+}
+impl<T: Borrow<[f32]>> Wavetable<T> {
+    // samples holds every level, so its length has to be a multiple of levels
+    pub fn from_parts(samples: T, levels: u8, interpolation: Interpolation) -> Option<Self> {
+        let len = samples.borrow().len();
+        if (levels == 0) != (len == 0) || (levels > 0 && len % levels as usize != 0) {
+            return None;
+        }
+        Some(Wavetable {
+            samples,
+            levels,
+            interpolation,
+            one_shot: false,
+        })
+    }
+    pub fn samples(&self) -> &[f32] {
+        self.samples.borrow()
+    }
+    pub fn levels(&self) -> u8 {
+        self.levels
+    }
+    pub fn is_empty(&self) -> bool {
+        self.levels == 0
+    }
+    // samples in one cycle
+    pub fn len(&self) -> usize {
+        self.samples()
+            .len()
+            .checked_div(self.levels as usize)
+            .unwrap_or(0)
+    }
+    pub fn to_vec(&self) -> Wavetable {
+        Wavetable {
+            samples: self.samples().to_vec(),
+            levels: self.levels,
+            interpolation: self.interpolation,
+            one_shot: self.one_shot,
+        }
+    }
+    pub fn as_slices(&self) -> Wavetable<&[f32]> {
+        Wavetable {
+            samples: self.samples(),
+            levels: self.levels,
+            interpolation: self.interpolation,
+            one_shot: self.one_shot,
+        }
+    }
+    pub fn at(&self, cycles: f32) -> f32 {
+        self.read(0, cycles)
+    }
+    // the freq lane that plays a clip at pitch times its own speed, for a pitch that changes over
+    // time the wave needs integral mode, if the clip was recorded at another rate than the pack's
+    // the ratio between them goes in the pitch too
+    pub fn speed(&self, pitch: &[f32]) -> Vec<f32> {
+        scale_polynomial(pitch, 1. / self.len() as f32).collect()
+    }
+    // at from the first level whose harmonics all stay under nyquist for samples dt cycles apart,
+    // or the last level if none of them do
+    pub fn at_bandlimited(&self, cycles: f32, dt: f32) -> f32 {
+        if dt >= 0.5 {
+            return 0.;
+        }
+        let top = self.len() / 2;
+        let level = (0..self.levels)
+            .find(|&level| (top >> level) as f32 * dt < 0.5)
+            .unwrap_or(self.levels.saturating_sub(1));
+        self.read(level, cycles)
+    }
+    fn read(&self, level: u8, cycles: f32) -> f32 {
+        let n = self.len();
+        if n == 0 {
+            return 0.;
+        }
+        if self.one_shot && !(0. ..1.).contains(&cycles) {
+            return 0.;
+        }
+        let table = &self.samples()[level as usize * n..][..n];
+        let pos = cycles.rem_euclid(1.) * n as f32;
+        let whole = pos.floor() as isize;
+        let frac = pos - whole as f32;
+        // a clip has silence either side where a loop wraps around
+        let at = |offset: isize| match (whole + offset).rem_euclid(n as isize) {
+            i if self.one_shot && i != whole + offset => 0.,
+            i => table[i as usize],
+        };
+        match self.interpolation {
+            Interpolation::Linear => at(0) + (at(1) - at(0)) * frac,
+            Interpolation::Cubic => {
+                let (before, a, b, after) = (at(-1), at(0), at(1), at(2));
+                let (t, t2, t3) = (frac, frac * frac, frac * frac * frac);
+                ((-t3 + 2. * t2 - t) * before
+                    + (3. * t3 - 5. * t2 + 2.) * a
+                    + (-3. * t3 + 4. * t2 + t) * b
+                    + (t3 - t2) * after)
+                    / 2.
+            }
+        }
+    }
+}
+impl<T: Borrow<[f32]>> Function for Wavetable<T> {
+    fn eval(&self, t: f32) -> f32 {
+        self.at(t)
+    }
+}
+
+#[test]
+fn wavetables() {
+    // a saw with enough samples to hold a few harmonics, halfway up its jump at the jump
+    let cycle: Vec<f32> = (0..64)
+        .map(|i| match i {
+            32 => 0.,
+            _ => Waveform::Saw.at(i as f32 / 64., 0.5),
+        })
+        .collect();
+    let saw = Wavetable::new(&cycle, 4, Interpolation::Linear).unwrap();
+    assert_eq!((saw.len(), saw.levels(), saw.samples().len()), (64, 4, 256));
+    assert_eq!(saw.at(0.25), Waveform::Saw.at(0.25, 0.5));
+    assert!((saw.at(0.126) - Waveform::Saw.at(0.126, 0.5)).abs() < 1e-5);
+    assert_eq!(saw.eval(1.25), saw.at(0.25));
+
+    // the top level only keeps the first four harmonics of the saw
+    let top = |c: f32| saw.read(3, c);
+    let fundamental = |c: f32| {
+        let sines = (1..=4).map(|h| {
+            let sign = if h % 2 == 1 { 1. } else { -1. };
+            sign * 2. / (core::f32::consts::PI * h as f32)
+                * (core::f32::consts::TAU * h as f32 * c).sin()
+        });
+        sines.sum::<f32>()
+    };
+    for i in 0..64 {
+        let c = i as f32 / 64.;
+        assert!(
+            (top(c) - fundamental(c)).abs() < 0.02,
+            "{} {}",
+            top(c),
+            fundamental(c)
+        );
+    }
+    // slow notes get the full table, fast ones the level that fits
+    assert_eq!(saw.at_bandlimited(0.3, 0.001), saw.at(0.3));
+    assert_eq!(saw.at_bandlimited(0.3, 0.1), top(0.3));
+    assert_eq!(saw.at_bandlimited(0.3, 0.5), 0.);
+
+    let sine = Wavetable::from_shape(Waveform::Sine, 0.5, 32, 1, Interpolation::Cubic).unwrap();
+    for i in 0..100 {
+        let c = i as f32 / 100.;
+        assert!((sine.at(c) - Waveform::Sine.at(c, 0.5)).abs() < 1e-3);
+    }
+    assert!(Wavetable::new(&[], 1, Interpolation::Linear).is_none());
+    assert!(Wavetable::from_parts(&[0., 1., 2.][..], 2, Interpolation::Linear).is_none());
+    assert!(Wavetable::<&[f32]>::default().is_empty());
+
+    let clip = Wavetable::clip(&[1., 2., 3., 4.], Interpolation::Linear).unwrap();
+    assert_eq!(clip.at(0.125), 1.5);
+    // fading into the silence after it instead of back to the start
+    assert_eq!(clip.at(0.875), 2.);
+    assert_eq!((clip.at(-0.1), clip.at(1.)), (0., 0.));
+    assert_eq!(clip.speed(&[0.5, 0.1]), [0.125, 0.025]);
+    assert_eq!(Wavetable::<&[f32]>::default().at(0.3), 0.);
+}
+
+// attack, decay, sustain, release as straight line segments
+// sustain is a fraction of peak and the release starts once the gate closes
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct Adsr {
+    pub peak: f32,
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub gate: f32,
+    pub release: f32,
+}
+impl Adsr {
+    fn held(&self, t: f32) -> f32 {
+        let Adsr {
+            peak,
+            attack,
+            decay,
+            sustain,
+            ..
+        } = *self;
+        if t < 0. {
+            0.
+        } else if t < attack {
+            peak * t / attack
+        } else if t < attack + decay {
+            let progress = (t - attack) / decay;
+            peak * (1. + (sustain - 1.) * progress)
+        } else {
+            peak * sustain
+        }
+    }
+}
+impl Function for Adsr {
+    fn eval(&self, t: f32) -> f32 {
+        if t < self.gate {
+            self.held(t)
+        } else if t >= self.gate + self.release {
+            0.
+        } else {
+            let progress = (t - self.gate) / self.release;
+            self.held(self.gate) * (1. - progress)
+        }
+    }
+}
+
+#[test]
+fn adsr_stages() {
+    let env = Adsr {
+        peak: 1.,
+        attack: 2.,
+        decay: 2.,
+        sustain: 0.5,
+        gate: 6.,
+        release: 4.,
+    };
+    let levels = [0., 1., 2., 3., 4., 5., 6., 8., 10., 11.].map(|t| env.eval(t));
+    assert_eq!(levels, [0., 0.5, 1., 0.75, 0.5, 0.5, 0.5, 0.25, 0., 0.]);
+    // letting go during the attack releases from wherever it got to
+    let short = Adsr { gate: 1., ..env };
+    assert_eq!(short.eval(3.), 0.25);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Envelope<T> {
+    Poly(T),
+    Adsr(Adsr),
+}
+impl<T: Function> Function for Envelope<T> {
+    fn eval(&self, t: f32) -> f32 {
+        match self {
+            Envelope::Poly(poly) => poly.eval(t),
+            Envelope::Adsr(adsr) => adsr.eval(t),
+        }
+    }
+    fn eval_block(&self, t0: f32, dt: f32, out: &mut [f32]) {
+        match self {
+            Envelope::Poly(poly) => poly.eval_block(t0, dt, out),
+            Envelope::Adsr(adsr) => adsr.eval_block(t0, dt, out),
+        }
+    }
+}
+
+// anything that can fill the amp lane of a packed wave
+pub trait Amplitude: Function {
+    fn coefficients(&self) -> &[f32];
+    fn adsr(&self) -> Option<Adsr>;
+}
+impl<T: Borrow<[f32]>> Amplitude for T {
+    fn coefficients(&self) -> &[f32] {
+        self.borrow()
+    }
+    fn adsr(&self) -> Option<Adsr> {
+        None
+    }
+}
+impl<T: Borrow<[f32]>> Amplitude for Envelope<T> {
+    fn coefficients(&self) -> &[f32] {
+        match self {
+            Envelope::Poly(poly) => poly.borrow(),
+            Envelope::Adsr(_) => &[],
+        }
+    }
+    fn adsr(&self) -> Option<Adsr> {
+        match self {
+            Envelope::Poly(_) => None,
+            Envelope::Adsr(adsr) => Some(*adsr),
         }
     }
 }
 
+// how many coefficients belong to each wave in a packed lane
+pub type RunLength = u16;
+
 #[derive(Debug, Clone)]
-pub struct MultiPoly<'a> {
-    pub(crate) coeffs: &'a [f32],
-    pub(crate) run_lengths: Iter<'a, u8>,
+pub struct MultiPoly<'a, T = f32> {
+    pub(crate) coeffs: &'a [T],
+    pub(crate) run_lengths: Iter<'a, RunLength>,
 }
-impl<'a> MultiPoly<'a> {
-    pub fn new(coeffs: &'a [f32], run_lengths: &'a [u8]) -> Option<Self> {
-        (coeffs.len() == run_lengths.iter().cloned().map(usize::from).sum()).then_some(Self {
+impl<'a, T> MultiPoly<'a, T> {
+    pub fn new(coeffs: &'a [T], run_lengths: &'a [RunLength]) -> Option<Self> {
+        Self::try_new(coeffs, run_lengths, "run_lengths").ok()
+    }
+    // lane is only there to name the runs in the error
+    pub fn try_new(
+        coeffs: &'a [T],
+        run_lengths: &'a [RunLength],
+        lane: &'static str,
+    ) -> Result<Self, PackError> {
+        let runs = run_lengths.iter().cloned().map(usize::from).sum();
+        if coeffs.len() != runs {
+            return Err(PackError::RunSum {
+                lane,
+                coefficients: coeffs.len(),
+                runs,
+            });
+        }
+        Ok(Self {
             coeffs,
             run_lengths: run_lengths.iter(),
         })
     }
 }
-impl<'a> Iterator for MultiPoly<'a> {
-    type Item = &'a [f32];
+impl<'a, T> Iterator for MultiPoly<'a, T> {
+    type Item = &'a [T];
 
     fn next(&mut self) -> Option<Self::Item> {
         let len = *self.run_lengths.next()?;
@@ -116,3 +1819,202 @@ impl<'a> Iterator for MultiPoly<'a> {
         self.run_lengths.size_hint()
     }
 }
+
+// polynomials laid end to end, each piece starts at its breakpoint and runs in its own time from there
+// anything before the first breakpoint comes from the first piece
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Piecewise<T = Vec<f32>, R = Vec<RunLength>> {
+    breaks: T,
+    coeffs: T,
+    runs: R,
+}
+impl<T: Borrow<[f32]>, R: Borrow<[RunLength]>> Piecewise<T, R> {
+    // one run per breakpoint and the breakpoints have to go up
+    pub fn new(breaks: T, coeffs: T, runs: R) -> Option<Self> {
+        let (b, r) = (breaks.borrow(), runs.borrow());
+        if b.is_empty() || b.len() != r.len() || b.windows(2).any(|w| w[0] >= w[1]) {
+            return None;
+        }
+        MultiPoly::new(coeffs.borrow(), r)?;
+        Some(Piecewise {
+            breaks,
+            coeffs,
+            runs,
+        })
+    }
+    pub fn breaks(&self) -> &[f32] {
+        self.breaks.borrow()
+    }
+    pub fn pieces(&self) -> MultiPoly<'_> {
+        MultiPoly {
+            coeffs: self.coeffs.borrow(),
+            run_lengths: self.runs.borrow().iter(),
+        }
+    }
+    // which piece t falls in and where that piece starts
+    fn locate(&self, t: f32) -> (usize, f32) {
+        let breaks = self.breaks();
+        let index = breaks.partition_point(|&b| b <= t).saturating_sub(1);
+        (index, breaks[index])
+    }
+}
+impl<T: Borrow<[f32]>, R: Borrow<[RunLength]>> Function for Piecewise<T, R> {
+    fn eval(&self, t: f32) -> f32 {
+        let (index, from) = self.locate(t);
+        self.pieces()
+            .nth(index)
+            .map_or(0., |piece| piece.eval(t - from))
+    }
+    // whole pieces up to the one t is in, measured from the first breakpoint and then moved to 0
+    fn integral(&self, t: f32) -> f32 {
+        let from_first = |t: f32| {
+            let (index, from) = self.locate(t);
+            let whole: f32 = self
+                .pieces()
+                .zip(self.breaks().windows(2))
+                .take(index)
+                .map(|(piece, w)| piece.integral(w[1] - w[0]))
+                .sum();
+            whole
+                + self
+                    .pieces()
+                    .nth(index)
+                    .map_or(0., |p| p.integral(t - from))
+        };
+        from_first(t) - from_first(0.)
+    }
+}
+
+// one polynomial over another, for decays like 1 / (1 + t) that keep going where a polynomial turns around
+// an empty den is taken as 1
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rational<T = Vec<f32>> {
+    pub num: T,
+    pub den: T,
+}
+impl<S: Scalar, T: Borrow<[S]>> Function<S> for Rational<T> {
+    fn eval(&self, t: S) -> S {
+        let den = self.den.borrow();
+        let num = self.num.borrow().eval(t);
+        if den.is_empty() {
+            num
+        } else {
+            num / den.eval(t)
+        }
+    }
+}
+
+#[test]
+fn rational() {
+    let decay = Rational {
+        num: vec![1.],
+        den: vec![1., 0.5],
+    };
+    assert_eq!([0., 2., 6.].map(|t| decay.eval(t)), [1., 0.5, 0.25]);
+    // 2 ln(1 + t/2)
+    assert!((decay.integral(6.) - 2. * 4f32.ln()).abs() < 1e-4);
+    let poly = Rational {
+        num: vec![1f64, 2.],
+        den: vec![],
+    };
+    assert_eq!(poly.eval(3.), 7.);
+}
+
+// what the packer needs to cut a function into single polynomials at its breakpoints,
+// piece hands back the polynomial in force at from, shifted so its time starts there
+pub trait Pieces {
+    fn breaks(&self) -> &[f32];
+    fn piece(&self, from: f32) -> Vec<f32>;
+}
+impl<T: Borrow<[f32]>> Pieces for T {
+    fn breaks(&self) -> &[f32] {
+        &[]
+    }
+    fn piece(&self, from: f32) -> Vec<f32> {
+        crate::poly::compose(self.borrow(), &[from, 1.])
+    }
+}
+impl<T: Borrow<[f32]>, R: Borrow<[RunLength]>> Pieces for Piecewise<T, R> {
+    fn breaks(&self) -> &[f32] {
+        Piecewise::breaks(self)
+    }
+    fn piece(&self, from: f32) -> Vec<f32> {
+        let (index, start) = self.locate(from);
+        let piece = self.pieces().nth(index).unwrap_or_default();
+        crate::poly::compose(piece, &[from - start, 1.])
+    }
+}
+
+#[test]
+fn piecewise() {
+    // up to 1 over 10, flat until 50, then down over 20
+    let env = Piecewise::new(
+        vec![0., 10., 50.],
+        vec![0., 0.1, 1., 1., -0.05],
+        vec![2, 1, 2],
+    )
+    .unwrap();
+    let levels = [-5., 0., 5., 10., 30., 50., 60., 70.].map(|t| env.eval(t));
+    assert_eq!(levels, [-0.5, 0., 0.5, 1., 1., 1., 0.5, 0.]);
+    assert_eq!(env.integral(10.), 5.);
+    assert_eq!(env.integral(70.), 5. + 40. + 10.);
+    assert_eq!(env.piece(60.), [0.5, -0.05]);
+    assert_eq!([1., 2.].piece(3.), [7., 2.]);
+
+    assert!(Piecewise::new(vec![0., 0.], vec![1., 1.], vec![1, 1]).is_none());
+    assert!(Piecewise::new(vec![0.], vec![1., 1.], vec![1]).is_none());
+    assert!(Piecewise::<Vec<f32>>::new(vec![], vec![], vec![]).is_none());
+}
+
+#[test]
+fn shapes() {
+    let quarters = [0., 0.25, 0.5, 0.75];
+    let sample = |shape: Waveform, width: f32| quarters.map(|c| shape.at(c, width));
+    assert_eq!(sample(Waveform::Square, 0.5), [1., 1., -1., -1.]);
+    assert_eq!(sample(Waveform::Triangle, 0.5), [0., 1., 0., -1.]);
+    assert_eq!(sample(Waveform::Saw, 0.5), [0., 0.5, -1., -0.5]);
+    assert_eq!(sample(Waveform::Pulse, 0.25), [1., -1., -1., -1.]);
+
+    let pulse = Wave {
+        freq: &[0.25][..],
+        amp: &[0.5][..],
+        phase: 0.0,
+        mode: PhaseMode::Product,
+        shape: Waveform::Pulse,
+        width: &[0.1, 0.2][..],
+    };
+    // width grows from 0.1 to 0.7 so the high part of the cycle gets longer
+    assert_eq!(pulse.eval(0.), 0.5);
+    assert_eq!(pulse.eval(1.), 0.5);
+    assert_eq!(pulse.eval(2.), -0.5);
+    assert_eq!(pulse.eval(3.), -0.5);
+    assert_eq!(pulse.eval(5.), 0.5);
+}
+
+#[test]
+fn bandlimiting() {
+    let saw = Wave {
+        freq: &[0.01][..],
+        amp: &[1.][..],
+        phase: 0.0,
+        mode: PhaseMode::Integral,
+        shape: Waveform::Saw,
+        width: &[][..],
+    };
+    // far from the jump nothing changes
+    assert_eq!(saw.eval_bandlimited(20., 1.), saw.eval(20.));
+    // right on the jump it lands halfway
+    assert!(saw.eval_bandlimited(50., 1.).abs() < 1e-5);
+    let square = Wave {
+        shape: Waveform::Square,
+        ..saw.clone()
+    };
+    assert!(square.eval_bandlimited(50., 1.).abs() < 1e-5);
+    let too_high = Wave {
+        freq: &[0.6][..],
+        ..saw
+    };
+    assert_eq!(too_high.eval_bandlimited(3., 1.), 0.);
+}
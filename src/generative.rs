@@ -0,0 +1,253 @@
+// notes left to chance, every step of a grid might play with some probability, picking its pitch
+// and velocity at random or walking a markov chain of notes
+// all the randomness comes from hashing the seed and the step counted from the region's start, so
+// the same seed and region always write the same music
+use alloc::vec::Vec;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{func::noise, instrument::Instrument, timeline::Timeline, PackError, TimedWavePacker};
+
+// what each row of randomness is for, so they don't follow each other
+const TRIGGER: u32 = 0;
+const PITCH: u32 = 1;
+const VELOCITY: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Markov {
+    notes: Vec<f32>,
+    // weights[from][to], a row doesn't need to add up to anything
+    weights: Vec<Vec<f32>>,
+    start: usize,
+}
+impl Markov {
+    // a weight for every pair of notes, none negative, and start one of the notes
+    pub fn new(notes: Vec<f32>, weights: Vec<Vec<f32>>, start: usize) -> Option<Self> {
+        let square = weights.len() == notes.len() && weights.iter().all(|r| r.len() == notes.len());
+        let positive = weights.iter().flatten().all(|&w| w >= 0. && w.is_finite());
+        (square && positive && start < notes.len()).then_some(Markov {
+            notes,
+            weights,
+            start,
+        })
+    }
+    pub fn notes(&self) -> &[f32] {
+        &self.notes
+    }
+    // the note after from for a random number from 0 to 1, a row of all 0 stays where it is
+    fn next(&self, from: usize, random: f32) -> usize {
+        let row = &self.weights[from];
+        let total: f32 = row.iter().sum();
+        if total <= 0. {
+            return from;
+        }
+        let mut left = random * total;
+        for (to, &weight) in row.iter().enumerate() {
+            if left < weight {
+                return to;
+            }
+            left -= weight;
+        }
+        row.iter().rposition(|&w| w > 0.).unwrap_or(from)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Pitches {
+    // any whole note from the first to the second
+    Range(f32, f32),
+    // each note played moves the chain on one step, the first one played is its start
+    Markov(Markov),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Generator {
+    pub pitches: Pitches,
+    // the chance each step plays, from 0 to 1
+    pub probability: f32,
+    // anywhere from the first to the second
+    pub velocity: (f32, f32),
+    // beats per step and how long each note is held in beats
+    pub step: f64,
+    pub length: f64,
+    pub seed: u32,
+}
+impl Generator {
+    pub fn new(pitches: Pitches, step: f64) -> Self {
+        Generator {
+            pitches,
+            probability: 1.,
+            velocity: (0.8, 0.8),
+            step,
+            length: step,
+            seed: 0,
+        }
+    }
+    fn random(&self, step: usize, row: u32) -> f32 {
+        (noise(step as i64 + ((self.seed as i64) << 32), row) + 1.) / 2.
+    }
+    // (beat, pitch, velocity) for every step that plays from from up to to
+    pub fn events(&self, (from, to): (f64, f64)) -> Vec<(f64, f32, f32)> {
+        if self.step <= 0. {
+            return Vec::new();
+        }
+        let count = ((to - from) / self.step).ceil().max(0.) as usize;
+        let mut state = None;
+        (0..count)
+            .filter(|&step| self.random(step, TRIGGER) < self.probability)
+            .map(|step| {
+                let random = self.random(step, PITCH);
+                let pitch = match &self.pitches {
+                    Pitches::Range(low, high) => {
+                        let (low, high) = (low.min(*high).ceil(), low.max(*high).floor());
+                        (low + (random * (high - low + 1.)).floor()).min(high)
+                    }
+                    Pitches::Markov(chain) => {
+                        let next = match state {
+                            None => chain.start,
+                            Some(at) => chain.next(at, random),
+                        };
+                        state = Some(next);
+                        chain.notes[next]
+                    }
+                };
+                let (quiet, loud) = self.velocity;
+                let velocity = quiet + (loud - quiet) * self.random(step, VELOCITY);
+                (from + step as f64 * self.step, pitch, velocity)
+            })
+            .collect()
+    }
+}
+
+impl TimedWavePacker {
+    // on the end like try_extend, so normalize after if anything later is already in
+    pub fn add_generated(
+        &mut self,
+        timeline: &Timeline,
+        instrument: &Instrument,
+        generator: &Generator,
+        region: (f64, f64),
+    ) -> Result<(), PackError> {
+        for (beat, pitch, velocity) in generator.events(region) {
+            self.add_note_at(
+                timeline,
+                instrument,
+                pitch,
+                (beat, generator.length),
+                velocity,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn generated() {
+    use alloc::vec;
+
+    let sparse = Generator {
+        probability: 0.25,
+        velocity: (0.2, 0.6),
+        ..Generator::new(Pitches::Range(60., 64.), 0.25)
+    };
+    let events = sparse.events((0., 256.));
+    // about a quarter of the 1024 steps
+    assert!((events.len() as f32 - 256.).abs() < 48., "{}", events.len());
+    for &(beat, pitch, velocity) in &events {
+        assert_eq!(beat % 0.25, 0.);
+        assert!([60., 61., 62., 63., 64.].contains(&pitch));
+        assert!((0.2..=0.6).contains(&velocity));
+    }
+    assert!([60., 64.].iter().all(|p| events.iter().any(|e| e.1 == *p)));
+    assert_eq!(events, sparse.events((0., 256.)));
+    let reseeded = Generator {
+        seed: 1,
+        ..sparse.clone()
+    };
+    assert_ne!(events, reseeded.events((0., 256.)));
+
+    // c only ever goes to e, e to g or back to c, g always back to c
+    let chain = Markov::new(
+        vec![60., 64., 67.],
+        vec![vec![0., 1., 0.], vec![1., 0., 1.], vec![1., 0., 0.]],
+        0,
+    )
+    .unwrap();
+    let walk = Generator::new(Pitches::Markov(chain), 0.5).events((0., 64.));
+    assert_eq!(walk.len(), 128);
+    assert_eq!(walk[0].1, 60.);
+    for pair in walk.windows(2) {
+        let allowed: &[f32] = match pair[0].1 {
+            60. => &[64.],
+            64. => &[60., 67.],
+            _ => &[60.],
+        };
+        assert!(allowed.contains(&pair[1].1));
+    }
+    assert!(walk.iter().any(|e| e.1 == 67.));
+    assert!(Markov::new(vec![60.], vec![vec![1., 0.]], 0).is_none());
+    assert!(Markov::new(vec![60.], vec![vec![-1.]], 0).is_none());
+    assert!(Markov::new(vec![60.], vec![vec![1.]], 1).is_none());
+
+    let timeline = Timeline::new(120., 48000.);
+    let mut packer = TimedWavePacker::new();
+    packer
+        .add_generated(&timeline, &Instrument::new(48000.), &sparse, (0., 16.))
+        .unwrap();
+    assert_eq!(packer.timings.len(), sparse.events((0., 16.)).len());
+    assert!(packer.get_pack().is_some());
+}
+
+#[test]
+fn generated_odds() {
+    use alloc::vec;
+
+    // a step moves on three times as often as it stays put
+    let chain = Markov::new(vec![60., 67.], vec![vec![1., 3.], vec![3., 1.]], 1).unwrap();
+    let walk = Generator::new(Pitches::Markov(chain), 0.25).events((0., 1000.));
+    assert_eq!(walk.len(), 4000);
+    assert_eq!(walk[0].1, 67.);
+    let moved = walk
+        .windows(2)
+        .filter(|pair| pair[0].1 != pair[1].1)
+        .count();
+    let moved = moved as f32 / (walk.len() - 1) as f32;
+    assert!((moved - 0.75).abs() < 0.03, "{}", moved);
+    // a note with nowhere to go stays where it is
+    let stuck = Markov::new(vec![60., 67.], vec![vec![0., 1.], vec![0., 0.]], 0).unwrap();
+    let walk = Generator::new(Pitches::Markov(stuck), 1.).events((0., 8.));
+    let pitches: Vec<f32> = walk.iter().map(|e| e.1).collect();
+    assert_eq!(pitches, [60., 67., 67., 67., 67., 67., 67., 67.]);
+
+    // never and always, at a fixed velocity
+    let odds = |probability| Generator {
+        probability,
+        velocity: (0.5, 0.5),
+        ..Generator::new(Pitches::Range(60.5, 62.5), 0.5)
+    };
+    assert!(odds(0.).events((0., 64.)).is_empty());
+    let every = odds(1.).events((2., 66.));
+    assert_eq!(every.len(), 128);
+    for (i, &(beat, pitch, velocity)) in every.iter().enumerate() {
+        assert_eq!(beat, 2. + 0.5 * i as f64);
+        // only the whole notes inside the range
+        assert!([61., 62.].contains(&pitch));
+        assert_eq!(velocity, 0.5);
+    }
+    // what plays only depends on the step from the region's start, not where the region is
+    assert_eq!(
+        odds(0.5)
+            .events((0., 8.))
+            .iter()
+            .map(|e| e.1)
+            .collect::<Vec<_>>(),
+        odds(0.5)
+            .events((4., 12.))
+            .iter()
+            .map(|e| e.1)
+            .collect::<Vec<_>>()
+    );
+}
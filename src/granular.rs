@@ -0,0 +1,217 @@
+// granular synthesis, a source cut into short overlapping grains that get packed as clips of their own
+// every control is a function of time since the cloud started, so a texture can drift through a
+// recording, thin out or pitch down as it goes
+// each grain keeps a copy of the bit of source it plays, so dense clouds of long grains pack big
+use alloc::{vec, vec::Vec};
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{
+    func::{noise, Function, Interpolation, PhaseMode, Wave, Waveform, Wavetable},
+    Fade, FadeCurve, Mix, Modulation, PackError, TimedWave, TimedWavePacker,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Grains<F = Vec<f32>> {
+    // grain length in samples, each one fades in over its first half and out over the rest
+    pub size: F,
+    // grains started per sample
+    pub density: F,
+    // how fast a grain plays its bit of source, 1 is as recorded
+    pub pitch: F,
+    // where in the source a grain starts reading, in samples
+    pub position: F,
+    pub gain: F,
+    // the most a grain's position gets moved either way at random, in samples
+    pub scatter: f32,
+    pub seed: u32,
+    pub interpolation: Interpolation,
+}
+impl Grains {
+    // at the source's own pitch and full gain, without scatter
+    pub fn new(size: Vec<f32>, density: Vec<f32>, position: Vec<f32>) -> Self {
+        Grains {
+            size,
+            density,
+            pitch: vec![1.],
+            position,
+            gain: vec![1.],
+            scatter: 0.,
+            seed: 0,
+            interpolation: Interpolation::Linear,
+        }
+    }
+}
+
+impl TimedWavePacker {
+    // grains starting from start until end go on the end of the pack, if it already has waves
+    // starting later than start it needs a normalize after
+    // a grain reading past the end of the source goes quiet there, one whose bit of source is too
+    // long for a run stops things with an error, leaving the grains before it packed
+    pub fn add_grains<F: Function>(
+        &mut self,
+        source: &[f32],
+        grains: &Grains<F>,
+        start: i64,
+        end: i64,
+    ) -> Result<(), PackError> {
+        let mut at = 0.;
+        for index in 0.. {
+            let time = start + at as i64;
+            if time >= end {
+                break;
+            }
+            let length = grains.size.eval(at).round().max(2.) as i64;
+            let pitch = grains.pitch.eval(at).max(0.);
+            let gain = grains.gain.eval(at);
+            let scatter = grains.scatter * noise(index + ((grains.seed as i64) << 32), 0);
+            let from = (grains.position.eval(at) + scatter).round().max(0.) as usize;
+            // one past the last sample read, for the interpolation
+            let reads = (length as f32 * pitch).ceil() as usize + 2;
+            let slice = source.get(from..(from + reads).min(source.len()));
+            let clip = slice.and_then(|s| Wavetable::clip(s, grains.interpolation));
+            if let Some(table) = clip.filter(|_| gain != 0.) {
+                let half = length / 2;
+                self.try_extend([TimedWave {
+                    start: time,
+                    end: time + half,
+                    wave: Wave {
+                        freq: vec![pitch / table.len() as f32],
+                        amp: vec![gain],
+                        phase: 0.,
+                        mode: PhaseMode::Product,
+                        shape: Waveform::Sine,
+                        width: vec![],
+                    },
+                    fade: Fade {
+                        fade_in: half,
+                        release: length - half,
+                        curve: FadeCurve::Smooth,
+                    },
+                    mix: Mix::default(),
+                    modulation: Modulation::default(),
+                    table,
+                }])?;
+            }
+            // nothing starts while the density is 0, it gets looked at again a grain later
+            let density = grains.density.eval(at);
+            at += if density > 0. {
+                1. / density
+            } else {
+                length as f32
+            };
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn granular() {
+    use crate::{Clipping, Player};
+
+    let source: Vec<f32> = (0..3000)
+        .map(|i| Waveform::Sine.at(i as f32 * 0.01, 0.5))
+        .collect();
+    // grains half overlapping and moving through the source in step with time, so the fades
+    // add back up to the source
+    let cloud = Grains::new(vec![100.], vec![0.02], vec![0., 1.]);
+    let mut packer = TimedWavePacker::new();
+    packer.add_grains(&source, &cloud, 0, 2000).unwrap();
+    assert_eq!(packer.timings.len(), 40);
+    let mut out = vec![0f32; 2000];
+    let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(2100));
+    player.set_clipping(Clipping::Off);
+    player.play(&mut out).unwrap();
+    for i in 100..1900 {
+        assert!(
+            (out[i] - source[i]).abs() < 0.05,
+            "{} {} {}",
+            i,
+            out[i],
+            source[i]
+        );
+    }
+
+    // an octave up, frozen in place with a little scatter, grains keep to their place in time
+    let frozen = Grains {
+        pitch: vec![2.],
+        scatter: 50.,
+        seed: 7,
+        ..Grains::new(vec![200.], vec![0.01, 0.0001], vec![1000.])
+    };
+    let mut packer = TimedWavePacker::new();
+    packer.add_grains(&source, &frozen, 500, 1500).unwrap();
+    assert!(packer.timings.windows(2).all(|w| w[0][0] < w[1][0]));
+    assert!(packer
+        .timings
+        .iter()
+        .all(|&[s, _]| (500..1500).contains(&s)));
+    assert!(packer.table_runs.iter().all(|&run| run == 402));
+    let mut again = TimedWavePacker::new();
+    again.add_grains(&source, &frozen, 500, 1500).unwrap();
+    assert_eq!(packer, again);
+}
+
+#[test]
+fn grain_controls() {
+    let source = vec![0.5f32; 1000];
+    let cloud = |grains: &Grains, end| {
+        let mut packer = TimedWavePacker::new();
+        packer.add_grains(&source, grains, 100, end).unwrap();
+        packer
+    };
+    // a grain every 100 samples, each 10 longer than the last, half of it fading in
+    let growing = cloud(&Grains::new(vec![50., 0.1], vec![0.01], vec![0.]), 600);
+    let starts = [100, 200, 300, 400, 500];
+    let lengths = [50, 60, 70, 80, 90];
+    for ((&[start, end], fade), (s, length)) in growing
+        .timings
+        .iter()
+        .zip(&growing.fades)
+        .zip(starts.into_iter().zip(lengths))
+    {
+        assert_eq!((start, end), (s, s + length / 2));
+        assert_eq!(
+            (fade.fade_in, fade.release),
+            (length / 2, length - length / 2)
+        );
+    }
+    assert_eq!(growing.timings.len(), 5);
+    // denser as it goes, so the gaps between grains shrink
+    let thickening = cloud(&Grains::new(vec![50.], vec![0.01, 0.0001], vec![0.]), 600);
+    let gaps: Vec<i64> = thickening
+        .timings
+        .windows(2)
+        .map(|w| w[1][0] - w[0][0])
+        .collect();
+    assert_eq!(gaps[..2], [100, 50]);
+    assert!(gaps.windows(2).all(|w| w[1] <= w[0]), "{:?}", gaps);
+    // no density waits a grain's length before looking again
+    let idle = cloud(&Grains::new(vec![100.], vec![0.], vec![0.]), 400);
+    assert_eq!(
+        idle.timings.iter().map(|t| t[0]).collect::<Vec<_>>(),
+        [100, 200, 300]
+    );
+
+    // half speed reads half as much source for a grain, and plays it over the whole grain
+    let low = cloud(
+        &Grains {
+            pitch: vec![0.5],
+            ..Grains::new(vec![100.], vec![0.01], vec![0.])
+        },
+        200,
+    );
+    assert_eq!(low.table_runs, [52]);
+    assert_eq!(low.freq_coef, [0.5 / 52.]);
+
+    // silent grains aren't packed at all
+    let silent = Grains {
+        gain: vec![0.],
+        ..Grains::new(vec![100.], vec![0.01], vec![0.])
+    };
+    assert!(cloud(&silent, 600).timings.is_empty());
+    // near the end of the source a grain gets what's left, past it there's nothing to play
+    let tail = cloud(&Grains::new(vec![100.], vec![0.01], vec![960., 0.5]), 400);
+    assert_eq!(tail.table_runs, [40]);
+}
@@ -0,0 +1,366 @@
+// players, effects and buses wired together and rendered a block at a time, so effect chains,
+// sends and submixes are a matter of connecting nodes instead of passing buffers around by hand
+// everything in the graph is stereo, every connection has its own gain and a node hears the sum
+// of whatever is connected into it
+// nodes get rendered in an order where everything feeding a node comes before it, all of them
+// every block, so players nobody is listening to still keep time
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{
+    error::Error,
+    fmt::{self, Display},
+    mem::take,
+};
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{Player, MIX_BLOCK};
+
+// anything that works on a block in place, time is the graph's time at the first frame
+pub trait Effect {
+    fn process(&mut self, time: i64, frames: &mut [[f32; 2]]);
+}
+impl<F: FnMut(i64, &mut [[f32; 2]])> Effect for F {
+    fn process(&mut self, time: i64, frames: &mut [[f32; 2]]) {
+        self(time, frames)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+enum Node<'a> {
+    // feeds silence into its edges once it's stopped, see Player::play
+    Source(Box<Player<'a>>),
+    Effect(Box<dyn Effect + 'a>),
+    // sums its inputs and nothing else, for submixes and send returns
+    Bus,
+    // a bus that gets turned down while its key node is loud
+    Duck(Ducker),
+}
+
+// sidechain ducking, follows how loud the key node is and turns the inputs down by depth times that,
+// so at full scale on the key a depth of 1 goes silent
+#[derive(Debug, Clone)]
+pub struct Ducker {
+    pub depth: f32,
+    // roughly how many samples it takes to duck and to come back up
+    pub attack: f32,
+    pub release: f32,
+    key: usize,
+    envelope: f32,
+}
+impl Ducker {
+    pub fn new(depth: f32, attack: f32, release: f32) -> Self {
+        Ducker {
+            depth,
+            attack,
+            release,
+            key: 0,
+            envelope: 0.,
+        }
+    }
+    fn run(&mut self, key: &[[f32; 2]], frames: &mut [[f32; 2]]) {
+        let attack = (-1. / self.attack).exp();
+        let release = (-1. / self.release).exp();
+        for (frame, key) in frames.iter_mut().zip(key) {
+            let peak = key[0].abs().max(key[1].abs());
+            let smoothing = if peak > self.envelope {
+                attack
+            } else {
+                release
+            };
+            self.envelope = peak + (self.envelope - peak) * smoothing;
+            let gain = (1. - self.depth * self.envelope.min(1.)).max(0.);
+            frame.iter_mut().for_each(|x| *x *= gain);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+    NoSuchNode(NodeId),
+    // players make their own sound, nothing can be connected into one
+    IntoSource(NodeId),
+    // the connection would feed a node back into itself
+    Cycle { from: NodeId, to: NodeId },
+}
+impl Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::NoSuchNode(id) => write!(f, "there's no node {}", id.0),
+            GraphError::IntoSource(id) => {
+                write!(f, "node {} is a player and can't take inputs", id.0)
+            }
+            GraphError::Cycle { from, to } => {
+                write!(f, "connecting {} to {} would make a loop", from.0, to.0)
+            }
+        }
+    }
+}
+impl Error for GraphError {}
+
+#[derive(Default)]
+pub struct Graph<'a> {
+    nodes: Vec<Node<'a>>,
+    // (from, to, gain)
+    edges: Vec<(usize, usize, f32)>,
+    order: Vec<usize>,
+    buffers: Vec<Vec<[f32; 2]>>,
+    output: Option<usize>,
+    time: i64,
+}
+impl<'a> Graph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn add(&mut self, node: Node<'a>) -> NodeId {
+        self.nodes.push(node);
+        self.buffers.push(vec![[0.; 2]; MIX_BLOCK]);
+        self.order.push(self.nodes.len() - 1);
+        NodeId(self.nodes.len() - 1)
+    }
+    pub fn add_source(&mut self, player: Player<'a>) -> NodeId {
+        self.add(Node::Source(Box::new(player)))
+    }
+    pub fn add_effect(&mut self, effect: impl Effect + 'a) -> NodeId {
+        self.add(Node::Effect(Box::new(effect)))
+    }
+    pub fn add_bus(&mut self) -> NodeId {
+        self.add(Node::Bus)
+    }
+    // a bus ducked by key, which gets rendered first without needing a connection
+    pub fn add_ducker(&mut self, key: NodeId, mut ducker: Ducker) -> Result<NodeId, GraphError> {
+        if key.0 >= self.nodes.len() {
+            return Err(GraphError::NoSuchNode(key));
+        }
+        ducker.key = key.0;
+        let id = self.add(Node::Duck(ducker));
+        self.sort();
+        Ok(id)
+    }
+    pub fn ducker_mut(&mut self, id: NodeId) -> Option<&mut Ducker> {
+        match self.nodes.get_mut(id.0)? {
+            Node::Duck(ducker) => Some(ducker),
+            _ => None,
+        }
+    }
+    pub fn player_mut(&mut self, id: NodeId) -> Option<&mut Player<'a>> {
+        match self.nodes.get_mut(id.0)? {
+            Node::Source(player) => Some(player),
+            _ => None,
+        }
+    }
+    // connecting the same pair again just changes the gain
+    pub fn connect(&mut self, from: NodeId, to: NodeId, gain: f32) -> Result<(), GraphError> {
+        for id in [from, to] {
+            if id.0 >= self.nodes.len() {
+                return Err(GraphError::NoSuchNode(id));
+            }
+        }
+        if let Node::Source(_) = self.nodes[to.0] {
+            return Err(GraphError::IntoSource(to));
+        }
+        if let Some(edge) = self
+            .edges
+            .iter_mut()
+            .find(|(f, t, _)| (*f, *t) == (from.0, to.0))
+        {
+            edge.2 = gain;
+            return Ok(());
+        }
+        self.edges.push((from.0, to.0, gain));
+        if !self.sort() {
+            self.edges.pop();
+            self.sort();
+            return Err(GraphError::Cycle { from, to });
+        }
+        Ok(())
+    }
+    pub fn disconnect(&mut self, from: NodeId, to: NodeId) {
+        self.edges.retain(|&(f, t, _)| (f, t) != (from.0, to.0));
+        self.sort();
+    }
+    // the node that ends up in the output buffer, silence until there is one
+    pub fn set_output(&mut self, id: NodeId) -> Result<(), GraphError> {
+        if id.0 >= self.nodes.len() {
+            return Err(GraphError::NoSuchNode(id));
+        }
+        self.output = Some(id.0);
+        Ok(())
+    }
+    pub fn current_time(&self) -> i64 {
+        self.time
+    }
+    // kahn's algorithm, false when there's a loop and some nodes can never go
+    fn sort(&mut self) -> bool {
+        // duckers wait on their keys as well as their inputs
+        let keys = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(n, node)| match node {
+                Node::Duck(ducker) => Some((ducker.key, n)),
+                _ => None,
+            });
+        let dependencies: Vec<(usize, usize)> = self
+            .edges
+            .iter()
+            .map(|&(from, to, _)| (from, to))
+            .chain(keys)
+            .collect();
+        let mut waiting = vec![0usize; self.nodes.len()];
+        for &(_, to) in &dependencies {
+            waiting[to] += 1;
+        }
+        let mut order: Vec<usize> = (0..self.nodes.len()).filter(|&n| waiting[n] == 0).collect();
+        let mut next = 0;
+        while let Some(&node) = order.get(next) {
+            next += 1;
+            for &(from, to) in &dependencies {
+                if from == node {
+                    waiting[to] -= 1;
+                    if waiting[to] == 0 {
+                        order.push(to);
+                    }
+                }
+            }
+        }
+        let complete = order.len() == self.nodes.len();
+        if complete {
+            self.order = order;
+        }
+        complete
+    }
+    pub fn play_stereo(&mut self, output: &mut [[f32; 2]]) {
+        for chunk in output.chunks_mut(MIX_BLOCK) {
+            for i in 0..self.order.len() {
+                let node = self.order[i];
+                let mut buffer = take(&mut self.buffers[node]);
+                let block = &mut buffer[..chunk.len()];
+                block.fill([0.; 2]);
+                for &(from, _, gain) in self.edges.iter().filter(|(_, to, _)| *to == node) {
+                    for (out, input) in block.iter_mut().zip(&self.buffers[from]) {
+                        out[0] += input[0] * gain;
+                        out[1] += input[1] * gain;
+                    }
+                }
+                match &mut self.nodes[node] {
+                    Node::Source(player) => {
+                        let _ = player.play_stereo(block);
+                    }
+                    Node::Effect(effect) => effect.process(self.time, block),
+                    Node::Bus => {}
+                    Node::Duck(ducker) => {
+                        let key = &self.buffers[ducker.key][..chunk.len()];
+                        ducker.run(key, block);
+                    }
+                }
+                self.buffers[node] = buffer;
+            }
+            match self.output {
+                Some(node) => chunk.copy_from_slice(&self.buffers[node][..chunk.len()]),
+                None => chunk.fill([0.; 2]),
+            }
+            self.time += chunk.len() as i64;
+        }
+    }
+}
+
+#[test]
+fn wiring() {
+    use crate::{LiveWave, Mix, TimedWave, TimedWavePacker};
+
+    // a source holding amp on the left side only, so each edge's gain shows up as it is
+    let from = |start: i64, amp: f32| -> TimedWavePacker {
+        [TimedWave {
+            mix: Mix {
+                gain: vec![],
+                pan: vec![-1.],
+                id: 0,
+            },
+            ..LiveWave::sine(start, 3000, 1., amp)
+        }]
+        .into_iter()
+        .collect()
+    };
+    let level = |amp: f32| from(0, amp);
+    let (drums, pads) = (level(0.5), level(0.25));
+    let mut graph = Graph::new();
+    let drums = graph.add_source(Player::new(drums.get_pack().unwrap(), 0, Some(1000)));
+    let pads = graph.add_source(Player::new(pads.get_pack().unwrap(), 0, Some(1000)));
+    // an insert on the drums that swaps the sides, a send from both into a quiet "reverb"
+    let swap =
+        graph.add_effect(|_, frames: &mut [[f32; 2]]| frames.iter_mut().for_each(|f| f.reverse()));
+    let reverb = graph.add_effect(|_, frames: &mut [[f32; 2]]| {
+        frames.iter_mut().flatten().for_each(|s| *s *= 0.1)
+    });
+    let master = graph.add_bus();
+    // added out of order so the sort has something to do
+    graph.connect(reverb, master, 1.).unwrap();
+    graph.connect(swap, master, 1.).unwrap();
+    graph.connect(drums, swap, 1.).unwrap();
+    graph.connect(pads, master, 0.5).unwrap();
+    graph.connect(drums, reverb, 1.).unwrap();
+    graph.connect(pads, reverb, 1.).unwrap();
+
+    let mut out = [[1.; 2]; 300];
+    graph.play_stereo(&mut out);
+    assert!(out.iter().all(|&s| s == [0.; 2]));
+    graph.set_output(master).unwrap();
+    graph.play_stereo(&mut out);
+    let [left, right] = out[0];
+    assert!((left - (0.125 + 0.075)).abs() < 1e-6);
+    assert!((right - 0.5).abs() < 1e-6);
+    assert_eq!(graph.current_time(), 600);
+    assert_eq!(graph.player_mut(drums).unwrap().current_time(), 600);
+
+    assert_eq!(
+        graph.connect(master, reverb, 1.),
+        Err(GraphError::Cycle {
+            from: master,
+            to: reverb
+        })
+    );
+    assert_eq!(
+        graph.connect(swap, swap, 1.),
+        Err(GraphError::Cycle {
+            from: swap,
+            to: swap
+        })
+    );
+    assert_eq!(
+        graph.connect(master, pads, 1.),
+        Err(GraphError::IntoSource(pads))
+    );
+    // the failed connections left things as they were
+    graph.disconnect(pads, master);
+    graph.play_stereo(&mut out[..1]);
+    assert!((out[0][0] - 0.075).abs() < 1e-6);
+
+    // music ducking under sfx that come in halfway through
+    let (music, sfx) = (level(0.5), from(1000, 1.));
+    let mut graph = Graph::new();
+    let music = graph.add_source(Player::new(music.get_pack().unwrap(), 0, Some(3000)));
+    let sfx = graph.add_source(Player::new(sfx.get_pack().unwrap(), 0, Some(3000)));
+    let ducked = graph.add_ducker(sfx, Ducker::new(0.5, 10., 1000.)).unwrap();
+    graph.connect(music, ducked, 1.).unwrap();
+    graph.set_output(ducked).unwrap();
+    let mut out = vec![[0.; 2]; 2000];
+    graph.play_stereo(&mut out);
+    assert!((out[999][0] - 0.5).abs() < 1e-6);
+    assert!((out[1999][0] - 0.25).abs() < 1e-3);
+    assert!(graph.ducker_mut(ducked).is_some());
+    // the key counts as an input when looking for loops
+    let keyed = graph.add_ducker(ducked, Ducker::new(1., 1., 1.)).unwrap();
+    assert_eq!(
+        graph.connect(keyed, ducked, 1.),
+        Err(GraphError::Cycle {
+            from: keyed,
+            to: ducked
+        })
+    );
+    assert_eq!(
+        graph.add_ducker(NodeId(9), Ducker::new(1., 1., 1.)),
+        Err(GraphError::NoSuchNode(NodeId(9)))
+    );
+}
@@ -0,0 +1,271 @@
+// chords and keys in midi note numbers, so generated parts can be kept in key and their
+// output fed straight to the note, chord and arpeggio helpers
+// intervals are in semitones up from the root
+use alloc::vec::Vec;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quality {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Major7,
+    Minor7,
+    Dominant7,
+    HalfDiminished7,
+    Diminished7,
+}
+impl Quality {
+    pub fn intervals(&self) -> &'static [f32] {
+        match self {
+            Quality::Major => &[0., 4., 7.],
+            Quality::Minor => &[0., 3., 7.],
+            Quality::Diminished => &[0., 3., 6.],
+            Quality::Augmented => &[0., 4., 8.],
+            Quality::Sus2 => &[0., 2., 7.],
+            Quality::Sus4 => &[0., 5., 7.],
+            Quality::Major7 => &[0., 4., 7., 11.],
+            Quality::Minor7 => &[0., 3., 7., 10.],
+            Quality::Dominant7 => &[0., 4., 7., 10.],
+            Quality::HalfDiminished7 => &[0., 3., 6., 10.],
+            Quality::Diminished7 => &[0., 3., 6., 9.],
+        }
+    }
+}
+
+pub fn chord(root: f32, quality: Quality) -> Vec<f32> {
+    quality.intervals().iter().map(|i| root + i).collect()
+}
+// the lowest notes moved up an octave inversion times, 1 puts the third in the bass
+pub fn invert(chord: &[f32], inversion: usize) -> Vec<f32> {
+    let mut chord = chord.to_vec();
+    for _ in 0..inversion.min(chord.len()) {
+        let lowest = chord.remove(0);
+        chord.push(lowest + 12.);
+    }
+    chord
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
+    Major,
+    Minor,
+    HarmonicMinor,
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+    Chromatic,
+}
+impl Mode {
+    // one octave's worth starting from 0, the octave itself left out
+    pub fn intervals(&self) -> &'static [f32] {
+        match self {
+            Mode::Major => &[0., 2., 4., 5., 7., 9., 11.],
+            Mode::Minor => &[0., 2., 3., 5., 7., 8., 10.],
+            Mode::HarmonicMinor => &[0., 2., 3., 5., 7., 8., 11.],
+            Mode::MelodicMinor => &[0., 2., 3., 5., 7., 9., 11.],
+            Mode::Dorian => &[0., 2., 3., 5., 7., 9., 10.],
+            Mode::Phrygian => &[0., 1., 3., 5., 7., 8., 10.],
+            Mode::Lydian => &[0., 2., 4., 6., 7., 9., 11.],
+            Mode::Mixolydian => &[0., 2., 4., 5., 7., 9., 10.],
+            Mode::Locrian => &[0., 1., 3., 5., 6., 8., 10.],
+            Mode::MajorPentatonic => &[0., 2., 4., 7., 9.],
+            Mode::MinorPentatonic => &[0., 3., 5., 7., 10.],
+            Mode::Blues => &[0., 3., 5., 6., 7., 10.],
+            Mode::Chromatic => &[0., 1., 2., 3., 4., 5., 6., 7., 8., 9., 10., 11.],
+        }
+    }
+}
+
+// a mode on a root, degrees count from 0 at the root and carry on into the octaves above and below
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Key {
+    pub root: f32,
+    pub mode: Mode,
+}
+impl Key {
+    pub fn new(root: f32, mode: Mode) -> Self {
+        Key { root, mode }
+    }
+    pub fn degree(&self, degree: i32) -> f32 {
+        let intervals = self.mode.intervals();
+        let size = intervals.len() as i32;
+        let octave = degree.div_euclid(size) as f32;
+        self.root + 12. * octave + intervals[degree.rem_euclid(size) as usize]
+    }
+    // the closest note in the key, the lower one when it's right between two
+    pub fn quantize(&self, pitch: f32) -> f32 {
+        let above = pitch - self.root;
+        let octave = (above / 12.).floor();
+        let within = above - 12. * octave;
+        let intervals = self.mode.intervals().iter().chain(&[12.]);
+        let closest = intervals.fold(0f32, |best, &i| {
+            if (i - within).abs() < (best - within).abs() {
+                i
+            } else {
+                best
+            }
+        });
+        self.root + 12. * octave + closest
+    }
+    pub fn contains(&self, pitch: f32) -> bool {
+        self.quantize(pitch) == pitch
+    }
+    // notes notes stacked in thirds from a degree, so 3 is the key's own triad there and 4 its
+    // seventh chord
+    pub fn chord(&self, degree: i32, notes: usize) -> Vec<f32> {
+        (0..notes as i32)
+            .map(|n| self.degree(degree + 2 * n))
+            .collect()
+    }
+}
+
+#[test]
+fn harmony() {
+    assert_eq!(chord(60., Quality::Major), [60., 64., 67.]);
+    assert_eq!(chord(57., Quality::Minor7), [57., 60., 64., 67.]);
+    assert_eq!(invert(&chord(60., Quality::Major), 1), [64., 67., 72.]);
+    assert_eq!(invert(&chord(60., Quality::Major), 2), [67., 72., 76.]);
+
+    let c = Key::new(60., Mode::Major);
+    assert_eq!(c.degree(0), 60.);
+    assert_eq!(c.degree(4), 67.);
+    assert_eq!(c.degree(7), 72.);
+    assert_eq!(c.degree(-1), 59.);
+    // ii, v7 and vii dim come out of the key on their own
+    assert_eq!(c.chord(1, 3), chord(62., Quality::Minor));
+    assert_eq!(c.chord(4, 4), chord(67., Quality::Dominant7));
+    assert_eq!(c.chord(6, 3), chord(71., Quality::Diminished));
+
+    assert_eq!(c.quantize(61.), 60.);
+    assert_eq!(c.quantize(61.6), 62.);
+    assert_eq!(c.quantize(70.), 69.);
+    assert_eq!(c.quantize(71.7), 72.);
+    assert_eq!(c.quantize(46.), 45.);
+    assert!(c.contains(64.) && !c.contains(63.));
+    let blues = Key::new(57., Mode::Blues);
+    assert_eq!(blues.quantize(65.), 64.);
+    assert_eq!(blues.quantize(58.9), 60.);
+    for pitch in 0..128 {
+        let pitch = pitch as f32;
+        let key = Key::new(62., Mode::Dorian);
+        assert!(key.contains(key.quantize(pitch)));
+        assert!((key.quantize(pitch) - pitch).abs() <= 1.);
+    }
+}
+
+#[test]
+fn harmony_rules() {
+    use Quality::*;
+
+    for quality in [
+        Major,
+        Minor,
+        Diminished,
+        Augmented,
+        Sus2,
+        Sus4,
+        Major7,
+        Minor7,
+        Dominant7,
+        HalfDiminished7,
+        Diminished7,
+    ] {
+        let intervals = quality.intervals();
+        assert_eq!(intervals[0], 0.);
+        assert!(intervals.windows(2).all(|w| w[0] < w[1] && w[1] < 12.));
+        // turning a chord all the way round is the same chord an octave up, and that's as far as
+        // it goes
+        let c = chord(60., quality);
+        let octave: Vec<f32> = c.iter().map(|p| p + 12.).collect();
+        assert_eq!(invert(&c, c.len()), octave);
+        assert_eq!(invert(&c, c.len() + 3), octave);
+        assert_eq!(invert(&c, 0), c);
+    }
+    assert_eq!(chord(60., Diminished7), [60., 63., 66., 69.]);
+    assert_eq!(chord(60., Sus4), [60., 65., 67.]);
+    assert!(invert(&[], 2).is_empty());
+
+    let modes = [
+        Mode::Major,
+        Mode::Minor,
+        Mode::HarmonicMinor,
+        Mode::MelodicMinor,
+        Mode::Dorian,
+        Mode::Phrygian,
+        Mode::Lydian,
+        Mode::Mixolydian,
+        Mode::Locrian,
+        Mode::MajorPentatonic,
+        Mode::MinorPentatonic,
+        Mode::Blues,
+        Mode::Chromatic,
+    ];
+    for mode in modes {
+        let key = Key::new(62., mode);
+        // degrees keep climbing through the octaves either way and are all in the key
+        for degree in -20..20 {
+            assert!(key.degree(degree) < key.degree(degree + 1));
+            assert!(key.contains(key.degree(degree)), "{:?} {}", mode, degree);
+        }
+        let size = mode.intervals().len() as i32;
+        assert_eq!(key.degree(size), 74.);
+        assert_eq!(key.degree(-size), 50.);
+    }
+    // the church modes are the major scale started from each of its degrees
+    let c = Key::new(60., Mode::Major);
+    for (start, mode) in [
+        Mode::Dorian,
+        Mode::Phrygian,
+        Mode::Lydian,
+        Mode::Mixolydian,
+        Mode::Minor,
+        Mode::Locrian,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let start = start as i32 + 1;
+        let key = Key::new(c.degree(start), mode);
+        for degree in -7..8 {
+            assert_eq!(key.degree(degree), c.degree(start + degree), "{:?}", mode);
+        }
+    }
+
+    // a minor, below the root as well
+    let a = Key::new(57., Mode::Minor);
+    assert_eq!(a.degree(-1), 55.);
+    assert_eq!(a.degree(-2), 53.);
+    assert_eq!(a.degree(-7), 45.);
+    assert_eq!(a.degree(-8), 43.);
+    assert_eq!(a.chord(0, 3), chord(57., Minor));
+    assert_eq!(a.chord(2, 3), chord(60., Major));
+    assert_eq!(a.chord(-3, 3), chord(52., Minor));
+    assert_eq!(a.chord(1, 4), chord(59., HalfDiminished7));
+    // the harmonic minor's raised seventh makes v major
+    assert_eq!(
+        Key::new(57., Mode::HarmonicMinor).chord(4, 3),
+        chord(64., Major)
+    );
+
+    // right between two notes goes to the lower one, across the octave too
+    let pentatonic = Key::new(60., Mode::MajorPentatonic);
+    assert_eq!(pentatonic.quantize(65.5), 64.);
+    assert_eq!(pentatonic.quantize(70.5), 69.);
+    assert_eq!(pentatonic.quantize(70.6), 72.);
+    assert_eq!(pentatonic.quantize(46.5), 45.);
+}
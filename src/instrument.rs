@@ -0,0 +1,349 @@
+// notes authored at a musical level, an instrument is the recipe that turns a pitch and a velocity
+// into the waves that play them
+// pitch is in midi note numbers (fractions are fine, 69 is a440) and velocity goes from 0 to 1
+use alloc::{vec, vec::Vec};
+use core::borrow::Borrow;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{
+    func::{noise, Adsr, Amplitude, Envelope, Function, PhaseMode, Wave, Waveform, Wavetable},
+    pitch::{EqualTemperament, Tuning},
+    poly::{add, mul},
+    Fade, LiveWave, Mix, Modulation, PackError, TimedWave, TimedWavePacker, OPEN,
+};
+
+// one oscillator in the recipe
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layer {
+    pub shape: Waveform,
+    // pulse width or pluck damping
+    pub width: f32,
+    // frequency as a multiple of the note's, 2 is an octave up
+    pub ratio: f32,
+    pub gain: f32,
+}
+impl Layer {
+    pub fn new(shape: Waveform) -> Self {
+        Layer {
+            shape,
+            width: 0.5,
+            ratio: 1.,
+            gain: 1.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instrument {
+    pub layers: Vec<Layer>,
+    // in samples, the peak gets scaled by the velocity and the gate is set to the note's duration
+    pub envelope: Adsr,
+    // how many copies of every layer play and how they're spread out
+    pub unison: Unison,
+    pub sample_rate: f32,
+}
+impl Instrument {
+    // a single sine with a quick attack and release
+    pub fn new(sample_rate: f32) -> Self {
+        Instrument {
+            layers: vec![Layer::new(Waveform::Sine)],
+            envelope: Adsr {
+                peak: 1.,
+                attack: 0.005 * sample_rate,
+                decay: 0.1 * sample_rate,
+                sustain: 0.8,
+                gate: 0.,
+                release: 0.05 * sample_rate,
+            },
+            unison: Unison::default(),
+            sample_rate,
+        }
+    }
+    // the waves for one note lasting duration samples before its release
+    pub fn note(
+        &self,
+        pitch: f32,
+        start: i64,
+        duration: i64,
+        velocity: f32,
+    ) -> Vec<TimedWave<Vec<f32>, Envelope<Vec<f32>>>> {
+        self.tuned_note(
+            &EqualTemperament::default(),
+            pitch,
+            start,
+            duration,
+            velocity,
+        )
+    }
+    // the same with the pitch played in some other tuning
+    pub fn tuned_note(
+        &self,
+        tuning: &impl Tuning,
+        pitch: f32,
+        start: i64,
+        duration: i64,
+        velocity: f32,
+    ) -> Vec<TimedWave<Vec<f32>, Envelope<Vec<f32>>>> {
+        let hz = tuning.hz(pitch);
+        let end = start + duration + self.envelope.release.ceil() as i64;
+        let envelope = Adsr {
+            peak: self.envelope.peak * velocity,
+            gate: duration as f32,
+            ..self.envelope
+        };
+        let layers = self.layers.iter().map(|layer| TimedWave {
+            start,
+            end,
+            wave: Wave {
+                freq: vec![hz * layer.ratio / self.sample_rate],
+                amp: Envelope::Adsr(Adsr {
+                    peak: envelope.peak * layer.gain,
+                    ..envelope
+                }),
+                phase: 0.,
+                mode: PhaseMode::Product,
+                shape: layer.shape,
+                width: vec![layer.width],
+            },
+            fade: Fade::default(),
+            mix: Mix::default(),
+            modulation: Modulation::default(),
+            table: Wavetable::default(),
+        });
+        layers.flat_map(|wave| self.unison.copies(&wave)).collect()
+    }
+    // a note for Player::schedule that holds until Player::note_off(id, ..) lets go of it
+    pub fn held_note(&self, pitch: f32, start: i64, velocity: f32, id: u32) -> Vec<LiveWave> {
+        let mut waves = self.note(pitch, start, 0, velocity);
+        for wave in &mut waves {
+            wave.end = OPEN;
+            wave.mix.id = id;
+            if let Envelope::Adsr(adsr) = &mut wave.wave.amp {
+                adsr.gate = f32::INFINITY;
+            }
+        }
+        waves
+    }
+}
+
+// one note as a stack of detuned copies, supersaw style, each starting somewhere random in its
+// cycle and panned across the spread
+// the copies share the note's gain so it gets no louder for having more of them
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Unison<T = Vec<f32>> {
+    pub voices: usize,
+    // cents between the flattest and sharpest copies over the note's own time
+    pub detune: T,
+    // 0 leaves the copies where the note is panned, 1 goes from hard left to hard right
+    pub spread: f32,
+    pub seed: u32,
+}
+impl Default for Unison {
+    fn default() -> Self {
+        Unison {
+            voices: 1,
+            detune: vec![],
+            spread: 0.,
+            seed: 0,
+        }
+    }
+}
+impl<T: Borrow<[f32]>> Unison<T> {
+    // a single voice is just the note
+    // a detune that moves puts the copies in integral mode, where sweeps come out at the right pitch
+    pub fn copies<A: Clone>(&self, note: &TimedWave<Vec<f32>, A>) -> Vec<TimedWave<Vec<f32>, A>> {
+        if self.voices <= 1 {
+            return vec![note.clone()];
+        }
+        let detune = self.detune.borrow();
+        let start = note.wave.freq.eval(0.);
+        let level = 1. / (self.voices as f32).sqrt();
+        (0..self.voices)
+            .map(|copy| {
+                // from -0.5 to 0.5 across the copies
+                let place = copy as f32 / (self.voices - 1) as f32 - 0.5;
+                // 2^(cents / 1200) is as good as 1 + cents ln 2 / 1200 to within a cent up to
+                // about a hundred cents, which keeps a moving detune a polynomial
+                let bend = match detune {
+                    [] => vec![1.],
+                    &[cents] => vec![(cents * place / 1200.).exp2()],
+                    _ => add(
+                        &[1.],
+                        &mul(detune, &[place * core::f32::consts::LN_2 / 1200.]),
+                    ),
+                };
+                let random = (noise(copy as i64 + ((self.seed as i64) << 32), 0) + 1.) / 2.;
+                let phase = match start {
+                    0. => note.wave.phase,
+                    start => note.wave.phase + random / start.abs(),
+                };
+                let mut pan = note.mix.pan.clone();
+                if pan.is_empty() {
+                    pan.push(0.);
+                }
+                pan[0] += 2. * self.spread * place;
+                let gain = match &note.mix.gain[..] {
+                    [] => vec![level],
+                    gain => mul(gain, &[level]),
+                };
+                TimedWave {
+                    wave: Wave {
+                        freq: mul(&note.wave.freq, &bend),
+                        phase,
+                        mode: match detune.len() {
+                            0 | 1 => note.wave.mode,
+                            _ => PhaseMode::Integral,
+                        },
+                        ..note.wave.clone()
+                    },
+                    mix: Mix {
+                        gain,
+                        pan,
+                        id: note.mix.id,
+                    },
+                    ..note.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+impl TimedWavePacker {
+    // the copies go on the end, like try_extend
+    pub fn add_unison<A: Amplitude + Clone>(
+        &mut self,
+        note: &TimedWave<Vec<f32>, A>,
+        unison: &Unison,
+    ) -> Result<(), PackError> {
+        self.try_extend(unison.copies(note))
+    }
+}
+
+impl TimedWavePacker {
+    // every wave of the note goes on the end, like try_extend
+    pub fn add_note(
+        &mut self,
+        instrument: &Instrument,
+        pitch: f32,
+        start: i64,
+        duration: i64,
+        velocity: f32,
+    ) -> Result<(), PackError> {
+        self.try_extend(instrument.note(pitch, start, duration, velocity))
+    }
+    pub fn add_tuned_note(
+        &mut self,
+        instrument: &Instrument,
+        tuning: &impl Tuning,
+        pitch: f32,
+        start: i64,
+        duration: i64,
+        velocity: f32,
+    ) -> Result<(), PackError> {
+        self.try_extend(instrument.tuned_note(tuning, pitch, start, duration, velocity))
+    }
+}
+
+#[test]
+fn notes() {
+    // a detuned saw over a quiet octave up square
+    let instrument = Instrument {
+        layers: vec![
+            Layer::new(Waveform::Saw),
+            Layer {
+                ratio: 2.,
+                gain: 0.25,
+                ..Layer::new(Waveform::Square)
+            },
+        ],
+        unison: Unison {
+            voices: 3,
+            detune: vec![20.],
+            spread: 0.5,
+            seed: 1,
+        },
+        ..Instrument::new(48000.)
+    };
+    let mut packer = TimedWavePacker::new();
+    packer.add_note(&instrument, 69., 100, 4800, 0.5).unwrap();
+    packer.add_note(&instrument, 81., 6000, 4800, 1.).unwrap();
+    assert_eq!(packer.timings.len(), 12);
+    assert_eq!(packer.timings[0], [100, 100 + 4800 + 2400]);
+    let cents = |f: f32| 1200. * (f * 48000. / 440.).log2();
+    assert!((cents(packer.freq_coef[0]) + 10.).abs() < 1e-2);
+    assert!(cents(packer.freq_coef[1]).abs() < 1e-2);
+    assert!((cents(packer.freq_coef[3]) - 1190.).abs() < 1e-2);
+    // an octave up for the second note's saw
+    assert!((cents(packer.freq_coef[6]) - 1190.).abs() < 1e-2);
+    assert_eq!(&packer.pan_coef[..3], [-0.5, 0., 0.5]);
+    assert_eq!(packer.shapes[3], Waveform::Square);
+    assert_eq!(packer.envelopes[0].peak, 0.5);
+    assert_eq!(packer.envelopes[3].peak, 0.125);
+    assert_eq!(packer.gain_coef[0], 1. / 3f32.sqrt());
+    assert_eq!(packer.envelopes[0].gate, 4800.);
+    assert!(packer.try_get_pack().is_ok());
+
+    // a just major third above a
+    let just = crate::pitch::Scale::just(69.);
+    let third = Instrument::new(48000.).tuned_note(&just, 73., 0, 100, 1.);
+    assert_eq!(third[0].wave.freq, [440. * 1.25 / 48000.]);
+
+    let held = instrument.held_note(69., 0, 1., 7);
+    assert_eq!(held.len(), 6);
+    assert!(held.iter().all(|tw| tw.end == OPEN && tw.mix.id == 7));
+    assert!(matches!(held[0].wave.amp, Envelope::Adsr(a) if a.gate == f32::INFINITY));
+}
+
+#[test]
+fn unison() {
+    let note = TimedWave {
+        start: 0,
+        end: 1000,
+        wave: Wave {
+            freq: vec![0.01],
+            amp: vec![0.5],
+            phase: 0.,
+            mode: PhaseMode::Product,
+            shape: Waveform::Saw,
+            width: vec![],
+        },
+        fade: Fade::default(),
+        mix: Mix {
+            gain: vec![],
+            pan: vec![-0.25],
+            id: 0,
+        },
+        modulation: Modulation::default(),
+        table: Wavetable::default(),
+    };
+    // the detune opening up from nothing to 40 cents over the note
+    let supersaw = Unison {
+        voices: 7,
+        detune: vec![0., 0.04],
+        spread: 0.5,
+        seed: 3,
+    };
+    let mut packer = TimedWavePacker::new();
+    packer.add_unison(&note, &supersaw).unwrap();
+    let copies = supersaw.copies(&note);
+    assert_eq!(copies.len(), 7);
+    assert!(copies.iter().all(|c| c.wave.mode == PhaseMode::Integral));
+    assert_eq!(copies[0].mix.pan, [-0.75]);
+    assert_eq!(copies[6].mix.pan, [0.25]);
+    // phases land all over the cycle, the same every time for a seed
+    let phases: Vec<f32> = copies.iter().map(|c| c.wave.phase).collect();
+    assert!(phases.iter().all(|&p| (0. ..100.).contains(&p)));
+    assert!(phases.windows(2).all(|w| w[0] != w[1]));
+    assert_eq!(copies, supersaw.copies(&note));
+    // together at the start, 40 cents apart by the end
+    let cents =
+        |c: &TimedWave<Vec<f32>, Vec<f32>>, t: f32| 1200. * (c.wave.freq.eval(t) / 0.01).log2();
+    assert_eq!(cents(&copies[0], 0.), 0.);
+    let spread = cents(&copies[6], 1000.) - cents(&copies[0], 1000.);
+    assert!((spread - 40.).abs() < 0.1, "{}", spread);
+    assert_eq!(Unison::default().copies(&note), [note]);
+}
@@ -1,2 +1,530 @@
-// add hermite interpolation idk
-// how do I do this??
+// turning breakpoints into polynomials, exact ones through sketched points and approximations of easings
+use alloc::{vec, vec::Vec};
+use core::f32::consts::PI;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{
+    func::{Piecewise, RunLength},
+    poly::{add, compose, fit_polynomial, mul, sub},
+};
+
+// the one polynomial that goes through every (t, value), degree one less than the number of points
+// two points at the same t can't both be hit, so that's None
+// every point added bends the whole thing, so past a handful they swing wildly in between
+pub fn lagrange(points: &[(f32, f32)]) -> Option<Vec<f32>> {
+    let nodes: Vec<(f64, f64, Option<f64>)> = points
+        .iter()
+        .map(|&(t, v)| (t as f64, v as f64, None))
+        .collect();
+    newton(&nodes)
+}
+
+// (t, value, slope), hits the values with the slopes given there, degree 2 * points - 1
+pub fn hermite(points: &[(f32, f32, f32)]) -> Option<Vec<f32>> {
+    let nodes: Vec<(f64, f64, Option<f64>)> = points
+        .iter()
+        .flat_map(|&(t, v, s)| {
+            [
+                (t as f64, v as f64, None),
+                (t as f64, v as f64, Some(s as f64)),
+            ]
+        })
+        .collect();
+    newton(&nodes)
+}
+
+// divided differences, a node given twice in a row carries the slope there
+fn newton(nodes: &[(f64, f64, Option<f64>)]) -> Option<Vec<f32>> {
+    let mut diffs: Vec<f64> = nodes.iter().map(|&(_, v, _)| v).collect();
+    for order in 1..nodes.len() {
+        for i in (order..nodes.len()).rev() {
+            let (from, to) = (nodes[i - order].0, nodes[i].0);
+            diffs[i] = if from != to {
+                (diffs[i] - diffs[i - 1]) / (to - from)
+            } else {
+                match nodes[i].2 {
+                    Some(slope) if order == 1 => slope,
+                    _ => return None,
+                }
+            };
+        }
+    }
+    // c0 + (t - t0)(c1 + (t - t1)(c2 + ...)) multiplied out from the inside
+    let coeffs = nodes
+        .iter()
+        .zip(&diffs)
+        .rev()
+        .fold(Vec::new(), |acc: Vec<f64>, (&(t, _, _), &c)| {
+            add(&mul(&acc, &[-t, 1.]), &[c])
+        });
+    Some(coeffs.into_iter().map(|c| c as f32).collect())
+}
+
+#[test]
+fn interpolating() {
+    use crate::func::Function;
+
+    let points = [(0., 1.), (100., 0.5), (250., 0.8), (400., 0.)];
+    let through = lagrange(&points).unwrap();
+    assert_eq!(through.len(), 4);
+    for (t, v) in points {
+        assert!((through.eval(t) - v).abs() < 1e-5);
+    }
+    assert!(lagrange(&[(1., 2.), (1., 3.)]).is_none());
+    assert_eq!(lagrange(&[]), Some(vec![]));
+    assert_eq!(lagrange(&[(3., 2.)]), Some(vec![2.]));
+
+    let points = [(0., 0., 0.), (1000., 1., 0.), (3000., 0.25, -0.001)];
+    let smooth = hermite(&points).unwrap();
+    let slope: Vec<f32> = crate::func::derive_polynomial(&smooth).collect();
+    assert_eq!(smooth.len(), 6);
+    for (t, v, s) in points {
+        assert!((smooth.eval(t) - v).abs() < 1e-4);
+        assert!((slope.eval(t) - s).abs() < 1e-6);
+    }
+    // a line with its own slope is still a line
+    let line = hermite(&[(1., 3., 2.), (2., 5., 2.)]).unwrap();
+    assert!(line[2..].iter().all(|c| c.abs() < 1e-6));
+    assert!(hermite(&[(1., 3., 2.), (1., 3., 2.)]).is_none());
+}
+
+// fritsch and carlson's cubics, one per gap, that never go past the points on either side of them
+// so an amp envelope through points between 0 and 1 stays between 0 and 1
+// it holds the last value after the last point and the first piece carries on before the first,
+// the ts have to go up
+pub fn monotone_cubic(points: &[(f32, f32)]) -> Option<Piecewise> {
+    let breaks: Vec<f32> = points.iter().map(|&(t, _)| t).collect();
+    let ts: Vec<f64> = breaks.iter().map(|&t| t as f64).collect();
+    let values: Vec<f64> = points.iter().map(|&(_, v)| v as f64).collect();
+    let widths: Vec<f64> = ts.windows(2).map(|w| w[1] - w[0]).collect();
+    let slopes: Vec<f64> = values
+        .windows(2)
+        .zip(&widths)
+        .map(|(v, h)| (v[1] - v[0]) / h)
+        .collect();
+    let mut tangents: Vec<f64> = (0..points.len())
+        .map(
+            |k| match (k.checked_sub(1).map(|k| slopes[k]), slopes.get(k)) {
+                (Some(before), Some(&after)) if before * after > 0. => (before + after) / 2.,
+                (Some(_), Some(_)) => 0.,
+                (Some(before), None) => before,
+                (None, Some(&after)) => after,
+                (None, None) => 0.,
+            },
+        )
+        .collect();
+    // tangents too steep for their gap get scaled back into the region that can't overshoot
+    for (k, &slope) in slopes.iter().enumerate() {
+        if slope == 0. {
+            tangents[k] = 0.;
+            tangents[k + 1] = 0.;
+            continue;
+        }
+        let (a, b) = (tangents[k] / slope, tangents[k + 1] / slope);
+        let size = a * a + b * b;
+        if size > 9. {
+            let shrink = 3. / size.sqrt();
+            tangents[k] = shrink * a * slope;
+            tangents[k + 1] = shrink * b * slope;
+        }
+    }
+    let mut coeffs: Vec<f32> = Vec::with_capacity(points.len() * 4);
+    for (k, (&h, &slope)) in widths.iter().zip(&slopes).enumerate() {
+        let (m0, m1) = (tangents[k], tangents[k + 1]);
+        coeffs.extend(
+            [
+                values[k],
+                m0,
+                (3. * slope - 2. * m0 - m1) / h,
+                (m0 + m1 - 2. * slope) / (h * h),
+            ]
+            .map(|c| c as f32),
+        );
+    }
+    coeffs.push(*values.last()? as f32);
+    let mut runs = vec![4; widths.len()];
+    runs.push(1);
+    Piecewise::new(breaks, coeffs, runs)
+}
+
+#[test]
+fn monotone() {
+    use crate::func::Function;
+
+    // a fast attack into a plateau, the kind of thing that rings with a plain spline
+    let points = [(0., 0.), (10., 1.), (200., 1.), (220., 0.3), (1000., 0.)];
+    let env = monotone_cubic(&points).unwrap();
+    for (t, v) in points {
+        assert!((env.eval(t) - v).abs() < 1e-5);
+    }
+    for w in points.windows(2) {
+        let (lo, hi) = (w[0].1.min(w[1].1), w[0].1.max(w[1].1));
+        for i in 0..=50 {
+            let value = env.eval(w[0].0 + (w[1].0 - w[0].0) * i as f32 / 50.);
+            assert!(value >= lo - 1e-5 && value <= hi + 1e-5);
+        }
+    }
+    assert_eq!(env.eval(5000.), 0.);
+    assert_eq!(monotone_cubic(&[(3., 0.5)]).unwrap().eval(10.), 0.5);
+    assert!(monotone_cubic(&[(1., 0.), (1., 1.)]).is_none());
+    assert!(monotone_cubic(&[]).is_none());
+}
+
+// a legato line's frequency, notes are (start, freq) and the starts have to go up
+// each note after the first slides in from the one before over glide samples (cut short if the
+// next note comes sooner) then holds, the slide is even in pitch, an exponential in frequency
+// stood in for by the cubic with the same ends and slopes there, which stays within a few cents
+// of it for anything up to an octave
+// play it in integral mode, the frequency moves
+pub fn glide(notes: &[(f32, f32)], glide: f32) -> Option<Piecewise> {
+    let (mut breaks, mut coeffs, mut runs) = (Vec::new(), Vec::new(), Vec::new());
+    let &(first, freq) = notes.first()?;
+    breaks.push(first);
+    coeffs.push(freq);
+    runs.push(1);
+    for (k, pair) in notes.windows(2).enumerate() {
+        let [(_, from), (start, to)] = [pair[0], pair[1]];
+        let next = notes.get(k + 2).map_or(f32::INFINITY, |&(next, _)| next);
+        let length = glide.min(next - start);
+        if length > 0. && from != to && from > 0. && to > 0. {
+            let rate = (to / from).ln() / length;
+            let slide = hermite(&[(0., from, from * rate), (length, to, to * rate)])?;
+            breaks.push(start);
+            runs.push(slide.len() as RunLength);
+            coeffs.extend(slide);
+            if start + length >= next {
+                continue;
+            }
+            breaks.push(start + length);
+        } else {
+            breaks.push(start);
+        }
+        coeffs.push(to);
+        runs.push(1);
+    }
+    Piecewise::new(breaks, coeffs, runs)
+}
+
+#[test]
+fn gliding() {
+    use crate::func::Function;
+
+    // up an octave, down a fifth cut short by the next note coming too soon, then back down
+    let notes = [
+        (0., 0.01),
+        (1000., 0.02),
+        (3000., 0.02 / 1.5),
+        (3100., 0.01),
+    ];
+    let line = glide(&notes, 400.).unwrap();
+    assert_eq!(line.breaks(), [0., 1000., 1400., 3000., 3100., 3500.]);
+    let cents = |a: f32, b: f32| 1200. * (a / b).log2();
+    for (t, freq) in [(500., 0.01), (1400., 0.02), (2999., 0.02), (3600., 0.01)] {
+        assert!(cents(line.eval(t), freq).abs() < 0.01, "{}", t);
+    }
+    // halfway up the octave is halfway in pitch, not in frequency
+    assert!(cents(line.eval(1200.), 0.01 * 2f32.sqrt()).abs() < 5.);
+    // the cut short slide still gets where it was going
+    assert!(cents(line.eval(3099.9), 0.02 / 1.5).abs() < 1.);
+    assert!(glide(&[(0., 0.01), (0., 0.02)], 10.).is_none());
+    assert!(glide(&[], 10.).is_none());
+}
+
+// easing curves, all going from 0 at t = 0 to 1 at t = 1 (back and elastic overshoot on the way)
+// the free functions only mean anything between 0 and 1, Easing clamps before it asks them
+// the formulas are the usual ones off easings.net
+const BACK: f32 = 1.70158;
+
+pub fn linear(t: f32) -> f32 {
+    t
+}
+
+pub fn quad_in(t: f32) -> f32 {
+    t * t
+}
+pub fn quad_out(t: f32) -> f32 {
+    1. - quad_in(1. - t)
+}
+pub fn quad_in_out(t: f32) -> f32 {
+    in_out(quad_in, t)
+}
+
+pub fn cubic_in(t: f32) -> f32 {
+    t * t * t
+}
+pub fn cubic_out(t: f32) -> f32 {
+    1. - cubic_in(1. - t)
+}
+pub fn cubic_in_out(t: f32) -> f32 {
+    in_out(cubic_in, t)
+}
+
+pub fn quart_in(t: f32) -> f32 {
+    t * t * t * t
+}
+pub fn quart_out(t: f32) -> f32 {
+    1. - quart_in(1. - t)
+}
+pub fn quart_in_out(t: f32) -> f32 {
+    in_out(quart_in, t)
+}
+
+// 2^(10t - 10) never quite gets to 0, so the start is pinned there
+pub fn expo_in(t: f32) -> f32 {
+    if t <= 0. {
+        0.
+    } else {
+        (10. * t - 10.).exp2()
+    }
+}
+pub fn expo_out(t: f32) -> f32 {
+    1. - expo_in(1. - t)
+}
+pub fn expo_in_out(t: f32) -> f32 {
+    in_out(expo_in, t)
+}
+
+// dips about 10% below the start before heading off
+pub fn back_in(t: f32) -> f32 {
+    (BACK + 1.) * t * t * t - BACK * t * t
+}
+pub fn back_out(t: f32) -> f32 {
+    1. - back_in(1. - t)
+}
+// the in out version dips further to make up for covering each half twice as fast
+pub fn back_in_out(t: f32) -> f32 {
+    let c = BACK * 1.525;
+    let half = |t: f32| (c + 1.) * t * t * t - c * t * t;
+    in_out(half, t)
+}
+
+pub fn elastic_in(t: f32) -> f32 {
+    if t <= 0. || t >= 1. {
+        return t.clamp(0., 1.);
+    }
+    -(10. * t - 10.).exp2() * ((10. * t - 10.75) * (2. * PI / 3.)).sin()
+}
+pub fn elastic_out(t: f32) -> f32 {
+    1. - elastic_in(1. - t)
+}
+// like back, the halves wobble at their own rate instead of being squashed copies
+pub fn elastic_in_out(t: f32) -> f32 {
+    if t <= 0. || t >= 1. {
+        return t.clamp(0., 1.);
+    }
+    let wobble = ((20. * t - 11.125) * (2. * PI / 4.5)).sin();
+    if t < 0.5 {
+        -(20. * t - 10.).exp2() * wobble / 2.
+    } else {
+        (-20. * t + 10.).exp2() * wobble / 2. + 1.
+    }
+}
+
+// four parabolas, each bounce 3/4 the length and 1/4 the height... ish
+pub fn bounce_out(t: f32) -> f32 {
+    const N: f32 = 7.5625;
+    const D: f32 = 2.75;
+    if t < 1. / D {
+        N * t * t
+    } else if t < 2. / D {
+        let t = t - 1.5 / D;
+        N * t * t + 0.75
+    } else if t < 2.5 / D {
+        let t = t - 2.25 / D;
+        N * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / D;
+        N * t * t + 0.984375
+    }
+}
+pub fn bounce_in(t: f32) -> f32 {
+    1. - bounce_out(1. - t)
+}
+pub fn bounce_in_out(t: f32) -> f32 {
+    in_out(bounce_in, t)
+}
+
+// the in curve squashed into the first half and turned around for the second
+fn in_out(ease_in: impl Fn(f32) -> f32, t: f32) -> f32 {
+    if t < 0.5 {
+        ease_in(2. * t) / 2.
+    } else {
+        1. - ease_in(2. - 2. * t) / 2.
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    QuartIn,
+    QuartOut,
+    QuartInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BounceIn,
+    BounceOut,
+    BounceInOut,
+}
+impl Easing {
+    // for anything that wants to list them out, like a dropdown
+    pub const ALL: [Easing; 22] = [
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::QuartIn,
+        Easing::QuartOut,
+        Easing::QuartInOut,
+        Easing::ExpoIn,
+        Easing::ExpoOut,
+        Easing::ExpoInOut,
+        Easing::BackIn,
+        Easing::BackOut,
+        Easing::BackInOut,
+        Easing::ElasticIn,
+        Easing::ElasticOut,
+        Easing::ElasticInOut,
+        Easing::BounceIn,
+        Easing::BounceOut,
+        Easing::BounceInOut,
+    ];
+
+    pub fn function(self) -> fn(f32) -> f32 {
+        match self {
+            Easing::Linear => linear,
+            Easing::QuadIn => quad_in,
+            Easing::QuadOut => quad_out,
+            Easing::QuadInOut => quad_in_out,
+            Easing::CubicIn => cubic_in,
+            Easing::CubicOut => cubic_out,
+            Easing::CubicInOut => cubic_in_out,
+            Easing::QuartIn => quart_in,
+            Easing::QuartOut => quart_out,
+            Easing::QuartInOut => quart_in_out,
+            Easing::ExpoIn => expo_in,
+            Easing::ExpoOut => expo_out,
+            Easing::ExpoInOut => expo_in_out,
+            Easing::BackIn => back_in,
+            Easing::BackOut => back_out,
+            Easing::BackInOut => back_in_out,
+            Easing::ElasticIn => elastic_in,
+            Easing::ElasticOut => elastic_out,
+            Easing::ElasticInOut => elastic_in_out,
+            Easing::BounceIn => bounce_in,
+            Easing::BounceOut => bounce_out,
+            Easing::BounceInOut => bounce_in_out,
+        }
+    }
+    pub fn ease(self, t: f32) -> f32 {
+        self.function()(t.clamp(0., 1.))
+    }
+    // from at time 0 to to at duration, holding on either side
+    pub fn between(self, from: f32, to: f32, duration: f32) -> impl Fn(f32) -> f32 + Copy {
+        move |time| {
+            let t = if duration > 0. { time / duration } else { 1. };
+            from + (to - from) * self.ease(t)
+        }
+    }
+    // the curves that are polynomials to begin with, in t
+    fn exact(self) -> Option<Vec<f32>> {
+        let ease_in = match self {
+            Easing::Linear => return Some(vec![0., 1.]),
+            Easing::QuadIn | Easing::QuadOut => vec![0., 0., 1.],
+            Easing::CubicIn | Easing::CubicOut => vec![0., 0., 0., 1.],
+            Easing::QuartIn | Easing::QuartOut => vec![0., 0., 0., 0., 1.],
+            Easing::BackIn | Easing::BackOut => vec![0., 0., -BACK, BACK + 1.],
+            _ => return None,
+        };
+        Some(match self {
+            Easing::QuadOut | Easing::CubicOut | Easing::QuartOut | Easing::BackOut => {
+                sub(&[1.], &compose(&ease_in, &[1., -1.]))
+            }
+            _ => ease_in,
+        })
+    }
+    // the same as between but as coefficients over time since the start, ready for a wave's amp or freq
+    // the ones that are already polynomials come out exact when degree allows, the rest are
+    // least squares fits and only mean anything between 0 and duration
+    pub fn polynomial(self, from: f32, to: f32, duration: f32, degree: usize) -> Vec<f32> {
+        const SAMPLES: usize = 256;
+        if duration <= 0. {
+            return vec![to];
+        }
+        if let Some(exact) = self.exact().filter(|e| e.len() <= degree + 1) {
+            let stretched = compose(&exact, &[0., 1. / duration]);
+            return compose(&[from, to - from], &stretched);
+        }
+        let curve = self.between(from, to, duration);
+        let samples: Vec<(f32, f32)> = (0..=SAMPLES)
+            .map(|i| {
+                let time = duration * i as f32 / SAMPLES as f32;
+                (time, curve(time))
+            })
+            .collect();
+        fit_polynomial(&samples, degree)
+    }
+}
+
+#[test]
+fn easings() {
+    use crate::func::Function;
+    use alloc::format;
+
+    for easing in Easing::ALL {
+        let ease = easing.function();
+        assert!(ease(0.).abs() < 1e-6, "{:?}", easing);
+        assert!((ease(1.) - 1.).abs() < 1e-6, "{:?}", easing);
+        assert_eq!(easing.ease(-1.), easing.ease(0.));
+        // every in out passes through the middle, and out is in turned around
+        if format!("{:?}", easing).ends_with("InOut") {
+            assert!((ease(0.5) - 0.5).abs() < 1e-6, "{:?}", easing);
+        }
+    }
+    for (ease_in, ease_out) in [
+        (quad_in as fn(f32) -> f32, quad_out as fn(f32) -> f32),
+        (expo_in, expo_out),
+        (elastic_in, elastic_out),
+        (bounce_in, bounce_out),
+    ] {
+        for t in [0.1, 0.3, 0.77] {
+            assert!((ease_out(t) - (1. - ease_in(1. - t))).abs() < 1e-6);
+        }
+    }
+    assert!(back_in(0.3) < 0. && back_out(0.7) > 1.);
+    assert!((bounce_out(1. / 2.75) - 1.).abs() < 1e-6);
+
+    // a swell from 0.1 to 0.8 over a second at 48k
+    let swell = Easing::CubicOut.between(0.1, 0.8, 48000.);
+    let exact = Easing::CubicOut.polynomial(0.1, 0.8, 48000., 3);
+    assert_eq!(exact.len(), 4);
+    for time in [0., 12000., 30000., 48000.] {
+        assert!((exact.eval(time) - swell(time)).abs() < 1e-5);
+    }
+    assert_eq!(swell(60000.), 0.8);
+    // squeezing a quartic into a line gets the best line instead
+    assert_eq!(Easing::QuartIn.polynomial(0., 1., 10., 1).len(), 2);
+    let fitted = Easing::ExpoInOut.polynomial(0., 1., 100., 12);
+    let curve = Easing::ExpoInOut.between(0., 1., 100.);
+    for time in [0., 20., 45., 80., 100.] {
+        assert!((fitted.eval(time) - curve(time)).abs() < 0.05);
+    }
+    assert_eq!(Easing::BounceOut.polynomial(0.2, 0.5, 0., 4), [0.5]);
+}
@@ -1,346 +1,4342 @@
+// without std the core needs nothing but an allocator, buffers come from the caller and float
+// math goes through libm, see float
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+
+pub mod arpeggiator;
+pub mod binary;
+pub mod crossfade;
 pub mod curve;
+pub mod drums;
+pub mod dsp;
+#[cfg(not(any(feature = "std", test)))]
+mod float;
 pub mod func;
+pub mod generative;
+pub mod granular;
+pub mod graph;
+pub mod harmony;
+pub mod instrument;
 pub mod interpolation;
+#[cfg(feature = "live")]
+pub mod live;
+#[cfg(feature = "midir")]
+pub mod live_midi;
+#[cfg(feature = "midi")]
+pub mod midi;
+pub mod mixer;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod pitch;
+#[cfg(feature = "playback")]
+pub mod playback;
+pub mod poly;
+pub mod resample;
+pub mod rhythm;
+#[cfg(feature = "rodio")]
+pub mod rodio_source;
+pub mod samples;
+pub mod score;
+pub mod timeline;
+#[cfg(feature = "hound")]
+pub mod wav;
+
+use alloc::{
+    vec,
+    vec::{Drain, Vec},
+};
+use core::{
+    borrow::Borrow,
+    error::Error,
+    f32::consts::{FRAC_PI_4, LN_2},
+    fmt::{self, Display},
+    iter::{from_fn, once, repeat_n, Peekable},
+    mem::{replace, size_of_val, take},
+    ops::{Deref, DerefMut, Range},
+    slice::Iter,
+    str::from_utf8,
+};
 
-use cpal::Sample;
-use std::{borrow::Borrow, iter::Peekable, mem::replace, slice::Iter};
+use crate::dsp::Compressor;
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::func::{
+    noise, stretch_polynomial, Adsr, Amplitude, Envelope, Function, Interpolation, MultiPoly,
+    PhaseMode, Pieces, RunLength, Sine, Wave, Waveform, Wavetable,
+};
+
+type Voice<'a> = TimedWave<&'a [f32], Envelope<&'a [f32]>>;
+type Voices<'a> = Vec<Playing<'a>>;
+const DEFAULT_VOICES: usize = 64;
+const DEFAULT_STEAL_FADE: i64 = 64;
+// how many samples of a voice get rendered in one go
+const SPAN: usize = 64;
+// how many frames Mixer, Crossfader and Graph render in one go, on the stack where they can
+pub(crate) const MIX_BLOCK: usize = 256;
+// a wave that owns its coefficients, for things scheduled while playing
+pub type LiveWave = TimedWave<Vec<f32>, Envelope<Vec<f32>>>;
+// the end of a scheduled wave that holds until Player::note_off lets go of it, an adsr amp on one
+// wants an infinite gate
+pub const OPEN: i64 = i64::MAX;
+// an end moved by some samples, an OPEN one stays open however it's moved
+fn move_end(end: i64, by: i64) -> i64 {
+    if end == OPEN {
+        OPEN
+    } else {
+        end.saturating_add(by)
+    }
+}
 
-use crate::func::{Function, MultiPoly, Wave};
+// how the player works out each wave's samples, the same for every voice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Rendering {
+    bandlimited: bool,
+    sine: Sine,
+}
 
 #[derive(Debug, Clone)]
 pub struct Player<'a> {
     pack: PackedTimedWaves<'a>,
+    // pack before any of it played, for restore to find its place in
+    whole: PackedTimedWaves<'a>,
     time: i64,
-    wakeup: i64,
-    current: Vec<TimedWave<&'a [f32]>>,
+    // play stops here, with None it stops once there's nothing left to play
+    wakeup: Option<i64>,
+    current: Voices<'a>,
+    thief: Thief<'a>,
+    live: Vec<LiveWave>,
+    rendering: Rendering,
+    gain: f32,
+    clipping: Clipping,
+    dynamics: Option<Compressor>,
+    rates: Rates,
+    // set_wave_gain's gains by Mix::id, anything not in here plays at 1
+    wave_gains: Vec<(u32, f32)>,
+    // nothing gets recorded while this has no room at all
+    events: Vec<WaveEvent>,
+    // the ones the last block went past, sized for every marker the pack has so crossing them
+    // never allocates
+    markers: Vec<Marker<'a>>,
+    // queue_next's pack and the sample it takes over on
+    next: Option<(PackedTimedWaves<'a>, i64)>,
+    transport: Transport,
 }
 impl<'a> Player<'a> {
-    pub fn new(pack: PackedTimedWaves<'a>, time: i64, wakeup: i64) -> Self {
+    pub fn new(pack: PackedTimedWaves<'a>, time: i64, wakeup: Option<i64>) -> Self {
+        Self::with_voices(pack, time, wakeup, DEFAULT_VOICES)
+    }
+    // play never grows the voice store, see set_stealing for what happens once every voice is busy
+    pub fn with_voices(
+        pack: PackedTimedWaves<'a>,
+        time: i64,
+        wakeup: Option<i64>,
+        voices: usize,
+    ) -> Self {
+        let markers = Vec::with_capacity(pack.marker_times.len());
         Player {
+            whole: pack.clone(),
             pack,
             time,
             wakeup,
-            current: Vec::new(),
+            current: Vec::with_capacity(voices),
+            thief: Thief::new(Stealing::default(), DEFAULT_STEAL_FADE, voices),
+            live: Vec::with_capacity(voices),
+            rendering: Rendering::default(),
+            gain: 1.,
+            clipping: Clipping::default(),
+            dynamics: None,
+            rates: Rates::default(),
+            wave_gains: Vec::new(),
+            events: Vec::new(),
+            markers,
+            next: None,
+            transport: Transport::Playing,
+        }
+    }
+    pub fn set_bandlimited(&mut self, bandlimited: bool) {
+        self.rendering.bandlimited = bandlimited;
+    }
+    // trades some accuracy in sine shapes for speed, see Sine
+    pub fn set_sine(&mut self, sine: Sine) {
+        self.rendering.sine = sine;
+    }
+    // applied to the mix before clipping
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+    pub fn set_clipping(&mut self, clipping: Clipping) {
+        self.clipping = clipping;
+    }
+    // a compressor or limiter on the mix, before gain and clipping
+    pub fn set_dynamics(&mut self, dynamics: Option<Compressor>) {
+        self.dynamics = dynamics;
+    }
+    pub fn dynamics_mut(&mut self) -> Option<&mut Compressor> {
+        self.dynamics.as_mut()
+    }
+    // for a pack written at one rate played on a device running at another, the player's own clock
+    // (time, wakeup, current_time and live waves) counts device samples and the pack's waves get
+    // moved onto it as they start, lanes and all, set it before playing
+    // either rate being 0 leaves things as they were
+    pub fn set_sample_rates(&mut self, pack_rate: u32, device_rate: u32) {
+        if pack_rate > 0 && device_rate > 0 {
+            self.rates = Rates {
+                pack: pack_rate as i64,
+                device: device_rate as i64,
+                ..self.rates
+            };
+        }
+    }
+    // stolen voices fade out over fade samples instead of cutting off
+    pub fn set_stealing(&mut self, stealing: Stealing, fade: i64) {
+        self.thief.stealing = stealing;
+        self.thief.fade = fade;
+    }
+    // plays alongside the pack, anything starting in the past starts now
+    pub fn schedule(&mut self, mut wave: LiveWave) {
+        if wave.start < self.time {
+            let late = self.time - wave.start;
+            wave.start += late;
+            wave.end = wave.end.saturating_add(late);
+        }
+        self.live.push(wave);
+    }
+    // lets go of every wave with this Mix::id at time, or now if that's gone by, their releases
+    // starting there as if it had been their end all along
+    // meant for OPEN waves but it shortens any, waves still to come out of the pack play as usual
+    pub fn note_off(&mut self, id: u32, time: i64) {
+        if id == 0 {
+            return;
+        }
+        let (now, time) = (self.time, time.max(self.time));
+        for tw in self.live.iter_mut().filter(|tw| tw.mix.id == id) {
+            if tw.let_go(time, 1.) && tw.stop() <= now {
+                record(&mut self.events, WaveEvent::Stop { id, time: now });
+            }
+        }
+        let scale = self.rates.scale();
+        for tw in self.current.iter_mut().filter(|tw| tw.mix.id == id) {
+            if tw.let_go(time, scale) && tw.stop() <= now {
+                record(&mut self.events, WaveEvent::Stop { id, time: now });
+            }
+        }
+    }
+    // every wave with this Mix::id that's playing fades out like a stolen voice, and scheduled ones
+    // that haven't started yet are dropped
+    // waves still to come out of the pack play as usual, so a pack can repeat a sound under one id
+    pub fn stop_wave(&mut self, id: u32) {
+        if id == 0 {
+            return;
+        }
+        let (now, fade) = (self.time, self.thief.fade);
+        self.live.retain(|tw| tw.mix.id != id || tw.start < now);
+        for tw in self.live.iter_mut().filter(|tw| tw.mix.id == id) {
+            if tw.cut(now, fade) && fade <= 0 {
+                record(&mut self.events, WaveEvent::Stop { id, time: now });
+            }
+        }
+        let voices = self.current.iter_mut().chain(&mut self.thief.fading);
+        for tw in voices.filter(|tw| tw.mix.id == id) {
+            if tw.cut(now, fade) && fade <= 0 {
+                record(&mut self.events, WaveEvent::Stop { id, time: now });
+            }
+        }
+    }
+    // starts recording a WaveEvent for every wave that starts or stops while playing, up to
+    // capacity of them between take_events, past that they're dropped so play never allocates
+    // 0 stops recording
+    pub fn observe(&mut self, capacity: usize) {
+        self.events = Vec::with_capacity(capacity);
+    }
+    // what's happened since the last call, in order, for after every block
+    pub fn take_events(&mut self) -> Drain<'_, WaveEvent> {
+        self.events.drain(..)
+    }
+    // the pack to play once this one's done, timed from 0 at the handoff
+    // whatever's still sounding carries on and whatever hadn't started yet in the old pack never
+    // does, queueing again replaces what was queued
+    // hands back the sample it'll switch on
+    pub fn queue_next(&mut self, pack: PackedTimedWaves<'a>, handoff: Handoff) -> i64 {
+        let rates = self.rates;
+        let end = || {
+            let playing = self.current.iter().map(|tw| tw.end);
+            let waiting = self.pack.timings.clone();
+            let waiting = waiting.map(|&[_, end]| rates.to_device_time(end));
+            playing.chain(waiting).fold(self.time, i64::max)
+        };
+        let at = match handoff {
+            Handoff::End => end(),
+            Handoff::Marker => match self.pack.marker_times.peek() {
+                Some(&&time) => rates.to_device_time(time),
+                None => end(),
+            },
+            Handoff::At(time) => time,
+        };
+        let at = at.max(self.time);
+        // a block going over the handoff can cross what's left of this pack's markers and all of
+        // the next one's
+        let room = self.pack.marker_times.len() + pack.marker_times.len();
+        self.markers
+            .reserve(room.saturating_sub(self.markers.len()));
+        self.next = Some((pack, at));
+        at
+    }
+    fn hand_off(&mut self) {
+        let Some((pack, at)) = self.next.take() else {
+            return;
+        };
+        // a marker right on the handoff is still the old pack's
+        self.pack
+            .cross_markers((at, at + 1), self.rates, &mut self.markers);
+        self.whole = pack.clone();
+        self.pack = pack;
+        self.rates.origin = at;
+    }
+    // where playback's got to, to pick up from later with restore
+    // scheduled waves are kept whole, the pack's voices by where they are in the pack
+    // a pack waiting on queue_next isn't part of it
+    pub fn snapshot(&self) -> PlayerState {
+        let saved = |voices: &Voices| -> Vec<VoiceState> {
+            voices
+                .iter()
+                .map(|tw| VoiceState {
+                    index: tw.index,
+                    end: tw.end,
+                    release: tw.fade.release,
+                    gate: match tw.wave.amp {
+                        Envelope::Adsr(adsr) => Some(adsr.gate),
+                        Envelope::Poly(_) => None,
+                    },
+                })
+                .collect()
+        };
+        PlayerState {
+            time: self.time,
+            wakeup: self.wakeup,
+            origin: self.rates.origin,
+            waves: self.pack.taken,
+            markers: self.whole.marker_times.len() - self.pack.marker_times.len(),
+            voices: saved(&self.current),
+            fading: saved(&self.thief.fading),
+            live: self.live.clone(),
+            wave_gains: self.wave_gains.clone(),
+        }
+    }
+    // goes back to a snapshot of a player on the same pack, or of one that was playing it after a
+    // handoff, leaving the player as it was if the snapshot doesn't fit
+    pub fn restore(&mut self, state: &PlayerState) -> Result<(), RestoreError> {
+        if state.voices.len() > self.current.capacity()
+            || state.fading.len() > self.thief.fading.capacity()
+        {
+            return Err(RestoreError::TooManyVoices);
+        }
+        let rates = Rates {
+            origin: state.origin,
+            ..self.rates
+        };
+        let mut pack = self.whole.clone();
+        let (mut current, mut fading) = (Vec::new(), Vec::new());
+        let mut waves = pack.sample_at(i64::MAX, rates);
+        for index in 0..state.waves {
+            let voice = waves.next().ok_or(RestoreError::WrongPack)?;
+            for (saved, into) in [(&state.voices, &mut current), (&state.fading, &mut fading)] {
+                for saved in saved.iter().filter(|v| v.index == index) {
+                    let mut voice = voice.clone();
+                    voice.end = saved.end;
+                    voice.fade.release = saved.release;
+                    if let (Envelope::Adsr(adsr), Some(gate)) = (&mut voice.wave.amp, saved.gate) {
+                        adsr.gate = gate;
+                    }
+                    into.push(Playing { index, voice });
+                }
+            }
+        }
+        if current.len() != state.voices.len() || fading.len() != state.fading.len() {
+            return Err(RestoreError::WrongPack);
+        }
+        for _ in 0..state.markers {
+            pack.marker_times.next().ok_or(RestoreError::WrongPack)?;
+            pack.marker_names.next();
+        }
+        self.pack = pack;
+        self.time = state.time;
+        self.wakeup = state.wakeup;
+        self.rates = rates;
+        self.current.clear();
+        self.current.append(&mut current);
+        self.thief.fading.clear();
+        self.thief.fading.append(&mut fading);
+        self.live.clone_from(&state.live);
+        self.wave_gains.clone_from(&state.wave_gains);
+        self.events.clear();
+        self.markers.clear();
+        self.next = None;
+        Ok(())
+    }
+    // the pack's markers the last play went past, in order and on the player's clock
+    pub fn markers(&self) -> &[Marker<'a>] {
+        &self.markers
+    }
+    // scales every wave with this Mix::id, ones that start later included, from the next block on
+    // 1 puts them back as they were
+    pub fn set_wave_gain(&mut self, id: u32, gain: f32) {
+        self.wave_gains.retain(|&(i, _)| i != id);
+        if id != 0 && gain != 1. {
+            self.wave_gains.push((id, gain));
+        }
+    }
+    #[cfg(feature = "live")]
+    pub(crate) fn live_full(&self) -> bool {
+        self.live.len() == self.live.capacity()
+    }
+    // moves where play stops, so a player that got there can carry on
+    pub fn set_wakeup(&mut self, wakeup: Option<i64>) {
+        self.wakeup = wakeup;
+    }
+    // whatever of the pack hasn't finished playing, the voices that are partway through included,
+    // as a packer on the pack's clock, the player's left with nothing from it
+    // scheduled waves and a pack waiting on queue_next stay with the player, and so do voices
+    // that were stolen since they're only fading out
+    // after play stops at a wakeup this is what the wakeup cut off
+    pub fn drain(&mut self) -> TimedWavePacker {
+        let now = self.time;
+        self.current.retain(|tw| tw.stop() > now);
+        let voices = self.current.drain(..).collect();
+        take(&mut self.pack).unravel(voices, self.rates)
+    }
+    // when the wakeup comes, or with no wakeup everything's finished, before the output's full, the
+    // rest of it is silenced and handed back, the clock staying put from there
+    // that's not a failure, anything mixing players can ignore it and carry on with silence, and
+    // whatever of the pack never got played can still be taken with drain
+    // plain f32s, anything else gets converted after, see playback::convert for cpal's formats
+    pub fn play<'b>(&mut self, output: &'b mut [f32]) -> Result<(), &'b mut [f32]> {
+        let (frames, _) = output.as_chunks_mut::<1>();
+        self.render(frames).map_err(|rest| rest.as_flattened_mut())
+    }
+    // left and right frames, each wave is placed by its pan lane
+    pub fn play_stereo<'b>(
+        &mut self,
+        output: &'b mut [[f32; 2]],
+    ) -> Result<(), &'b mut [[f32; 2]]> {
+        self.render(output)
+    }
+    // play_stereo into separate left and right buffers, as far as the shorter goes
+    // an early end hands back how many frames played, everything after them is silent
+    pub fn play_planar(&mut self, left: &mut [f32], right: &mut [f32]) -> Result<(), usize> {
+        let length = left.len().min(right.len());
+        let mut frames = [[0f32; 2]; SPAN];
+        for from in (0..length).step_by(SPAN) {
+            let count = SPAN.min(length - from);
+            let frames = &mut frames[..count];
+            let result = self.render(frames).map_err(|rest| count - rest.len());
+            for (i, [l, r]) in frames.iter().enumerate() {
+                (left[from + i], right[from + i]) = (*l, *r);
+            }
+            if let Err(played) = result {
+                left[from + count..length].fill(0.);
+                right[from + count..length].fill(0.);
+                return Err(from + played);
+            }
         }
+        Ok(())
     }
-    //this actually doesn't work at all when the buffer runs out
-    pub fn play<'b, N: Sample>(
+    fn render<'b, const C: usize>(
         &mut self,
-        output: &'b mut [N],
-    ) -> Result<(), (TimedWavePacker, &'b mut [N])> {
-        let mut current = replace(&mut self.current, Vec::new());
+        output: &'b mut [[f32; C]],
+    ) -> Result<(), &'b mut [[f32; C]]> {
+        self.markers.clear();
+        if matches!(self.transport, Transport::Paused | Transport::Stopped) {
+            output.fill([0.; C]);
+            return Ok(());
+        }
+        let mut current = take(&mut self.current);
         let mut buffer = output;
         loop {
-            match self.pack.deposit_current(current, self.time, self.wakeup) {
-                Ok((c, next_pause)) => {
-                    let start_time = self.time;
-                    let valid_for = next_pause - start_time;
-                    let cut = buffer.len().min(valid_for as usize);
-                    current = c;
-                    let (working, future) = buffer.split_at_mut(cut);
-                    buffer = future;
-                    self.time += cut as i64;
-                    for (current_sample, time) in working.iter_mut().zip(start_time..) {
-                        let sample_value = current.iter().map(|tw| tw.eval(time)).sum::<f32>();
-                        *current_sample = Sample::from(&(sample_value as f32));
+            if self.next.as_ref().is_some_and(|&(_, at)| self.time >= at) {
+                self.hand_off();
+            }
+            let now = self.time;
+            let reached = self.wakeup.is_some_and(|wakeup| now >= wakeup);
+            let (c, next_pause) = if reached {
+                (current, now)
+            } else {
+                self.pack.deposit_current(
+                    current,
+                    now,
+                    self.wakeup.unwrap_or(i64::MAX),
+                    &mut self.thief,
+                    self.rates,
+                )
+            };
+            current = c;
+            // with no wakeup the last scheduled or stolen wave to stop is where it all ends
+            let stops = self.live.iter().map(|tw| tw.stop());
+            let stops = stops.chain(self.thief.fading.iter().map(|tw| tw.stop()));
+            let ending = stops.filter(|&stop| stop > now).min();
+            let finished = self.wakeup.is_none()
+                && self.next.is_none()
+                && current.is_empty()
+                && ending.is_none()
+                && self.pack.timings.peek().is_none();
+            if reached || finished {
+                buffer.fill([0.; C]);
+                self.current = current;
+                self.live.retain(|tw| tw.stop() > now);
+                self.thief.fading.retain(|tw| tw.stop() > now);
+                return Err(buffer);
+            }
+            let next_pause = match (self.wakeup, ending) {
+                (None, Some(ending)) => next_pause.min(ending),
+                _ => next_pause,
+            };
+            let next_pause = match self.next {
+                Some((_, at)) => next_pause.min(at),
+                None => next_pause,
+            };
+            let start_time = self.time;
+            let valid_for = next_pause - start_time;
+            let cut = buffer.len().min(valid_for as usize);
+            let (working, future) = buffer.split_at_mut(cut);
+            buffer = future;
+            self.time += cut as i64;
+            self.pack
+                .cross_markers((start_time, self.time), self.rates, &mut self.markers);
+            if self.events.capacity() > 0 {
+                let (from, to) = (start_time, self.time);
+                let recorded = self.events.len();
+                let voices = current.iter().chain(&self.thief.fading);
+                for (id, start, stop) in voices
+                    .map(|tw| (tw.mix.id, tw.start, tw.stop()))
+                    .chain(self.live.iter().map(|tw| (tw.mix.id, tw.start, tw.stop())))
+                {
+                    // a wave that never gets a sample in is left out
+                    if stop <= start {
+                        continue;
+                    }
+                    if (from..to).contains(&start) {
+                        record(&mut self.events, WaveEvent::Start { id, time: start });
                     }
-                    if buffer.len() == 0 {
-                        self.current = current;
-                        return Ok(());
+                    // a voice stopping right on to is gone before the next span gets looked at
+                    if from < stop && stop <= to {
+                        record(&mut self.events, WaveEvent::Stop { id, time: stop });
                     }
                 }
-                Err(packer) => return Err((packer, buffer)),
+                self.events[recorded..].sort_unstable_by_key(|e| e.sort_key());
+            }
+            // one voice at a time over a short span, so each voice's samples come out
+            // together and the sines can be done a handful at once
+            for (frames, span_start) in working.chunks_mut(SPAN).zip((start_time..).step_by(SPAN)) {
+                let mut mix = [[0f32; C]; SPAN];
+                let mut scratch = [0f32; SPAN];
+                let mix = &mut mix[..frames.len()];
+                let scratch = &mut scratch[..frames.len()];
+                let scale = self.rates.scale();
+                let gains = &self.wave_gains;
+                let level = |id| gains.iter().find(|g| g.0 == id).map_or(1., |g| g.1);
+                for tw in current.iter().chain(&self.thief.fading) {
+                    let level = level(tw.mix.id);
+                    tw.accumulate(span_start, scratch, mix, self.rendering, scale, level);
+                }
+                for tw in &self.live {
+                    let level = level(tw.mix.id);
+                    tw.accumulate(span_start, scratch, mix, self.rendering, 1., level);
+                }
+                if let Some(dynamics) = &mut self.dynamics {
+                    dynamics.run(mix);
+                }
+                for (frame, channels) in frames.iter_mut().zip(mix.iter()) {
+                    let gain = self.gain * self.transport.step();
+                    for (out, channel) in frame.iter_mut().zip(channels) {
+                        *out = self.clipping.apply(channel * gain);
+                    }
+                }
+            }
+            if buffer.is_empty() {
+                self.current = current;
+                let now = self.time;
+                self.live.retain(|tw| tw.stop() > now);
+                self.thief.fading.retain(|tw| tw.stop() > now);
+                return Ok(());
             }
         }
     }
     pub fn current_time(&self) -> i64 {
         self.time
     }
+    // play puts out silence without the clock moving until resume
+    pub fn pause(&mut self) {
+        if self.transport == Transport::Playing {
+            self.transport = Transport::Paused;
+        }
+    }
+    // carries on after pause, or after stop as if it had never happened
+    pub fn resume(&mut self) {
+        self.transport = Transport::Playing;
+    }
+    // the mix fades out over fade samples from the next block on, then it's finished and play
+    // puts out silence like when paused
+    pub fn stop(&mut self, fade: i64) {
+        self.transport = match fade {
+            ..=0 => Transport::Stopped,
+            _ => Transport::Stopping { left: fade, fade },
+        };
+    }
+    pub fn is_paused(&self) -> bool {
+        self.transport == Transport::Paused
+    }
+    pub fn is_finished(&self) -> bool {
+        self.transport == Transport::Stopped
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transport {
+    Playing,
+    Paused,
+    // left samples to go of a fade fade long
+    Stopping { left: i64, fade: i64 },
+    Stopped,
+}
+impl Transport {
+    // the gain for the next sample
+    fn step(&mut self) -> f32 {
+        match self {
+            Transport::Playing => 1.,
+            Transport::Paused | Transport::Stopped => 0.,
+            Transport::Stopping { left, fade } => {
+                let gain = *left as f32 / *fade as f32;
+                *left -= 1;
+                if *left <= 0 {
+                    *self = Transport::Stopped;
+                }
+                gain
+            }
+        }
+    }
+}
+// a named position in a pack, for cues like a drop or a loop point that game logic waits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Marker<'a> {
+    pub time: i64,
+    pub name: &'a str,
+}
+
+// a wave starting or stopping while a player plays it, with its Mix::id and the sample it happens
+// on, a stop is the first sample after its release
+// waves already going when playing starts never get a start, and a stolen voice that can't fade
+// never gets a stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaveEvent {
+    Start { id: u32, time: i64 },
+    Stop { id: u32, time: i64 },
+}
+impl WaveEvent {
+    pub fn time(&self) -> i64 {
+        match *self {
+            WaveEvent::Start { time, .. } | WaveEvent::Stop { time, .. } => time,
+        }
+    }
+    pub fn id(&self) -> u32 {
+        match *self {
+            WaveEvent::Start { id, .. } | WaveEvent::Stop { id, .. } => id,
+        }
+    }
+    // stops before starts on the same sample
+    fn sort_key(&self) -> (i64, bool) {
+        (self.time(), matches!(self, WaveEvent::Start { .. }))
+    }
+}
+// only what there's already room for goes in, so play never allocates
+fn record<T>(list: &mut Vec<T>, item: T) {
+    if list.len() < list.capacity() {
+        list.push(item);
+    }
+}
+
+// when Player::queue_next's pack takes over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Handoff {
+    // once every wave in the playing pack has got to its end, releases carry on underneath
+    #[default]
+    End,
+    // on the playing pack's next marker, or its end when there isn't one
+    Marker,
+    // on a sample of the player's clock, like a bar line from a Timeline
+    At(i64),
+}
+
+// a voice out of the pack and which of the pack's waves it is, so snapshots can find it again
+#[derive(Debug, Clone, PartialEq)]
+struct Playing<'a> {
+    index: usize,
+    voice: Voice<'a>,
+}
+impl<'a> Deref for Playing<'a> {
+    type Target = Voice<'a>;
+    fn deref(&self) -> &Voice<'a> {
+        &self.voice
+    }
+}
+impl DerefMut for Playing<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.voice
+    }
+}
+
+// everything Player::restore needs to carry on from where Player::snapshot was taken
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlayerState {
+    time: i64,
+    wakeup: Option<i64>,
+    origin: i64,
+    // how many of the pack's waves and markers had come out
+    waves: usize,
+    markers: usize,
+    voices: Vec<VoiceState>,
+    fading: Vec<VoiceState>,
+    live: Vec<LiveWave>,
+    wave_gains: Vec<(u32, f32)>,
+}
+impl PlayerState {
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+}
+// a pack voice by its place in the pack, with what stealing, stop_wave or note_off did to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct VoiceState {
+    index: usize,
+    end: i64,
+    release: i64,
+    gate: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreError {
+    // the snapshot has waves or markers this player's pack doesn't
+    WrongPack,
+    // more voices were going than this player has room for
+    TooManyVoices,
+}
+impl Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestoreError::WrongPack => write!(f, "the snapshot was taken playing a different pack"),
+            RestoreError::TooManyVoices => {
+                write!(
+                    f,
+                    "the snapshot has more voices than the player has room for"
+                )
+            }
+        }
+    }
+}
+impl Error for RestoreError {}
+
+// what the player does with a wave that starts while every voice is busy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Stealing {
+    // the new wave never plays
+    Skip,
+    // the voice that started first makes way
+    #[default]
+    Oldest,
+    // the voice with the lowest amplitude envelope right now makes way
+    Quietest,
+}
+
+// makes room for new voices, holding onto the ones it takes while they fade out
+#[derive(Debug, Clone)]
+struct Thief<'a> {
+    stealing: Stealing,
+    fade: i64,
+    fading: Voices<'a>,
+}
+impl<'a> Thief<'a> {
+    fn new(stealing: Stealing, fade: i64, voices: usize) -> Self {
+        Thief {
+            stealing,
+            fade,
+            fading: Vec::with_capacity(voices),
+        }
+    }
+    fn admit(&mut self, store: &mut Voices<'a>, wave: Playing<'a>, time: i64, scale: f32) {
+        if store.len() < store.capacity() {
+            store.push(wave);
+            return;
+        }
+        let victim = match self.stealing {
+            Stealing::Skip => None,
+            Stealing::Oldest => (0..store.len()).min_by_key(|&i| store[i].start),
+            Stealing::Quietest => (0..store.len()).min_by(|&a, &b| {
+                store[a]
+                    .level(time, scale)
+                    .total_cmp(&store[b].level(time, scale))
+            }),
+        };
+        let Some(victim) = victim else {
+            return;
+        };
+        let mut stolen = replace(&mut store[victim], wave);
+        // a voice that can't fade because too many already are just gets cut
+        if self.fading.len() < self.fading.capacity() && self.fade > 0 {
+            stolen.end = time;
+            stolen.fade.release = self.fade;
+            self.fading.push(stolen);
+        }
+    }
+}
+
+// the pack's sample rate against the device's, same for both when nothing needs converting, and
+// the device sample the pack's 0 lands on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rates {
+    pack: i64,
+    device: i64,
+    origin: i64,
+}
+impl Default for Rates {
+    fn default() -> Self {
+        Rates {
+            pack: 1,
+            device: 1,
+            origin: 0,
+        }
+    }
+}
+impl Rates {
+    // to the nearest sample, pushed out to the ends for anything that won't fit
+    fn convert(time: i64, from: i64, to: i64) -> i64 {
+        if from == to {
+            return time;
+        }
+        let scaled = (time as i128 * to as i128 * 2 + from as i128).div_euclid(from as i128 * 2);
+        scaled.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+    fn to_device_time(self, time: i64) -> i64 {
+        Self::convert(time, self.pack, self.device).saturating_add(self.origin)
+    }
+    fn to_pack_time(self, time: i64) -> i64 {
+        Self::convert(time.saturating_sub(self.origin), self.device, self.pack)
+    }
+    // pack samples per device sample
+    fn scale(self) -> f32 {
+        (self.pack as f64 / self.device as f64) as f32
+    }
+    // the fades are lengths, so they only get scaled, and an OPEN end stays open
+    fn retime<T, A, F>(
+        mut tw: TimedWave<T, A, F>,
+        time: impl Fn(i64) -> i64,
+        length: impl Fn(i64) -> i64,
+    ) -> TimedWave<T, A, F> {
+        tw.start = time(tw.start);
+        if tw.end != OPEN {
+            tw.end = time(tw.end);
+        }
+        tw.fade.fade_in = length(tw.fade.fade_in);
+        tw.fade.release = length(tw.fade.release);
+        tw
+    }
+    fn to_device<T, A, F>(self, tw: TimedWave<T, A, F>) -> TimedWave<T, A, F> {
+        Self::retime(
+            tw,
+            |t| self.to_device_time(t),
+            |l| Self::convert(l, self.pack, self.device),
+        )
+    }
+    fn to_pack<T, A, F>(self, tw: TimedWave<T, A, F>) -> TimedWave<T, A, F> {
+        Self::retime(
+            tw,
+            |t| self.to_pack_time(t),
+            |l| Self::convert(l, self.device, self.pack),
+        )
+    }
+}
+
+// what happens to a mix that goes past full scale before it's converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Clipping {
+    // passes everything through, for float output with headroom to spare
+    Off,
+    #[default]
+    Hard,
+    // cubic waveshaper, unity gain around zero and flat at full scale from 1.5 on
+    Soft,
+}
+impl Clipping {
+    pub fn apply(self, sample: f32) -> f32 {
+        match self {
+            Clipping::Off => sample,
+            Clipping::Hard => sample.clamp(-1., 1.),
+            Clipping::Soft => {
+                // written out so x^3 stays exact and 1.5 lands right on full scale
+                let x = sample.clamp(-1.5, 1.5);
+                x - 4. / 27. * (x * x * x)
+            }
+        }
+    }
+}
+
+// the wave most tests play, with freq 1 every sample lands on the sine's peak so it plays amp for
+// as long as it lasts, tests change whatever else they need on top
+#[cfg(test)]
+impl LiveWave {
+    fn sine(start: i64, end: i64, freq: f32, amp: f32) -> Self {
+        TimedWave {
+            start,
+            end,
+            wave: Wave {
+                freq: vec![freq],
+                amp: Envelope::Poly(vec![amp]),
+                phase: 0.25,
+                mode: PhaseMode::Product,
+                shape: Waveform::Sine,
+                width: vec![],
+            },
+            fade: Fade::default(),
+            mix: Mix::default(),
+            modulation: Modulation::default(),
+            table: Wavetable::default(),
+        }
+    }
+}
+
+#[test]
+fn clipping() {
+    assert_eq!(Clipping::Off.apply(3.), 3.);
+    assert_eq!(Clipping::Hard.apply(-3.), -1.);
+    assert_eq!(Clipping::Soft.apply(0.), 0.);
+    assert_eq!(Clipping::Soft.apply(1.5), 1.);
+    assert_eq!(Clipping::Soft.apply(-7.), -1.);
+    // close to linear for quiet signals, squashed near the top
+    assert!((Clipping::Soft.apply(0.1) - 0.1).abs() < 1e-3);
+    assert!(Clipping::Soft.apply(1.) < 1.);
+
+    let waves: TimedWavePacker = [(0, 4), (1, 4)]
+        .into_iter()
+        .map(|(start, end)| LiveWave::sine(start, end, 1., 1.))
+        .collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(20));
+    player.set_gain(0.75);
+    let mut playback = [0.; 3];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.75, 1., 1.]);
 }
+
 #[test]
 fn buffer_writing() {
-    let wave = Wave {
-        freq: &[1.][..],
-        amp: &[0.25][..],
-        phase: 0.25,
-    };
     let waves: TimedWavePacker = [(0, 6), (5, 8), (7, 9), (8, 12)]
         .into_iter()
-        .map(|(start, end)| TimedWave {
-            start,
-            end,
-            wave: wave.clone(),
-        })
+        .map(|(start, end)| LiveWave::sine(start, end, 1., 0.25))
         .collect();
     let waves = waves.get_pack().unwrap();
-    let mut player = Player::new(waves, 0, 11);
+    let mut player = Player::new(waves, 0, Some(11));
     let mut playback = [0.; 7];
     player.play(&mut playback).unwrap();
     assert_eq!(playback, [0.25, 0.25, 0.25, 0.25, 0.25, 0.5, 0.25]);
 }
 
+#[test]
+fn long_waves() {
+    // an hour in at 48k, way past where f32 can count samples
+    let start = 48000 * 3600;
+    for mode in [PhaseMode::Product, PhaseMode::Integral] {
+        let mut wave = LiveWave::sine(0, 2 * start, 0.0123, 0.5);
+        wave.wave.mode = mode;
+        let waves: TimedWavePacker = [wave].into_iter().collect();
+        let mut player = Player::new(waves.get_pack().unwrap(), start, None);
+        let mut playback = [0.; 200];
+        player.play(&mut playback).unwrap();
+        for (i, sample) in playback.into_iter().enumerate() {
+            let cycles = ((start + i as i64) as f64 + 0.25) * 0.0123f32 as f64;
+            let expected = 0.5 * (core::f64::consts::TAU * cycles).sin();
+            assert!((sample as f64 - expected).abs() < 1e-4, "{:?} {}", mode, i);
+        }
+    }
+}
+
+#[test]
+fn sine_choices() {
+    let sine = LiveWave::sine(0, 300, 0., 0.5);
+    let sweep = TimedWave {
+        wave: Wave {
+            freq: vec![0.0123, 0.0001],
+            phase: 0.,
+            mode: PhaseMode::Integral,
+            ..sine.wave
+        },
+        ..sine
+    };
+    let waves: TimedWavePacker = [sweep].into_iter().collect();
+    let play = |sine, bandlimited| {
+        let mut player = Player::new(waves.get_pack().unwrap(), 0, None);
+        player.set_sine(sine);
+        player.set_bandlimited(bandlimited);
+        let mut out = [0.; 300];
+        let _ = player.play(&mut out);
+        out
+    };
+    let exact = play(Sine::Exact, false);
+    for sine in [Sine::Polynomial, Sine::Table] {
+        for bandlimited in [false, true] {
+            let out = play(sine, bandlimited);
+            let close = out.iter().zip(&exact).all(|(a, b)| (a - b).abs() < 1e-5);
+            assert!(close, "{:?}", sine);
+        }
+    }
+}
+
+#[test]
+fn envelope_playback() {
+    let mut wave = LiveWave::sine(0, 8, 1., 1.);
+    wave.wave.amp = Envelope::Adsr(Adsr {
+        peak: 1.,
+        attack: 2.,
+        decay: 2.,
+        sustain: 0.5,
+        gate: 6.,
+        release: 2.,
+    });
+    let waves: TimedWavePacker = [wave].into_iter().collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(20));
+    let mut playback = [0.; 9];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0., 0.5, 1., 0.75, 0.5, 0.5, 0.5, 0.25, 0.]);
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub struct TimedWave<T> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+// freq gets its own type so it can be something like a Piecewise, everything else that isn't amp is T
+pub struct TimedWave<T, A = T, F = T> {
     pub start: i64,
     pub end: i64,
-    pub wave: Wave<T, T>,
+    pub wave: Wave<F, A, T>,
+    pub fade: Fade,
+    pub mix: Mix<T>,
+    pub modulation: Modulation<T>,
+    // played instead of the wave's shape unless it's empty, the width only matters to the shape
+    pub table: Wavetable<T>,
+}
+impl<T, A, F> TimedWave<T, A, F> {
+    // when the voice actually goes quiet, release included
+    pub fn stop(&self) -> i64 {
+        self.end.saturating_add(self.fade.release)
+    }
+    // so an OPEN end doesn't overflow
+    fn length(&self) -> i64 {
+        self.end.saturating_sub(self.start)
+    }
+    // ends the wave at time with a release of fade, unless it's already ending by then
+    fn cut(&mut self, time: i64, fade: i64) -> bool {
+        let cutting = self.end > time;
+        if cutting {
+            self.end = time;
+            self.fade.release = fade;
+        }
+        cutting
+    }
 }
-impl<T: Borrow<[f32]>> TimedWave<T> {
-    fn eval(&self, time: i64) -> f32 {
+impl<T, F> TimedWave<T, Envelope<T>, F> {
+    // ends the wave at time if it was going on any longer, an adsr amp gets its gate closed there
+    // and the wave lasts out its release, scale is as in carrier
+    fn let_go(&mut self, time: i64, scale: f32) -> bool {
+        let time = time.max(self.start);
+        if self.end <= time {
+            return false;
+        }
+        match &mut self.wave.amp {
+            Envelope::Adsr(adsr) => {
+                let held = (time - self.start) as f32 * scale;
+                if held >= adsr.gate {
+                    return false;
+                }
+                adsr.gate = held;
+                let release = (adsr.release / scale).ceil() as i64;
+                self.end = self.end.min(time.saturating_add(release));
+            }
+            Envelope::Poly(_) => self.end = time,
+        }
+        true
+    }
+}
+impl<T: Borrow<[f32]>, A: Function, F: Function> TimedWave<T, A, F> {
+    // scale is how many of the wave's samples go by in one of the player's, the lanes get
+    // read at the stretched time while start, end and the fades are already on the player's clock
+    // wave time is kept in f64 up to where the oscillator's worked out, so a long wave doesn't
+    // lose its pitch once the samples since its start outgrow f32, the lanes get it as f32
+    fn wave_time(&self, time: i64, scale: f32) -> f64 {
+        (time - self.start) as f64 * scale as f64
+    }
+    fn carrier(&self, t: f64) -> Carrier {
+        Carrier {
+            cycles: self.wave.cycles_f64(t),
+            freq: self.wave.freq.eval(t as f32),
+        }
+    }
+    // where the oscillator is at wave time t, modulation included
+    fn cycles(&self, t: f64) -> f64 {
+        let carrier = self.carrier(t);
+        carrier.cycles + self.modulation.cycles_at(t, carrier)
+    }
+    fn amp(&self, t: f64) -> f32 {
+        self.wave.amp.eval(t as f32) * self.modulation.amp_at(t, self.carrier(t))
+    }
+    // a looping table only cares where in the cycle it is, a one shot needs to know it's done
+    fn wrap(&self, cycles: f64) -> f32 {
+        if self.table.is_empty() {
+            self.wave.shape.wrap(cycles)
+        } else if self.table.one_shot {
+            cycles as f32
+        } else {
+            (cycles - cycles.floor()) as f32
+        }
+    }
+    // a pulse packed without a width is an even square instead of sitting at -1 the whole time
+    fn width_at(&self, t: f32) -> f32 {
+        match self.wave.shape {
+            Waveform::Pulse if self.wave.width.borrow().is_empty() => 0.5,
+            _ => self.wave.width_at(t),
+        }
+    }
+    fn shape_at(&self, t: f32, cycles: f64, sine: Sine) -> f32 {
+        let cycles = self.wrap(cycles);
+        if !self.table.is_empty() {
+            self.table.at(cycles)
+        } else if self.wave.shape == Waveform::Sine {
+            sine.at(cycles)
+        } else {
+            self.wave.shape.at(cycles, self.width_at(t))
+        }
+    }
+    fn eval(&self, time: i64, scale: f32, sine: Sine) -> f32 {
+        let adjusted = time - self.start;
+        let t = self.wave_time(time, scale);
+        self.amp(t)
+            * self.shape_at(t as f32, self.cycles(t), sine)
+            * self.fade.gain(adjusted, self.length())
+            * self.mix.gain_at(t as f32)
+    }
+    // one player sample is scale units of wave time
+    fn eval_bandlimited(&self, time: i64, scale: f32, sine: Sine) -> f32 {
+        let adjusted = time - self.start;
+        let t = self.wave_time(time, scale);
+        let cycles = self.cycles(t);
+        let dt = (self.cycles(t + scale as f64) - cycles).abs() as f32;
+        let (cycles, width_t) = (self.wrap(cycles), t as f32);
+        let shape = if !self.table.is_empty() {
+            self.table.at_bandlimited(cycles, dt)
+        } else if self.wave.shape == Waveform::Sine && dt < 0.5 {
+            // a sine has nothing to correct, it just goes quiet past nyquist
+            sine.at(cycles)
+        } else {
+            self.wave
+                .shape
+                .at_bandlimited(cycles, self.width_at(width_t), dt)
+        };
+        self.amp(t) * shape * self.fade.gain(adjusted, self.length()) * self.mix.gain_at(t as f32)
+    }
+    // how loud the voice is meant to be right now, leaving out where the oscillator happens to be
+    fn level(&self, time: i64, scale: f32) -> f32 {
         let adjusted = time - self.start;
-        self.wave.eval(adjusted as f32)
+        let t = self.wave_time(time, scale);
+        let gain = self.fade.gain(adjusted, self.length()) * self.mix.gain_at(t as f32);
+        (self.amp(t) * gain).abs()
+    }
+    // how much of the wave goes to each of C channels, only stereo looks at the pan
+    fn spread<const C: usize>(&self, time: i64, scale: f32) -> [f32; C] {
+        let mut gains = [1.; C];
+        if let [left, right] = &mut gains[..] {
+            let pan = self.mix.pan_at(self.wave_time(time, scale) as f32);
+            // equal power, so a wave keeps its loudness as it moves across
+            let angle = (pan.clamp(-1., 1.) + 1.) * FRAC_PI_4;
+            (*left, *right) = (angle.cos(), angle.sin());
+        }
+        gains
+    }
+    fn sample(&self, time: i64, rendering: Rendering, scale: f32) -> f32 {
+        if rendering.bandlimited {
+            self.eval_bandlimited(time, scale, rendering.sine)
+        } else {
+            self.eval(time, scale, rendering.sine)
+        }
+    }
+    // fills values with the samples from time on, same as calling sample for each
+    fn sample_block(&self, time: i64, values: &mut [f32], rendering: Rendering, scale: f32) {
+        #[cfg(feature = "simd")]
+        if !rendering.bandlimited && self.wave.shape == Waveform::Sine && self.table.is_empty() {
+            return self.sine_block(time, values, scale, rendering.sine);
+        }
+        for (value, time) in values.iter_mut().zip(time..) {
+            *value = self.sample(time, rendering, scale);
+        }
+    }
+    // the phases get worked out one by one but the sines are done eight at a time,
+    // the phase is brought back near zero first so it's less accurate than sin only far from it
+    // the cheaper sines are one lane at a time anyway so they only get the multiplying done together
+    #[cfg(feature = "simd")]
+    fn sine_block(&self, time: i64, values: &mut [f32], scale: f32, sine: Sine) {
+        use wide::f32x8;
+
+        // the last chunk gets padded so every sample takes the same path wherever the block starts
+        for (chunk, time) in values.chunks_mut(8).zip((time..).step_by(8)) {
+            let (mut cycles, mut amps, mut gains) = ([0f32; 8], [0f32; 8], [0f32; 8]);
+            for (i, time) in (time..time + chunk.len() as i64).enumerate() {
+                let adjusted = time - self.start;
+                let t = self.wave_time(time, scale);
+                cycles[i] = self.wrap(self.cycles(t));
+                amps[i] = self.amp(t);
+                gains[i] = self.fade.gain(adjusted, self.length()) * self.mix.gain_at(t as f32);
+            }
+            let sines = match sine {
+                Sine::Exact => {
+                    let cycles = f32x8::from(cycles);
+                    ((cycles - cycles.round()) * core::f32::consts::TAU).sin()
+                }
+                _ => f32x8::from(cycles.map(|c| sine.at(c))),
+            };
+            let samples = (f32x8::from(amps) * sines * f32x8::from(gains)).to_array();
+            chunk.copy_from_slice(&samples[..chunk.len()]);
+        }
+    }
+    // adds whatever part of the wave falls in the span starting at time onto mix, times level
+    fn accumulate<const C: usize>(
+        &self,
+        time: i64,
+        scratch: &mut [f32],
+        mix: &mut [[f32; C]],
+        rendering: Rendering,
+        scale: f32,
+        level: f32,
+    ) {
+        let len = mix.len() as i64;
+        let from = (self.start - time).clamp(0, len) as usize;
+        let to = (self.stop() - time).clamp(0, len) as usize;
+        if from >= to {
+            return;
+        }
+        let first = time + from as i64;
+        let values = &mut scratch[from..to];
+        self.sample_block(first, values, rendering, scale);
+        for ((channels, value), time) in mix[from..to].iter_mut().zip(values).zip(first..) {
+            for (channel, gain) in channels.iter_mut().zip(self.spread::<C>(time, scale)) {
+                *channel += *value * gain * level;
+            }
+        }
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-pub struct TimedWavePacker {
-    pub timings: Vec<(i64, i64)>,
-    pub freq_coef: Vec<f32>,
-    pub freq_runs: Vec<u8>,
-    pub amp_coef: Vec<f32>,
-    pub amp_runs: Vec<u8>,
-    pub phases: Vec<f32>,
+#[cfg(feature = "simd")]
+#[test]
+fn simd_sines() {
+    let wave: Voice = TimedWave {
+        start: 10,
+        end: 5000,
+        wave: Wave {
+            freq: &[0.0123, 0.000001][..],
+            amp: Envelope::Poly(&[0.5, 0.0001][..]),
+            phase: 0.1,
+            mode: PhaseMode::Integral,
+            shape: Waveform::Sine,
+            width: &[][..],
+        },
+        fade: Fade::default(),
+        mix: Mix::default(),
+        modulation: Modulation::default(),
+        table: Wavetable::default(),
+    };
+    let mut block = [0f32; 4990];
+    wave.sample_block(10, &mut block, Rendering::default(), 1.);
+    for (value, time) in block.into_iter().zip(10..) {
+        assert!((value - wave.sample(time, Rendering::default(), 1.)).abs() < 1e-4);
+    }
 }
-impl<'a> TimedWavePacker {
-    pub fn new() -> Self {
-        Self::default()
+
+// per wave mix polynomials over the wave's own time
+// an empty gain leaves the wave alone, pan runs from -1 (left) to 1 (right) and empty is centred
+// id is a handle for Player::stop_wave and set_wave_gain to find the wave by while it plays, any
+// number of waves can share one and 0 is none
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mix<T> {
+    pub gain: T,
+    pub pan: T,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub id: u32,
+}
+impl<T: Borrow<[f32]>> Mix<T> {
+    pub fn gain_at(&self, t: f32) -> f32 {
+        let gain = self.gain.borrow();
+        if gain.is_empty() {
+            1.
+        } else {
+            gain.eval(t)
+        }
     }
-    pub fn get_pack(&'a self) -> Option<PackedTimedWaves<'a>> {
-        let TimedWavePacker {
-            timings,
-            freq_coef,
-            freq_runs,
-            amp_coef,
-            amp_runs,
-            phases,
-        } = self;
-        PackedTimedWaves::new(timings, freq_coef, freq_runs, amp_coef, amp_runs, phases)
+    pub fn pan_at(&self, t: f32) -> f32 {
+        self.pan.borrow().eval(t)
+    }
+}
+
+// per wave modulation, an lfo pushing the wave's amp, freq and phase around by a depth polynomial
+// over the wave's own time for each, so vibrato and tremolo don't have to be baked into the lanes
+// amp moves by depth times the lfo as a fraction of itself, freq by depth cycles per sample and
+// phase by depth cycles, an empty depth leaves that one alone
+// a rate of 0 holds the lfo at 1, so the depths themselves are the modulation
+// with a ratio the lfo is an fm operator instead, following the wave's own pitch
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Modulation<T> {
+    // in cycles per sample
+    pub rate: f32,
+    // when it isn't 0 the lfo runs this many times as fast as the wave's oscillator and rate is
+    // ignored, a phase depth is then the fm index in cycles
+    pub ratio: f32,
+    // where in its cycle the lfo starts
+    pub offset: f32,
+    pub shape: Waveform,
+    pub amp: T,
+    pub freq: T,
+    pub phase: T,
+}
+impl Modulation<Vec<f32>> {
+    // a sine wobbling the pitch of a wave playing at around freq by up to cents either way
+    // the depth is worked out for freq, it's as many cents off the further the wave moves from it
+    pub fn vibrato(freq: f32, rate: f32, cents: f32) -> Self {
+        Modulation {
+            rate,
+            shape: Waveform::Sine,
+            freq: vec![freq * (cents * LN_2 / 1200.).sinh()],
+            ..Modulation::default()
+        }
+    }
+    // a sine taking the amp from 1 - depth to 1 + depth times itself, so it works on any amp
+    pub fn tremolo(rate: f32, depth: f32) -> Self {
+        Modulation {
+            rate,
+            shape: Waveform::Sine,
+            amp: vec![depth],
+            ..Modulation::default()
+        }
+    }
+}
+// the wave's own oscillator at some time, what a ratio follows
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Carrier {
+    pub cycles: f64,
+    pub freq: f32,
+}
+// wave time is f64 here like in the player, see TimedWave::wave_time
+impl<T: Borrow<[f32]>> Modulation<T> {
+    // cycles per sample and how many cycles the lfo is through, brought back near 0
+    fn lfo(&self, t: f64, carrier: Carrier) -> (f32, f32) {
+        let (rate, cycles) = if self.ratio != 0. {
+            let ratio = self.ratio as f64;
+            (self.ratio * carrier.freq, ratio * carrier.cycles)
+        } else {
+            (self.rate, self.rate as f64 * t)
+        };
+        (rate, self.shape.wrap(cycles + self.offset as f64))
+    }
+    pub fn lfo_at(&self, t: f64, carrier: Carrier) -> f32 {
+        match self.lfo(t, carrier) {
+            (0., _) => 1.,
+            (_, cycles) => self.shape.at(cycles, 0.5),
+        }
+    }
+    pub fn amp_at(&self, t: f64, carrier: Carrier) -> f32 {
+        let amp = self.amp.borrow();
+        if amp.is_empty() {
+            1.
+        } else {
+            1. + amp.eval(t as f32) * self.lfo_at(t, carrier)
+        }
+    }
+    // how far the oscillator has been pushed along by wave time t, in cycles
+    pub fn cycles_at(&self, t: f64, carrier: Carrier) -> f64 {
+        let (freq, phase) = (self.freq.borrow(), self.phase.borrow());
+        let mut cycles = 0.;
+        if !phase.is_empty() {
+            cycles += (phase.eval(t as f32) * self.lfo_at(t, carrier)) as f64;
+        }
+        if !freq.is_empty() {
+            cycles += match self.lfo(t, carrier) {
+                (0., _) => freq.integral_f64(t),
+                // the depth and rate are taken as steady while integrating, close while they
+                // change slowly next to the lfo, and this way it only depends on where the lfo is
+                (rate, lfo) => (freq.eval(t as f32) * self.shape.integral(lfo, 0.5) / rate) as f64,
+            };
+        }
+        cycles
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(bytemuck::NoUninit, bytemuck::CheckedBitPattern)]
+#[repr(i64)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    // smoothstep, 3x^2 - 2x^3
+    Smooth,
+}
+impl FadeCurve {
+    pub fn at(&self, progress: f32) -> f32 {
+        let progress = progress.clamp(0., 1.);
+        match self {
+            FadeCurve::Linear => progress,
+            FadeCurve::Smooth => [0., 0., 3., -2.].eval(progress),
+        }
+    }
+}
+
+// declicking ramps in samples, the release keeps the voice going past its end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(bytemuck::NoUninit, bytemuck::CheckedBitPattern)]
+#[repr(C)]
+pub struct Fade {
+    pub fade_in: i64,
+    pub release: i64,
+    pub curve: FadeCurve,
+}
+impl Fade {
+    // gain at a time relative to the start of a wave lasting length samples
+    pub fn gain(&self, time: i64, length: i64) -> f32 {
+        let fade_in = if time < self.fade_in {
+            self.curve.at(time as f32 / self.fade_in as f32)
+        } else {
+            1.
+        };
+        let past_end = time - length;
+        let release = if past_end >= 0 {
+            self.curve
+                .at(1. - (past_end + 1) as f32 / (self.release + 1) as f32)
+        } else {
+            1.
+        };
+        fade_in * release
+    }
+}
+
+#[test]
+fn release_tail() {
+    let wave = TimedWave {
+        fade: Fade {
+            fade_in: 2,
+            release: 3,
+            curve: FadeCurve::Linear,
+        },
+        ..LiveWave::sine(1, 5, 1., 1.)
+    };
+    let waves: TimedWavePacker = [wave].into_iter().collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(20));
+    let mut playback = [0.; 10];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0., 0., 0.5, 1., 1., 0.75, 0.5, 0.25, 0., 0.]);
+}
+
+#[test]
+fn stereo_mix() {
+    let wave = |start, gain, pan| TimedWave {
+        mix: Mix { gain, pan, id: 0 },
+        ..LiveWave::sine(start, start + 2, 1., 1.)
+    };
+    let waves: TimedWavePacker = [
+        wave(0, vec![], vec![-1.]),
+        wave(2, vec![0.5], vec![1.]),
+        // sweeps from the left edge through the centre
+        wave(4, vec![], vec![-1., 1.]),
+    ]
+    .into_iter()
+    .collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(20));
+    let mut playback = [[0.; 2]; 6];
+    player.play_stereo(&mut playback).unwrap();
+    let rounded = playback.map(|frame| frame.map(|s: f32| (s * 1000.).round() / 1000.));
+    let centre = FRAC_PI_4.cos();
+    let centre = (centre * 1000.).round() / 1000.;
+    assert_eq!(
+        rounded,
+        [
+            [1., 0.],
+            [1., 0.],
+            [0., 0.5],
+            [0., 0.5],
+            [1., 0.],
+            [centre, centre]
+        ]
+    );
+
+    // mono ignores the pan but still takes the gain
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(20));
+    let mut playback = [0.; 4];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [1., 1., 0.5, 0.5]);
+}
+
+#[test]
+fn modulated() {
+    use crate::func::Piecewise;
+
+    let wave = |freq, modulation| {
+        let sine = LiveWave::sine(0, 400, freq, 0.5);
+        TimedWave {
+            wave: Wave {
+                phase: 0.,
+                mode: PhaseMode::Integral,
+                ..sine.wave
+            },
+            modulation,
+            ..sine
+        }
+    };
+    let play = |waves: &[LiveWave]| {
+        let packer: TimedWavePacker = waves.iter().cloned().collect();
+        let mut out = [0f32; 400];
+        let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(400));
+        player.set_clipping(Clipping::Off);
+        player.play(&mut out).unwrap();
+        out
+    };
+
+    // a square lfo turning a quarter of the amp up and down every 50 samples
+    let tremolo = Modulation {
+        rate: 0.01,
+        shape: Waveform::Square,
+        amp: vec![0.5],
+        ..Modulation::default()
+    };
+    let mut wobbling = wave(0.01, tremolo);
+    wobbling.wave.phase = 25.;
+    let wobbling = play(&[wobbling]);
+    assert!((wobbling[0] - 0.75).abs() < 1e-5);
+    assert!((wobbling[50] + 0.25).abs() < 1e-5);
+
+    // held at 1, a freq depth is just more frequency and a phase depth a head start
+    let higher = play(&[wave(
+        0.01,
+        Modulation {
+            freq: vec![0.01],
+            phase: vec![0.25],
+            ..Modulation::default()
+        },
+    )]);
+    for (t, s) in higher.into_iter().enumerate() {
+        let expected = 0.5 * (core::f32::consts::TAU * (0.02 * t as f32 + 0.25)).sin();
+        assert!((s - expected).abs() < 1e-3);
+    }
+
+    // vibrato survives being cut into pieces, lfo and drift both carrying across the cuts
+    let sweep = Piecewise::new(vec![0., 150.], vec![0.01, 0.02], vec![1, 1]).unwrap();
+    let vibrato = TimedWave {
+        start: 0,
+        end: 400,
+        wave: Wave {
+            freq: sweep,
+            amp: &[0.5][..],
+            phase: 0.,
+            mode: PhaseMode::Integral,
+            shape: Waveform::Sine,
+            width: &[][..],
+        },
+        fade: Fade::default(),
+        mix: Mix::default(),
+        modulation: Modulation {
+            rate: 0.003,
+            shape: Waveform::Triangle,
+            freq: &[0.002][..],
+            ..Modulation::default()
+        },
+        table: Wavetable::default(),
+    };
+    let drifting = TimedWave {
+        modulation: Modulation {
+            freq: &[0.001, 0.00001][..],
+            ..Modulation::default()
+        },
+        ..vibrato.clone()
+    };
+    for tw in [vibrato, drifting] {
+        let mut packer = TimedWavePacker::new();
+        packer.try_extend_pieces([tw.clone()]).unwrap();
+        assert_eq!(packer.timings.len(), 2);
+        let mut out = [0f32; 400];
+        Player::new(packer.get_pack().unwrap(), 0, Some(400))
+            .play(&mut out)
+            .unwrap();
+        for (time, s) in out.into_iter().enumerate() {
+            assert!(
+                (s - tw.sample(time as i64, Rendering::default(), 1.)).abs() < 1e-3,
+                "{}",
+                time
+            );
+        }
+    }
+}
+
+#[test]
+fn ornaments() {
+    let carrier = Carrier::default();
+    let (freq, rate) = (0.01, 0.0001);
+    let vibrato = Modulation::vibrato(freq, rate, 50.);
+    // the oscillator's speed at the top and bottom of the lfo
+    let speed = |t: f64| vibrato.cycles_at(t + 0.5, carrier) - vibrato.cycles_at(t - 0.5, carrier);
+    let cents = |t: f64| 1200. * ((freq + speed(t) as f32) / freq).log2();
+    // a steady depth in frequency is a hair short of the cents at both ends
+    assert!((cents(2500.) - 50.).abs() < 1.);
+    assert!((cents(7500.) + 50.).abs() < 1.);
+    assert!(cents(5000.).abs() < 0.1);
+
+    let tremolo = Modulation::tremolo(rate, 0.25);
+    assert!((tremolo.amp_at(2500., carrier) - 1.25).abs() < 1e-5);
+    assert!((tremolo.amp_at(7500., carrier) - 0.75).abs() < 1e-5);
+    assert_eq!(tremolo.cycles_at(1234., carrier), 0.);
+}
+
+#[test]
+fn frequency_modulation() {
+    use crate::func::Piecewise;
+
+    // a 2:1 operator pair, once as a wave with another wave for its phase and once packed as a
+    // wave with a ratio modulator, the index being a third of a cycle either way
+    let (freq, index) = (0.01, 1. / 3.);
+    let operators = Wave {
+        freq: &[freq][..],
+        amp: &[0.5][..],
+        phase: Wave {
+            freq: &[2. * freq][..],
+            amp: &[index / freq][..],
+            phase: 0.,
+            mode: PhaseMode::Product,
+            shape: Waveform::Sine,
+            width: &[][..],
+        },
+        mode: PhaseMode::Product,
+        shape: Waveform::Sine,
+        width: &[][..],
+    };
+    let mut pair = LiveWave::sine(0, 500, freq, 0.5);
+    pair.wave.phase = 0.;
+    pair.modulation = Modulation {
+        ratio: 2.,
+        phase: vec![index],
+        ..Modulation::default()
+    };
+    let packer: TimedWavePacker = [pair].into_iter().collect();
+    let mut out = [0f32; 500];
+    Player::new(packer.get_pack().unwrap(), 0, Some(500))
+        .play(&mut out)
+        .unwrap();
+    for (t, s) in out.into_iter().enumerate() {
+        assert!((s - operators.eval(t as f32)).abs() < 1e-4, "{}", t);
+    }
+
+    // the modulator follows the wave when it's cut into pieces on a pitch change
+    let jump = Piecewise::new(vec![0., 230.], vec![freq, 1.5 * freq], vec![1, 1]).unwrap();
+    let fm = TimedWave {
+        start: 0,
+        end: 500,
+        wave: Wave {
+            freq: jump,
+            amp: &[0.5][..],
+            phase: 0.,
+            mode: PhaseMode::Integral,
+            shape: Waveform::Sine,
+            width: &[][..],
+        },
+        fade: Fade::default(),
+        mix: Mix::default(),
+        modulation: Modulation {
+            ratio: 3.,
+            offset: 0.1,
+            phase: &[index][..],
+            ..Modulation::default()
+        },
+        table: Wavetable::default(),
+    };
+    let mut packer = TimedWavePacker::new();
+    packer.try_extend_pieces([fm.clone()]).unwrap();
+    Player::new(packer.get_pack().unwrap(), 0, Some(500))
+        .play(&mut out)
+        .unwrap();
+    for (t, s) in out.into_iter().enumerate() {
+        assert!(
+            (s - fm.sample(t as i64, Rendering::default(), 1.)).abs() < 1e-3,
+            "{}",
+            t
+        );
+    }
+}
+
+#[test]
+fn wavetable_voice() {
+    // a triangle sampled at its corners comes back exactly when read linearly
+    let corners = [0., 0.5, 1., 0.5, 0., -0.5, -1., -0.5];
+    let table = Wavetable::new(&corners, 3, Interpolation::Linear).unwrap();
+    let mut wave = LiveWave::sine(0, 16, 0.3, 0.5);
+    wave.wave.phase = 0.;
+    wave.table = table;
+    let packer: TimedWavePacker = [wave.clone()].into_iter().collect();
+    let mut out = [0f32; 16];
+    Player::new(packer.get_pack().unwrap(), 0, Some(16))
+        .play(&mut out)
+        .unwrap();
+    for (time, sample) in out.into_iter().enumerate() {
+        let expected = 0.5 * Waveform::Triangle.at(time as f32 * 0.3, 0.5);
+        assert!((sample - expected).abs() < 1e-6, "{} {}", sample, expected);
+    }
+    // even the third harmonic is past nyquist, so the band limited player reads the last level,
+    // which only has the fundamental, 4.8 samples into its cycle at time 2
+    let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(16));
+    player.set_bandlimited(true);
+    player.play(&mut out).unwrap();
+    let fundamental = (2. + 2f32.sqrt()) / 4. * Waveform::Sine.at(5. / 8., 0.5);
+    assert!((out[2] - 0.5 * 0.8 * fundamental).abs() < 1e-5);
+    let bandlimited = Rendering {
+        bandlimited: true,
+        ..Rendering::default()
+    };
+    assert_eq!(out[2], wave.sample(2, bandlimited, 1.));
+}
+
+#[test]
+fn pulse_voice() {
+    let square = LiveWave::sine(0, 8, 0.25, 0.5);
+    let square = TimedWave {
+        wave: Wave {
+            phase: 0.,
+            shape: Waveform::Pulse,
+            ..square.wave
+        },
+        ..square
+    };
+    let narrow = TimedWave {
+        wave: Wave {
+            width: vec![0.25],
+            ..square.wave.clone()
+        },
+        ..square.clone()
+    };
+    let play = |wave: &LiveWave, bandlimited| {
+        let packer: TimedWavePacker = [wave.clone()].into_iter().collect();
+        let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(8));
+        player.set_bandlimited(bandlimited);
+        let mut out = [0f32; 8];
+        player.play(&mut out).unwrap();
+        out
+    };
+    // with no width lane it's high for half the cycle, not stuck low
+    assert_eq!(
+        play(&square, false),
+        [0.5, 0.5, -0.5, -0.5, 0.5, 0.5, -0.5, -0.5]
+    );
+    assert_eq!(
+        play(&narrow, false),
+        [0.5, -0.5, -0.5, -0.5, 0.5, -0.5, -0.5, -0.5]
+    );
+    let smoothed = play(&square, true);
+    assert!(smoothed.iter().sum::<f32>().abs() < 1e-3, "{:?}", smoothed);
+}
+
+#[test]
+fn noise_voice() {
+    // a burst of pink noise dying away, like a hit
+    let burst = LiveWave::sine(10, 110, 1., 0.);
+    let burst = TimedWave {
+        wave: Wave {
+            amp: Envelope::Poly(vec![0.5, -0.005]),
+            phase: 1234.,
+            shape: Waveform::Pink,
+            ..burst.wave
+        },
+        ..burst
+    };
+    let hit: TimedWavePacker = [burst.clone()].into_iter().collect();
+    let mut whole = [0f32; 120];
+    Player::new(hit.get_pack().unwrap(), 0, Some(120))
+        .play(&mut whole)
+        .unwrap();
+    assert!(whole[..10].iter().chain(&whole[110..]).all(|&s| s == 0.));
+    assert!(whole[10..30].iter().any(|&s| s != 0.));
+    // it's worked out from the time alone, so starting partway gives the same noise
+    let mut later = [0f32; 60];
+    Player::new(hit.get_pack().unwrap(), 60, Some(120))
+        .play(&mut later)
+        .unwrap();
+    assert_eq!(later, whole[60..]);
+    // and so does playing it a few samples at a time
+    let mut player = Player::new(hit.get_pack().unwrap(), 0, Some(120));
+    let mut pieces = [0f32; 120];
+    for piece in pieces.chunks_mut(7) {
+        player.play(piece).unwrap();
+    }
+    assert_eq!(pieces, whole);
+
+    // the amp polynomial shapes it like any other wave, it dies away as the amp falls to 0
+    let loudness =
+        |samples: &[f32]| samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    assert!(loudness(&whole[10..40]) > 4. * loudness(&whole[80..110]));
+    assert!(whole[10..110]
+        .iter()
+        .enumerate()
+        .all(|(t, s)| s.abs() <= 0.5 - 0.005 * t as f32 + 1e-6));
+
+    // another phase is another hit, with the same envelope
+    let other: TimedWavePacker = [TimedWave {
+        wave: Wave {
+            phase: 99.,
+            ..burst.wave.clone()
+        },
+        ..burst.clone()
+    }]
+    .into_iter()
+    .collect();
+    let mut another = [0f32; 120];
+    Player::new(other.get_pack().unwrap(), 0, Some(120))
+        .play(&mut another)
+        .unwrap();
+    assert_ne!(another[10..110], whole[10..110]);
+    assert!(another[110..].iter().all(|&s| s == 0.));
+}
+
+#[test]
+fn clip_voice() {
+    // a recorded hit at half speed and half gain, over a quiet sine bed
+    let hit = Wavetable::clip(&[1., 0.5, -0.5, 0.], Interpolation::Linear).unwrap();
+    let voice = |freq, amp, table| {
+        let sine = LiveWave::sine(2, 12, 0., amp);
+        TimedWave {
+            wave: Wave {
+                freq,
+                phase: 0.,
+                ..sine.wave
+            },
+            table,
+            ..sine
+        }
+    };
+    let packer: TimedWavePacker = [
+        voice(vec![0.25], 0.125, Wavetable::default()),
+        voice(hit.speed(&[0.5]), 0.5, hit.clone()),
+    ]
+    .into_iter()
+    .collect();
+    let mut out = [0f32; 14];
+    Player::new(packer.get_pack().unwrap(), 0, Some(14))
+        .play(&mut out)
+        .unwrap();
+    let played = [1., 0.75, 0.5, 0., -0.5, -0.25, 0., 0., 0., 0.];
+    for (i, (&sample, clip)) in out[2..12].iter().zip(played).enumerate() {
+        let bed = 0.125 * Waveform::Sine.at(i as f32 * 0.25, 0.5);
+        assert!((sample - bed - 0.5 * clip).abs() < 1e-6, "{} {}", i, sample);
+    }
+}
+
+#[test]
+fn clip_pitch() {
+    let ones = [1f32; 4];
+    let play = |pitch: &[f32], table: Wavetable| {
+        let sine = LiveWave::sine(0, 16, 0., 1.);
+        let voice = TimedWave {
+            wave: Wave {
+                freq: table.speed(pitch),
+                phase: 0.,
+                mode: PhaseMode::Integral,
+                ..sine.wave
+            },
+            table,
+            ..sine
+        };
+        let packer: TimedWavePacker = [voice].into_iter().collect();
+        let mut out = [0f32; 16];
+        Player::new(packer.get_pack().unwrap(), 0, Some(16))
+            .play(&mut out)
+            .unwrap();
+        out
+    };
+    // speeding up from half speed it reads 0.5t + 0.025t^2 samples in, so the clip runs out a
+    // little after t = 6 and stays quiet for the rest of the wave
+    let clip = Wavetable::clip(&ones, Interpolation::Linear).unwrap();
+    let rising = play(&[0.5, 0.05], clip.clone());
+    assert!(
+        rising[..5].iter().all(|&s| (s - 1.).abs() < 1e-5),
+        "{:?}",
+        rising
+    );
+    assert!(rising[6] > 0.);
+    assert!(rising[7..].iter().all(|&s| s == 0.), "{:?}", rising);
+    // twice as fast is over twice as soon
+    let fast = play(&[2.], clip);
+    assert!(fast[..2].iter().all(|&s| s != 0.));
+    assert!(fast[2..].iter().all(|&s| s == 0.), "{:?}", fast);
+    // the same samples as a looping table go round again instead of ending
+    let cycle = [1., 0.5, -0.5, 0.];
+    let looped = play(
+        &[1.],
+        Wavetable::new(&cycle, 1, Interpolation::Linear).unwrap(),
+    );
+    for (t, &s) in looped.iter().enumerate() {
+        assert!((s - cycle[t % 4]).abs() < 1e-5, "{} {}", t, s);
+    }
+}
+
+#[test]
+fn live_scheduling() {
+    let waves = TimedWavePacker::new();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(20));
+    let live = |start, end| LiveWave::sine(start, end, 1., 0.5);
+    let mut playback = [0.; 4];
+    player.schedule(live(2, 5));
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0., 0., 0.5, 0.5]);
+    // one late note gets pushed to now rather than losing its head
+    player.schedule(live(1, 3));
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [1., 0.5, 0., 0.]);
+    assert!(player.live.is_empty());
+}
+
+#[test]
+fn rate_adaptation() {
+    use crate::func::scale_polynomial;
+
+    let freq = [0.01, 0.00001];
+    let sine = LiveWave::sine(100, 600, 0., 0.);
+    let authored = TimedWave {
+        wave: Wave {
+            freq: freq.to_vec(),
+            amp: Envelope::Poly(vec![0.5, 0.0005]),
+            phase: 0.1,
+            mode: PhaseMode::Integral,
+            ..sine.wave
+        },
+        fade: Fade {
+            fade_in: 20,
+            release: 40,
+            curve: FadeCurve::Linear,
+        },
+        ..sine
+    };
+    let waves: TimedWavePacker = [authored.clone()].into_iter().collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(1400));
+    player.set_sample_rates(24000, 48000);
+    let mut playback = [0.; 1400];
+    player.play(&mut playback).unwrap();
+
+    // what it would have taken to write the same thing at 48k by hand
+    let by_hand = TimedWave {
+        start: 200,
+        end: 1200,
+        wave: Wave {
+            freq: scale_polynomial(stretch_polynomial::<_, _, f32>(&freq, 2.), 0.5).collect(),
+            amp: Envelope::Poly(stretch_polynomial(&[0.5, 0.0005], 2.).collect()),
+            // phase is a time offset, so it gets stretched too
+            phase: 0.2,
+            ..authored.wave
+        },
+        fade: Fade {
+            fade_in: 40,
+            release: 80,
+            curve: FadeCurve::Linear,
+        },
+        ..authored
+    };
+    for (time, played) in playback.into_iter().enumerate() {
+        let time = time as i64;
+        let expected = if (200..1280).contains(&time) {
+            by_hand.sample(time, Rendering::default(), 1.)
+        } else {
+            0.
+        };
+        assert!((played - expected).abs() < 1e-4, "{} {}", time, played);
+    }
+}
+
+#[test]
+fn voice_limit() {
+    let waves: TimedWavePacker = [(0, 4), (1, 4), (2, 4), (4, 6)]
+        .into_iter()
+        .map(|(start, end)| LiveWave::sine(start, end, 1., 0.25))
+        .collect();
+    let mut player = Player::with_voices(waves.get_pack().unwrap(), 0, Some(20), 2);
+    player.set_stealing(Stealing::Skip, 0);
+    let mut playback = [0.; 6];
+    player.play(&mut playback).unwrap();
+    // the third wave finds no free voice and is skipped, the store never grows
+    assert_eq!(playback, [0.25, 0.5, 0.5, 0.5, 0.25, 0.25]);
+    assert_eq!(player.current.capacity(), 2);
+}
+
+#[test]
+fn voice_stealing() {
+    let waves: TimedWavePacker = [(0, 0.5), (1, 0.25), (2, 1.)]
+        .into_iter()
+        .map(|(start, amp)| LiveWave::sine(start, 8, 1., amp))
+        .collect();
+    let render = |stealing| {
+        let mut player = Player::with_voices(waves.get_pack().unwrap(), 0, Some(20), 2);
+        player.set_stealing(stealing, 3);
+        player.set_clipping(Clipping::Off);
+        let mut playback = [0.; 6];
+        player.play(&mut playback).unwrap();
+        playback
+    };
+    // the stolen voice releases over three samples underneath the new one
+    assert_eq!(
+        render(Stealing::Oldest),
+        [0.5, 0.75, 1.625, 1.5, 1.375, 1.25]
+    );
+    assert_eq!(
+        render(Stealing::Quietest),
+        [0.5, 0.75, 1.6875, 1.625, 1.5625, 1.5]
+    );
+}
+
+#[test]
+fn transport() {
+    let waves: TimedWavePacker = [LiveWave::sine(0, 100, 1., 0.5)].into_iter().collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(200));
+    let mut playback = [0.; 2];
+    player.play(&mut playback).unwrap();
+    player.pause();
+    assert!(player.is_paused());
+    player.play(&mut playback).unwrap();
+    assert_eq!((playback, player.current_time()), ([0.; 2], 2));
+    player.resume();
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.5; 2]);
+    player.stop(4);
+    let mut playback = [0.; 6];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.5, 0.375, 0.25, 0.125, 0., 0.]);
+    assert!(player.is_finished() && !player.is_paused());
+    player.pause();
+    assert!(player.is_finished());
+    player.play(&mut playback).unwrap();
+    assert_eq!((playback, player.current_time()), ([0.; 6], 10));
+}
+
+#[test]
+fn planar() {
+    let wave = |start, end, pan| TimedWave {
+        mix: Mix {
+            pan,
+            ..Mix::default()
+        },
+        ..LiveWave::sine(start, end, 1., 0.5)
+    };
+    let waves: TimedWavePacker = [wave(0, 90, vec![-1.]), wave(40, 100, vec![1.])]
+        .into_iter()
+        .collect();
+    let mut frames = [[0.; 2]; 150];
+    let _ = Player::new(waves.get_pack().unwrap(), 0, None).play_stereo(&mut frames);
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, None);
+    let (mut left, mut right) = ([1.; 150], [1.; 160]);
+    assert_eq!(player.play_planar(&mut left, &mut right), Err(100));
+    assert!(left
+        .iter()
+        .zip(&right)
+        .eq(frames.iter().map(|[l, r]| (l, r))));
+    assert_eq!(right[150..], [1.; 10]);
+}
+
+#[test]
+fn draining() {
+    let wave = |start, end| LiveWave::sine(start, end, 1., 0.5);
+    let waves: TimedWavePacker = [wave(0, 6), wave(5, 8), wave(10, 14)].into_iter().collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(7));
+    let mut playback = [1.; 10];
+    let rest = player.play(&mut playback).unwrap_err();
+    assert_eq!(rest.len(), 3);
+    assert_eq!(playback, [0.5, 0.5, 0.5, 0.5, 0.5, 1., 0.5, 0., 0., 0.]);
+    assert_eq!(player.current_time(), 7);
+    // playing more at the wakeup is all silence and nothing's lost
+    assert!(player.play(&mut playback).is_err());
+    assert_eq!(player.current_time(), 7);
+    let rest: TimedWavePacker = [wave(5, 8), wave(10, 14)].into_iter().collect();
+    assert_eq!(player.drain(), rest);
+    assert!(player.drain().timings.is_empty());
+
+    // with no wakeup it plays to the end of the last wave
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, None);
+    let mut playback = [1.; 16];
+    let rest = player.play(&mut playback).unwrap_err();
+    assert_eq!(rest.len(), 2);
+    assert_eq!(player.current_time(), 14);
+    assert_eq!(playback[10..], [0.5, 0.5, 0.5, 0.5, 0., 0.]);
+    // a scheduled wave keeps it going
+    player.schedule(wave(16, 18));
+    player.play(&mut playback[..3]).unwrap();
+    assert!(player.play(&mut playback).is_err());
+    assert_eq!(player.current_time(), 18);
+    player.set_wakeup(Some(20));
+    player.play(&mut playback[..2]).unwrap();
+    assert_eq!(player.current_time(), 20);
+}
+
+#[test]
+fn wave_handles() {
+    let wave = |amp, id| TimedWave {
+        mix: Mix {
+            id,
+            ..Mix::default()
+        },
+        ..LiveWave::sine(0, 20, 1., amp)
+    };
+    let waves: TimedWavePacker = [wave(0.5, 1), wave(0.25, 2)].into_iter().collect();
+    assert_eq!(waves.ids, [1, 2]);
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(40));
+    player.set_stealing(Stealing::Oldest, 3);
+    player.set_clipping(Clipping::Off);
+    let mut playback = [0.; 2];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.75, 0.75]);
+    player.set_wave_gain(2, 2.);
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [1., 1.]);
+
+    // the spell gets interrupted, its scheduled follow up never plays
+    player.schedule(LiveWave {
+        start: 8,
+        ..wave(1., 1)
+    });
+    player.stop_wave(1);
+    assert!(player.live.is_empty());
+    let mut playback = [0.; 4];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.875, 0.75, 0.625, 0.5]);
+    player.set_wave_gain(2, 1.);
+    assert!(player.wave_gains.is_empty());
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.25; 4]);
+}
+
+#[test]
+fn open_notes() {
+    let wave = |start, amp, id| {
+        let sine = LiveWave::sine(start, OPEN, 1., 0.);
+        TimedWave {
+            wave: Wave { amp, ..sine.wave },
+            fade: Fade {
+                release: 3,
+                ..Fade::default()
+            },
+            mix: Mix {
+                id,
+                ..Mix::default()
+            },
+            ..sine
+        }
+    };
+    let waves = TimedWavePacker::new();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(40));
+    player.schedule(wave(0, Envelope::Poly(vec![0.5]), 1));
+    let mut playback = [0.; 4];
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.5; 4]);
+    // the fade's release starts where the note's let go of
+    player.note_off(1, 6);
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.5, 0.5, 0.375, 0.25]);
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0.125, 0., 0., 0.]);
+    assert!(player.live.is_empty());
+
+    let adsr = Adsr {
+        peak: 1.,
+        sustain: 1.,
+        gate: f32::INFINITY,
+        release: 2.,
+        ..Adsr::default()
+    };
+    player.schedule(wave(13, Envelope::Adsr(adsr), 2));
+    player.play(&mut playback).unwrap();
+    // a note off from the past lets go now
+    player.note_off(2, 0);
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [1., 0.5, 0., 0.]);
+    assert_eq!(player.live[0].end, 18);
+}
+
+#[test]
+fn wave_events() {
+    use WaveEvent::{Start, Stop};
+
+    let wave = |start, end, release, id| TimedWave {
+        fade: Fade {
+            release,
+            ..Fade::default()
+        },
+        mix: Mix {
+            id,
+            ..Mix::default()
+        },
+        ..LiveWave::sine(start, end, 1., 0.5)
+    };
+    let waves: TimedWavePacker = [wave(2, 5, 0, 1), wave(4, 6, 2, 2)].into_iter().collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(40));
+    player.observe(16);
+    player.schedule(wave(7, 9, 0, 3));
+    let mut playback = [0.; 6];
+    player.play(&mut playback).unwrap();
+    let events: Vec<_> = player.take_events().collect();
+    assert_eq!(
+        events,
+        [
+            Start { id: 1, time: 2 },
+            Start { id: 2, time: 4 },
+            Stop { id: 1, time: 5 },
+        ]
+    );
+    player.play(&mut playback).unwrap();
+    let events: Vec<_> = player.take_events().collect();
+    assert_eq!(
+        events,
+        [
+            Start { id: 3, time: 7 },
+            Stop { id: 2, time: 8 },
+            Stop { id: 3, time: 9 },
+        ]
+    );
+
+    // cut off with no fade the stop comes from stop_wave itself
+    player.set_stealing(Stealing::Oldest, 0);
+    player.schedule(wave(12, 20, 0, 4));
+    player.play(&mut playback).unwrap();
+    player.stop_wave(4);
+    player.play(&mut playback).unwrap();
+    let events: Vec<_> = player.take_events().map(|e| (e.id(), e.time())).collect();
+    assert_eq!(events, [(4, 12), (4, 18)]);
+    player.observe(0);
+    player.schedule(wave(24, 26, 0, 5));
+    player.play(&mut playback).unwrap();
+    assert_eq!(player.take_events().count(), 0);
+}
+
+// what a pack asks of whatever plays it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PackStats {
+    pub waves: usize,
+    // from the first start to the end of the last release, 0 when there's nothing and OPEN when
+    // a wave never ends
+    pub duration: i64,
+    // the most waves sounding at once, releases included, what Player::with_voices needs
+    pub max_voices: usize,
+    // every polynomial coefficient, envelope and table sample, the bulk of a pack's memory
+    pub coefficient_bytes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimedWavePacker {
+    pub timings: Vec<[i64; 2]>,
+    pub freq_coef: Vec<f32>,
+    pub freq_runs: Vec<RunLength>,
+    pub amp_coef: Vec<f32>,
+    pub amp_runs: Vec<RunLength>,
+    pub phases: Vec<f32>,
+    pub modes: Vec<PhaseMode>,
+    pub shapes: Vec<Waveform>,
+    pub width_coef: Vec<f32>,
+    pub width_runs: Vec<RunLength>,
+    // one adsr per wave with an envelope amp, runs are 0 or 1
+    pub envelopes: Vec<Adsr>,
+    pub envelope_runs: Vec<RunLength>,
+    pub fades: Vec<Fade>,
+    pub gain_coef: Vec<f32>,
+    pub gain_runs: Vec<RunLength>,
+    pub pan_coef: Vec<f32>,
+    pub pan_runs: Vec<RunLength>,
+    pub lfo_rates: Vec<f32>,
+    pub lfo_offsets: Vec<f32>,
+    pub lfo_shapes: Vec<Waveform>,
+    pub mod_amp_coef: Vec<f32>,
+    pub mod_amp_runs: Vec<RunLength>,
+    pub mod_freq_coef: Vec<f32>,
+    pub mod_freq_runs: Vec<RunLength>,
+    pub mod_phase_coef: Vec<f32>,
+    pub mod_phase_runs: Vec<RunLength>,
+    pub lfo_ratios: Vec<f32>,
+    // every mip level of a wave's table end to end, a run of 0 plays the shape
+    pub table_coef: Vec<f32>,
+    pub table_runs: Vec<RunLength>,
+    pub table_levels: Vec<u8>,
+    pub table_interpolations: Vec<Interpolation>,
+    pub table_one_shots: Vec<bool>,
+    // each wave's Mix::id
+    pub ids: Vec<u32>,
+    // markers sorted by time, with each one's name as utf-8 laid end to end like coefficients
+    pub marker_times: Vec<i64>,
+    pub marker_names: Vec<u8>,
+    pub marker_runs: Vec<RunLength>,
+}
+impl<'a> TimedWavePacker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn as_slices(&'a self) -> PackSlices<'a> {
+        let TimedWavePacker {
+            timings,
+            freq_coef,
+            freq_runs,
+            amp_coef,
+            amp_runs,
+            phases,
+            modes,
+            shapes,
+            width_coef,
+            width_runs,
+            envelopes,
+            envelope_runs,
+            fades,
+            gain_coef,
+            gain_runs,
+            pan_coef,
+            pan_runs,
+            lfo_rates,
+            lfo_ratios,
+            lfo_offsets,
+            lfo_shapes,
+            mod_amp_coef,
+            mod_amp_runs,
+            mod_freq_coef,
+            mod_freq_runs,
+            mod_phase_coef,
+            mod_phase_runs,
+            table_coef,
+            table_runs,
+            table_levels,
+            table_interpolations,
+            table_one_shots,
+            ids,
+            marker_times,
+            marker_names,
+            marker_runs,
+        } = self;
+        PackSlices {
+            timings,
+            freq_coef,
+            freq_runs,
+            amp_coef,
+            amp_runs,
+            phases,
+            modes,
+            shapes,
+            width_coef,
+            width_runs,
+            envelopes,
+            envelope_runs,
+            fades,
+            gain_coef,
+            gain_runs,
+            pan_coef,
+            pan_runs,
+            lfo_rates,
+            lfo_ratios,
+            lfo_offsets,
+            lfo_shapes,
+            mod_amp_coef,
+            mod_amp_runs,
+            mod_freq_coef,
+            mod_freq_runs,
+            mod_phase_coef,
+            mod_phase_runs,
+            table_coef,
+            table_runs,
+            table_levels,
+            table_interpolations,
+            table_one_shots,
+            ids,
+            marker_times,
+            marker_names,
+            marker_runs,
+        }
+    }
+    pub fn get_pack(&'a self) -> Option<PackedTimedWaves<'a>> {
+        PackedTimedWaves::new(self.as_slices())
+    }
+    pub fn try_get_pack(&'a self) -> Result<PackedTimedWaves<'a>, PackError> {
+        PackedTimedWaves::try_new(self.as_slices())
+    }
+    // every wave rebuilt from the lanes in the order they're in, sorted or not, for looking at a
+    // pack without playing it
+    pub fn waves(
+        &'a self,
+    ) -> Result<impl Iterator<Item = TimedWave<&'a [f32], Envelope<&'a [f32]>>>, PackError> {
+        let mut pack = PackedTimedWaves::unsorted(self.as_slices())?;
+        Ok(from_fn(move || pack.sample(i64::MAX).next()))
+    }
+    // the waves making sound at time, releases included
+    pub fn active_at(
+        &'a self,
+        time: i64,
+    ) -> Result<impl Iterator<Item = TimedWave<&'a [f32], Envelope<&'a [f32]>>>, PackError> {
+        Ok(self
+            .waves()?
+            .filter(move |tw| tw.start <= time && time < tw.stop()))
+    }
+    // the waves starting somewhere in range
+    pub fn events_in(
+        &'a self,
+        range: Range<i64>,
+    ) -> Result<impl Iterator<Item = TimedWave<&'a [f32], Envelope<&'a [f32]>>>, PackError> {
+        Ok(self.waves()?.filter(move |tw| range.contains(&tw.start)))
+    }
+    pub fn stats(&self) -> Result<PackStats, PackError> {
+        PackedTimedWaves::unsorted(self.as_slices())?;
+        let spans = self.timings.iter().zip(&self.fades);
+        let spans: Vec<[i64; 2]> = spans
+            .map(|(&[s, e], f)| [s, e.saturating_add(f.release)])
+            .collect();
+        let first = spans.iter().map(|[start, _]| *start).min();
+        let last = spans.iter().map(|[_, stop]| *stop).max();
+        // a wave stopping at the same time another starts doesn't overlap it
+        let mut edges: Vec<(i64, isize)> = spans
+            .iter()
+            .flat_map(|&[start, stop]| [(start, 1), (stop, -1)])
+            .collect();
+        edges.sort_unstable();
+        let mut sounding = 0;
+        let mut max_voices = 0;
+        for (_, change) in edges {
+            sounding += change;
+            max_voices = max_voices.max(sounding as usize);
+        }
+        let coefficients = [
+            &self.freq_coef,
+            &self.amp_coef,
+            &self.width_coef,
+            &self.gain_coef,
+            &self.pan_coef,
+            &self.mod_amp_coef,
+            &self.mod_freq_coef,
+            &self.mod_phase_coef,
+            &self.table_coef,
+        ];
+        let coefficient_bytes = coefficients
+            .map(|lane| size_of_val(&lane[..]))
+            .iter()
+            .sum::<usize>()
+            + size_of_val(&self.envelopes[..]);
+        Ok(PackStats {
+            waves: self.timings.len(),
+            duration: first.zip(last).map_or(0, |(first, last)| match last {
+                OPEN => OPEN,
+                last => last.saturating_sub(first),
+            }),
+            max_voices,
+            coefficient_bytes,
+        })
+    }
+    // a new marker goes after any already at its time
+    pub fn add_marker(&mut self, time: i64, name: &str) -> Result<(), PackError> {
+        let index = self.marker_times.partition_point(|&t| t <= time);
+        let run = run_length("marker_runs", index, name.len())?;
+        let at: usize = self.marker_runs[..index].iter().map(|&r| r as usize).sum();
+        self.marker_times.insert(index, time);
+        self.marker_runs.insert(index, run);
+        self.marker_names.splice(at..at, name.bytes());
+        Ok(())
+    }
+    // in order, stopping early at a name that doesn't fit its lanes
+    pub fn markers(&self) -> impl Iterator<Item = Marker<'_>> {
+        let mut names = self.marker_names.as_slice();
+        let marked = self.marker_times.iter().zip(&self.marker_runs);
+        marked.map_while(move |(&time, &run)| {
+            let (name, rest) = names.split_at_checked(run as usize)?;
+            names = rest;
+            Some(Marker {
+                time,
+                name: from_utf8(name).ok()?,
+            })
+        })
+    }
+    fn move_markers(&mut self, from: &mut TimedWavePacker) {
+        self.marker_times = take(&mut from.marker_times);
+        self.marker_names = take(&mut from.marker_names);
+        self.marker_runs = take(&mut from.marker_runs);
+    }
+    // stable sort of every wave by start time, so waves can be added in any order and sorted once
+    // anything get_pack would reject other than the order comes back as an error, leaving the lanes alone
+    pub fn normalize(&mut self) -> Result<(), PackError> {
+        let mut pack = PackedTimedWaves::unsorted(self.as_slices())?;
+        let mut waves: Vec<Voice> = pack.sample(i64::MAX).collect();
+        waves.sort_by_key(|tw| tw.start);
+        let mut sorted = TimedWavePacker::new();
+        sorted.try_extend(waves)?;
+        sorted.move_markers(self);
+        *self = sorted;
+        Ok(())
+    }
+    // interleaves other's waves by start time, on a tie the ones already here go first
+    pub fn merge(&mut self, other: &TimedWavePacker) -> Result<(), PackError> {
+        self.append_shifted(other, 0)
+    }
+    // merges in other with every wave moved later by offset samples, for sequencing clips
+    pub fn append_shifted(
+        &mut self,
+        other: &TimedWavePacker,
+        offset: i64,
+    ) -> Result<(), PackError> {
+        let (mut ours, mut theirs) = (self.try_get_pack()?, other.try_get_pack()?);
+        let mut ours = ours.sample(i64::MAX).peekable();
+        let mut theirs = theirs
+            .sample(i64::MAX)
+            .map(|mut tw| {
+                tw.start = tw.start.saturating_add(offset);
+                tw.end = move_end(tw.end, offset);
+                tw
+            })
+            .peekable();
+        let mut merged = TimedWavePacker::new();
+        merged.try_extend(from_fn(|| match (ours.peek(), theirs.peek()) {
+            (Some(a), Some(b)) if b.start < a.start => theirs.next(),
+            (Some(_), _) => ours.next(),
+            (None, _) => theirs.next(),
+        }))?;
+        // ours are copied rather than taken, so self is left whole if one of other's doesn't go in
+        merged.marker_times.clone_from(&self.marker_times);
+        merged.marker_names.clone_from(&self.marker_names);
+        merged.marker_runs.clone_from(&self.marker_runs);
+        for marker in other.markers() {
+            merged.add_marker(marker.time.saturating_add(offset), marker.name)?;
+        }
+        *self = merged;
+        Ok(())
+    }
+    // everything offset samples later, or earlier for a negative offset, OPEN ends stay open
+    pub fn shift(&mut self, offset: i64) {
+        for [start, end] in &mut self.timings {
+            *start = start.saturating_add(offset);
+            *end = move_end(*end, offset);
+        }
+        for time in &mut self.marker_times {
+            *time = time.saturating_add(offset);
+        }
+    }
+    // pulls every wave's start strength of the way (0 to 1) to the nearest multiple of grid
+    // samples, the end going with it so nothing changes length
+    // moving towards the nearest line never lets waves pass each other, so the order holds
+    // overshooting the line could, so strength is held to 0 to 1 and NaN doesn't move anything
+    pub fn quantize(&mut self, grid: f64, strength: f32) {
+        if grid <= 0. || strength.is_nan() {
+            return;
+        }
+        let strength = strength.clamp(0., 1.);
+        for [start, end] in &mut self.timings {
+            let line = (*start as f64 / grid).round() * grid;
+            let moved = (*start as f64 + (line - *start as f64) * strength as f64).round() as i64;
+            *end = move_end(*end, moved - *start);
+            *start = moved;
+        }
+    }
+    // nudges every wave up to timing samples earlier or later, end and all, and scales its amp
+    // (envelope or polynomial) by up to amp either way, 0.1 being 10% louder or quieter
+    // the same seed always nudges the same way and waves pushed past each other get sorted again
+    pub fn humanize(&mut self, seed: u32, timing: f32, amp: f32) -> Result<(), PackError> {
+        PackedTimedWaves::unsorted(self.as_slices())?;
+        let (mut amp_at, mut envelope_at) = (0, 0);
+        for index in 0..self.timings.len() {
+            let random = |row| noise(index as i64 + ((seed as i64) << 32), row);
+            let shift = (random(0) * timing).round() as i64;
+            let [start, end] = &mut self.timings[index];
+            *start = start.saturating_add(shift);
+            *end = move_end(*end, shift);
+            let scale = 1. + random(1) * amp;
+            let amp_run = self.amp_runs[index] as usize;
+            for coef in &mut self.amp_coef[amp_at..amp_at + amp_run] {
+                *coef *= scale;
+            }
+            amp_at += amp_run;
+            if self.envelope_runs[index] > 0 {
+                self.envelopes[envelope_at].peak *= scale;
+                envelope_at += 1;
+            }
+        }
+        self.normalize()
+    }
+    // every wave's pitch times ratio, freq modulation depths along with it so vibrato stays the
+    // same number of cents wide
+    pub fn transpose_ratio(&mut self, ratio: f32) {
+        for coef in self.freq_coef.iter_mut().chain(&mut self.mod_freq_coef) {
+            *coef *= ratio;
+        }
+    }
+    pub fn transpose(&mut self, semitones: f32) {
+        self.transpose_ratio((semitones / 12.).exp2());
+    }
+    // makes everything last factor times as long, 2 is half speed
+    // every polynomial is stretched along with the timings, fades, envelopes and lfos, and the
+    // pitch drops like a slowed down tape unless it's preserved
+    // times land on the nearest sample
+    pub fn stretch(&mut self, factor: f32, preserve_pitch: bool) -> Result<(), PackError> {
+        if !(factor.is_finite() && factor > 0.) {
+            return Err(PackError::StretchFactor);
+        }
+        PackedTimedWaves::unsorted(self.as_slices())?;
+        let time = |t: i64| (t as f64 * factor as f64).round() as i64;
+        for [start, end] in &mut self.timings {
+            *start = time(*start);
+            *end = if *end == OPEN { OPEN } else { time(*end) };
+        }
+        for marker in &mut self.marker_times {
+            *marker = time(*marker);
+        }
+        for fade in &mut self.fades {
+            fade.fade_in = time(fade.fade_in);
+            fade.release = time(fade.release);
+        }
+        for env in &mut self.envelopes {
+            env.attack *= factor;
+            env.decay *= factor;
+            env.gate *= factor;
+            env.release *= factor;
+        }
+        // phases are time offsets
+        self.phases.iter_mut().for_each(|p| *p *= factor);
+        self.lfo_rates.iter_mut().for_each(|r| *r /= factor);
+        for (coef, runs) in [
+            (&mut self.freq_coef, &self.freq_runs),
+            (&mut self.amp_coef, &self.amp_runs),
+            (&mut self.width_coef, &self.width_runs),
+            (&mut self.gain_coef, &self.gain_runs),
+            (&mut self.pan_coef, &self.pan_runs),
+            (&mut self.mod_amp_coef, &self.mod_amp_runs),
+            (&mut self.mod_freq_coef, &self.mod_freq_runs),
+            (&mut self.mod_phase_coef, &self.mod_phase_runs),
+        ] {
+            let mut at = 0;
+            for &run in runs {
+                let poly = &mut coef[at..at + run as usize];
+                let stretched: Vec<f32> = stretch_polynomial(&*poly, factor).collect();
+                poly.copy_from_slice(&stretched);
+                at += run as usize;
+            }
+        }
+        if !preserve_pitch {
+            self.transpose_ratio(1. / factor);
+        }
+        Ok(())
+    }
+    // waves added this way are sines, use the shape lanes directly for anything else
+    // every lane has to give one per timing, the counts in a LaneLength are this call's, and
+    // nothing's added unless it all checks out
+    pub fn bulk_generate<F: Iterator<Item = f32>, A: Iterator<Item = f32>>(
+        &mut self,
+        timings: impl Iterator<Item = (i64, i64)>,
+        frequencies: impl Iterator<Item = F>,
+        amplitudes: impl Iterator<Item = A>,
+        phases: impl Iterator<Item = f32>,
+        modes: impl Iterator<Item = PhaseMode>,
+    ) -> Result<(), PackError> {
+        let start_count = self.timings.len();
+        let timings: Vec<[i64; 2]> = timings.map(|(start, end)| [start, end]).collect();
+        let added = timings.len();
+        let (freq_coef, freq_runs) = flatten("freq_runs", start_count, frequencies)?;
+        let (amp_coef, amp_runs) = flatten("amp_runs", start_count, amplitudes)?;
+        let (phases, modes): (Vec<f32>, Vec<PhaseMode>) = (phases.collect(), modes.collect());
+        let counts = [
+            ("freq_runs", freq_runs.len()),
+            ("amp_runs", amp_runs.len()),
+            ("phases", phases.len()),
+            ("modes", modes.len()),
+        ];
+        if let Some(&(lane, found)) = counts.iter().find(|&&(_, found)| found != added) {
+            return Err(PackError::LaneLength {
+                lane,
+                expected: added,
+                found,
+            });
+        }
+        self.timings.extend(timings);
+        self.freq_coef.extend(freq_coef);
+        self.freq_runs.extend(freq_runs);
+        self.amp_coef.extend(amp_coef);
+        self.amp_runs.extend(amp_runs);
+        self.phases.extend(phases);
+        self.modes.extend(modes);
+        self.shapes.extend(repeat_n(Waveform::Sine, added));
+        self.width_runs.extend(repeat_n(0, added));
+        self.envelope_runs.extend(repeat_n(0, added));
+        self.fades.extend(repeat_n(Fade::default(), added));
+        self.gain_runs.extend(repeat_n(0, added));
+        self.pan_runs.extend(repeat_n(0, added));
+        self.lfo_rates.extend(repeat_n(0., added));
+        self.lfo_ratios.extend(repeat_n(0., added));
+        self.lfo_offsets.extend(repeat_n(0., added));
+        self.lfo_shapes.extend(repeat_n(Waveform::Sine, added));
+        self.mod_amp_runs.extend(repeat_n(0, added));
+        self.mod_freq_runs.extend(repeat_n(0, added));
+        self.mod_phase_runs.extend(repeat_n(0, added));
+        self.table_runs.extend(repeat_n(0, added));
+        self.table_levels.extend(repeat_n(0, added));
+        self.table_interpolations
+            .extend(repeat_n(Interpolation::default(), added));
+        self.table_one_shots.extend(repeat_n(false, added));
+        self.ids.extend(repeat_n(0, added));
+        Ok(())
+    }
+    // an additive stack, partial n (counting the fundamental as 1) is a sine at n times its freq
+    // with whatever amp partial_amp hands back for n, partials at or past nyquist are left out
+    // they all start together and go on the end, like try_extend
+    pub fn add_harmonic_series<A: Amplitude>(
+        &mut self,
+        fundamental: f32,
+        partials: usize,
+        mut partial_amp: impl FnMut(usize) -> A,
+        (start, end): (i64, i64),
+    ) -> Result<(), PackError> {
+        let waves = (1..=partials)
+            .map(|n| (n, n as f32 * fundamental))
+            .take_while(|&(_, freq)| freq.abs() < 0.5)
+            .map(|(n, freq)| TimedWave {
+                start,
+                end,
+                wave: Wave {
+                    freq: vec![freq],
+                    amp: partial_amp(n),
+                    phase: 0.,
+                    mode: PhaseMode::Product,
+                    shape: Waveform::Sine,
+                    width: vec![],
+                },
+                fade: Fade::default(),
+                mix: Mix::default(),
+                modulation: Modulation::default(),
+                table: Wavetable::default(),
+            });
+        self.try_extend(waves.collect::<Vec<_>>())
+    }
+    // stops at the first wave with a polynomial too long to pack, everything before it stays
+    pub fn try_extend<T: Borrow<[f32]>, A: Amplitude>(
+        &mut self,
+        iter: impl IntoIterator<Item = TimedWave<T, A>>,
+    ) -> Result<(), PackError> {
+        for TimedWave {
+            start,
+            end,
+            wave,
+            fade,
+            mix,
+            modulation,
+            table,
+        } in iter
+        {
+            let Wave {
+                freq,
+                amp,
+                phase,
+                mode,
+                shape,
+                width,
+            } = wave;
+            let index = self.timings.len();
+            let adsr = amp.adsr();
+            let (freq, amp, width) = (freq.borrow(), amp.coefficients(), width.borrow());
+            let f_len = run_length("freq_runs", index, freq.len())?;
+            let a_len = run_length("amp_runs", index, amp.len())?;
+            let w_len = run_length("width_runs", index, width.len())?;
+            let (gain, pan) = (mix.gain.borrow(), mix.pan.borrow());
+            let g_len = run_length("gain_runs", index, gain.len())?;
+            let p_len = run_length("pan_runs", index, pan.len())?;
+            let (mod_amp, mod_freq, mod_phase) = (
+                modulation.amp.borrow(),
+                modulation.freq.borrow(),
+                modulation.phase.borrow(),
+            );
+            let ma_len = run_length("mod_amp_runs", index, mod_amp.len())?;
+            let mf_len = run_length("mod_freq_runs", index, mod_freq.len())?;
+            let mp_len = run_length("mod_phase_runs", index, mod_phase.len())?;
+            let t_len = run_length("table_runs", index, table.samples().len())?;
+            self.table_coef.extend_from_slice(table.samples());
+            self.table_runs.push(t_len);
+            self.table_levels.push(table.levels());
+            self.table_interpolations.push(table.interpolation);
+            self.table_one_shots.push(table.one_shot);
+            self.ids.push(mix.id);
+            self.lfo_rates.push(modulation.rate);
+            self.lfo_ratios.push(modulation.ratio);
+            self.lfo_offsets.push(modulation.offset);
+            self.lfo_shapes.push(modulation.shape);
+            self.mod_amp_coef.extend_from_slice(mod_amp);
+            self.mod_amp_runs.push(ma_len);
+            self.mod_freq_coef.extend_from_slice(mod_freq);
+            self.mod_freq_runs.push(mf_len);
+            self.mod_phase_coef.extend_from_slice(mod_phase);
+            self.mod_phase_runs.push(mp_len);
+            self.gain_coef.extend_from_slice(gain);
+            self.gain_runs.push(g_len);
+            self.pan_coef.extend_from_slice(pan);
+            self.pan_runs.push(p_len);
+            self.timings.push([start, end]);
+            self.fades.push(fade);
+            self.envelopes.extend(adsr);
+            self.envelope_runs.push(adsr.is_some() as RunLength);
+            self.freq_coef.extend_from_slice(freq);
+            self.amp_coef.extend_from_slice(amp);
+            self.width_coef.extend_from_slice(width);
+            self.freq_runs.push(f_len);
+            self.amp_runs.push(a_len);
+            self.width_runs.push(w_len);
+            self.phases.push(phase);
+            self.modes.push(mode);
+            self.shapes.push(shape);
+        }
+        Ok(())
+    }
+}
+#[test]
+fn bulk_generating() {
+    let mut packer = TimedWavePacker::new();
+    packer
+        .bulk_generate(
+            [(0, 4), (2, 6)].into_iter(),
+            [vec![1.], vec![0.5, 0.01]].into_iter().map(Vec::into_iter),
+            [vec![0.5], vec![0.25]].into_iter().map(Vec::into_iter),
+            [0.25, 0.].into_iter(),
+            [PhaseMode::Product, PhaseMode::Integral].into_iter(),
+        )
+        .unwrap();
+    assert_eq!(packer.freq_runs, [1, 2]);
+    assert!(packer.try_get_pack().is_ok());
+    let before = packer.clone();
+
+    // a polynomial too long for its run turns the whole lot away
+    let too_long = vec![0.; RunLength::MAX as usize + 1];
+    assert_eq!(
+        packer.bulk_generate(
+            [(6, 8), (6, 9)].into_iter(),
+            [vec![1.], too_long.clone()].into_iter().map(Vec::into_iter),
+            [vec![0.5], vec![0.5]].into_iter().map(Vec::into_iter),
+            [0., 0.].into_iter(),
+            [PhaseMode::Product; 2].into_iter(),
+        ),
+        Err(PackError::TooManyCoefficients {
+            lane: "freq_runs",
+            index: 3,
+            count: too_long.len()
+        })
+    );
+    assert_eq!(packer, before);
+    // and so does a lane that runs short of the timings
+    assert_eq!(
+        packer.bulk_generate(
+            [(6, 8), (6, 9)].into_iter(),
+            [vec![1.], vec![2.]].into_iter().map(Vec::into_iter),
+            [vec![0.5], vec![0.5]].into_iter().map(Vec::into_iter),
+            [0.].into_iter(),
+            [PhaseMode::Product; 2].into_iter(),
+        ),
+        Err(PackError::LaneLength {
+            lane: "phases",
+            expected: 2,
+            found: 1
+        })
+    );
+    assert_eq!(packer, before);
+    assert!(packer.try_get_pack().is_ok());
+}
+
+#[test]
+fn harmonic_series() {
+    // a saw built from sines, upper partials dying away faster
+    let fundamental = 0.03;
+    let mut packer = TimedWavePacker::new();
+    packer
+        .add_harmonic_series(
+            fundamental,
+            40,
+            |n| vec![0.5 / n as f32, -0.001 / (n * n) as f32],
+            (0, 200),
+        )
+        .unwrap();
+    // 16 of them fit under nyquist
+    assert_eq!(packer.timings.len(), 16);
+    assert_eq!(packer.freq_coef[2], 3. * fundamental);
+    let mut out = [0f32; 200];
+    let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(200));
+    player.set_clipping(Clipping::Off);
+    player.play(&mut out).unwrap();
+    for (t, sample) in out.into_iter().enumerate() {
+        let t = t as f32;
+        let expected: f32 = (1..=16)
+            .map(|n| {
+                let amp = 0.5 / n as f32 - 0.001 / (n * n) as f32 * t;
+                amp * Waveform::Sine.at(t * n as f32 * fundamental, 0.5)
+            })
+            .sum();
+        assert!(
+            (sample - expected).abs() < 1e-4,
+            "{} {} {}",
+            t,
+            sample,
+            expected
+        );
     }
-    pub fn bulk_generate<F: Iterator<Item = f32>, A: Iterator<Item = f32>>(
+
+    // envelopes work too
+    let mut packer = TimedWavePacker::new();
+    let adsr = Adsr {
+        peak: 0.25,
+        attack: 5.,
+        decay: 5.,
+        sustain: 0.5,
+        gate: 20.,
+        release: 5.,
+    };
+    packer
+        .add_harmonic_series(0.1, 3, |_| Envelope::<Vec<f32>>::Adsr(adsr), (0, 30))
+        .unwrap();
+    assert_eq!(packer.envelopes, [adsr; 3]);
+}
+
+impl TimedWavePacker {
+    // the pack only holds one polynomial per lane per wave, so waves whose freq or amp are pieced
+    // together get cut at the breakpoints (rounded to the sample) into waves that play back to back,
+    // the oscillator picks up where the last piece left it except for integral mode pieces that start
+    // at zero frequency, and only breakpoints between start and end count
+    // pieces of overlapping waves can land out of order, normalize sorts that out
+    pub fn try_extend_pieces<T, A, F>(
         &mut self,
-        timings: impl Iterator<Item = (i64, i64)>,
-        frequencies: impl Iterator<Item = F>,
-        amplitudes: impl Iterator<Item = A>,
-        phases: impl Iterator<Item = f32>,
-    ) {
-        self.timings.extend(timings);
-        for freq_group in frequencies {
-            let start_len = self.freq_coef.len();
-            self.freq_coef.extend(freq_group);
-            let end_len = self.freq_coef.len();
-            self.freq_runs.push((end_len - start_len) as u8);
-        }
-        for amp_group in amplitudes {
-            let start_len = self.amp_coef.len();
-            self.amp_coef.extend(amp_group);
-            let end_len = self.amp_coef.len();
-            self.amp_runs.push((end_len - start_len) as u8);
+        iter: impl IntoIterator<Item = TimedWave<T, A, F>>,
+    ) -> Result<(), PackError>
+    where
+        T: Borrow<[f32]>,
+        A: Pieces + Function,
+        F: Pieces + Function,
+    {
+        for tw in iter {
+            let length = tw.length();
+            let mut cuts: Vec<i64> = tw
+                .wave
+                .freq
+                .breaks()
+                .iter()
+                .chain(tw.wave.amp.breaks())
+                .map(|&b| b.round() as i64)
+                .filter(|&b| b > 0 && b < length)
+                .collect();
+            cuts.sort_unstable();
+            cuts.dedup();
+            let starts = once(0).chain(cuts.iter().copied());
+            let ends = cuts.iter().copied().chain(once(length));
+            let last = cuts.len();
+            let pieces = starts.zip(ends).enumerate().map(|(i, (from, to))| {
+                let at = from as f32;
+                // rate 0 freq modulation is the only kind that leaves the oscillator somewhere the
+                // new piece doesn't know about, everything else only depends on where the lfo is
+                let held = (tw.modulation.rate, tw.modulation.ratio) == (0., 0.);
+                let drift = if held {
+                    tw.modulation.freq.integral(at)
+                } else {
+                    0.
+                };
+                let freq = tw.wave.freq.eval(at);
+                let phase = match tw.wave.mode {
+                    PhaseMode::Product if freq == 0. => tw.wave.phase + at,
+                    PhaseMode::Product => tw.wave.phase + at + drift / freq,
+                    PhaseMode::Integral => {
+                        if from == 0 {
+                            tw.wave.phase
+                        } else if freq == 0. {
+                            0.
+                        } else {
+                            let cycles = tw.wave.cycles_f64(at as f64) + drift as f64;
+                            cycles.rem_euclid(1.) as f32 / freq
+                        }
+                    }
+                };
+                let wave = Wave {
+                    freq: tw.wave.freq.piece(at),
+                    amp: tw.wave.amp.piece(at),
+                    phase,
+                    mode: tw.wave.mode,
+                    shape: tw.wave.shape,
+                    width: tw.wave.width.piece(at),
+                };
+                // the lfo picks up where it was, a ratio one from wherever the oscillator was
+                let lfo = if tw.modulation.ratio != 0. {
+                    let cycles = tw.wave.cycles_f64(at as f64) - wave.cycles_f64(0.);
+                    tw.modulation.ratio as f64 * cycles
+                } else {
+                    tw.modulation.rate as f64 * at as f64
+                };
+                let offset = tw.modulation.shape.wrap(tw.modulation.offset as f64 + lfo);
+                let modulation = Modulation {
+                    rate: tw.modulation.rate,
+                    ratio: tw.modulation.ratio,
+                    offset,
+                    shape: tw.modulation.shape,
+                    amp: tw.modulation.amp.piece(at),
+                    freq: tw.modulation.freq.piece(at),
+                    phase: tw.modulation.phase.piece(at),
+                };
+                let fade = Fade {
+                    fade_in: if i == 0 { tw.fade.fade_in } else { 0 },
+                    release: if i == last { tw.fade.release } else { 0 },
+                    curve: tw.fade.curve,
+                };
+                TimedWave {
+                    start: tw.start + from,
+                    end: tw.start + to,
+                    wave,
+                    fade,
+                    mix: Mix {
+                        gain: tw.mix.gain.piece(at),
+                        pan: tw.mix.pan.piece(at),
+                        id: tw.mix.id,
+                    },
+                    modulation,
+                    table: tw.table.to_vec(),
+                }
+            });
+            self.try_extend(pieces.collect::<Vec<_>>())?;
         }
-        self.phases.extend(phases);
+        Ok(())
     }
 }
-impl<T: Borrow<[f32]>> Extend<TimedWave<T>> for TimedWavePacker {
-    fn extend<I: IntoIterator<Item = TimedWave<T>>>(&mut self, iter: I) {
-        for TimedWave { start, end, wave } in iter {
-            let timing = (start, end);
-            self.timings.push(timing);
-            let Wave { freq, amp, phase } = wave;
-            let (freq, amp) = (freq.borrow(), amp.borrow());
-            let f_len = freq.len() as u8;
-            let a_len = freq.len() as u8;
-            self.freq_coef.extend_from_slice(freq);
-            self.amp_coef.extend_from_slice(amp);
-            self.freq_runs.push(f_len);
-            self.amp_runs.push(a_len);
-            self.phases.push(phase);
+
+#[test]
+fn piecewise_packing() {
+    use crate::func::Piecewise;
+
+    let amp = Piecewise::new(
+        vec![0., 100., 400.],
+        vec![0., 0.005, 0.5, 0.5, -0.0025],
+        vec![2, 1, 2],
+    )
+    .unwrap();
+    let sweep = Piecewise::new(vec![0., 250.], vec![0.01, 0.00004, 0.02], vec![2, 1]).unwrap();
+    let waves = [PhaseMode::Product, PhaseMode::Integral].map(|mode| TimedWave {
+        start: 20,
+        end: 600,
+        wave: Wave {
+            freq: sweep.clone(),
+            amp: amp.clone(),
+            phase: 0.1,
+            mode,
+            shape: Waveform::Sine,
+            width: &[][..],
+        },
+        fade: Fade {
+            fade_in: 10,
+            release: 30,
+            curve: FadeCurve::Linear,
+        },
+        mix: Mix {
+            gain: &[1., -0.0001][..],
+            pan: &[][..],
+            id: 0,
+        },
+        modulation: Modulation::default(),
+        table: Wavetable::default(),
+    });
+    for tw in waves {
+        let mut packer = TimedWavePacker::new();
+        packer.try_extend_pieces([tw.clone()]).unwrap();
+        assert_eq!(
+            packer.timings,
+            [[20, 120], [120, 270], [270, 420], [420, 600]]
+        );
+        let mut playback = [0.; 640];
+        Player::new(packer.get_pack().unwrap(), 0, Some(640))
+            .play(&mut playback)
+            .unwrap();
+        for (time, played) in playback.into_iter().enumerate() {
+            let time = time as i64;
+            let expected = if (20..630).contains(&time) {
+                tw.sample(time, Rendering::default(), 1.)
+            } else {
+                0.
+            };
+            assert!(
+                (played - expected).abs() < 1e-3,
+                "{} {} {}",
+                time,
+                played,
+                expected
+            );
+        }
+    }
+}
+
+// groups of coefficients laid end to end with a run for each, first is the index of the first group
+fn flatten<I: Iterator<Item = f32>>(
+    lane: &'static str,
+    first: usize,
+    groups: impl Iterator<Item = I>,
+) -> Result<(Vec<f32>, Vec<RunLength>), PackError> {
+    let (mut coef, mut runs) = (Vec::new(), Vec::new());
+    for (index, group) in groups.enumerate() {
+        let start = coef.len();
+        coef.extend(group);
+        runs.push(run_length(lane, first + index, coef.len() - start)?);
+    }
+    Ok((coef, runs))
+}
+fn run_length(lane: &'static str, index: usize, count: usize) -> Result<RunLength, PackError> {
+    RunLength::try_from(count).map_err(|_| PackError::TooManyCoefficients { lane, index, count })
+}
+// Extend can't hand back an error, so this panics where try_extend would return one
+impl<T: Borrow<[f32]>, A: Amplitude> Extend<TimedWave<T, A>> for TimedWavePacker {
+    fn extend<I: IntoIterator<Item = TimedWave<T, A>>>(&mut self, iter: I) {
+        if let Err(e) = self.try_extend(iter) {
+            panic!("{}", e);
         }
     }
 }
-impl<T: Borrow<[f32]>> FromIterator<TimedWave<T>> for TimedWavePacker {
-    fn from_iter<I: IntoIterator<Item = TimedWave<T>>>(iter: I) -> Self {
+impl<T: Borrow<[f32]>, A: Amplitude> FromIterator<TimedWave<T, A>> for TimedWavePacker {
+    fn from_iter<I: IntoIterator<Item = TimedWave<T, A>>>(iter: I) -> Self {
         let mut def = Self::new();
         def.extend(iter);
         def
     }
 }
 
+// what get_pack found wrong, lanes are named after the TimedWavePacker fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackError {
+    // a lane with one entry per wave doesn't have as many entries as there are timings
+    LaneLength {
+        lane: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    // the wave at index starts before the one in front of it
+    Unsorted {
+        index: usize,
+    },
+    // a run lane adds up to a different number of coefficients than its coefficient lane holds
+    RunSum {
+        lane: &'static str,
+        coefficients: usize,
+        runs: usize,
+    },
+    // the wave at index has a polynomial longer than a run can describe
+    TooManyCoefficients {
+        lane: &'static str,
+        index: usize,
+        count: usize,
+    },
+    // the wave at index has a table that doesn't split evenly into its mip levels
+    TableLevels {
+        index: usize,
+        samples: usize,
+        levels: u8,
+    },
+    // marker_runs doesn't have a name length for every marker time
+    MarkerCount {
+        markers: usize,
+        names: usize,
+    },
+    // the marker at index has a name that isn't utf-8
+    MarkerName {
+        index: usize,
+    },
+    // the marker at index comes before the one in front of it
+    UnsortedMarker {
+        index: usize,
+    },
+    // stretch only takes a factor that's finite and over 0, anything else would leave the waves
+    // out of order or their times meaningless
+    StretchFactor,
+}
+impl Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackError::LaneLength {
+                lane,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} has {} entries but there are {} waves",
+                lane, found, expected
+            ),
+            PackError::Unsorted { index } => {
+                write!(f, "wave {} starts before the wave in front of it", index)
+            }
+            PackError::RunSum {
+                lane,
+                coefficients,
+                runs,
+            } => write!(
+                f,
+                "{} covers {} coefficients but there are {}",
+                lane, runs, coefficients
+            ),
+            PackError::TooManyCoefficients { lane, index, count } => write!(
+                f,
+                "wave {} has {} coefficients for {}, the most a run can hold is {}",
+                index,
+                count,
+                lane,
+                RunLength::MAX
+            ),
+            PackError::TableLevels {
+                index,
+                samples,
+                levels,
+            } => write!(
+                f,
+                "wave {} has a table of {} samples that can't be split into {} levels",
+                index, samples, levels
+            ),
+            PackError::MarkerCount { markers, names } => write!(
+                f,
+                "there are {} markers but {} marker name lengths",
+                markers, names
+            ),
+            PackError::MarkerName { index } => write!(f, "marker {} isn't named in utf-8", index),
+            PackError::UnsortedMarker { index } => {
+                write!(f, "marker {} comes before the marker in front of it", index)
+            }
+            PackError::StretchFactor => write!(f, "a stretch factor has to be finite and over 0"),
+        }
+    }
+}
+impl Error for PackError {}
+
+// borrowed view of every lane in a pack, laid out like TimedWavePacker
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PackSlices<'a> {
+    pub timings: &'a [[i64; 2]],
+    pub freq_coef: &'a [f32],
+    pub freq_runs: &'a [RunLength],
+    pub amp_coef: &'a [f32],
+    pub amp_runs: &'a [RunLength],
+    pub phases: &'a [f32],
+    pub modes: &'a [PhaseMode],
+    pub shapes: &'a [Waveform],
+    pub width_coef: &'a [f32],
+    pub width_runs: &'a [RunLength],
+    pub envelopes: &'a [Adsr],
+    pub envelope_runs: &'a [RunLength],
+    pub fades: &'a [Fade],
+    pub gain_coef: &'a [f32],
+    pub gain_runs: &'a [RunLength],
+    pub pan_coef: &'a [f32],
+    pub pan_runs: &'a [RunLength],
+    pub lfo_rates: &'a [f32],
+    pub lfo_offsets: &'a [f32],
+    pub lfo_shapes: &'a [Waveform],
+    pub mod_amp_coef: &'a [f32],
+    pub mod_amp_runs: &'a [RunLength],
+    pub mod_freq_coef: &'a [f32],
+    pub mod_freq_runs: &'a [RunLength],
+    pub mod_phase_coef: &'a [f32],
+    pub mod_phase_runs: &'a [RunLength],
+    pub lfo_ratios: &'a [f32],
+    pub table_coef: &'a [f32],
+    pub table_runs: &'a [RunLength],
+    pub table_levels: &'a [u8],
+    pub table_interpolations: &'a [Interpolation],
+    pub table_one_shots: &'a [bool],
+    pub ids: &'a [u32],
+    pub marker_times: &'a [i64],
+    pub marker_names: &'a [u8],
+    pub marker_runs: &'a [RunLength],
+}
+impl<'a> PackSlices<'a> {
+    pub fn to_packer(&self) -> TimedWavePacker {
+        TimedWavePacker {
+            timings: self.timings.to_vec(),
+            freq_coef: self.freq_coef.to_vec(),
+            freq_runs: self.freq_runs.to_vec(),
+            amp_coef: self.amp_coef.to_vec(),
+            amp_runs: self.amp_runs.to_vec(),
+            phases: self.phases.to_vec(),
+            modes: self.modes.to_vec(),
+            shapes: self.shapes.to_vec(),
+            width_coef: self.width_coef.to_vec(),
+            width_runs: self.width_runs.to_vec(),
+            envelopes: self.envelopes.to_vec(),
+            envelope_runs: self.envelope_runs.to_vec(),
+            fades: self.fades.to_vec(),
+            gain_coef: self.gain_coef.to_vec(),
+            gain_runs: self.gain_runs.to_vec(),
+            pan_coef: self.pan_coef.to_vec(),
+            pan_runs: self.pan_runs.to_vec(),
+            lfo_rates: self.lfo_rates.to_vec(),
+            lfo_ratios: self.lfo_ratios.to_vec(),
+            lfo_offsets: self.lfo_offsets.to_vec(),
+            lfo_shapes: self.lfo_shapes.to_vec(),
+            mod_amp_coef: self.mod_amp_coef.to_vec(),
+            mod_amp_runs: self.mod_amp_runs.to_vec(),
+            mod_freq_coef: self.mod_freq_coef.to_vec(),
+            mod_freq_runs: self.mod_freq_runs.to_vec(),
+            mod_phase_coef: self.mod_phase_coef.to_vec(),
+            mod_phase_runs: self.mod_phase_runs.to_vec(),
+            table_coef: self.table_coef.to_vec(),
+            table_runs: self.table_runs.to_vec(),
+            table_levels: self.table_levels.to_vec(),
+            table_interpolations: self.table_interpolations.to_vec(),
+            table_one_shots: self.table_one_shots.to_vec(),
+            ids: self.ids.to_vec(),
+            marker_times: self.marker_times.to_vec(),
+            marker_names: self.marker_names.to_vec(),
+            marker_runs: self.marker_runs.to_vec(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PackedTimedWaves<'a> {
-    timings: Peekable<Iter<'a, (i64, i64)>>,
+    // how many waves have come out so far
+    taken: usize,
+    timings: Peekable<Iter<'a, [i64; 2]>>,
     frequencies: MultiPoly<'a>,
     amplitudes: MultiPoly<'a>,
     phases: Iter<'a, f32>,
+    modes: Iter<'a, PhaseMode>,
+    shapes: Iter<'a, Waveform>,
+    widths: MultiPoly<'a>,
+    envelopes: MultiPoly<'a, Adsr>,
+    fades: Iter<'a, Fade>,
+    gains: MultiPoly<'a>,
+    pans: MultiPoly<'a>,
+    lfo_rates: Iter<'a, f32>,
+    lfo_ratios: Iter<'a, f32>,
+    lfo_offsets: Iter<'a, f32>,
+    lfo_shapes: Iter<'a, Waveform>,
+    mod_amps: MultiPoly<'a>,
+    mod_freqs: MultiPoly<'a>,
+    mod_phases: MultiPoly<'a>,
+    tables: MultiPoly<'a>,
+    table_levels: Iter<'a, u8>,
+    table_interpolations: Iter<'a, Interpolation>,
+    table_one_shots: Iter<'a, bool>,
+    ids: Iter<'a, u32>,
+    marker_times: Peekable<Iter<'a, i64>>,
+    marker_names: MultiPoly<'a, u8>,
 }
 impl<'a, 's> PackedTimedWaves<'a> {
-    pub fn new(
-        timings: &'a [(i64, i64)],
-        frequency_coef: &'a [f32],
-        frequency_runs: &'a [u8],
-        amplitude_coef: &'a [f32],
-        amplitude_runs: &'a [u8],
-        phases: &'a [f32],
-    ) -> Option<Self> {
-        ((timings.len() == frequency_runs.len())
-            && (frequency_runs.len() == amplitude_runs.len())
-            && (phases.len() == timings.len())
-            && timings.windows(2).all(|s| s[0].0 <= s[1].0))
-        .then_some(Self {
+    pub fn new(slices: PackSlices<'a>) -> Option<Self> {
+        Self::try_new(slices).ok()
+    }
+    pub fn try_new(slices: PackSlices<'a>) -> Result<Self, PackError> {
+        let pack = Self::unsorted(slices)?;
+        if let Some(index) = slices.timings.windows(2).position(|s| s[0][0] > s[1][0]) {
+            return Err(PackError::Unsorted { index: index + 1 });
+        }
+        Ok(pack)
+    }
+    // every check but the ordering, the player needs sorted starts but normalize doesn't
+    fn unsorted(slices: PackSlices<'a>) -> Result<Self, PackError> {
+        let PackSlices {
+            timings,
+            freq_coef,
+            freq_runs,
+            amp_coef,
+            amp_runs,
+            phases,
+            modes,
+            shapes,
+            width_coef,
+            width_runs,
+            envelopes,
+            envelope_runs,
+            fades,
+            gain_coef,
+            gain_runs,
+            pan_coef,
+            pan_runs,
+            lfo_rates,
+            lfo_ratios,
+            lfo_offsets,
+            lfo_shapes,
+            mod_amp_coef,
+            mod_amp_runs,
+            mod_freq_coef,
+            mod_freq_runs,
+            mod_phase_coef,
+            mod_phase_runs,
+            table_coef,
+            table_runs,
+            table_levels,
+            table_interpolations,
+            table_one_shots,
+            ids,
+            marker_times,
+            marker_names,
+            marker_runs,
+        } = slices;
+        let per_wave = [
+            ("freq_runs", freq_runs.len()),
+            ("amp_runs", amp_runs.len()),
+            ("phases", phases.len()),
+            ("modes", modes.len()),
+            ("shapes", shapes.len()),
+            ("width_runs", width_runs.len()),
+            ("envelope_runs", envelope_runs.len()),
+            ("fades", fades.len()),
+            ("gain_runs", gain_runs.len()),
+            ("pan_runs", pan_runs.len()),
+            ("lfo_rates", lfo_rates.len()),
+            ("lfo_ratios", lfo_ratios.len()),
+            ("lfo_offsets", lfo_offsets.len()),
+            ("lfo_shapes", lfo_shapes.len()),
+            ("mod_amp_runs", mod_amp_runs.len()),
+            ("mod_freq_runs", mod_freq_runs.len()),
+            ("mod_phase_runs", mod_phase_runs.len()),
+            ("table_runs", table_runs.len()),
+            ("table_levels", table_levels.len()),
+            ("table_interpolations", table_interpolations.len()),
+            ("table_one_shots", table_one_shots.len()),
+            ("ids", ids.len()),
+        ];
+        if let Some(&(lane, found)) = per_wave.iter().find(|&&(_, len)| len != timings.len()) {
+            return Err(PackError::LaneLength {
+                lane,
+                expected: timings.len(),
+                found,
+            });
+        }
+        if marker_runs.len() != marker_times.len() {
+            return Err(PackError::MarkerCount {
+                markers: marker_times.len(),
+                names: marker_runs.len(),
+            });
+        }
+        let names = MultiPoly::try_new(marker_names, marker_runs, "marker_runs")?;
+        if let Some(index) = names.clone().position(|name| from_utf8(name).is_err()) {
+            return Err(PackError::MarkerName { index });
+        }
+        if let Some(index) = marker_times.windows(2).position(|t| t[0] > t[1]) {
+            return Err(PackError::UnsortedMarker { index: index + 1 });
+        }
+        let tables = table_runs.iter().zip(table_levels);
+        if let Some(index) = tables.clone().position(|(&run, &levels)| {
+            (run == 0) != (levels == 0) || (levels > 0 && run % levels as RunLength != 0)
+        }) {
+            let (&samples, &levels) = tables.clone().nth(index).unwrap();
+            return Err(PackError::TableLevels {
+                index,
+                samples: samples as usize,
+                levels,
+            });
+        }
+        Ok(Self {
+            taken: 0,
             timings: timings.iter().peekable(),
-            frequencies: MultiPoly::new(frequency_coef, frequency_runs)?,
-            amplitudes: MultiPoly::new(amplitude_coef, amplitude_runs)?,
+            frequencies: MultiPoly::try_new(freq_coef, freq_runs, "freq_runs")?,
+            amplitudes: MultiPoly::try_new(amp_coef, amp_runs, "amp_runs")?,
             phases: phases.iter(),
+            modes: modes.iter(),
+            shapes: shapes.iter(),
+            widths: MultiPoly::try_new(width_coef, width_runs, "width_runs")?,
+            envelopes: MultiPoly::try_new(envelopes, envelope_runs, "envelope_runs")?,
+            fades: fades.iter(),
+            gains: MultiPoly::try_new(gain_coef, gain_runs, "gain_runs")?,
+            pans: MultiPoly::try_new(pan_coef, pan_runs, "pan_runs")?,
+            lfo_rates: lfo_rates.iter(),
+            lfo_ratios: lfo_ratios.iter(),
+            lfo_offsets: lfo_offsets.iter(),
+            lfo_shapes: lfo_shapes.iter(),
+            mod_amps: MultiPoly::try_new(mod_amp_coef, mod_amp_runs, "mod_amp_runs")?,
+            mod_freqs: MultiPoly::try_new(mod_freq_coef, mod_freq_runs, "mod_freq_runs")?,
+            mod_phases: MultiPoly::try_new(mod_phase_coef, mod_phase_runs, "mod_phase_runs")?,
+            tables: MultiPoly::try_new(table_coef, table_runs, "table_runs")?,
+            table_levels: table_levels.iter(),
+            table_interpolations: table_interpolations.iter(),
+            table_one_shots: table_one_shots.iter(),
+            ids: ids.iter(),
+            marker_times: marker_times.iter().peekable(),
+            marker_names: names,
         })
     }
+    // markers up to to on the device's clock go into crossed, ones before from were skipped over
+    fn cross_markers(
+        &mut self,
+        (from, to): (i64, i64),
+        rates: Rates,
+        crossed: &mut Vec<Marker<'a>>,
+    ) {
+        while let Some(time) = self
+            .marker_times
+            .next_if(|&&t| rates.to_device_time(t) < to)
+        {
+            let time = rates.to_device_time(*time);
+            let name = self.marker_names.next().unwrap_or_default();
+            if time >= from {
+                let name = from_utf8(name).unwrap_or_default();
+                record(crossed, Marker { time, name });
+            }
+        }
+    }
+    // the first sample after every remaining wave has finished its release, OPEN if one of them
+    // never ends
+    pub fn end_time(&self) -> i64 {
+        self.timings
+            .clone()
+            .zip(self.fades.clone())
+            .map(|(&[_, end], fade)| end.saturating_add(fade.release))
+            .max()
+            .unwrap_or(0)
+    }
     fn sample(&'s mut self, last_time: i64) -> WaveSlice<'s, 'a> {
+        self.sample_at(last_time, Rates::default())
+    }
+    // last_time is on the device's clock, and so are the waves that come out
+    fn sample_at(&'s mut self, last_time: i64, rates: Rates) -> WaveSlice<'s, 'a> {
         WaveSlice {
             waves: self,
             stop: last_time,
+            rates,
         }
     }
-    fn unravel(self, current_store: Vec<TimedWave<&'a [f32]>>) -> TimedWavePacker {
+    fn unravel(self, current_store: Voices<'a>, rates: Rates) -> TimedWavePacker {
         let mut packer = TimedWavePacker::new();
-        packer.extend(current_store);
-        {
-            let MultiPoly {
-                coeffs,
-                run_lengths,
-            } = self.amplitudes;
-            packer.amp_coef.extend_from_slice(coeffs);
-            packer.amp_runs.extend_from_slice(run_lengths.as_ref());
-        }
-        {
+        packer.extend(current_store.into_iter().map(|tw| rates.to_pack(tw.voice)));
+        let lanes = [
+            (self.amplitudes, &mut packer.amp_coef, &mut packer.amp_runs),
+            (
+                self.frequencies,
+                &mut packer.freq_coef,
+                &mut packer.freq_runs,
+            ),
+            (self.widths, &mut packer.width_coef, &mut packer.width_runs),
+            (self.gains, &mut packer.gain_coef, &mut packer.gain_runs),
+            (self.pans, &mut packer.pan_coef, &mut packer.pan_runs),
+            (
+                self.mod_amps,
+                &mut packer.mod_amp_coef,
+                &mut packer.mod_amp_runs,
+            ),
+            (
+                self.mod_freqs,
+                &mut packer.mod_freq_coef,
+                &mut packer.mod_freq_runs,
+            ),
+            (
+                self.mod_phases,
+                &mut packer.mod_phase_coef,
+                &mut packer.mod_phase_runs,
+            ),
+            (self.tables, &mut packer.table_coef, &mut packer.table_runs),
+        ];
+        for (poly, coef_lane, run_lane) in lanes {
             let MultiPoly {
                 coeffs,
                 run_lengths,
-            } = self.frequencies;
-            packer.freq_coef.extend_from_slice(coeffs);
-            packer.freq_runs.extend_from_slice(run_lengths.as_ref());
+            } = poly;
+            coef_lane.extend_from_slice(coeffs);
+            run_lane.extend_from_slice(run_lengths.as_ref());
         }
         packer.phases.extend_from_slice(self.phases.as_slice());
+        packer.modes.extend_from_slice(self.modes.as_slice());
+        packer.shapes.extend_from_slice(self.shapes.as_slice());
+        packer.envelopes.extend_from_slice(self.envelopes.coeffs);
+        packer
+            .envelope_runs
+            .extend_from_slice(self.envelopes.run_lengths.as_ref());
+        packer.fades.extend_from_slice(self.fades.as_slice());
+        packer
+            .lfo_rates
+            .extend_from_slice(self.lfo_rates.as_slice());
+        packer
+            .lfo_ratios
+            .extend_from_slice(self.lfo_ratios.as_slice());
+        packer
+            .lfo_offsets
+            .extend_from_slice(self.lfo_offsets.as_slice());
+        packer
+            .lfo_shapes
+            .extend_from_slice(self.lfo_shapes.as_slice());
+        packer
+            .table_levels
+            .extend_from_slice(self.table_levels.as_slice());
+        packer
+            .table_interpolations
+            .extend_from_slice(self.table_interpolations.as_slice());
+        packer
+            .table_one_shots
+            .extend_from_slice(self.table_one_shots.as_slice());
+        packer.ids.extend_from_slice(self.ids.as_slice());
+        packer.marker_times.extend(self.marker_times);
+        packer
+            .marker_names
+            .extend_from_slice(self.marker_names.coeffs);
+        packer
+            .marker_runs
+            .extend_from_slice(self.marker_names.run_lengths.as_ref());
         packer.timings.extend(self.timings);
         packer
     }
+    // the store with everything that's started by time in it, and when it next needs looking at
     fn deposit_current(
         &mut self,
-        mut current_store: Vec<TimedWave<&'a [f32]>>,
+        mut current_store: Voices<'a>,
         time: i64,
         wakeup_time: i64,
-    ) -> Result<(Vec<TimedWave<&'a [f32]>>, i64), TimedWavePacker> {
-        current_store.retain(|tw| tw.end > time);
-        if time >= wakeup_time {
-            let capture = replace(self, Self::default());
-            return Err(capture.unravel(current_store));
+        thief: &mut Thief<'a>,
+        rates: Rates,
+    ) -> (Voices<'a>, i64) {
+        current_store.retain(|tw| tw.stop() > time);
+        // the store only moves around, so filling it up to capacity keeps this allocation free
+        // waves that were over before time came around, like when starting partway through, are skipped
+        let mut waves = self.sample_at(time, rates);
+        while let Some(voice) = waves.next() {
+            if voice.stop() > time {
+                let index = waves.waves.taken - 1;
+                let wave = Playing { index, voice };
+                thief.admit(&mut current_store, wave, time, rates.scale());
+            }
         }
-        current_store.extend(self.sample(time));
 
         let kill_wakeup_time = current_store
             .iter()
-            .map(|tw| tw.end)
+            .map(|tw| tw.stop())
             .min()
             .unwrap_or(wakeup_time);
-        let birth_wakeup_time = self.timings.peek().map(|&&(s, _)| s).unwrap_or(wakeup_time);
+        let birth_wakeup_time = self
+            .timings
+            .peek()
+            .map(|&&[s, _]| rates.to_device_time(s))
+            .unwrap_or(wakeup_time);
         let real_wakeup = kill_wakeup_time.min(birth_wakeup_time).min(wakeup_time);
 
-        Ok((current_store, real_wakeup))
+        (current_store, real_wakeup)
     }
 }
 impl<'a> Default for PackedTimedWaves<'a> {
     fn default() -> Self {
-        Self::new(&[], &[], &[], &[], &[], &[]).unwrap()
+        Self::new(PackSlices::default()).unwrap()
     }
 }
 
 #[test]
-fn depositing() {
-    let waves: TimedWavePacker = [(0, 6), (5, 8), (7, 9), (8, 12)]
+fn pack_errors() {
+    let waves: TimedWavePacker = [(0, 6), (5, 8), (7, 9)]
         .into_iter()
-        .map(|(start, end)| TimedWave {
-            start,
-            end,
-            wave: Wave::default(),
-        })
+        .map(|(start, end)| LiveWave::sine(start, end, 1., 0.5))
         .collect();
-    let mut waves = waves.get_pack().unwrap();
-    let deposit = match waves.deposit_current(Vec::new(), 0, 8) {
-        Ok((d, 5)) => d,
-        Ok((_, n)) => panic!("next pause was {} insead of 5", n),
-        Err(_) => panic!("failed to deposit"),
-    };
+    assert!(waves.try_get_pack().is_ok());
+
+    let mut short = waves.clone();
+    short.modes.pop();
     assert_eq!(
-        deposit,
-        vec![TimedWave {
-            start: 0,
-            end: 6,
-            wave: Wave::default()
-        }]
+        short.try_get_pack().err(),
+        Some(PackError::LaneLength {
+            lane: "modes",
+            expected: 3,
+            found: 2
+        })
+    );
+
+    let mut unsorted = waves.clone();
+    unsorted.timings[2][0] = 1;
+    assert_eq!(
+        unsorted.try_get_pack().err(),
+        Some(PackError::Unsorted { index: 2 })
+    );
+
+    let mut overrun = waves.clone();
+    overrun.amp_runs[1] = 2;
+    assert_eq!(
+        overrun.try_get_pack().err(),
+        Some(PackError::RunSum {
+            lane: "amp_runs",
+            coefficients: 3,
+            runs: 4
+        })
+    );
+
+    let mut uneven = waves.clone();
+    uneven.table_coef = vec![0.; 3];
+    uneven.table_runs[2] = 3;
+    uneven.table_levels[2] = 2;
+    assert_eq!(
+        uneven.try_get_pack().err(),
+        Some(PackError::TableLevels {
+            index: 2,
+            samples: 3,
+            levels: 2
+        })
     );
 
-    let deposit = match waves.deposit_current(deposit, 5, 8) {
-        Ok((d, 6)) => d,
-        Ok((_, n)) => panic!("next pause was {} insead of 6", n),
-        Err(_) => panic!("failed to deposit"),
+    // long fits are fine, only ones past what a run can count are turned away
+    let fit = vec![0.; 300];
+    let too_long = vec![0.; RunLength::MAX as usize + 1];
+    let mut packer = TimedWavePacker::new();
+    let wave = |freq| {
+        let sine = LiveWave::sine(0, 1, 0., 0.5);
+        TimedWave {
+            wave: Wave { freq, ..sine.wave },
+            ..sine
+        }
     };
     assert_eq!(
-        deposit,
-        vec![
-            TimedWave {
-                start: 0,
-                end: 6,
-                wave: Wave::default()
+        packer.try_extend([wave(fit), wave(too_long.clone())]),
+        Err(PackError::TooManyCoefficients {
+            lane: "freq_runs",
+            index: 1,
+            count: too_long.len()
+        })
+    );
+    assert_eq!(packer.freq_runs, [300]);
+    assert!(packer.try_get_pack().is_ok());
+}
+
+#[test]
+fn normalizing() {
+    let wave = |start, freq| {
+        let sine = LiveWave::sine(start, start + 2, 0., 0.5);
+        TimedWave {
+            wave: Wave {
+                freq,
+                phase: start as f32,
+                ..sine.wave
             },
-            TimedWave {
-                start: 5,
-                end: 8,
-                wave: Wave::default()
-            }
-        ]
+            ..sine
+        }
+    };
+    let mut packer: TimedWavePacker = [
+        wave(4, vec![1., 2., 3.]),
+        wave(0, vec![4.]),
+        wave(4, vec![5., 6.]),
+        wave(2, vec![]),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(
+        packer.try_get_pack().err(),
+        Some(PackError::Unsorted { index: 1 })
     );
+    packer.normalize().unwrap();
+    assert_eq!(packer.timings, [[0, 2], [2, 4], [4, 6], [4, 6]]);
+    assert_eq!(packer.phases, [0., 2., 4., 4.]);
+    assert_eq!(packer.freq_runs, [1, 0, 3, 2]);
+    assert_eq!(packer.freq_coef, [4., 1., 2., 3., 5., 6.]);
+    assert!(packer.try_get_pack().is_ok());
+
+    let mut broken = packer.clone();
+    broken.phases.pop();
+    assert!(broken.normalize().is_err());
+    assert_eq!(broken.timings, packer.timings);
+}
+
+#[test]
+fn merging() {
+    let clip = |timings: &[(i64, i64)], freq: f32| -> TimedWavePacker {
+        timings
+            .iter()
+            .map(|&(start, end)| LiveWave::sine(start, end, freq, 1.))
+            .collect()
+    };
+    let mut song = clip(&[(0, 4), (4, 8)], 1.);
+    song.merge(&clip(&[(0, 2), (2, 6), (6, 8)], 2.)).unwrap();
+    assert_eq!(song.timings, [[0, 4], [0, 2], [2, 6], [4, 8], [6, 8]]);
+    assert_eq!(song.freq_coef, [1., 2., 2., 1., 2.]);
+
+    song.append_shifted(&clip(&[(0, 3)], 3.), 8).unwrap();
+    assert_eq!(song.timings.last(), Some(&[8, 11]));
+    assert_eq!(song.freq_coef.last(), Some(&3.));
+    assert!(song.try_get_pack().is_ok());
+}
 
-    let deposit = match waves.deposit_current(deposit, 6, 8) {
-        Ok((d, 7)) => d,
-        Ok((_, n)) => panic!("next pause was {} insead of 7", n),
-        Err(_) => panic!("failed to deposit"),
+#[test]
+fn inspecting() {
+    let wave = |start, end, freq| TimedWave {
+        fade: Fade {
+            release: 10,
+            ..Fade::default()
+        },
+        ..LiveWave::sine(start, end, freq, 0.5)
+    };
+    let packer: TimedWavePacker = [
+        wave(0, 100, 0.01),
+        wave(50, 60, 0.02),
+        wave(80, 200, 0.03),
+        wave(150, 160, 0.04),
+    ]
+    .into_iter()
+    .collect();
+    let freqs = |waves: &mut dyn Iterator<Item = Voice>| -> Vec<f32> {
+        waves.map(|tw| tw.wave.freq[0]).collect()
     };
     assert_eq!(
-        deposit,
-        vec![TimedWave {
-            start: 5,
-            end: 8,
-            wave: Wave::default()
-        }]
+        freqs(&mut packer.waves().unwrap()),
+        [0.01, 0.02, 0.03, 0.04]
     );
+    assert_eq!(freqs(&mut packer.active_at(55).unwrap()), [0.01, 0.02]);
+    // the first is in its release and the second's just finished
+    assert_eq!(freqs(&mut packer.active_at(105).unwrap()), [0.01, 0.03]);
+    assert_eq!(freqs(&mut packer.active_at(70).unwrap()), [0.01]);
+    assert_eq!(freqs(&mut packer.events_in(50..150).unwrap()), [0.02, 0.03]);
+    let first = packer.active_at(0).unwrap().next().unwrap();
+    assert_eq!(first.wave.amp, Envelope::Poly(&[0.5][..]));
+    assert_eq!(first.fade.release, 10);
+
+    let mut broken = packer.clone();
+    broken.phases.pop();
+    assert!(broken.active_at(0).is_err());
+}
 
-    let deposit = match waves.deposit_current(deposit, 7, 8) {
-        Ok((d, 8)) => d,
-        Ok((_, n)) => panic!("next pause was {} insead of 8", n),
-        Err(_) => panic!("failed to deposit"),
+#[test]
+fn pack_stats() {
+    let wave = |start, end, freq| {
+        let sine = LiveWave::sine(start, end, 0., 0.5);
+        TimedWave {
+            wave: Wave { freq, ..sine.wave },
+            fade: Fade {
+                release: 10,
+                ..Fade::default()
+            },
+            ..sine
+        }
     };
+    let packer: TimedWavePacker = [
+        wave(100, 200, vec![0.01]),
+        wave(150, 160, vec![0.01, 0.001]),
+        wave(150, 300, vec![0.01]),
+        // starts right as the second one's release ends
+        wave(170, 180, vec![0.01]),
+        wave(400, 500, vec![0.01]),
+    ]
+    .into_iter()
+    .collect();
+    let stats = packer.stats().unwrap();
+    assert_eq!(stats.waves, 5);
+    assert_eq!(stats.duration, 410);
+    assert_eq!(stats.max_voices, 3);
+    assert_eq!(stats.coefficient_bytes, (6 + 5) * 4);
+    assert_eq!(
+        TimedWavePacker::new().stats().unwrap(),
+        PackStats::default()
+    );
+}
+
+#[test]
+fn markers() {
+    let mut packer: TimedWavePacker = [(20, 30), (0, 30)]
+        .into_iter()
+        .map(|(start, end)| LiveWave::sine(start, end, 1., 0.5))
+        .collect();
+    packer.add_marker(10, "drop").unwrap();
+    packer.add_marker(4, "intro").unwrap();
+    packer.add_marker(10, "loop").unwrap();
+    packer.normalize().unwrap();
+    packer.shift(2);
+    let marked: Vec<_> = packer.markers().map(|m| (m.time, m.name)).collect();
+    assert_eq!(marked, [(6, "intro"), (12, "drop"), (12, "loop")]);
+    let mut other = packer.clone();
+    other.merge(&packer).unwrap();
+    assert_eq!(other.markers().count(), 6);
+    // an append that fails leaves the pack it was going into alone, markers and all
+    let mut broken = packer.clone();
+    broken.marker_names[0] = 0xff;
+    let before = other.clone();
+    assert_eq!(
+        other.append_shifted(&broken, 100),
+        Err(PackError::MarkerName { index: 0 })
+    );
+    assert_eq!(other, before);
+
+    let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(40));
+    let mut playback = [0.; 8];
+    player.play(&mut playback).unwrap();
+    assert_eq!(
+        player.markers(),
+        [Marker {
+            time: 6,
+            name: "intro"
+        }]
+    );
+    player.play(&mut playback).unwrap();
+    let names: Vec<_> = player.markers().iter().map(|m| m.name).collect();
+    assert_eq!(names, ["drop", "loop"]);
+    player.play(&mut playback).unwrap();
+    assert!(player.markers().is_empty());
+    // starting partway through skips the ones already gone by
+    let mut player = Player::new(packer.get_pack().unwrap(), 8, Some(40));
+    player.play(&mut playback).unwrap();
+    assert_eq!(player.markers().len(), 2);
+    // there's room for every marker before playing, this pack's and the queued one's, so going
+    // past all of them in one block doesn't grow the buffer
+    let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(60));
+    player.queue_next(packer.get_pack().unwrap(), Handoff::At(20));
+    let room = player.markers.capacity();
+    assert!(room >= 6);
+    player.play(&mut [0.; 60]).unwrap();
+    assert_eq!(player.markers().len(), 6);
+    assert_eq!(player.markers.capacity(), room);
+
+    let mut broken = packer.clone();
+    broken.marker_runs.pop();
+    assert_eq!(
+        broken.try_get_pack().err(),
+        Some(PackError::MarkerCount {
+            markers: 3,
+            names: 2
+        })
+    );
+    let mut broken = packer.clone();
+    broken.marker_names[0] = 0xff;
+    assert_eq!(
+        broken.try_get_pack().err(),
+        Some(PackError::MarkerName { index: 0 })
+    );
+    let mut broken = packer.clone();
+    broken.marker_times[0] = 20;
     assert_eq!(
-        deposit,
-        vec![
-            TimedWave {
-                start: 5,
-                end: 8,
-                wave: Wave::default()
+        broken.try_get_pack().err(),
+        Some(PackError::UnsortedMarker { index: 1 })
+    );
+}
+
+#[test]
+fn pack_handoff() {
+    let clip = |end, amp, release| -> TimedWavePacker {
+        [TimedWave {
+            fade: Fade {
+                release,
+                ..Fade::default()
             },
-            TimedWave {
-                start: 7,
-                end: 9,
-                wave: Wave::default()
-            }
-        ]
+            ..LiveWave::sine(0, end, 1., amp)
+        }]
+        .into_iter()
+        .collect()
+    };
+    let (mut verse, chorus) = (clip(10, 0.5, 3), clip(4, 0.25, 0));
+    let mut player = Player::new(verse.get_pack().unwrap(), 0, Some(40));
+    assert_eq!(
+        player.queue_next(chorus.get_pack().unwrap(), Handoff::End),
+        10
+    );
+    let mut playback = [0.; 16];
+    player.play(&mut playback).unwrap();
+    // the verse's release goes on under the start of the chorus
+    assert_eq!(playback[8..], [0.5, 0.5, 0.625, 0.5, 0.375, 0.25, 0., 0.]);
+
+    verse.add_marker(6, "loop").unwrap();
+    let mut player = Player::new(verse.get_pack().unwrap(), 0, Some(40));
+    let mut playback = [0.; 4];
+    player.play(&mut playback).unwrap();
+    assert_eq!(
+        player.queue_next(chorus.get_pack().unwrap(), Handoff::Marker),
+        6
+    );
+    player.play(&mut playback).unwrap();
+    assert_eq!(
+        player.markers(),
+        [Marker {
+            time: 6,
+            name: "loop"
+        }]
+    );
+    assert_eq!(playback, [0.5, 0.5, 0.75, 0.75]);
+    assert_eq!(
+        player.queue_next(verse.get_pack().unwrap(), Handoff::At(1)),
+        8
     );
+}
+
+#[test]
+fn snapshots() {
+    let wave = |start, freq, id| {
+        let sine = LiveWave::sine(start, start + 30, freq, 0.);
+        TimedWave {
+            wave: Wave {
+                amp: Envelope::Adsr(Adsr {
+                    peak: 0.5,
+                    attack: 4.,
+                    sustain: 1.,
+                    gate: 20.,
+                    release: 10.,
+                    ..Adsr::default()
+                }),
+                phase: 0.,
+                shape: Waveform::Triangle,
+                ..sine.wave
+            },
+            mix: Mix {
+                id,
+                ..Mix::default()
+            },
+            ..sine
+        }
+    };
+    let mut packer: TimedWavePacker = [(0, 0.01), (5, 0.02), (12, 0.03), (40, 0.04)]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (start, freq))| wave(start, freq, i as u32 + 1))
+        .collect();
+    packer.add_marker(50, "end").unwrap();
+    let mut player = Player::with_voices(packer.get_pack().unwrap(), 0, Some(1000), 2);
+    player.set_stealing(Stealing::Oldest, 6);
+    let mut playback = [0.; 16];
+    player.play(&mut playback).unwrap();
+    player.note_off(2, 18);
+    player.schedule(wave(20, 0.05, 9));
+    let state = player.snapshot();
+    assert_eq!(state.time(), 16);
+
+    let mut before = [0.; 64];
+    player.play(&mut before).unwrap();
+    let markers = player.markers().to_vec();
+    player.restore(&state).unwrap();
+    let mut again = [0.; 64];
+    player.play(&mut again).unwrap();
+    assert_eq!(before, again);
+    assert_eq!(player.markers(), markers);
+    // a save loaded into a new player
+    let mut loaded = Player::with_voices(packer.get_pack().unwrap(), 0, Some(1000), 2);
+    loaded.set_stealing(Stealing::Oldest, 6);
+    loaded.restore(&state).unwrap();
+    loaded.play(&mut again).unwrap();
+    assert_eq!(before, again);
+
+    let empty = TimedWavePacker::new();
+    let mut wrong = Player::new(empty.get_pack().unwrap(), 0, Some(1000));
+    assert_eq!(wrong.restore(&state), Err(RestoreError::WrongPack));
+    let mut cramped = Player::with_voices(packer.get_pack().unwrap(), 0, Some(1000), 1);
+    assert_eq!(cramped.restore(&state), Err(RestoreError::TooManyVoices));
+}
+
+#[test]
+fn retiming() {
+    let wave = |start| LiveWave::sine(start, start + 50, 0.01, 0.5);
+    let loose: TimedWavePacker = [3, 96, 104, 149, 151, 260].map(wave).into_iter().collect();
+    let starts = |packer: &TimedWavePacker| -> Vec<i64> {
+        packer.timings.iter().map(|[start, _]| *start).collect()
+    };
+    let mut tight = loose.clone();
+    tight.quantize(100., 1.);
+    assert_eq!(starts(&tight), [0, 100, 100, 100, 200, 300]);
+    assert!(tight.timings.iter().all(|[start, end]| end - start == 50));
+    let mut half = loose.clone();
+    half.quantize(100., 0.5);
+    assert_eq!(starts(&half), [2, 98, 102, 125, 176, 280]);
+    assert!(half.try_get_pack().is_ok());
+    let mut untouched = loose.clone();
+    untouched.quantize(0., 1.);
+    untouched.quantize(100., 0.);
+    untouched.quantize(100., -1.);
+    untouched.quantize(100., f32::NAN);
+    assert_eq!(untouched, loose);
+    // past 1 is as far as 1, it doesn't overshoot the line and swap 149 and 151 round
+    let mut over = loose.clone();
+    over.quantize(100., 3.);
+    assert_eq!(over, tight);
 
-    let packer = match waves.deposit_current(deposit, 8, 8) {
-        Err(p) => p,
-        Ok(_) => panic!("deposit failed to abort"),
+    tight.shift(-100);
+    assert_eq!(starts(&tight), [-100, 0, 0, 0, 100, 200]);
+    assert_eq!(tight.timings[5][1], 250);
+    assert!(tight.try_get_pack().is_ok());
+}
+
+#[test]
+fn open_packs() {
+    let held = TimedWave {
+        fade: Fade {
+            release: 3,
+            ..Fade::default()
+        },
+        ..LiveWave::sine(10, OPEN, 0.01, 0.5)
+    };
+    let mut packer: TimedWavePacker = [held, LiveWave::sine(0, 100, 0.01, 0.5)]
+        .into_iter()
+        .collect();
+    packer.normalize().unwrap();
+    // a pack that never ends says so rather than overflowing
+    assert_eq!(packer.get_pack().unwrap().end_time(), OPEN);
+    assert_eq!(packer.stats().unwrap().duration, OPEN);
+    assert_eq!(packer.stats().unwrap().max_voices, 2);
+
+    // moved about every which way, the held note keeps its open end
+    let ends = |packer: &TimedWavePacker| -> Vec<i64> {
+        packer.timings.iter().map(|[_, end]| *end).collect()
+    };
+    let mut shifted = packer.clone();
+    shifted.shift(1);
+    assert_eq!(shifted.timings, [[1, 101], [11, OPEN]]);
+    shifted.shift(-5);
+    assert_eq!(shifted.timings, [[-4, 96], [6, OPEN]]);
+    let mut appended = packer.clone();
+    appended.append_shifted(&packer, 50).unwrap();
+    assert_eq!(ends(&appended), [100, OPEN, 150, OPEN]);
+    let mut quantized = packer.clone();
+    quantized.quantize(8., 1.);
+    assert_eq!(quantized.timings, [[0, 100], [8, OPEN]]);
+    let mut humanized = packer.clone();
+    humanized.humanize(3, 4., 0.1).unwrap();
+    assert!(ends(&humanized).contains(&OPEN));
+    let mut stretched = packer.clone();
+    stretched.stretch(0.5, false).unwrap();
+    assert_eq!(stretched.timings, [[0, 50], [5, OPEN]]);
+    assert!(stretched.try_get_pack().is_ok());
+    let mut timed = TimedWavePacker::new();
+    let timeline = crate::timeline::Timeline::new(120., 48000.);
+    let held = LiveWave::sine(0, OPEN, 0.01, 0.5);
+    timed.try_extend_at(&timeline, 1., [held]).unwrap();
+    assert_eq!(timed.timings, [[24000, OPEN]]);
+}
+
+#[test]
+fn humanizing() {
+    let adsr = Adsr {
+        peak: 1.,
+        attack: 1.,
+        decay: 1.,
+        sustain: 0.5,
+        gate: 10.,
+        release: 1.,
+    };
+    let wave = |start, amp| {
+        let sine = LiveWave::sine(start, start + 20, 0.01, 0.);
+        TimedWave {
+            wave: Wave { amp, ..sine.wave },
+            ..sine
+        }
+    };
+    let mut grid = TimedWavePacker::new();
+    grid.try_extend((0..64).map(|i| match i % 2 {
+        0 => wave(i * 20, Envelope::Poly(vec![0.5, 0.001])),
+        _ => wave(i * 20, Envelope::Adsr(adsr)),
+    }))
+    .unwrap();
+    let mut loose = grid.clone();
+    loose.humanize(7, 8., 0.2).unwrap();
+    assert!(loose.try_get_pack().is_ok());
+    assert_eq!(loose.timings.len(), 64);
+    assert_ne!(loose.timings, grid.timings);
+    assert!(loose.timings.iter().all(|[start, end]| end - start == 20));
+    // far enough apart that none get past each other, so each is still near its own grid line
+    for ([start, _], i) in loose.timings.iter().zip(0..) {
+        assert!((start - i * 20).abs() <= 8);
+    }
+    for pair in loose.amp_coef.chunks(2) {
+        let scale = pair[0] / 0.5;
+        assert!((0.8..=1.2).contains(&scale));
+        assert!((pair[1] - 0.001 * scale).abs() < 1e-9);
+    }
+    assert!(loose
+        .envelopes
+        .iter()
+        .all(|e| (0.8..=1.2).contains(&e.peak)));
+    assert!(loose.envelopes.iter().any(|e| e.peak != 1.));
+
+    let mut again = grid.clone();
+    again.humanize(7, 8., 0.2).unwrap();
+    assert_eq!(again, loose);
+    grid.humanize(8, 8., 0.2).unwrap();
+    assert_ne!(grid, loose);
+}
+
+#[test]
+fn transposing() {
+    let wave = |freq| {
+        let sine = LiveWave::sine(0, 1000, 0., 0.5);
+        TimedWave {
+            wave: Wave {
+                freq,
+                phase: 0.,
+                mode: PhaseMode::Integral,
+                ..sine.wave
+            },
+            modulation: Modulation::vibrato(0.01, 0.001, 30.),
+            ..sine
+        }
+    };
+    let cue: TimedWavePacker = [wave(vec![0.01]), wave(vec![0.01, 0.00001])]
+        .into_iter()
+        .collect();
+    let mut fifth = cue.clone();
+    fifth.transpose(7.);
+    let mut octave = cue.clone();
+    octave.transpose_ratio(2.);
+    let ratio = (7f32 / 12.).exp2();
+    let render = |packer: &TimedWavePacker| {
+        let mut out = [0f32; 1000];
+        Player::new(packer.get_pack().unwrap(), 0, Some(1000))
+            .play(&mut out)
+            .unwrap();
+        out
+    };
+    let (up, high) = (render(&fifth), render(&octave));
+    for (t, (up, high)) in up.into_iter().zip(high).enumerate() {
+        // both waves are the same once their coefficients are scaled, and so is the vibrato
+        let expected = |ratio: f32| {
+            let scaled = |tw: LiveWave| TimedWave {
+                wave: Wave {
+                    freq: tw.wave.freq.iter().map(|f| f * ratio).collect(),
+                    ..tw.wave.clone()
+                },
+                modulation: Modulation::vibrato(0.01 * ratio, 0.001, 30.),
+                ..tw
+            };
+            let sample = |tw| scaled(tw).sample(t as i64, Rendering::default(), 1.);
+            sample(wave(vec![0.01])) + sample(wave(vec![0.01, 0.00001]))
+        };
+        assert!((up - expected(ratio)).abs() < 1e-3, "{}", t);
+        assert!((high - expected(2.)).abs() < 1e-3, "{}", t);
+    }
+    assert_eq!(octave.amp_coef, cue.amp_coef);
+}
+
+#[test]
+fn stretching() {
+    let adsr = Adsr {
+        peak: 0.5,
+        attack: 10.,
+        decay: 20.,
+        sustain: 0.5,
+        gate: 200.,
+        release: 50.,
+    };
+    let sweep = TimedWave {
+        start: 10,
+        end: 300,
+        wave: Wave {
+            freq: vec![0.01, 0.00002],
+            amp: Envelope::Poly(vec![0.5, -0.001]),
+            phase: 3.,
+            mode: PhaseMode::Integral,
+            shape: Waveform::Saw,
+            width: vec![],
+        },
+        fade: Fade {
+            fade_in: 20,
+            release: 30,
+            curve: FadeCurve::Smooth,
+        },
+        mix: Mix {
+            gain: vec![1., -0.001],
+            pan: vec![],
+            id: 0,
+        },
+        modulation: Modulation::vibrato(0.01, 0.01, 50.),
+        table: Wavetable::default(),
+    };
+    let held = TimedWave {
+        start: 40,
+        end: 400,
+        wave: Wave {
+            freq: vec![0.02],
+            amp: Envelope::Adsr(adsr),
+            mode: PhaseMode::Product,
+            shape: Waveform::Sine,
+            ..sweep.wave.clone()
+        },
+        modulation: Modulation::tremolo(0.005, 0.3),
+        ..sweep.clone()
     };
+    let cue: TimedWavePacker = [sweep, held].into_iter().collect();
+    let render = |packer: &TimedWavePacker, length: usize| {
+        let mut out = vec![0f32; length];
+        let mut player = Player::new(packer.get_pack().unwrap(), 0, Some(length as i64));
+        player.set_clipping(Clipping::Off);
+        player.play(&mut out).unwrap();
+        out
+    };
+    let original = render(&cue, 400);
+
+    // half speed tape, every other sample lines up with the original
+    let mut slow = cue.clone();
+    slow.stretch(2., false).unwrap();
+    assert_eq!(slow.timings, [[20, 600], [80, 800]]);
+    assert_eq!(slow.envelopes[0].gate, 400.);
+    let slowed = render(&slow, 800);
+    for (t, s) in original.iter().enumerate() {
+        assert!((slowed[2 * t] - s).abs() < 1e-3, "{}", t);
+    }
+
+    // as long again but at the original pitch, the lfos slow down all the same
+    let mut kept = cue.clone();
+    kept.stretch(2., true).unwrap();
+    assert_eq!(kept.freq_coef[..2], [0.01, 0.00001]);
+    assert_eq!(kept.freq_coef[2], 0.02);
+    assert_eq!(kept.lfo_rates, slow.lfo_rates);
+    assert_eq!(kept.mod_freq_coef, cue.mod_freq_coef);
+    assert_eq!(kept.timings, slow.timings);
+
+    // no length, backwards or not a number at all doesn't touch anything
+    for factor in [0., -0., -2., f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        let mut bad = cue.clone();
+        assert_eq!(bad.stretch(factor, false), Err(PackError::StretchFactor));
+        assert_eq!(bad, cue);
+    }
+}
+
+#[test]
+fn depositing() {
+    let waves: TimedWavePacker = [(0, 6), (5, 8), (7, 9), (8, 12)]
+        .into_iter()
+        .map(|(start, end)| TimedWave {
+            start,
+            end,
+            wave: Wave::default(),
+            fade: Fade::default(),
+            mix: Mix::default(),
+            modulation: Modulation::default(),
+            table: Wavetable::default(),
+        })
+        .collect();
+    let mut waves = waves.get_pack().unwrap();
+    let mut thief = Thief::new(Stealing::Skip, 0, 4);
+    // the player always hands back amps as envelopes
+    let silent = |index, start, end| Playing {
+        index,
+        voice: TimedWave {
+            start,
+            end,
+            wave: Wave {
+                freq: &[][..],
+                amp: Envelope::Poly(&[][..]),
+                phase: 0.0,
+                mode: PhaseMode::Product,
+                shape: Waveform::Sine,
+                width: &[][..],
+            },
+            fade: Fade::default(),
+            mix: Mix::default(),
+            modulation: Modulation::default(),
+            table: Wavetable::default(),
+        },
+    };
+    let deposit =
+        match waves.deposit_current(Vec::with_capacity(4), 0, 8, &mut thief, Rates::default()) {
+            (d, 5) => d,
+            (_, n) => panic!("next pause was {} insead of 5", n),
+        };
+    assert_eq!(deposit, vec![silent(0, 0, 6)]);
+
+    let deposit = match waves.deposit_current(deposit, 5, 8, &mut thief, Rates::default()) {
+        (d, 6) => d,
+        (_, n) => panic!("next pause was {} insead of 6", n),
+    };
+    assert_eq!(deposit, vec![silent(0, 0, 6), silent(1, 5, 8)]);
+
+    let deposit = match waves.deposit_current(deposit, 6, 8, &mut thief, Rates::default()) {
+        (d, 7) => d,
+        (_, n) => panic!("next pause was {} insead of 7", n),
+    };
+    assert_eq!(deposit, vec![silent(1, 5, 8)]);
+
+    let deposit = match waves.deposit_current(deposit, 7, 8, &mut thief, Rates::default()) {
+        (d, 8) => d,
+        (_, n) => panic!("next pause was {} insead of 8", n),
+    };
+    assert_eq!(deposit, vec![silent(1, 5, 8), silent(2, 7, 9)]);
+
+    // what drain does at 8
+    let mut deposit = deposit;
+    deposit.retain(|tw| tw.stop() > 8);
+    let packer = take(&mut waves).unravel(deposit, Rates::default());
     let correct_packer: TimedWavePacker = [(7, 9), (8, 12)]
         .into_iter()
         .map(|(start, end)| TimedWave {
             start,
             end,
             wave: Wave::default(),
+            fade: Fade::default(),
+            mix: Mix::default(),
+            modulation: Modulation::default(),
+            table: Wavetable::default(),
         })
         .collect();
     assert_eq!(packer, correct_packer);
@@ -350,18 +4346,68 @@ fn depositing() {
 struct WaveSlice<'w, 's> {
     waves: &'w mut PackedTimedWaves<'s>,
     stop: i64,
+    rates: Rates,
 }
 impl<'w, 's> Iterator for WaveSlice<'w, 's> {
-    type Item = TimedWave<&'s [f32]>;
+    type Item = Voice<'s>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (start, end) = *self.waves.timings.next_if(|&&(s, _e)| s <= self.stop)?;
+        let rates = self.rates;
+        let [start, end] = *self
+            .waves
+            .timings
+            .next_if(|&&[s, _e]| rates.to_device_time(s) <= self.stop)?;
+        self.waves.taken += 1;
         let wave = (&mut self.waves.phases)
+            .zip(&mut self.waves.modes)
+            .zip(&mut self.waves.shapes)
             .zip(&mut self.waves.frequencies)
             .zip(&mut self.waves.amplitudes)
+            .zip(&mut self.waves.widths)
+            .zip(&mut self.waves.envelopes)
             .next()
-            .map(|((&phase, freq), amp)| Wave { freq, amp, phase })?;
-        Some(TimedWave { start, end, wave })
+            .map(
+                |((((((&phase, &mode), &shape), freq), amp), width), adsr)| Wave {
+                    freq,
+                    amp: adsr
+                        .first()
+                        .map_or(Envelope::Poly(amp), |&a| Envelope::Adsr(a)),
+                    phase,
+                    mode,
+                    shape,
+                    width,
+                },
+            )?;
+        let &fade = self.waves.fades.next()?;
+        let mix = Mix {
+            gain: self.waves.gains.next()?,
+            pan: self.waves.pans.next()?,
+            id: *self.waves.ids.next()?,
+        };
+        let modulation = Modulation {
+            rate: *self.waves.lfo_rates.next()?,
+            ratio: *self.waves.lfo_ratios.next()?,
+            offset: *self.waves.lfo_offsets.next()?,
+            shape: *self.waves.lfo_shapes.next()?,
+            amp: self.waves.mod_amps.next()?,
+            freq: self.waves.mod_freqs.next()?,
+            phase: self.waves.mod_phases.next()?,
+        };
+        let mut table = Wavetable::from_parts(
+            self.waves.tables.next()?,
+            *self.waves.table_levels.next()?,
+            *self.waves.table_interpolations.next()?,
+        )?;
+        table.one_shot = *self.waves.table_one_shots.next()?;
+        Some(rates.to_device(TimedWave {
+            start,
+            end,
+            wave,
+            fade,
+            mix,
+            modulation,
+            table,
+        }))
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
         (0, self.waves.phases.size_hint().1)
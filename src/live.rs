@@ -0,0 +1,87 @@
+// lock free handoff of waves from another thread into a player that's already running
+// the receiver lives next to the player in the audio callback and gets fed before every block,
+// future start times then land on their exact sample
+use rtrb::{PushError, RingBuffer};
+
+use crate::{LiveWave, Player};
+
+pub fn wave_channel(capacity: usize) -> (WaveSender, WaveReceiver) {
+    let (producer, consumer) = RingBuffer::new(capacity);
+    (
+        WaveSender { waves: producer },
+        WaveReceiver { waves: consumer },
+    )
+}
+
+#[derive(Debug)]
+pub struct WaveSender {
+    waves: rtrb::Producer<LiveWave>,
+}
+impl WaveSender {
+    // hands the wave back if the audio thread hasn't caught up yet
+    #[allow(clippy::result_large_err)]
+    pub fn send(&mut self, wave: LiveWave) -> Result<(), LiveWave> {
+        self.waves.push(wave).map_err(|PushError::Full(wave)| wave)
+    }
+    pub fn is_abandoned(&self) -> bool {
+        self.waves.is_abandoned()
+    }
+}
+
+#[derive(Debug)]
+pub struct WaveReceiver {
+    waves: rtrb::Consumer<LiveWave>,
+}
+impl WaveReceiver {
+    // waves stay queued while the player has no room for them
+    pub fn feed(&mut self, player: &mut Player) {
+        while !player.live_full() {
+            match self.waves.pop() {
+                Ok(wave) => player.schedule(wave),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[test]
+fn cross_thread() {
+    use crate::{
+        func::{Envelope, PhaseMode, Wave, Waveform, Wavetable},
+        Fade, Mix, Modulation, TimedWave, TimedWavePacker,
+    };
+
+    let (mut sender, mut receiver) = wave_channel(4);
+    let game = std::thread::spawn(move || {
+        for start in [3, 9] {
+            let wave = TimedWave {
+                start,
+                end: start + 2,
+                wave: Wave {
+                    freq: vec![1.],
+                    amp: Envelope::Poly(vec![0.5]),
+                    phase: 0.25,
+                    mode: PhaseMode::Product,
+                    shape: Waveform::Sine,
+                    width: vec![],
+                },
+                fade: Fade::default(),
+                mix: Mix::default(),
+                modulation: Modulation::default(),
+                table: Wavetable::default(),
+            };
+            sender.send(wave).unwrap();
+        }
+    });
+    game.join().unwrap();
+
+    let waves = TimedWavePacker::new();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(20));
+    let mut playback = [0.; 6];
+    receiver.feed(&mut player);
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0., 0., 0., 0.5, 0.5, 0.]);
+    receiver.feed(&mut player);
+    player.play(&mut playback).unwrap();
+    assert_eq!(playback, [0., 0., 0., 0.5, 0.5, 0.]);
+}
@@ -0,0 +1,167 @@
+// real time note input from a midi port
+// each note-on goes through an Instrument to become a LiveWave, which the player picks up on its next block
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+};
+
+use midir::{ConnectErrorKind, InitError, MidiInput, MidiInputConnection};
+
+use crate::{LiveWave, Player};
+
+pub trait Instrument: Send + 'static {
+    // the instrument decides how long the note lasts, None skips it
+    fn note_on(&mut self, channel: u8, key: u8, velocity: u8, start: i64) -> Option<LiveWave>;
+}
+impl<F: FnMut(u8, u8, u8, i64) -> Option<LiveWave> + Send + 'static> Instrument for F {
+    fn note_on(&mut self, channel: u8, key: u8, velocity: u8, start: i64) -> Option<LiveWave> {
+        self(channel, key, velocity, start)
+    }
+}
+
+#[derive(Debug)]
+pub enum LiveMidiError {
+    Init(InitError),
+    NoSuchPort,
+    Connect(ConnectErrorKind),
+}
+impl Display for LiveMidiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiveMidiError::Init(e) => write!(f, "couldn't start midi input: {}", e),
+            LiveMidiError::NoSuchPort => write!(f, "no midi input port with that name"),
+            LiveMidiError::Connect(e) => write!(f, "couldn't connect to midi port: {}", e),
+        }
+    }
+}
+impl Error for LiveMidiError {}
+
+struct Translator<I> {
+    instrument: I,
+    clock: Arc<AtomicI64>,
+    latency: i64,
+    waves: Sender<LiveWave>,
+}
+impl<I: Instrument> Translator<I> {
+    fn handle(&mut self, message: &[u8]) {
+        let &[status, key, velocity, ..] = message else {
+            return;
+        };
+        if status & 0xf0 != 0x90 || velocity == 0 {
+            return;
+        }
+        let start = self.clock.load(Ordering::Relaxed) + self.latency;
+        if let Some(wave) = self.instrument.note_on(status & 0x0f, key, velocity, start) {
+            // the scheduler being dropped just means nobody is listening anymore
+            let _ = self.waves.send(wave);
+        }
+    }
+}
+
+pub struct MidiScheduler {
+    _connection: MidiInputConnection<()>,
+    clock: Arc<AtomicI64>,
+    waves: Receiver<LiveWave>,
+}
+impl MidiScheduler {
+    // connects to the first port whose name contains port
+    // latency is in samples and gives the audio thread time to pick new notes up
+    pub fn connect(
+        port: &str,
+        latency: i64,
+        instrument: impl Instrument,
+    ) -> Result<Self, LiveMidiError> {
+        let input = MidiInput::new("audynomial").map_err(LiveMidiError::Init)?;
+        let found = input
+            .ports()
+            .into_iter()
+            .find(|p| input.port_name(p).is_ok_and(|name| name.contains(port)))
+            .ok_or(LiveMidiError::NoSuchPort)?;
+        let clock = Arc::new(AtomicI64::new(0));
+        let (sender, waves) = channel();
+        let mut translator = Translator {
+            instrument,
+            clock: clock.clone(),
+            latency,
+            waves: sender,
+        };
+        let connection = input
+            .connect(
+                &found,
+                "audynomial-in",
+                move |_, message, _| translator.handle(message),
+                (),
+            )
+            .map_err(|e| LiveMidiError::Connect(e.kind()))?;
+        Ok(MidiScheduler {
+            _connection: connection,
+            clock,
+            waves,
+        })
+    }
+    // call from the audio thread before every block
+    pub fn feed(&self, player: &mut Player) {
+        self.clock.store(player.current_time(), Ordering::Relaxed);
+        for wave in self.waves.try_iter() {
+            player.schedule(wave);
+        }
+    }
+}
+
+#[test]
+fn note_translation() {
+    use crate::{
+        func::{Envelope, PhaseMode, Wave, Waveform, Wavetable},
+        Fade, Mix, Modulation, TimedWave,
+    };
+
+    let (sender, waves) = channel();
+    let clock = Arc::new(AtomicI64::new(100));
+    let mut translator = Translator {
+        instrument: |_channel, key: u8, velocity: u8, start| {
+            Some(TimedWave {
+                start,
+                end: start + 10,
+                wave: Wave {
+                    freq: vec![key as f32],
+                    amp: Envelope::Poly(vec![velocity as f32]),
+                    phase: 0.,
+                    mode: PhaseMode::Product,
+                    shape: Waveform::Sine,
+                    width: vec![],
+                },
+                fade: Fade::default(),
+                mix: Mix::default(),
+                modulation: Modulation::default(),
+                table: Wavetable::default(),
+            })
+        },
+        clock: clock.clone(),
+        latency: 5,
+        waves: sender,
+    };
+    translator.handle(&[0x91, 60, 90]);
+    // note-off, zero velocity note-on and a controller change are all skipped
+    translator.handle(&[0x81, 60, 0]);
+    translator.handle(&[0x91, 60, 0]);
+    translator.handle(&[0xb0, 7, 100]);
+    clock.store(200, Ordering::Relaxed);
+    translator.handle(&[0x90, 64, 30]);
+
+    let got: Vec<_> = waves
+        .try_iter()
+        .map(|tw| (tw.start, tw.wave.freq[0], tw.wave.amp))
+        .collect();
+    assert_eq!(
+        got,
+        [
+            (105, 60., Envelope::Poly(vec![90.])),
+            (205, 64., Envelope::Poly(vec![30.]))
+        ]
+    );
+}
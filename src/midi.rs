@@ -0,0 +1,174 @@
+// standard midi file import
+// every note becomes a sine at the note's pitch with a flat amplitude taken from its velocity
+use std::collections::{HashMap, VecDeque};
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::{
+    func::{PhaseMode, Wave, Waveform, Wavetable},
+    pitch::midi_to_hz,
+    Fade, Mix, Modulation, TimedWave, TimedWavePacker,
+};
+
+// microseconds per beat until the file says otherwise, 120bpm
+const DEFAULT_TEMPO: f64 = 500_000.;
+
+struct Clock {
+    timing: Timing,
+    // (tick, microseconds per beat), sorted by tick
+    tempos: Vec<(u64, f64)>,
+}
+impl Clock {
+    fn seconds(&self, tick: u64) -> f64 {
+        let ticks_per_beat = match self.timing {
+            Timing::Metrical(ticks) => ticks.as_int() as f64,
+            Timing::Timecode(fps, subframes) => {
+                return tick as f64 / (fps.as_f32() as f64 * subframes as f64)
+            }
+        };
+        let mut seconds = 0.;
+        let mut last_tick = 0;
+        let mut tempo = DEFAULT_TEMPO;
+        for &(at, change) in self.tempos.iter().take_while(|&&(at, _)| at < tick) {
+            seconds += (at - last_tick) as f64 * tempo / ticks_per_beat / 1e6;
+            last_tick = at;
+            tempo = change;
+        }
+        seconds + (tick - last_tick) as f64 * tempo / ticks_per_beat / 1e6
+    }
+}
+
+struct Note {
+    start: u64,
+    end: u64,
+    key: u8,
+    vel: u8,
+}
+
+pub fn load(bytes: &[u8], sample_rate: f32) -> Result<TimedWavePacker, midly::Error> {
+    let smf = Smf::parse(bytes)?;
+
+    let mut tempos = Vec::new();
+    let mut notes = Vec::new();
+    for track in &smf.tracks {
+        let mut tick = 0;
+        let mut held: HashMap<(u8, u8), VecDeque<(u64, u8)>> = HashMap::new();
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                    tempos.push((tick, tempo.as_int() as f64))
+                }
+                TrackEventKind::Midi { channel, message } => {
+                    let (key, vel) = match message {
+                        MidiMessage::NoteOn { key, vel } => (key.as_int(), vel.as_int()),
+                        MidiMessage::NoteOff { key, .. } => (key.as_int(), 0),
+                        _ => continue,
+                    };
+                    let voice = (channel.as_int(), key);
+                    if vel > 0 {
+                        held.entry(voice).or_default().push_back((tick, vel));
+                    } else if let Some((start, vel)) =
+                        held.get_mut(&voice).and_then(VecDeque::pop_front)
+                    {
+                        notes.push(Note {
+                            start,
+                            end: tick,
+                            key,
+                            vel,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+        // anything never released stops with its track
+        for ((_, key), starts) in held {
+            notes.extend(starts.into_iter().map(|(start, vel)| Note {
+                start,
+                end: tick,
+                key,
+                vel,
+            }));
+        }
+    }
+    tempos.sort_by_key(|&(tick, _)| tick);
+    notes.sort_by_key(|note| note.start);
+
+    let clock = Clock {
+        timing: smf.header.timing,
+        tempos,
+    };
+    let to_sample = |tick| (clock.seconds(tick) * sample_rate as f64).round() as i64;
+    Ok(notes
+        .into_iter()
+        .map(|note| {
+            let start = to_sample(note.start);
+            let end = to_sample(note.end).max(start + 1);
+            let hz = midi_to_hz(note.key as f32);
+            TimedWave {
+                start,
+                end,
+                wave: Wave {
+                    freq: vec![hz / sample_rate],
+                    amp: vec![note.vel as f32 / 127.],
+                    phase: 0.,
+                    mode: PhaseMode::Product,
+                    shape: Waveform::Sine,
+                    width: vec![],
+                },
+                fade: Fade::default(),
+                mix: Mix::default(),
+                modulation: Modulation::default(),
+                table: Wavetable::default(),
+            }
+        })
+        .collect())
+}
+
+#[test]
+fn tempo_changes() {
+    use midly::{Format, Header, TrackEvent};
+
+    let event = |delta: u32, kind| TrackEvent {
+        delta: delta.into(),
+        kind,
+    };
+    let note = |on: bool, key: u8, vel: u8| TrackEventKind::Midi {
+        channel: 0.into(),
+        message: if on {
+            MidiMessage::NoteOn {
+                key: key.into(),
+                vel: vel.into(),
+            }
+        } else {
+            MidiMessage::NoteOff {
+                key: key.into(),
+                vel: 0.into(),
+            }
+        },
+    };
+    let smf = Smf {
+        header: Header::new(Format::SingleTrack, Timing::Metrical(100.into())),
+        tracks: vec![vec![
+            // one beat at 120bpm, then one at 60bpm
+            event(0, note(true, 69, 127)),
+            event(
+                100,
+                TrackEventKind::Meta(MetaMessage::Tempo(1_000_000.into())),
+            ),
+            event(0, note(true, 81, 0)),
+            event(0, note(false, 69, 0)),
+            event(0, note(true, 57, 127)),
+            event(100, note(false, 57, 0)),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ]],
+    };
+    let mut bytes = Vec::new();
+    smf.write(&mut bytes).unwrap();
+
+    let packer = load(&bytes, 1000.).unwrap();
+    assert_eq!(packer.timings, [[0, 500], [500, 1500]]);
+    assert_eq!(packer.freq_coef, [0.44, 0.22]);
+    assert_eq!(packer.amp_coef, [1., 1.]);
+}
@@ -0,0 +1,166 @@
+// several players summed into one output, each with its own fader, so music layers and sfx beds
+// can be balanced without packing them again
+// muted tracks and the ones left out by a solo keep playing silently so they stay in time
+// a track whose player has stopped adds nothing to the mix, see Player::play for when that is
+use alloc::vec::Vec;
+
+use crate::{Clipping, Player, MIX_BLOCK};
+
+#[derive(Debug, Clone)]
+pub struct Track<'a> {
+    pub player: Player<'a>,
+    pub gain: f32,
+    // balance from -1 (left only) to 1 (right only), centre leaves both sides alone, mono ignores it
+    pub pan: f32,
+    pub mute: bool,
+    // once any track is soloed only soloed tracks are heard
+    pub solo: bool,
+}
+impl<'a> Track<'a> {
+    pub fn new(player: Player<'a>) -> Self {
+        Track {
+            player,
+            gain: 1.,
+            pan: 0.,
+            mute: false,
+            solo: false,
+        }
+    }
+    fn sides(&self) -> [f32; 2] {
+        let pan = self.pan.clamp(-1., 1.);
+        [(1. - pan).min(1.), (1. + pan).min(1.)]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mixer<'a> {
+    tracks: Vec<Track<'a>>,
+    gain: f32,
+    clipping: Clipping,
+}
+impl<'a> Default for Mixer<'a> {
+    fn default() -> Self {
+        Mixer {
+            tracks: Vec::new(),
+            gain: 1.,
+            clipping: Clipping::default(),
+        }
+    }
+}
+impl<'a> Mixer<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // the index the track can be found at later
+    pub fn add(&mut self, track: Track<'a>) -> usize {
+        self.tracks.push(track);
+        self.tracks.len() - 1
+    }
+    pub fn track(&self, index: usize) -> Option<&Track<'a>> {
+        self.tracks.get(index)
+    }
+    pub fn track_mut(&mut self, index: usize) -> Option<&mut Track<'a>> {
+        self.tracks.get_mut(index)
+    }
+    pub fn tracks(&self) -> &[Track<'a>] {
+        &self.tracks
+    }
+    pub fn into_tracks(self) -> Vec<Track<'a>> {
+        self.tracks
+    }
+    // applied to the sum before clipping, each player has already clipped its own output
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+    }
+    pub fn set_clipping(&mut self, clipping: Clipping) {
+        self.clipping = clipping;
+    }
+    pub fn play(&mut self, output: &mut [f32]) {
+        let (frames, _) = output.as_chunks_mut::<1>();
+        self.render(frames);
+    }
+    pub fn play_stereo(&mut self, output: &mut [[f32; 2]]) {
+        self.render(output);
+    }
+    fn render<const C: usize>(&mut self, output: &mut [[f32; C]]) {
+        let soloing = self.tracks.iter().any(|t| t.solo);
+        for chunk in output.chunks_mut(MIX_BLOCK) {
+            chunk.fill([0.; C]);
+            let mut rendered = [[0f32; C]; MIX_BLOCK];
+            let rendered = &mut rendered[..chunk.len()];
+            for track in &mut self.tracks {
+                // a stopped track leaves rendered silent, muted or not it still keeps time
+                let _ = track.player.render(rendered);
+                if track.mute || (soloing && !track.solo) {
+                    continue;
+                }
+                let mut gains = [track.gain; C];
+                if let [left, right] = &mut gains[..] {
+                    let [l, r] = track.sides();
+                    *left *= l;
+                    *right *= r;
+                }
+                for (frame, track_frame) in chunk.iter_mut().zip(rendered.iter()) {
+                    for ((out, sample), gain) in frame.iter_mut().zip(track_frame).zip(gains) {
+                        *out += sample * gain;
+                    }
+                }
+            }
+            for sample in chunk.as_flattened_mut() {
+                *sample = self.clipping.apply(*sample * self.gain);
+            }
+        }
+    }
+}
+
+#[test]
+fn mixing() {
+    use crate::{LiveWave, TimedWavePacker};
+
+    // each track holds its own level, so the output is down to the faders, pans and solos
+    let level =
+        |amp: f32| -> TimedWavePacker { [LiveWave::sine(0, 1000, 1., amp)].into_iter().collect() };
+    let (music, ambience, sfx) = (level(0.5), level(0.25), level(0.125));
+    let mut mixer = Mixer::new();
+    let music = mixer.add(Track::new(Player::new(
+        music.get_pack().unwrap(),
+        0,
+        Some(1000),
+    )));
+    let ambience = mixer.add(Track {
+        gain: 0.5,
+        ..Track::new(Player::new(ambience.get_pack().unwrap(), 0, Some(1000)))
+    });
+    let sfx = mixer.add(Track::new(Player::new(
+        sfx.get_pack().unwrap(),
+        0,
+        Some(500),
+    )));
+
+    let mut out = [0.; 4];
+    mixer.play(&mut out);
+    assert_eq!(out, [0.5 + 0.125 + 0.125; 4]);
+
+    mixer.track_mut(music).unwrap().mute = true;
+    mixer.play(&mut out);
+    assert_eq!(out, [0.25; 4]);
+    mixer.track_mut(ambience).unwrap().solo = true;
+    mixer.play(&mut out);
+    assert_eq!(out, [0.125; 4]);
+    // everything kept time while it was quiet
+    assert!(mixer.tracks().iter().all(|t| t.player.current_time() == 12));
+
+    let track = mixer.track_mut(ambience).unwrap();
+    track.solo = false;
+    track.pan = -0.5;
+    mixer.track_mut(music).unwrap().mute = false;
+    mixer.track_mut(sfx).unwrap().gain = 0.;
+    mixer.set_gain(2.);
+    mixer.set_clipping(Clipping::Off);
+    let mut stereo = [[0.; 2]; 4];
+    mixer.play_stereo(&mut stereo);
+    let centre = core::f32::consts::FRAC_PI_4.cos();
+    let [left, right] = stereo[0];
+    assert!((left - 2. * centre * (0.5 + 0.125)).abs() < 1e-6);
+    assert!((right - 2. * centre * (0.5 + 0.125 * 0.5)).abs() < 1e-6);
+}
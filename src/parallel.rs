@@ -0,0 +1,97 @@
+// offline rendering spread over rayon's threads, realtime playback stays on the one audio thread
+// the buffer is cut into chunks and every chunk gets its own player that starts partway into the pack,
+// setup gets each of those players before it plays so gain and the like carry over
+// chunks don't know about each other, so voice stealing can play out differently near the boundaries
+use rayon::prelude::*;
+
+use crate::{PackedTimedWaves, Player};
+
+const CHUNK: usize = 16384;
+
+pub fn render_parallel<F>(pack: &PackedTimedWaves, start: i64, output: &mut [f32], setup: F)
+where
+    F: Fn(&mut Player) + Sync,
+{
+    output
+        .par_chunks_mut(CHUNK)
+        .enumerate()
+        .for_each(|(i, chunk)| {
+            let time = start + (i * CHUNK) as i64;
+            let mut player = Player::new(pack.clone(), time, Some(time + chunk.len() as i64));
+            setup(&mut player);
+            // the wakeup is right where the chunk ends, so there's nothing left for play to complain about
+            let _ = player.play(chunk);
+        });
+}
+
+pub fn render_parallel_stereo<F>(
+    pack: &PackedTimedWaves,
+    start: i64,
+    output: &mut [[f32; 2]],
+    setup: F,
+) where
+    F: Fn(&mut Player) + Sync,
+{
+    output
+        .par_chunks_mut(CHUNK)
+        .enumerate()
+        .for_each(|(i, chunk)| {
+            let time = start + (i * CHUNK) as i64;
+            let mut player = Player::new(pack.clone(), time, Some(time + chunk.len() as i64));
+            setup(&mut player);
+            let _ = player.play_stereo(chunk);
+        });
+}
+
+#[test]
+fn matches_serial() {
+    use crate::{
+        func::{PhaseMode, Wave, Waveform, Wavetable},
+        Clipping, Fade, Mix, Modulation, TimedWave, TimedWavePacker,
+    };
+
+    // long waves that straddle the chunk boundaries and short ones that live inside a chunk
+    let waves: TimedWavePacker = (0..40)
+        .map(|i| TimedWave {
+            start: i * 1500,
+            end: i * 1500 + if i % 2 == 0 { 20000 } else { 700 },
+            wave: Wave {
+                freq: &[0.003, 0.000001][..],
+                amp: &[0.25][..],
+                phase: 0.,
+                mode: PhaseMode::Product,
+                shape: Waveform::Sine,
+                width: &[][..],
+            },
+            fade: Fade::default(),
+            mix: Mix {
+                gain: &[][..],
+                pan: &[0.5][..],
+                id: 0,
+            },
+            modulation: Modulation::default(),
+            table: Wavetable::default(),
+        })
+        .collect();
+    let len = 80000;
+    let setup = |player: &mut Player| {
+        player.set_gain(0.5);
+        player.set_clipping(Clipping::Soft);
+    };
+
+    let mut serial = vec![0f32; len];
+    let mut player = Player::new(waves.get_pack().unwrap(), 100, Some(100 + len as i64));
+    setup(&mut player);
+    player.play(&mut serial[..]).unwrap();
+    let mut parallel = vec![1f32; len];
+    render_parallel(&waves.get_pack().unwrap(), 100, &mut parallel, setup);
+    assert_eq!(parallel, serial);
+
+    let mut serial = vec![[0f32; 2]; len];
+    let mut player = Player::new(waves.get_pack().unwrap(), 100, Some(100 + len as i64));
+    setup(&mut player);
+    player.play_stereo(&mut serial[..]).unwrap();
+    let mut parallel = vec![[1f32; 2]; len];
+    render_parallel_stereo(&waves.get_pack().unwrap(), 100, &mut parallel, setup);
+    assert_eq!(parallel, serial);
+}
@@ -0,0 +1,345 @@
+// musical pitch, midi note numbers and names against hz
+// notes are midi numbers, 69 is a4 and fractions land between the keys, all in twelve tone equal
+// temperament tuned to a440 unless said otherwise
+use alloc::{format, string::String, vec::Vec};
+use core::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+
+pub const A4: f32 = 440.;
+const NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+pub fn midi_to_hz(note: f32) -> f32 {
+    tuned_midi_to_hz(note, A4)
+}
+pub fn hz_to_midi(hz: f32) -> f32 {
+    tuned_hz_to_midi(hz, A4)
+}
+// for a4 tuned somewhere other than 440, like 432 or a baroque 415
+pub fn tuned_midi_to_hz(note: f32, a4: f32) -> f32 {
+    a4 * ((note - 69.) / 12.).exp2()
+}
+pub fn tuned_hz_to_midi(hz: f32, a4: f32) -> f32 {
+    69. + 12. * (hz / a4).log2()
+}
+
+// how far apart two frequencies are, positive when to is higher
+pub fn cents(from: f32, to: f32) -> f32 {
+    1200. * (to / from).log2()
+}
+pub fn detune(hz: f32, cents: f32) -> f32 {
+    hz * (cents / 1200.).exp2()
+}
+// step steps up from root with the octave split into divisions equal steps, 12 is the usual
+pub fn equal_step(root: f32, step: f32, divisions: u32) -> f32 {
+    root * (step / divisions as f32).exp2()
+}
+pub fn semitones_to_ratio(semitones: f32) -> f32 {
+    (semitones / 12.).exp2()
+}
+pub fn ratio_to_semitones(ratio: f32) -> f32 {
+    12. * ratio.log2()
+}
+
+// names like "A4", "C#3", "Bb2" or "c-1", sharps and flats can stack, octave -1 starts at note 0
+// whatever doesn't come out as a midi note (0 to 127) is None
+pub fn parse_note(name: &str) -> Option<u8> {
+    let mut chars = name.trim().chars();
+    let letter = chars.next()?.to_ascii_uppercase();
+    let mut note = match letter {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let octave = rest.trim_start_matches(['#', 'b']);
+    for accidental in rest[..rest.len() - octave.len()].chars() {
+        note += if accidental == '#' { 1 } else { -1 };
+    }
+    let octave: i32 = octave.parse().ok()?;
+    u8::try_from(note + 12 * (octave + 1))
+        .ok()
+        .filter(|&n| n < 128)
+}
+// sharps only, 61 is "C#4"
+pub fn note_name(note: u8) -> String {
+    let octave = note as i32 / 12 - 1;
+    format!("{}{}", NAMES[note as usize % 12], octave)
+}
+
+#[test]
+fn pitches() {
+    assert_eq!(midi_to_hz(69.), 440.);
+    assert!((midi_to_hz(60.) - 261.6256).abs() < 1e-3);
+    assert!((hz_to_midi(880.) - 81.).abs() < 1e-5);
+    assert!((tuned_midi_to_hz(69. - 12., 432.) - 216.).abs() < 1e-3);
+    assert!((hz_to_midi(midi_to_hz(61.3)) - 61.3).abs() < 1e-4);
+
+    assert!((cents(440., 880.) - 1200.).abs() < 1e-3);
+    assert!((cents(440., detune(440., -35.)) + 35.).abs() < 1e-3);
+    assert!((equal_step(440., 12., 12) - 880.).abs() < 1e-3);
+    // a fifth in 19 tone equal temperament
+    assert!((equal_step(1., 11., 19) - 1.4938).abs() < 1e-4);
+    assert!((ratio_to_semitones(semitones_to_ratio(7.)) - 7.).abs() < 1e-5);
+
+    for (name, note) in [
+        ("A4", 69),
+        ("C#3", 49),
+        ("Db3", 49),
+        ("bb2", 46),
+        ("C-1", 0),
+    ] {
+        assert_eq!(parse_note(name), Some(note), "{}", name);
+    }
+    assert_eq!(parse_note("B#3"), parse_note("C4"));
+    assert_eq!(parse_note("G9"), Some(127));
+    for bad in ["G#9", "Cb-1", "H2", "A", "", "A4.5", "A#x"] {
+        assert_eq!(parse_note(bad), None, "{}", bad);
+    }
+    assert_eq!(note_name(61), "C#4");
+    assert_eq!(note_name(0), "C-1");
+    for note in 0..128 {
+        assert_eq!(parse_note(&note_name(note)), Some(note));
+    }
+}
+
+// anything that says how a note number sounds, for playing notes in other tunings
+// note numbers still count keys up from the bottom of a midi keyboard, it's what they sound like
+// that changes
+pub trait Tuning {
+    fn hz(&self, note: f32) -> f32;
+}
+
+// the octave (or any other period) split into equal steps, the usual is 12 with 69 at 440
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EqualTemperament {
+    pub divisions: u32,
+    // a ratio, 2 for the octave
+    pub period: f32,
+    pub root_note: f32,
+    pub root_hz: f32,
+}
+impl Default for EqualTemperament {
+    fn default() -> Self {
+        EqualTemperament {
+            divisions: 12,
+            period: 2.,
+            root_note: 69.,
+            root_hz: A4,
+        }
+    }
+}
+impl Tuning for EqualTemperament {
+    fn hz(&self, note: f32) -> f32 {
+        let steps = (note - self.root_note) / self.divisions as f32;
+        self.root_hz * self.period.powf(steps)
+    }
+}
+
+// a repeating list of ratios above the root, what just intonation and scala files both are
+// the last ratio is the period the scale repeats at, usually 2, and the root itself is left out
+// so a scale of n ratios puts n keys in each period
+// keys between the degrees (fractional notes) slide evenly in pitch from one to the next
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scale {
+    pub ratios: Vec<f32>,
+    pub root_note: f32,
+    pub root_hz: f32,
+}
+impl Scale {
+    // five limit just intonation on root, the key that keeps its equal tempered pitch
+    pub fn just(root_note: f32) -> Self {
+        let ratios = [
+            16. / 15.,
+            9. / 8.,
+            6. / 5.,
+            5. / 4.,
+            4. / 3.,
+            45. / 32.,
+            3. / 2.,
+            8. / 5.,
+            5. / 3.,
+            9. / 5.,
+            15. / 8.,
+            2.,
+        ];
+        Scale {
+            ratios: ratios.to_vec(),
+            root_note,
+            root_hz: midi_to_hz(root_note),
+        }
+    }
+    // a .scl file's contents, rooted on root_note at root_hz since scala leaves that to the
+    // keyboard mapping
+    pub fn from_scala(scl: &str, root_note: f32, root_hz: f32) -> Result<Self, ScalaError> {
+        // the description and the count come first, comments can go anywhere
+        let mut lines = scl
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.starts_with('!'));
+        let missing = ScalaError::Count {
+            line: scl.lines().count() + 1,
+        };
+        let (line, count) = lines.nth(1).ok_or(missing)?;
+        let count: usize = count
+            .split_whitespace()
+            .next()
+            .and_then(|c| c.parse().ok())
+            .filter(|&c| c > 0)
+            .ok_or(ScalaError::Count { line: line + 1 })?;
+        let ratios = lines
+            .take(count)
+            .map(|(line, pitch)| parse_pitch(pitch).ok_or(ScalaError::Pitch { line: line + 1 }))
+            .collect::<Result<Vec<_>, _>>()?;
+        if ratios.len() < count {
+            return Err(ScalaError::Short {
+                expected: count,
+                found: ratios.len(),
+            });
+        }
+        Ok(Scale {
+            ratios,
+            root_note,
+            root_hz,
+        })
+    }
+    fn ratio(&self, step: i64) -> f32 {
+        let size = self.ratios.len() as i64;
+        let period = self.ratios[size as usize - 1];
+        let degree = step.rem_euclid(size) as usize;
+        let below = if degree == 0 {
+            1.
+        } else {
+            self.ratios[degree - 1]
+        };
+        below * period.powi(step.div_euclid(size) as i32)
+    }
+}
+impl Tuning for Scale {
+    // an empty scale has nothing to repeat, every key is the root
+    fn hz(&self, note: f32) -> f32 {
+        if self.ratios.is_empty() {
+            return self.root_hz;
+        }
+        let steps = note - self.root_note;
+        let step = steps.floor();
+        let (below, above) = (self.ratio(step as i64), self.ratio(step as i64 + 1));
+        self.root_hz * below * (above / below).powf(steps - step)
+    }
+}
+
+// a pitch line, cents when there's a point in it and a ratio (or a whole number) otherwise,
+// anything after the pitch is a label
+fn parse_pitch(line: &str) -> Option<f32> {
+    let pitch = line.split_whitespace().next()?;
+    let ratio = if pitch.contains('.') {
+        (pitch.parse::<f32>().ok()? / 1200.).exp2()
+    } else if let Some((num, den)) = pitch.split_once('/') {
+        num.parse::<u64>().ok()? as f32 / den.parse::<u64>().ok()? as f32
+    } else {
+        pitch.parse::<u64>().ok()? as f32
+    };
+    Some(ratio).filter(|r| r.is_finite() && *r > 0.)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalaError {
+    // lines are counted from 1, comments included
+    Count { line: usize },
+    Pitch { line: usize },
+    // the file ran out before the pitches it said it had
+    Short { expected: usize, found: usize },
+}
+impl Display for ScalaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScalaError::Count { line } => write!(f, "line {} isn't a note count", line),
+            ScalaError::Pitch { line } => {
+                write!(f, "line {} isn't a ratio or an amount of cents", line)
+            }
+            ScalaError::Short { expected, found } => {
+                write!(
+                    f,
+                    "said there are {} pitches but only {} are there",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+impl Error for ScalaError {}
+
+#[test]
+fn tunings() {
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-3 * b;
+    let twelve = EqualTemperament::default();
+    for note in [0., 60., 61.5, 69., 127.] {
+        assert!(close(twelve.hz(note), midi_to_hz(note)));
+    }
+    // bohlen pierce, 13 steps to a tritave
+    let bp = EqualTemperament {
+        divisions: 13,
+        period: 3.,
+        ..EqualTemperament::default()
+    };
+    assert!(close(bp.hz(69. + 13.), 3. * 440.));
+
+    let just = Scale::just(60.);
+    let c = midi_to_hz(60.);
+    assert!(close(just.hz(60.), c));
+    assert!(close(just.hz(64.), c * 5. / 4.));
+    assert!(close(just.hz(67. + 12.), c * 3.));
+    assert!(close(just.hz(59.), c * 15. / 16.));
+    assert!(close(just.hz(47.), c * 15. / 32.));
+    // halfway between the keys is halfway in pitch
+    assert!(close(just.hz(66.5), c * (45. / 32. * 3. / 2f32).sqrt()));
+
+    let scl = "! meantone.scl
+!
+ Quarter comma meantone, a few notes
+ 4
+!
+ 193.157
+ 5/4  major third
+ 696.578
+ 2
+";
+    let meantone = Scale::from_scala(scl, 60., c).unwrap();
+    assert!(close(meantone.ratios[0], (193.157f32 / 1200.).exp2()));
+    assert_eq!((meantone.ratios[1], meantone.ratios[3]), (1.25, 2.));
+    assert!(close(meantone.hz(66.), c * 5. / 2.));
+    assert!(close(meantone.hz(58.), c / 2. * 1.25));
+
+    assert_eq!(
+        Scale::from_scala("title\n3\n1/1\n3/2\n", 60., c),
+        Err(ScalaError::Short {
+            expected: 3,
+            found: 2
+        })
+    );
+    assert_eq!(
+        Scale::from_scala("! only a comment\n", 60., c),
+        Err(ScalaError::Count { line: 2 })
+    );
+    assert_eq!(
+        Scale::from_scala("title\nthree\n", 60., c),
+        Err(ScalaError::Count { line: 2 })
+    );
+    assert_eq!(
+        Scale::from_scala("title\n2\n!\n3/0\n2\n", 60., c),
+        Err(ScalaError::Pitch { line: 4 })
+    );
+}
@@ -0,0 +1,345 @@
+// the cpal glue everyone was writing by hand
+// opens the default output device and keeps a player running on its audio thread
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    sync::{Arc, Mutex},
+};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BuildStreamError, DefaultStreamConfigError, Device, PauseStreamError, PlayStreamError, Sample,
+    SampleFormat, SampleRate, Stream, StreamConfig, StreamError, SupportedStreamConfigsError,
+};
+
+use crate::{
+    func::{noise, Sine},
+    Clipping, PackedTimedWaves, Player,
+};
+
+const BLOCK: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputConfig {
+    // None takes whatever the device prefers, check StreamHandle::sample_rate if the pack cares
+    pub sample_rate: Option<u32>,
+    // the rate the pack was written at, if the device ends up somewhere else the player converts
+    // as it goes, see Player::set_sample_rates
+    pub pack_rate: Option<u32>,
+    pub start_time: i64,
+    // the stream goes quiet here, or with None once the pack's done
+    pub wakeup: Option<i64>,
+    pub bandlimited: bool,
+    pub sine: Sine,
+    pub gain: f32,
+    pub clipping: Clipping,
+    // only does anything on devices that take 16 bit integers
+    pub dither: Dither,
+}
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            sample_rate: None,
+            pack_rate: None,
+            start_time: 0,
+            wakeup: None,
+            bandlimited: false,
+            sine: Sine::default(),
+            gain: 1.,
+            clipping: Clipping::default(),
+            dither: Dither::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PlaybackError {
+    NoDevice,
+    DefaultConfig(DefaultStreamConfigError),
+    SupportedConfigs(SupportedStreamConfigsError),
+    UnsupportedRate(u32),
+    Build(BuildStreamError),
+    Play(PlayStreamError),
+    Pause(PauseStreamError),
+}
+impl Display for PlaybackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaybackError::NoDevice => write!(f, "no output device available"),
+            PlaybackError::DefaultConfig(e) => write!(f, "couldn't get the device's config: {}", e),
+            PlaybackError::SupportedConfigs(e) => {
+                write!(f, "couldn't list the device's configs: {}", e)
+            }
+            PlaybackError::UnsupportedRate(rate) => {
+                write!(f, "the device can't play at {}hz", rate)
+            }
+            PlaybackError::Build(e) => write!(f, "couldn't build the output stream: {}", e),
+            PlaybackError::Play(e) => write!(f, "couldn't start the output stream: {}", e),
+            PlaybackError::Pause(e) => write!(f, "couldn't pause the output stream: {}", e),
+        }
+    }
+}
+impl Error for PlaybackError {}
+
+// the stream stops when this is dropped
+pub struct StreamHandle {
+    stream: Stream,
+    sample_rate: u32,
+    channels: u16,
+    error: Arc<Mutex<Option<StreamError>>>,
+}
+impl StreamHandle {
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+    pub fn pause(&self) -> Result<(), PlaybackError> {
+        self.stream.pause().map_err(PlaybackError::Pause)
+    }
+    pub fn resume(&self) -> Result<(), PlaybackError> {
+        self.stream.play().map_err(PlaybackError::Play)
+    }
+    pub fn stop(self) {}
+    // the last thing the device complained about since this was called
+    pub fn take_error(&self) -> Option<StreamError> {
+        self.error.lock().ok()?.take()
+    }
+}
+
+// the pack has to outlive the stream, so load it from something static or leak it
+pub fn start_output(
+    pack: PackedTimedWaves<'static>,
+    config: OutputConfig,
+) -> Result<StreamHandle, PlaybackError> {
+    let device = cpal::default_host()
+        .default_output_device()
+        .ok_or(PlaybackError::NoDevice)?;
+    let supported = match config.sample_rate {
+        None => device
+            .default_output_config()
+            .map_err(PlaybackError::DefaultConfig)?,
+        Some(rate) => device
+            .supported_output_configs()
+            .map_err(PlaybackError::SupportedConfigs)?
+            .filter(|c| (c.min_sample_rate()..=c.max_sample_rate()).contains(&SampleRate(rate)))
+            // floats skip a conversion, so take them when offered
+            .max_by_key(|c| c.sample_format() == SampleFormat::F32)
+            .ok_or(PlaybackError::UnsupportedRate(rate))?
+            .with_sample_rate(SampleRate(rate)),
+    };
+    let stream_config = supported.config();
+    let mut player = Player::new(pack, config.start_time, config.wakeup);
+    player.set_bandlimited(config.bandlimited);
+    player.set_sine(config.sine);
+    player.set_gain(config.gain);
+    player.set_clipping(config.clipping);
+    if let Some(rate) = config.pack_rate {
+        player.set_sample_rates(rate, stream_config.sample_rate.0);
+    }
+    let error = Arc::new(Mutex::new(None));
+    let stream = match supported.sample_format() {
+        SampleFormat::I16 => build::<i16>(
+            &device,
+            &stream_config,
+            player,
+            config.dither,
+            error.clone(),
+        ),
+        SampleFormat::U16 => build::<u16>(
+            &device,
+            &stream_config,
+            player,
+            config.dither,
+            error.clone(),
+        ),
+        SampleFormat::F32 => build::<f32>(
+            &device,
+            &stream_config,
+            player,
+            config.dither,
+            error.clone(),
+        ),
+    }?;
+    stream.play().map_err(PlaybackError::Play)?;
+    Ok(StreamHandle {
+        stream,
+        sample_rate: stream_config.sample_rate.0,
+        channels: stream_config.channels,
+        error,
+    })
+}
+
+fn build<T: Sample>(
+    device: &Device,
+    config: &StreamConfig,
+    mut player: Player<'static>,
+    dither: Dither,
+    error: Arc<Mutex<Option<StreamError>>>,
+) -> Result<Stream, PlaybackError> {
+    let channels = config.channels as usize;
+    // anything but stereo gets mono copied across, so that's all that needs dithering
+    let mut converter = Converter::new(dither, if channels == 2 { 2 } else { 1 });
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| fill(&mut player, &mut converter, data, channels),
+            move |e| {
+                if let Ok(mut last) = error.lock() {
+                    *last = Some(e);
+                }
+            },
+        )
+        .map_err(PlaybackError::Build)
+}
+
+// what the player renders into whatever format the device wants, as far as the shorter goes
+pub fn convert<T: Sample>(from: &[f32], to: &mut [T]) {
+    for (out, sample) in to.iter_mut().zip(from) {
+        *out = T::from(sample);
+    }
+}
+
+// rounding to 16 bits leaves quiet fades stepping down in audible stairs, dither trades that for a
+// little steady hiss
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    // straight through cpal's conversion, which truncates
+    #[default]
+    Off,
+    // triangular noise a step either way added before rounding
+    Tpdf,
+    // tpdf with each sample's rounding error taken off the next, moving the hiss up towards the
+    // top of the spectrum where it's harder to hear
+    Shaped,
+}
+
+// convert with dithering, keeping the noise and each channel's error going between blocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Converter {
+    pub dither: Dither,
+    step: i64,
+    errors: Vec<f32>,
+}
+impl Converter {
+    // channels is how the samples handed to convert are interleaved
+    pub fn new(dither: Dither, channels: usize) -> Self {
+        Converter {
+            dither,
+            step: 0,
+            errors: vec![0.; channels.max(1)],
+        }
+    }
+    pub fn convert<T: Sample>(&mut self, from: &[f32], to: &mut [T]) {
+        let integer = matches!(T::FORMAT, SampleFormat::I16 | SampleFormat::U16);
+        if self.dither == Dither::Off || !integer {
+            return convert(from, to);
+        }
+        let channels = self.errors.len();
+        for (i, (out, sample)) in to.iter_mut().zip(from).enumerate() {
+            // in steps of the integer format from here
+            let error = &mut self.errors[i % channels];
+            let wanted = sample * i16::MAX as f32 - *error;
+            let tpdf = (noise(self.step, 0) + noise(self.step, 1)) / 2.;
+            self.step += 1;
+            let rounded = (wanted + tpdf)
+                .round()
+                .clamp(i16::MIN as f32, i16::MAX as f32);
+            if self.dither == Dither::Shaped {
+                // held in so clipping can't feed back into itself
+                *error = (rounded - wanted).clamp(-2., 2.);
+            }
+            *out = T::from(&(rounded as i16));
+        }
+    }
+}
+
+// renders blocks on the stack, stereo devices get the waves' pans and anything else
+// gets the mono mix copied across every channel
+fn fill<T: Sample>(
+    player: &mut Player,
+    converter: &mut Converter,
+    data: &mut [T],
+    channels: usize,
+) {
+    if channels == 2 {
+        let mut stereo = [[0f32; 2]; BLOCK];
+        for chunk in data.chunks_mut(BLOCK * 2) {
+            let stereo = &mut stereo[..chunk.len() / 2];
+            // the device keeps getting silence once the player stops, see Player::play
+            let _ = player.play_stereo(stereo);
+            converter.convert(stereo.as_flattened(), chunk);
+        }
+        return;
+    }
+    let mut mono = [0f32; BLOCK];
+    let mut converted = [T::from(&0f32); BLOCK];
+    for chunk in data.chunks_mut(BLOCK * channels) {
+        let mono = &mut mono[..chunk.len() / channels];
+        let converted = &mut converted[..mono.len()];
+        let _ = player.play(mono);
+        converter.convert(mono, converted);
+        for (frame, sample) in chunk.chunks_mut(channels).zip(converted.iter()) {
+            frame.fill(*sample);
+        }
+    }
+}
+
+#[test]
+fn channel_fill() {
+    use crate::{LiveWave, TimedWavePacker};
+
+    let waves: TimedWavePacker = [LiveWave::sine(1, 3, 1., 0.5)].into_iter().collect();
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(4));
+    let mut data = [1.; 15];
+    fill(
+        &mut player,
+        &mut Converter::new(Dither::Off, 1),
+        &mut data,
+        3,
+    );
+    // everything past the wakeup is silence
+    assert_eq!(
+        data,
+        [0., 0., 0., 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0., 0., 0., 0., 0., 0.]
+    );
+
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, Some(4));
+    let mut data = [1.; 10];
+    fill(
+        &mut player,
+        &mut Converter::new(Dither::Off, 2),
+        &mut data,
+        2,
+    );
+    let centre = 0.5 * std::f32::consts::FRAC_PI_4.cos();
+    assert_eq!(
+        data,
+        [0., 0., centre, centre, centre, centre, 0., 0., 0., 0.]
+    );
+}
+
+#[test]
+fn dithering() {
+    // a third of a step, which truncates away to nothing
+    let quiet = [0.3 / i16::MAX as f32; 20000];
+    let mut out = [0i16; 20000];
+    Converter::new(Dither::Off, 1).convert(&quiet, &mut out);
+    assert!(out.iter().all(|&s| s == 0));
+    for dither in [Dither::Tpdf, Dither::Shaped] {
+        let mut converter = Converter::new(dither, 2);
+        converter.convert(&quiet[..10000], &mut out[..10000]);
+        converter.convert(&quiet[10000..], &mut out[10000..]);
+        let mean = out.iter().map(|&s| s as f32).sum::<f32>() / out.len() as f32;
+        assert!((mean - 0.3).abs() < 0.03, "{:?} {}", dither, mean);
+        assert!(out.iter().all(|s| s.abs() <= 2));
+    }
+    // unsigned gets the same offset cpal uses
+    let mut out = [0u16; 4];
+    Converter::new(Dither::Tpdf, 1).convert(&[0.; 4], &mut out);
+    assert!(out.iter().all(|s| s.abs_diff(32768) <= 1));
+    let mut out = [0f32; 4];
+    Converter::new(Dither::Shaped, 1).convert(&quiet[..4], &mut out);
+    assert_eq!(out, quiet[..4]);
+}
@@ -0,0 +1,180 @@
+// arithmetic on coefficient slices, lowest power first like everywhere else
+// for building envelopes out of smaller pieces before they get packed
+use alloc::{vec, vec::Vec};
+use core::iter::once;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::func::{Function, Scalar};
+
+pub fn add<S: Scalar>(a: &[S], b: &[S]) -> Vec<S> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut out = long.to_vec();
+    for (out, &c) in out.iter_mut().zip(short) {
+        *out = *out + c;
+    }
+    out
+}
+
+pub fn sub<S: Scalar>(a: &[S], b: &[S]) -> Vec<S> {
+    let negated: Vec<S> = b.iter().map(|&c| -c).collect();
+    add(a, &negated)
+}
+
+pub fn mul<S: Scalar>(a: &[S], b: &[S]) -> Vec<S> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut out = vec![S::default(); a.len() + b.len() - 1];
+    for (i, &l) in a.iter().enumerate() {
+        for (j, &r) in b.iter().enumerate() {
+            out[i + j] = out[i + j] + l * r;
+        }
+    }
+    out
+}
+
+// outer(inner(t)), horner's rule with polynomials in place of numbers
+pub fn compose<S: Scalar>(outer: &[S], inner: &[S]) -> Vec<S> {
+    outer
+        .iter()
+        .rev()
+        .fold(Vec::new(), |acc, &c| add(&mul(&acc, inner), &[c]))
+}
+
+// the constant of integration is zero, same as integrate_polynomial
+pub fn integrate<S: Scalar>(poly: &[S]) -> Vec<S> {
+    once(S::default())
+        .chain(
+            poly.iter()
+                .enumerate()
+                .map(|(i, &c)| c / S::from_usize(i + 1)),
+        )
+        .collect()
+}
+
+pub fn integrate_between<S: Scalar>(poly: &[S], from: S, to: S) -> S {
+    poly.integral(to) - poly.integral(from)
+}
+
+// value and slope in one pass
+pub fn eval_with_derivative<S: Scalar>(poly: &[S], t: S) -> (S, S) {
+    poly.iter()
+        .rev()
+        .fold((S::default(), S::default()), |(value, slope), &c| {
+            (value * t + c, slope * t + value)
+        })
+}
+
+// least squares fit through recorded or sketched points, ready to go in a wave's freq or amp
+// asking for more coefficients than there are points gets as many as the points can pin down
+pub fn fit_polynomial(samples: &[(f32, f32)], degree: usize) -> Vec<f32> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    // fitting against t squeezed into [-1, 1] keeps the powers from drowning each other out
+    let (lo, hi) = samples
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &(t, _)| {
+            (lo.min(t as f64), hi.max(t as f64))
+        });
+    let center = (lo + hi) / 2.;
+    let half = (hi - lo) / 2.;
+    let cols = if half == 0. {
+        1
+    } else {
+        degree.min(samples.len() - 1) + 1
+    };
+    let mut rows: Vec<Vec<f64>> = samples
+        .iter()
+        .map(|&(t, _)| {
+            let u = if half == 0. {
+                0.
+            } else {
+                (t as f64 - center) / half
+            };
+            crate::func::ts(u).take(cols).collect()
+        })
+        .collect();
+    let mut values: Vec<f64> = samples.iter().map(|&(_, v)| v as f64).collect();
+
+    // householder qr, reflecting each column below the diagonal away
+    for k in 0..cols {
+        let mut v: Vec<f64> = rows[k..].iter().map(|row| row[k]).collect();
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm == 0. {
+            continue;
+        }
+        v[0] += v[0].signum() * norm;
+        let v_norm = v.iter().map(|x| x * x).sum::<f64>();
+        for j in k..cols {
+            let dot: f64 = rows[k..].iter().zip(&v).map(|(row, x)| row[j] * x).sum();
+            for (row, x) in rows[k..].iter_mut().zip(&v) {
+                row[j] -= 2. * dot / v_norm * x;
+            }
+        }
+        let dot: f64 = values[k..].iter().zip(&v).map(|(y, x)| y * x).sum();
+        for (y, x) in values[k..].iter_mut().zip(&v) {
+            *y -= 2. * dot / v_norm * x;
+        }
+    }
+    // back substitution, directions the points don't cover (repeated times) are left at zero
+    let biggest = (0..cols).map(|k| rows[k][k].abs()).fold(0., f64::max);
+    let mut scaled = vec![0f64; cols];
+    for k in (0..cols).rev() {
+        if rows[k][k].abs() <= 1e-12 * biggest {
+            continue;
+        }
+        let known: f64 = (k + 1..cols).map(|j| rows[k][j] * scaled[j]).sum();
+        scaled[k] = (values[k] - known) / rows[k][k];
+    }
+
+    let unscale = if half == 0. {
+        vec![0.]
+    } else {
+        vec![-center / half, 1. / half]
+    };
+    compose(&scaled, &unscale)
+        .into_iter()
+        .map(|c| c as f32)
+        .collect()
+}
+
+#[test]
+fn fitting() {
+    let cubic = [0.5f32, -0.02, 0.0003, -0.000001];
+    let samples: Vec<(f32, f32)> = (0..50)
+        .map(|i| (i as f32 * 4., cubic.eval(i as f32 * 4.)))
+        .collect();
+    let fit = fit_polynomial(&samples, 3);
+    for &(t, v) in &samples {
+        assert!((fit.eval(t) - v).abs() < 1e-4);
+    }
+    // the best line through three points that don't line up
+    let line = fit_polynomial(&[(0., 0.), (1., 1.), (2., 1.)], 1);
+    assert!((line[0] - 1. / 6.).abs() < 1e-6 && (line[1] - 0.5).abs() < 1e-6);
+    // two points only ever make a line
+    assert_eq!(fit_polynomial(&[(1., 2.), (3., 6.)], 5).len(), 2);
+    assert_eq!(fit_polynomial(&[(1., 2.), (1., 4.)], 2), [3.]);
+    assert_eq!(fit_polynomial(&[], 2), []);
+}
+
+#[test]
+fn arithmetic() {
+    let a = [1., 2.];
+    let b = [0., 0., 3.];
+    assert_eq!(add(&a, &b), [1., 2., 3.]);
+    assert_eq!(sub(&a, &b), [1., 2., -3.]);
+    // (1 + 2t)(3t^2) = 3t^2 + 6t^3
+    assert_eq!(mul(&a, &b), [0., 0., 3., 6.]);
+    // 1 + 2(3t^2)
+    assert_eq!(compose(&a, &b), [1., 0., 6.]);
+    // (1 + 2t)^2 = 1 + 4t + 4t^2
+    assert_eq!(compose(&[0., 0., 1.], &a), [1., 4., 4.]);
+
+    assert_eq!(integrate(&a), [0., 1., 1.]);
+    assert_eq!(integrate_between(&a, 1., 2.), 4.);
+    // 1 + 2t + 3t^2 at 2 is 17 with slope 14
+    assert_eq!(eval_with_derivative(&[1., 2., 3.], 2.), (17., 14.));
+    assert_eq!(eval_with_derivative::<f32>(&[], 2.), (0., 0.));
+}
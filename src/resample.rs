@@ -0,0 +1,154 @@
+// converting rendered buffers from the rate they were made at to the rate something else wants
+// cubic is cheap and fine for going up a little, sinc is the one for export or for going down,
+// where everything above the new nyquist has to be filtered out instead of folding back
+// anything past either end of the input counts as silence
+use alloc::vec::Vec;
+use core::f64::consts::PI;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kernel {
+    // catmull rom through the four nearest samples
+    Cubic,
+    // blackman windowed sinc reaching this many zero crossings out each side
+    Sinc { zero_crossings: usize },
+}
+impl Default for Kernel {
+    fn default() -> Self {
+        Kernel::Sinc { zero_crossings: 16 }
+    }
+}
+
+pub fn resample(input: &[f32], from: u32, to: u32, kernel: Kernel) -> Vec<f32> {
+    let frames: &[[f32; 1]] = bytemuck::cast_slice(input);
+    resample_frames(frames, from, to, kernel)
+        .into_iter()
+        .map(|[s]| s)
+        .collect()
+}
+
+pub fn resample_stereo(input: &[[f32; 2]], from: u32, to: u32, kernel: Kernel) -> Vec<[f32; 2]> {
+    resample_frames(input, from, to, kernel)
+}
+
+// as many output frames as it takes to cover the input, rounded up
+pub fn resample_frames<const C: usize>(
+    input: &[[f32; C]],
+    from: u32,
+    to: u32,
+    kernel: Kernel,
+) -> Vec<[f32; C]> {
+    if from == 0 || to == 0 {
+        return Vec::new();
+    }
+    let (from, to) = (from as u64, to as u64);
+    let length = (input.len() as u64 * to).div_ceil(from) as usize;
+    let frame = |i: i64| {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| input.get(i))
+            .copied()
+            .unwrap_or([0.; C])
+    };
+    let mut weights = Vec::new();
+    (0..length as u64)
+        .map(|i| {
+            // kept as a whole sample and a fraction so long buffers don't drift
+            let whole = (i * from / to) as i64;
+            let frac = (i * from % to) as f64 / to as f64;
+            let first = match kernel {
+                Kernel::Cubic => cubic(frac, &mut weights),
+                Kernel::Sinc { zero_crossings } => {
+                    sinc(frac, zero_crossings, to as f64 / from as f64, &mut weights)
+                }
+            };
+            let mut out = [0f32; C];
+            for (k, &w) in weights.iter().enumerate() {
+                let sample = frame(whole + first + k as i64);
+                for (out, s) in out.iter_mut().zip(sample) {
+                    *out += (w * s as f64) as f32;
+                }
+            }
+            out
+        })
+        .collect()
+}
+
+// fills in weights for the samples from whole + the returned offset onwards
+fn cubic(frac: f64, weights: &mut Vec<f64>) -> i64 {
+    let (t, t2, t3) = (frac, frac * frac, frac * frac * frac);
+    weights.clear();
+    weights.extend([
+        (-t3 + 2. * t2 - t) / 2.,
+        (3. * t3 - 5. * t2 + 2.) / 2.,
+        (-3. * t3 + 4. * t2 + t) / 2.,
+        (t3 - t2) / 2.,
+    ]);
+    -1
+}
+
+// going down, the sinc gets stretched so its cutoff lands on the new nyquist
+fn sinc(frac: f64, zero_crossings: usize, ratio: f64, weights: &mut Vec<f64>) -> i64 {
+    let cutoff = ratio.min(1.);
+    let reach = zero_crossings.max(1) as f64 / cutoff;
+    let first = (frac - reach).ceil() as i64;
+    let last = (frac + reach).floor() as i64;
+    weights.clear();
+    weights.extend((first..=last).map(|k| {
+        let d = k as f64 - frac;
+        let x = PI * cutoff * d;
+        let sinc = if x == 0. { 1. } else { x.sin() / x };
+        let w = (d / reach + 1.) / 2.;
+        let window = 0.42 - 0.5 * (2. * PI * w).cos() + 0.08 * (4. * PI * w).cos();
+        cutoff * sinc * window
+    }));
+    // so a constant comes out at the same level, the window takes a little off otherwise
+    let total: f64 = weights.iter().sum();
+    if total != 0. {
+        weights.iter_mut().for_each(|w| *w /= total);
+    }
+    first
+}
+
+#[test]
+fn resampling() {
+    let tone = |freq: f32, rate: u32, length: usize| -> Vec<f32> {
+        (0..length)
+            .map(|i| (2. * core::f32::consts::PI * freq * i as f32 / rate as f32).sin())
+            .collect()
+    };
+    let input = tone(440., 44100, 4410);
+    for kernel in [Kernel::Cubic, Kernel::default()] {
+        let same = resample(&input, 44100, 44100, kernel);
+        assert!(same.iter().zip(&input).all(|(a, b)| (a - b).abs() < 1e-5));
+    }
+    assert_eq!(resample(&input, 44100, 48000, Kernel::Cubic).len(), 4800);
+
+    // away from the ends, where the silence past them leaks in
+    for (kernel, tolerance) in [(Kernel::Cubic, 1e-3), (Kernel::default(), 1e-4)] {
+        let up = resample(&input, 44100, 48000, kernel);
+        let expected = tone(440., 48000, 4800);
+        for i in 100..4700 {
+            assert!(
+                (up[i] - expected[i]).abs() < tolerance,
+                "{:?} {}",
+                kernel,
+                i
+            );
+        }
+    }
+
+    // 15k can't exist at 22050, so it has to go rather than alias down to 7050
+    let high = tone(15000., 48000, 4800);
+    let down = resample(&high, 48000, 22050, Kernel::default());
+    let power = down[200..2000].iter().map(|s| s * s).sum::<f32>() / 1800.;
+    assert!(power < 1e-4);
+
+    let stereo: Vec<[f32; 2]> = input.iter().map(|&s| [s, -s]).collect();
+    let down = resample_stereo(&stereo, 44100, 22050, Kernel::default());
+    assert_eq!(down.len(), 2205);
+    assert!(down.iter().all(|[l, r]| l == &-r));
+    assert!(resample(&input, 0, 48000, Kernel::Cubic).is_empty());
+}
@@ -0,0 +1,163 @@
+// repeating step patterns on a beat grid, euclidean ones spreading some hits as evenly as they'll
+// go over a bar of steps, which is most of the world's drum patterns (3 over 8 is the tresillo)
+use alloc::vec::Vec;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{drums::Drum, instrument::Instrument, timeline::Timeline, PackError, TimedWavePacker};
+
+// hits hits as evenly spread over steps steps as they go, starting on a hit, then turned
+// rotation steps to the left
+pub fn euclidean(hits: usize, steps: usize, rotation: usize) -> Vec<bool> {
+    let mut pattern: Vec<bool> = (0..steps).map(|i| i * hits % steps < hits).collect();
+    if steps > 0 {
+        pattern.rotate_left(rotation % steps);
+    }
+    pattern
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rhythm {
+    pub steps: Vec<bool>,
+    // beats per step, 0.25 for sixteenths
+    pub step: f64,
+}
+impl Rhythm {
+    pub fn euclidean(hits: usize, steps: usize, step: f64) -> Self {
+        Rhythm {
+            steps: euclidean(hits, steps, 0),
+            step,
+        }
+    }
+    // the beats of every hit from from up to to, the pattern starting over every so often
+    pub fn beats(&self, (from, to): (f64, f64)) -> Vec<f64> {
+        if self.step <= 0. {
+            return Vec::new();
+        }
+        let count = ((to - from) / self.step).ceil().max(0.) as usize;
+        (0..count)
+            .filter(|i| self.steps.get(i % self.steps.len().max(1)) == Some(&true))
+            .map(|i| from + i as f64 * self.step)
+            .collect()
+    }
+}
+
+impl TimedWavePacker {
+    // on the end like try_extend, so normalize after if anything later is already in
+    pub fn add_rhythm_hits(
+        &mut self,
+        timeline: &Timeline,
+        drum: Drum,
+        rhythm: &Rhythm,
+        region: (f64, f64),
+        velocity: f32,
+    ) -> Result<(), PackError> {
+        let hits = rhythm
+            .beats(region)
+            .into_iter()
+            .map(|beat| (beat, velocity));
+        self.add_hits_at(timeline, drum, hits)
+    }
+    // each note is held for a step
+    pub fn add_rhythm_notes(
+        &mut self,
+        timeline: &Timeline,
+        instrument: &Instrument,
+        pitch: f32,
+        rhythm: &Rhythm,
+        region: (f64, f64),
+        velocity: f32,
+    ) -> Result<(), PackError> {
+        for beat in rhythm.beats(region) {
+            self.add_note_at(timeline, instrument, pitch, (beat, rhythm.step), velocity)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn euclidean_rhythms() {
+    use alloc::string::String;
+
+    let show = |pattern: Vec<bool>| -> String {
+        pattern
+            .into_iter()
+            .map(|hit| if hit { 'x' } else { '.' })
+            .collect()
+    };
+    assert_eq!(show(euclidean(3, 8, 0)), "x..x..x.");
+    assert_eq!(show(euclidean(5, 8, 0)), "x.x.xx.x");
+    assert_eq!(show(euclidean(4, 16, 0)), "x...x...x...x...");
+    assert_eq!(show(euclidean(3, 8, 3)), "x..x.x..");
+    assert_eq!(show(euclidean(0, 4, 0)), "....");
+    assert_eq!(show(euclidean(4, 4, 1)), "xxxx");
+    assert!(euclidean(3, 0, 2).is_empty());
+
+    let tresillo = Rhythm::euclidean(3, 8, 0.5);
+    assert_eq!(tresillo.beats((0., 8.)), [0., 1.5, 3., 4., 5.5, 7.]);
+    assert_eq!(tresillo.beats((1., 3.)), [1., 2.5]);
+    assert!(Rhythm::euclidean(3, 0, 0.5).beats((0., 8.)).is_empty());
+
+    let timeline = Timeline::new(120., 48000.);
+    let mut packer = TimedWavePacker::new();
+    packer
+        .add_rhythm_hits(&timeline, Drum::Kick, &tresillo, (0., 4.), 1.)
+        .unwrap();
+    let instrument = Instrument::new(48000.);
+    let pulse = Rhythm::euclidean(1, 1, 1.);
+    packer
+        .add_rhythm_notes(&timeline, &instrument, 48., &pulse, (0., 4.), 1.)
+        .unwrap();
+    // three kicks of two waves and four notes
+    assert_eq!(packer.timings.len(), 3 * 2 + 4);
+    assert_eq!(packer.timings[2][0], 36000);
+    assert_eq!(packer.timings[7][0], 24000);
+    packer.normalize().unwrap();
+    assert!(packer.get_pack().is_some());
+}
+
+#[test]
+fn euclidean_spread() {
+    // more hits than steps fills every step
+    assert_eq!(euclidean(5, 3, 0), [true; 3]);
+    assert_eq!(euclidean(0, 3, 1), [false; 3]);
+    for steps in 1..=16 {
+        for hits in 0..=steps + 2 {
+            let pattern = euclidean(hits, steps, 0);
+            assert_eq!(pattern.len(), steps);
+            assert_eq!(pattern.iter().filter(|&&hit| hit).count(), hits.min(steps));
+            assert_eq!(pattern[0], hits > 0);
+            // the gaps between hits, going round the end, are all within a step of each other
+            let at: Vec<usize> = (0..steps).filter(|&i| pattern[i]).collect();
+            let gaps: Vec<usize> = (0..at.len())
+                .map(|i| (at[(i + 1) % at.len()] + steps - at[i] - 1) % steps + 1)
+                .collect();
+            let (least, most) = (gaps.iter().min(), gaps.iter().max());
+            assert!(most
+                .zip(least)
+                .is_none_or(|(most, least)| most - least <= 1));
+            // turning it is the same pattern started somewhere else, a whole turn is no turn
+            for rotation in 0..2 * steps {
+                let turned = euclidean(hits, steps, rotation);
+                assert_eq!(turned[0], pattern[rotation % steps]);
+                assert_eq!(turned.iter().filter(|&&hit| hit).count(), hits.min(steps));
+            }
+            assert_eq!(euclidean(hits, steps, steps), pattern);
+        }
+    }
+
+    // notes are held for a step at whatever the tempo is where they start
+    let mut timeline = Timeline::new(120., 1000.);
+    timeline.set_tempo(2., 60.);
+    let instrument = Instrument::new(1000.);
+    let mut packer = TimedWavePacker::new();
+    let every_other = Rhythm::euclidean(2, 4, 0.5);
+    packer
+        .add_rhythm_notes(&timeline, &instrument, 48., &every_other, (0., 4.), 1.)
+        .unwrap();
+    let starts: Vec<i64> = packer.timings.iter().map(|t| t[0]).collect();
+    assert_eq!(starts, [0, 500, 1000, 2000]);
+    let gates: Vec<f32> = packer.envelopes.iter().map(|e| e.gate).collect();
+    assert_eq!(gates, [250., 250., 500., 500.]);
+}
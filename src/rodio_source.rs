@@ -0,0 +1,63 @@
+// lets a player go anywhere rodio takes a Source, mono at whatever rate the pack was written for
+// the source ends when the player reaches its wakeup time, or runs out without one, or is stopped
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::{samples::Samples, Player};
+
+#[derive(Debug, Clone)]
+pub struct PlayerSource<'a> {
+    samples: Samples<'a>,
+    sample_rate: u32,
+}
+impl<'a> PlayerSource<'a> {
+    pub fn new(player: Player<'a>, sample_rate: u32) -> Self {
+        PlayerSource {
+            samples: Samples::new(player),
+            sample_rate,
+        }
+    }
+    pub fn into_player(self) -> Player<'a> {
+        self.samples.into_player()
+    }
+}
+impl<'a> Iterator for PlayerSource<'a> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.samples.next()
+    }
+}
+impl<'a> Source for PlayerSource<'a> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        1
+    }
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[test]
+fn source_matches_player() {
+    use crate::{LiveWave, TimedWavePacker};
+
+    let waves: TimedWavePacker = [(0, 1500), (1000, 2500)]
+        .into_iter()
+        .map(|(start, end)| LiveWave::sine(start, end, 0.01, 0.5))
+        .collect();
+    let mut direct = vec![0f32; 2500];
+    Player::new(waves.get_pack().unwrap(), 0, Some(2500))
+        .play(&mut direct[..])
+        .unwrap();
+
+    let source = PlayerSource::new(Player::new(waves.get_pack().unwrap(), 0, Some(2500)), 44100);
+    assert_eq!(source.sample_rate(), 44100);
+    assert_eq!(source.collect::<Vec<_>>(), direct);
+}
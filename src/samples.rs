@@ -0,0 +1,93 @@
+// a player as an iterator of mono samples for offline use, tests and analysis, rendering a block
+// at a time behind the scenes
+// it ends where play would hand back the rest of a buffer, at the wakeup or once everything's
+// finished without one, or once the player's been stopped
+use alloc::{vec, vec::Vec};
+
+use crate::Player;
+
+const BLOCK: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct Samples<'a> {
+    player: Player<'a>,
+    block: Vec<f32>,
+    pos: usize,
+    finished: bool,
+}
+impl<'a> Samples<'a> {
+    pub fn new(player: Player<'a>) -> Self {
+        Samples {
+            player,
+            block: vec![0.; BLOCK],
+            pos: BLOCK,
+            finished: false,
+        }
+    }
+    // the samples rendered but not taken yet are lost
+    pub fn into_player(self) -> Player<'a> {
+        self.player
+    }
+    pub fn player(&self) -> &Player<'a> {
+        &self.player
+    }
+}
+impl<'a> Iterator for Samples<'a> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos == self.block.len() {
+            if self.finished || self.player.is_finished() {
+                return None;
+            }
+            self.block.resize(BLOCK, 0.);
+            // whatever got written before the end is still worth playing
+            let unplayed = match self.player.play(&mut self.block) {
+                Ok(()) => 0,
+                Err(rest) => rest.len(),
+            };
+            if unplayed > 0 {
+                self.finished = true;
+                self.block.truncate(BLOCK - unplayed);
+            }
+            self.pos = 0;
+        }
+        let sample = *self.block.get(self.pos)?;
+        self.pos += 1;
+        Some(sample)
+    }
+}
+impl<'a> IntoIterator for Player<'a> {
+    type Item = f32;
+    type IntoIter = Samples<'a>;
+
+    fn into_iter(self) -> Samples<'a> {
+        Samples::new(self)
+    }
+}
+
+#[test]
+fn samples() {
+    use crate::{LiveWave, TimedWavePacker};
+
+    let waves: TimedWavePacker = [(0, 1500), (1000, 2500)]
+        .into_iter()
+        .map(|(start, end)| LiveWave::sine(start, end, 0.01, 0.5))
+        .collect();
+    let mut direct = vec![0f32; 2500];
+    Player::new(waves.get_pack().unwrap(), 0, None)
+        .play(&mut direct[..])
+        .unwrap();
+    // with no wakeup it runs out with the last wave
+    let player = Player::new(waves.get_pack().unwrap(), 0, None);
+    assert_eq!(player.into_iter().collect::<Vec<_>>(), direct);
+    let peak = Player::new(waves.get_pack().unwrap(), 0, Some(1200))
+        .into_iter()
+        .map(f32::abs)
+        .fold(0., f32::max);
+    assert!(peak > 0.9 && peak <= 1.);
+
+    let mut player = Player::new(waves.get_pack().unwrap(), 0, None);
+    player.stop(0);
+    assert_eq!(player.into_iter().count(), 0);
+}
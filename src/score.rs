@@ -0,0 +1,199 @@
+// music written out in rust, a chain of notes, chords and rests per track that compiles down to a
+// packer, for test music and jamming without a midi file
+// lengths are in beats (quarter notes, see the constants) and pitches are midi note numbers, so
+// pitch::parse_note or plain numbers work for them
+// every track starts at beat 0 with its own cursor that each note, chord, hit or rest moves on
+use alloc::vec::Vec;
+
+use crate::{
+    drums::Drum,
+    instrument::Instrument,
+    timeline::{Meter, Timeline},
+    PackError, TimedWavePacker,
+};
+
+pub const WHOLE: f64 = 4.;
+pub const HALF: f64 = 2.;
+pub const QUARTER: f64 = 1.;
+pub const EIGHTH: f64 = 0.5;
+pub const SIXTEENTH: f64 = 0.25;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    Notes {
+        pitches: Vec<f32>,
+        beat: f64,
+        beats: f64,
+        velocity: f32,
+    },
+    Hit {
+        drum: Drum,
+        beat: f64,
+        velocity: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Part {
+    instrument: Instrument,
+    cursor: f64,
+    velocity: f32,
+    // how much of each note's length it's held for, the rest is a gap before the next
+    gate: f64,
+    events: Vec<Event>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    timeline: Timeline,
+    parts: Vec<Part>,
+}
+impl Score {
+    // starts off with a track playing a plain Instrument::new
+    pub fn new(bpm: f64, sample_rate: f32) -> Self {
+        Score {
+            timeline: Timeline::new(bpm, sample_rate),
+            parts: Vec::new(),
+        }
+        .track(Instrument::new(sample_rate))
+    }
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+    // a new track from beat 0, everything after goes on it
+    pub fn track(mut self, instrument: Instrument) -> Self {
+        self.parts.push(Part {
+            instrument,
+            cursor: 0.,
+            velocity: 0.8,
+            gate: 1.,
+            events: Vec::new(),
+        });
+        self
+    }
+    fn part(&mut self) -> &mut Part {
+        self.parts.last_mut().unwrap()
+    }
+    // for the notes after, on this track
+    pub fn velocity(mut self, velocity: f32) -> Self {
+        self.part().velocity = velocity;
+        self
+    }
+    // 1 is legato, 0.5 holds each note for half its length
+    pub fn gate(mut self, gate: f64) -> Self {
+        self.part().gate = gate;
+        self
+    }
+    pub fn note(self, pitch: f32, beats: f64) -> Self {
+        self.chord(&[pitch], beats)
+    }
+    pub fn chord(mut self, pitches: &[f32], beats: f64) -> Self {
+        let part = self.part();
+        part.events.push(Event::Notes {
+            pitches: pitches.to_vec(),
+            beat: part.cursor,
+            beats: beats * part.gate,
+            velocity: part.velocity,
+        });
+        part.cursor += beats;
+        self
+    }
+    pub fn hit(mut self, drum: Drum, beats: f64) -> Self {
+        let part = self.part();
+        part.events.push(Event::Hit {
+            drum,
+            beat: part.cursor,
+            velocity: part.velocity,
+        });
+        part.cursor += beats;
+        self
+    }
+    pub fn rest(mut self, beats: f64) -> Self {
+        self.part().cursor += beats;
+        self
+    }
+    // moves this track's cursor to a beat, like Timeline::bar gives
+    pub fn at(mut self, beat: f64) -> Self {
+        self.part().cursor = beat;
+        self
+    }
+    // a tempo change from this track's cursor on, for every track
+    pub fn tempo(mut self, bpm: f64) -> Self {
+        let beat = self.part().cursor;
+        self.timeline.set_tempo(beat, bpm);
+        self
+    }
+    pub fn meter(mut self, bar: u32, meter: Meter) -> Self {
+        self.timeline.set_meter(bar, meter);
+        self
+    }
+    // how many beats the longest track runs for
+    pub fn length(&self) -> f64 {
+        self.parts.iter().map(|p| p.cursor).fold(0., f64::max)
+    }
+    // every track's waves sorted together, ready for get_pack
+    pub fn build(&self) -> Result<TimedWavePacker, PackError> {
+        let mut packer = TimedWavePacker::new();
+        for part in &self.parts {
+            for event in &part.events {
+                match event {
+                    Event::Notes {
+                        pitches,
+                        beat,
+                        beats,
+                        velocity,
+                    } => {
+                        for &pitch in pitches {
+                            let (timeline, instrument) = (&self.timeline, &part.instrument);
+                            let timing = (*beat, *beats);
+                            packer.add_note_at(timeline, instrument, pitch, timing, *velocity)?;
+                        }
+                    }
+                    Event::Hit {
+                        drum,
+                        beat,
+                        velocity,
+                    } => packer.add_hits_at(&self.timeline, *drum, [(*beat, *velocity)])?,
+                }
+            }
+        }
+        packer.normalize()?;
+        Ok(packer)
+    }
+}
+
+#[test]
+fn scores() {
+    use crate::{func::Waveform, instrument::Layer, pitch::parse_note};
+    use alloc::vec;
+
+    let [c4, e4, g4] = ["C4", "E4", "G4"].map(|n| parse_note(n).unwrap() as f32);
+    let bass = Instrument {
+        layers: vec![Layer::new(Waveform::Saw)],
+        ..Instrument::new(48000.)
+    };
+    let score = Score::new(120., 48000.)
+        .note(c4, QUARTER)
+        .chord(&[e4, g4], HALF)
+        .rest(QUARTER)
+        .tempo(60.)
+        .velocity(0.5)
+        .gate(0.5)
+        .note(c4, WHOLE)
+        .track(bass)
+        .at(2.)
+        .note(c4 - 24., HALF)
+        .track(Instrument::new(48000.))
+        .hit(Drum::Kick, QUARTER)
+        .hit(Drum::Snare, QUARTER);
+    assert_eq!(score.length(), 8.);
+    let packer = score.build().unwrap();
+    // 4 notes of one wave each, a bass note and two hits of two waves
+    assert_eq!(packer.timings.len(), 4 + 1 + 4);
+    assert!(packer.get_pack().is_some());
+    let starts: Vec<i64> = packer.timings.iter().map(|t| t[0]).collect();
+    assert_eq!(starts, [0, 0, 0, 24000, 24000, 24000, 24000, 48000, 96000]);
+    // the last note is at 60, half of a whole note is 2 seconds
+    let last = packer.envelopes.last().unwrap();
+    assert_eq!((last.gate, last.peak), (96000., 0.5));
+}
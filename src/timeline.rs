@@ -0,0 +1,217 @@
+// musical time, beats and bars turned into samples through a tempo map and time signatures
+// so parts written in beats follow along when the tempo's changed instead of being retimed
+// a beat is a quarter note, tempo changes land on a beat and hold until the next one, and bars
+// are counted from 0 with time signatures changing at the start of a bar
+use alloc::{vec, vec::Vec};
+use core::borrow::Borrow;
+
+#[cfg(not(any(feature = "std", test)))]
+use crate::float::Float;
+use crate::{
+    drums::Drum, func::Amplitude, instrument::Instrument, move_end, PackError, TimedWave,
+    TimedWavePacker,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Meter {
+    // 6 and 8 for 6/8
+    pub beats: u32,
+    pub unit: u32,
+}
+impl Meter {
+    // in quarter notes, 6/8 is 3
+    pub fn length(&self) -> f64 {
+        self.beats as f64 * 4. / self.unit as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timeline {
+    pub sample_rate: f32,
+    // (beat, bpm) sorted by beat, the first is always at 0
+    tempos: Vec<(f64, f64)>,
+    // (bar, meter) sorted by bar, the first is always at 0
+    meters: Vec<(u32, Meter)>,
+}
+impl Timeline {
+    // in 4/4
+    pub fn new(bpm: f64, sample_rate: f32) -> Self {
+        Timeline {
+            sample_rate,
+            tempos: vec![(0., bpm)],
+            meters: vec![(0, Meter { beats: 4, unit: 4 })],
+        }
+    }
+    // replaces any change already on that beat, anything before beat 0 goes on 0
+    pub fn set_tempo(&mut self, beat: f64, bpm: f64) {
+        let beat = beat.max(0.);
+        match self.tempos.binary_search_by(|(at, _)| at.total_cmp(&beat)) {
+            Ok(i) => self.tempos[i].1 = bpm,
+            Err(i) => self.tempos.insert(i, (beat, bpm)),
+        }
+    }
+    pub fn set_meter(&mut self, bar: u32, meter: Meter) {
+        match self.meters.binary_search_by_key(&bar, |&(at, _)| at) {
+            Ok(i) => self.meters[i].1 = meter,
+            Err(i) => self.meters.insert(i, (bar, meter)),
+        }
+    }
+    pub fn tempos(&self) -> &[(f64, f64)] {
+        &self.tempos
+    }
+    pub fn meters(&self) -> &[(u32, Meter)] {
+        &self.meters
+    }
+    pub fn tempo_at(&self, beat: f64) -> f64 {
+        let i = self.tempos.partition_point(|&(at, _)| at <= beat);
+        self.tempos[i.saturating_sub(1)].1
+    }
+    // beats before 0 carry on at the first tempo
+    pub fn seconds(&self, beat: f64) -> f64 {
+        let mut seconds = 0.;
+        let (mut last, mut bpm) = self.tempos[0];
+        for &(at, change) in self.tempos[1..].iter().take_while(|&&(at, _)| at < beat) {
+            seconds += (at - last) * 60. / bpm;
+            (last, bpm) = (at, change);
+        }
+        seconds + (beat - last) * 60. / bpm
+    }
+    pub fn sample(&self, beat: f64) -> i64 {
+        (self.seconds(beat) * self.sample_rate as f64).round() as i64
+    }
+    // which beat is playing at a sample, the other way round from sample
+    pub fn beat(&self, sample: i64) -> f64 {
+        let seconds = sample as f64 / self.sample_rate as f64;
+        let (mut last, mut bpm) = self.tempos[0];
+        let mut passed = 0.;
+        for &(at, change) in &self.tempos[1..] {
+            let span = (at - last) * 60. / bpm;
+            if passed + span > seconds {
+                break;
+            }
+            passed += span;
+            (last, bpm) = (at, change);
+        }
+        last + (seconds - passed) * bpm / 60.
+    }
+    // where beat (in quarter notes) of bar is, in beats from the start
+    pub fn bar(&self, bar: u32, beat: f64) -> f64 {
+        let mut start = 0.;
+        let (mut last, mut meter) = self.meters[0];
+        for &(at, change) in self.meters[1..].iter().take_while(|&&(at, _)| at <= bar) {
+            start += (at - last) as f64 * meter.length();
+            (last, meter) = (at, change);
+        }
+        start + (bar - last) as f64 * meter.length() + beat
+    }
+    // the bar a beat is in and how far into it, the other way round from bar
+    pub fn bar_of(&self, beat: f64) -> (u32, f64) {
+        let mut start = 0.;
+        let (mut last, mut meter) = self.meters[0];
+        for &(at, change) in &self.meters[1..] {
+            let span = (at - last) as f64 * meter.length();
+            if start + span > beat {
+                break;
+            }
+            start += span;
+            (last, meter) = (at, change);
+        }
+        let bars = ((beat - start) / meter.length()).floor().max(0.);
+        (last + bars as u32, beat - start - bars * meter.length())
+    }
+}
+
+impl TimedWavePacker {
+    // waves timed from 0 moved to start at beat, on the end like try_extend
+    pub fn try_extend_at<T: Borrow<[f32]>, A: Amplitude>(
+        &mut self,
+        timeline: &Timeline,
+        beat: f64,
+        waves: impl IntoIterator<Item = TimedWave<T, A>>,
+    ) -> Result<(), PackError> {
+        let offset = timeline.sample(beat);
+        self.try_extend(waves.into_iter().map(|tw| TimedWave {
+            start: tw.start.saturating_add(offset),
+            end: move_end(tw.end, offset),
+            ..tw
+        }))
+    }
+    // a note held for beats beats from beat, whatever the tempo does in between
+    pub fn add_note_at(
+        &mut self,
+        timeline: &Timeline,
+        instrument: &Instrument,
+        pitch: f32,
+        (beat, beats): (f64, f64),
+        velocity: f32,
+    ) -> Result<(), PackError> {
+        let start = timeline.sample(beat);
+        let duration = timeline.sample(beat + beats) - start;
+        self.add_note(instrument, pitch, start, duration, velocity)
+    }
+    // hits are (beat, velocity)
+    pub fn add_hits_at(
+        &mut self,
+        timeline: &Timeline,
+        drum: Drum,
+        hits: impl IntoIterator<Item = (f64, f32)>,
+    ) -> Result<(), PackError> {
+        let hits = hits
+            .into_iter()
+            .map(|(beat, velocity)| (timeline.sample(beat), velocity));
+        self.add_hits(drum, timeline.sample_rate, hits)
+    }
+    pub fn add_marker_at(
+        &mut self,
+        timeline: &Timeline,
+        beat: f64,
+        name: &str,
+    ) -> Result<(), PackError> {
+        self.add_marker(timeline.sample(beat), name)
+    }
+}
+
+#[test]
+fn timelines() {
+    let mut timeline = Timeline::new(120., 48000.);
+    assert_eq!(timeline.sample(1.), 24000);
+    assert_eq!(timeline.sample(-1.), -24000);
+    // half speed for the second bar then twice as fast, the second change on 8 replacing the first
+    timeline.set_tempo(4., 60.);
+    timeline.set_tempo(8., 120.);
+    timeline.set_tempo(8., 240.);
+    assert_eq!(timeline.tempos().len(), 3);
+    assert_eq!(timeline.sample(5.), 96000 + 48000);
+    assert_eq!(timeline.sample(9.), 96000 + 4 * 48000 + 12000);
+    assert_eq!(timeline.tempo_at(7.9), 60.);
+    for beat in [-2., 0., 3.5, 4., 6.25, 8., 20.] {
+        assert!((timeline.beat(timeline.sample(beat)) - beat).abs() < 1e-9);
+    }
+
+    // two bars of 4/4, two of 3/4, then 6/8
+    timeline.set_meter(2, Meter { beats: 3, unit: 4 });
+    timeline.set_meter(4, Meter { beats: 6, unit: 8 });
+    assert_eq!(timeline.bar(0, 1.), 1.);
+    assert_eq!(timeline.bar(3, 0.), 11.);
+    assert_eq!(timeline.bar(5, 0.5), 14. + 3. + 0.5);
+    for (bar, beat) in [(0, 0.), (1, 3.5), (2, 0.), (3, 2.), (6, 1.)] {
+        assert_eq!(timeline.bar_of(timeline.bar(bar, beat)), (bar, beat));
+    }
+
+    // slowing down stretches the note rather than leaving it where it was
+    let mut packer = TimedWavePacker::new();
+    let instrument = Instrument::new(48000.);
+    packer
+        .add_note_at(&timeline, &instrument, 60., (3., 2.), 1.)
+        .unwrap();
+    packer
+        .add_hits_at(&timeline, Drum::Kick, [(timeline.bar(2, 0.), 1.)])
+        .unwrap();
+    assert_eq!(packer.timings[0][0], 72000);
+    assert_eq!(packer.envelopes[0].gate, (24000 + 48000) as f32);
+    assert_eq!(packer.timings[1][0], timeline.sample(8.));
+    packer.add_marker_at(&timeline, 8., "chorus").unwrap();
+    assert_eq!(packer.marker_times, [timeline.sample(8.)]);
+}
@@ -0,0 +1,141 @@
+// offline rendering, runs a player as fast as it can go and writes the result out as a mono wav
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, Write},
+    path::Path,
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::{PackedTimedWaves, Player, OPEN};
+
+const BLOCK: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WavDepth {
+    Int16,
+    Int24,
+    #[default]
+    Float32,
+}
+impl WavDepth {
+    fn spec(self, sample_rate: u32) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            WavDepth::Int16 => (16, SampleFormat::Int),
+            WavDepth::Int24 => (24, SampleFormat::Int),
+            WavDepth::Float32 => (32, SampleFormat::Float),
+        };
+        WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+pub fn render_to_wav(
+    pack: PackedTimedWaves,
+    sample_rate: u32,
+    depth: WavDepth,
+    path: impl AsRef<Path>,
+) -> Result<(), hound::Error> {
+    write_wav(
+        pack,
+        sample_rate,
+        depth,
+        BufWriter::new(File::create(path)?),
+    )
+}
+
+// renders from sample 0 until the last wave has finished its release, a pack with an OPEN wave
+// in it never gets there so it's turned away
+pub fn write_wav<W: Write + Seek>(
+    pack: PackedTimedWaves,
+    sample_rate: u32,
+    depth: WavDepth,
+    out: W,
+) -> Result<(), hound::Error> {
+    let length = pack.end_time().max(0);
+    if length == OPEN {
+        let open = io::Error::new(io::ErrorKind::InvalidInput, "a wave in the pack never ends");
+        return Err(hound::Error::IoError(open));
+    }
+    let mut writer = WavWriter::new(out, depth.spec(sample_rate))?;
+    let mut player = Player::new(pack, 0, Some(length));
+    let mut block = [0f32; BLOCK];
+    let mut remaining = length as usize;
+    while remaining > 0 {
+        let block = &mut block[..remaining.min(BLOCK)];
+        remaining -= block.len();
+        // the wakeup is the end of the pack so the player never hands it back early
+        player
+            .play(block)
+            .expect("player stopped before the end of the pack");
+        for &sample in block.iter() {
+            let sample = sample.clamp(-1., 1.);
+            match depth {
+                WavDepth::Int16 => writer.write_sample((sample * i16::MAX as f32) as i16)?,
+                WavDepth::Int24 => writer.write_sample((sample * 8_388_607.) as i32)?,
+                WavDepth::Float32 => writer.write_sample(sample)?,
+            }
+        }
+    }
+    writer.finalize()
+}
+
+#[test]
+fn bounce() {
+    use std::io::Cursor;
+
+    use hound::WavReader;
+
+    use crate::{Fade, LiveWave, TimedWave, TimedWavePacker};
+
+    let waves: TimedWavePacker = [(0, 3000), (2000, 6000)]
+        .into_iter()
+        .map(|(start, end)| TimedWave {
+            fade: Fade {
+                release: 100,
+                ..Fade::default()
+            },
+            ..LiveWave::sine(start, end, 0.01, 0.5)
+        })
+        .collect();
+    let mut live = vec![0f32; 6100];
+    Player::new(waves.get_pack().unwrap(), 0, Some(6100))
+        .play(&mut live[..])
+        .unwrap();
+
+    for depth in [WavDepth::Int16, WavDepth::Int24, WavDepth::Float32] {
+        let mut file = Cursor::new(Vec::new());
+        write_wav(waves.get_pack().unwrap(), 48000, depth, &mut file).unwrap();
+        file.set_position(0);
+        let reader = WavReader::new(file).unwrap();
+        assert_eq!(reader.spec(), depth.spec(48000));
+        let bounced: Vec<f32> = match depth {
+            WavDepth::Int16 => reader
+                .into_samples::<i16>()
+                .map(|s| s.unwrap() as f32 / i16::MAX as f32)
+                .collect(),
+            WavDepth::Int24 => reader
+                .into_samples::<i32>()
+                .map(|s| s.unwrap() as f32 / 8_388_607.)
+                .collect(),
+            WavDepth::Float32 => reader.into_samples::<f32>().map(Result::unwrap).collect(),
+        };
+        assert_eq!(bounced.len(), live.len());
+        assert!(bounced.iter().zip(&live).all(|(b, l)| (b - l).abs() < 1e-4));
+    }
+
+    // a held note never runs out, so there's no end to render up to
+    let held: TimedWavePacker = [LiveWave::sine(0, OPEN, 0.01, 0.5)].into_iter().collect();
+    let mut file = Cursor::new(Vec::new());
+    assert!(write_wav(
+        held.get_pack().unwrap(),
+        48000,
+        WavDepth::Float32,
+        &mut file
+    )
+    .is_err());
+}